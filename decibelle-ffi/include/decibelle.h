@@ -0,0 +1,180 @@
+#ifndef DECIBELLE_H
+#define DECIBELLE_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum DecibelleValueType {
+  DecibelleVoid,
+  DecibelleNumber,
+  DecibelleString,
+  DecibelleBoolean,
+  DecibelleTuple,
+  DecibelleFunction,
+  DecibelleReference,
+  DecibelleResult,
+  DecibelleOption,
+  DecibelleIterator,
+  DecibelleChannel,
+  DecibelleTask,
+} DecibelleValueType;
+
+/**
+ * An interpreter session. Create with decibelle_create and free with decibelle_destroy.
+ */
+typedef struct DecibelleInterpreter DecibelleInterpreter;
+
+/**
+ * A copy of a value taken out of an interpreter. Free with decibelle_value_free.
+ */
+typedef struct DecibelleValue DecibelleValue;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates a new interpreter.
+ */
+struct DecibelleInterpreter *decibelle_create(void);
+
+/**
+ * Frees an interpreter.
+ *
+ * # Safety
+ * interpreter must be NULL or a pointer returned by decibelle_create which hasn't been freed.
+ */
+void decibelle_destroy(struct DecibelleInterpreter *interpreter);
+
+/**
+ * Runs a program. Returns true on success.
+ * On failure, the error can be read with decibelle_last_error.
+ *
+ * # Safety
+ * interpreter must be a valid interpreter and source a valid NUL terminated string.
+ */
+bool decibelle_eval(struct DecibelleInterpreter *interpreter, const char *source);
+
+/**
+ * Returns the error from the last call to decibelle_eval, or NULL if it succeeded.
+ * The string is owned by the interpreter and is valid until the next decibelle_eval.
+ *
+ * # Safety
+ * interpreter must be a valid interpreter.
+ */
+const char *decibelle_last_error(const struct DecibelleInterpreter *interpreter);
+
+/**
+ * Returns a copy of a global variable, or NULL if there is no such variable.
+ *
+ * # Safety
+ * interpreter must be a valid interpreter and name a valid NUL terminated string.
+ */
+struct DecibelleValue *decibelle_get_global(const struct DecibelleInterpreter *interpreter,
+                                            const char *name);
+
+/**
+ * Frees a value.
+ *
+ * # Safety
+ * value must be NULL or a value returned by this library which hasn't been freed.
+ */
+void decibelle_value_free(struct DecibelleValue *value);
+
+/**
+ * Returns the type of a value.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+enum DecibelleValueType decibelle_value_type(const struct DecibelleValue *value);
+
+/**
+ * Returns the number in a value, or 0 if the value isn't a number.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+double decibelle_value_number(const struct DecibelleValue *value);
+
+/**
+ * Returns the boolean in a value, or false if the value isn't a boolean.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+bool decibelle_value_boolean(const struct DecibelleValue *value);
+
+/**
+ * Returns the number of elements in a tuple or record, or 0 if the value isn't a tuple or record.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+uintptr_t decibelle_value_tuple_length(const struct DecibelleValue *value);
+
+/**
+ * Returns a copy of a tuple element or record field, or NULL if the value isn't a tuple or record
+ * or the index is too large.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+struct DecibelleValue *decibelle_value_tuple_get(const struct DecibelleValue *value,
+                                                 uintptr_t index);
+
+/**
+ * Returns whether a value is an Ok result.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+bool decibelle_value_is_ok(const struct DecibelleValue *value);
+
+/**
+ * Returns whether a value is a Some option.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+bool decibelle_value_is_some(const struct DecibelleValue *value);
+
+/**
+ * Returns a copy of the value in a Some option, or NULL if the value isn't a Some option.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+struct DecibelleValue *decibelle_value_option_get(const struct DecibelleValue *value);
+
+/**
+ * Returns a copy of the value or error in a result, or NULL if the value isn't a result.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+struct DecibelleValue *decibelle_value_result_get(const struct DecibelleValue *value);
+
+/**
+ * Returns the value formatted the same way print formats it. Free with decibelle_string_free.
+ *
+ * # Safety
+ * value must be a valid value.
+ */
+char *decibelle_value_to_string(const struct DecibelleValue *value);
+
+/**
+ * Frees a string returned by decibelle_value_to_string.
+ *
+ * # Safety
+ * string must be NULL or a string returned by decibelle_value_to_string which hasn't been freed.
+ */
+void decibelle_string_free(char *string);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* DECIBELLE_H */
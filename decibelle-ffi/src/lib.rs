@@ -0,0 +1,285 @@
+//! C ABI for embedding the decibelle interpreter.
+//!
+//! The header for these functions, include/decibelle.h, is generated with cbindgen from cbindgen.toml and checked in.
+
+use std::{
+    ffi::{c_char, CStr, CString},
+    ptr,
+};
+
+use decibelle::{tokenizer::Value, Interpreter};
+
+/// An interpreter session. Create with decibelle_create and free with decibelle_destroy.
+pub struct DecibelleInterpreter {
+    interpreter: Interpreter,
+    last_error: Option<CString>,
+}
+
+/// A copy of a value taken out of an interpreter. Free with decibelle_value_free.
+pub struct DecibelleValue {
+    value: Value,
+}
+
+#[repr(C)]
+pub enum DecibelleValueType {
+    DecibelleVoid,
+    DecibelleNumber,
+    DecibelleString,
+    DecibelleBoolean,
+    DecibelleTuple,
+    DecibelleFunction,
+    DecibelleReference,
+    DecibelleResult,
+    DecibelleOption,
+    DecibelleIterator,
+    DecibelleChannel,
+    DecibelleTask,
+}
+
+/// Creates a new interpreter.
+#[no_mangle]
+pub extern "C" fn decibelle_create() -> *mut DecibelleInterpreter {
+    Box::into_raw(Box::new(DecibelleInterpreter {
+        interpreter: Interpreter::new(),
+        last_error: None,
+    }))
+}
+
+/// Frees an interpreter.
+///
+/// # Safety
+/// interpreter must be NULL or a pointer returned by decibelle_create which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_destroy(interpreter: *mut DecibelleInterpreter) {
+    if !interpreter.is_null() {
+        drop(Box::from_raw(interpreter));
+    }
+}
+
+/// Runs a program. Returns true on success.
+/// On failure, the error can be read with decibelle_last_error.
+///
+/// # Safety
+/// interpreter must be a valid interpreter and source a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_eval(
+    interpreter: *mut DecibelleInterpreter,
+    source: *const c_char,
+) -> bool {
+    let interpreter = &mut *interpreter;
+    interpreter.last_error = None;
+
+    let Ok(program) = CStr::from_ptr(source).to_str() else {
+        interpreter.last_error = Some(c"Program isn't valid UTF-8.".into());
+        return false;
+    };
+
+    match interpreter.interpreter.eval(program) {
+        Ok(()) => true,
+        Err(error) => {
+            interpreter.last_error = Some(to_c_string(error.report(program)));
+            false
+        }
+    }
+}
+
+/// Returns the error from the last call to decibelle_eval, or NULL if it succeeded.
+/// The string is owned by the interpreter and is valid until the next decibelle_eval.
+///
+/// # Safety
+/// interpreter must be a valid interpreter.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_last_error(
+    interpreter: *const DecibelleInterpreter,
+) -> *const c_char {
+    match &(*interpreter).last_error {
+        Some(error) => error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Returns a copy of a global variable, or NULL if there is no such variable.
+///
+/// # Safety
+/// interpreter must be a valid interpreter and name a valid NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_get_global(
+    interpreter: *const DecibelleInterpreter,
+    name: *const c_char,
+) -> *mut DecibelleValue {
+    let Ok(name) = CStr::from_ptr(name).to_str() else {
+        return ptr::null_mut();
+    };
+
+    match (*interpreter).interpreter.get_global(name) {
+        Some(value) => Box::into_raw(Box::new(DecibelleValue { value })),
+        None => ptr::null_mut(),
+    }
+}
+
+/// Frees a value.
+///
+/// # Safety
+/// value must be NULL or a value returned by this library which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_free(value: *mut DecibelleValue) {
+    if !value.is_null() {
+        drop(Box::from_raw(value));
+    }
+}
+
+/// Returns the type of a value.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_type(value: *const DecibelleValue) -> DecibelleValueType {
+    match (*value).value {
+        Value::Void => DecibelleValueType::DecibelleVoid,
+        Value::Number(_) => DecibelleValueType::DecibelleNumber,
+        Value::String(_) => DecibelleValueType::DecibelleString,
+        Value::Boolean(_) => DecibelleValueType::DecibelleBoolean,
+        // Records' fields are read by position, like tuples'.
+        Value::Tuple(_) | Value::Record(_) => DecibelleValueType::DecibelleTuple,
+        Value::Function(_) => DecibelleValueType::DecibelleFunction,
+        Value::Reference(_) => DecibelleValueType::DecibelleReference,
+        Value::Result(_) => DecibelleValueType::DecibelleResult,
+        Value::Option(_) => DecibelleValueType::DecibelleOption,
+        Value::Iterator(_) => DecibelleValueType::DecibelleIterator,
+        Value::Channel(_) => DecibelleValueType::DecibelleChannel,
+        Value::Task(_) => DecibelleValueType::DecibelleTask,
+    }
+}
+
+/// Returns the number in a value, or 0 if the value isn't a number.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_number(value: *const DecibelleValue) -> f64 {
+    match (*value).value {
+        Value::Number(number) => number,
+        _ => 0.0,
+    }
+}
+
+/// Returns the boolean in a value, or false if the value isn't a boolean.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_boolean(value: *const DecibelleValue) -> bool {
+    matches!((*value).value, Value::Boolean(true))
+}
+
+/// Returns the number of elements in a tuple or record, or 0 if the value isn't a tuple or record.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_tuple_length(value: *const DecibelleValue) -> usize {
+    match &(*value).value {
+        Value::Tuple(values) => values.len(),
+        Value::Record(fields) => fields.len(),
+        _ => 0,
+    }
+}
+
+/// Returns a copy of a tuple element or record field, or NULL if the value isn't a tuple or record
+/// or the index is too large.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_tuple_get(
+    value: *const DecibelleValue,
+    index: usize,
+) -> *mut DecibelleValue {
+    match &(*value).value {
+        Value::Tuple(values) if index < values.len() => Box::into_raw(Box::new(DecibelleValue {
+            value: values[index].clone(),
+        })),
+        Value::Record(fields) if index < fields.len() => Box::into_raw(Box::new(DecibelleValue {
+            value: fields[index].1.clone(),
+        })),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Returns whether a value is an Ok result.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_is_ok(value: *const DecibelleValue) -> bool {
+    matches!(&(*value).value, Value::Result(result) if result.is_ok())
+}
+
+/// Returns whether a value is a Some option.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_is_some(value: *const DecibelleValue) -> bool {
+    matches!(&(*value).value, Value::Option(Some(_)))
+}
+
+/// Returns a copy of the value in a Some option, or NULL if the value isn't a Some option.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_option_get(
+    value: *const DecibelleValue,
+) -> *mut DecibelleValue {
+    match &(*value).value {
+        Value::Option(Some(value)) => Box::into_raw(Box::new(DecibelleValue {
+            value: (**value).clone(),
+        })),
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Returns a copy of the value or error in a result, or NULL if the value isn't a result.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_result_get(
+    value: *const DecibelleValue,
+) -> *mut DecibelleValue {
+    match &(*value).value {
+        Value::Result(result) => {
+            let (Ok(value) | Err(value)) = &**result;
+            Box::into_raw(Box::new(DecibelleValue {
+                value: value.clone(),
+            }))
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Returns the value formatted the same way print formats it. Free with decibelle_string_free.
+///
+/// # Safety
+/// value must be a valid value.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_value_to_string(value: *const DecibelleValue) -> *mut c_char {
+    to_c_string((*value).value.to_string()).into_raw()
+}
+
+/// Frees a string returned by decibelle_value_to_string.
+///
+/// # Safety
+/// string must be NULL or a string returned by decibelle_value_to_string which hasn't been freed.
+#[no_mangle]
+pub unsafe extern "C" fn decibelle_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Converts a string to a C string, dropping any NUL characters in it.
+fn to_c_string(string: String) -> CString {
+    CString::new(string.replace('\0', "")).unwrap()
+}
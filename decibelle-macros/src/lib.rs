@@ -0,0 +1,424 @@
+//! Macros for embedding decibelle in Rust.
+//!
+//! The decibelle! macro embeds a program in Rust code and tokenizes and parses it when the Rust code is compiled,
+//! so syntax errors are compiler errors:
+//!
+//! ```ignore
+//! const PROGRAM: decibelle::Program = decibelle_macros::decibelle! {
+//!     let x = 1 + 2;
+//!     print(x);
+//! };
+//!
+//! Interpreter::new().eval_program(&PROGRAM).unwrap();
+//! ```
+//!
+//! The program is written out from the Rust tokens, so it has to be made of tokens Rust accepts. Strings are copied
+//! as they're written, escapes included, and `//` comments are left out.
+//!
+//! `#[derive(ScriptType)]` converts a struct with named fields to and from a record with the same fields, whose types
+//! implement decibelle::script_type::ScriptValue. The methods listed in `#[script(methods(...))]` take &self,
+//! and are registered by Interpreter::register_type as functions named after the struct and the method:
+//!
+//! ```ignore
+//! #[derive(ScriptType)]
+//! #[script(methods(length))]
+//! struct Point {
+//!     x: f64,
+//!     y: f64,
+//! }
+//!
+//! impl Point {
+//!     fn length(&self) -> f64 {
+//!         self.x.hypot(self.y)
+//!     }
+//! }
+//!
+//! interpreter.register_type::<Point>();
+//! interpreter.eval("print(point_length((x: 3, y: 4)));").unwrap();
+//! ```
+
+use decibelle::{parser::parse, tokenizer::tokenize};
+use proc_macro::{Delimiter, Literal, Spacing, Span, TokenStream, TokenTree};
+
+#[proc_macro]
+pub fn decibelle(input: TokenStream) -> TokenStream {
+    let mut source = Source::default();
+    source.write_stream(input);
+    let errors = match tokenize(&source.text) {
+        Ok(tokens) => match parse(&tokens) {
+            Ok(_) => vec![],
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| (source.line_span(error.lines.0), error.error.to_string()))
+                .collect(),
+        },
+        // Tokenizer errors don't say which line they're on apart from in their message.
+        Err(errors) => errors
+            .into_iter()
+            .map(|error| (Span::call_site(), error.to_string()))
+            .collect(),
+    };
+
+    let program = format!(
+        "::decibelle::Program::new_unchecked({})",
+        Literal::string(if errors.is_empty() { &source.text } else { "" })
+    )
+    .parse::<TokenStream>()
+    .unwrap();
+    if errors.is_empty() {
+        return program;
+    }
+    // The errors are in a block which still has the type of a program, so only they're reported.
+    let mut block = errors
+        .into_iter()
+        .flat_map(|(span, error)| compile_error(&error, span))
+        .collect::<TokenStream>();
+    block.extend(program);
+    TokenStream::from(TokenTree::Group(proc_macro::Group::new(
+        Delimiter::Brace,
+        block,
+    )))
+}
+
+#[proc_macro_derive(ScriptType, attributes(script))]
+pub fn derive_script_type(input: TokenStream) -> TokenStream {
+    match ScriptStruct::parse(input) {
+        Ok(script_struct) => script_struct.implementation().parse().unwrap(),
+        Err((error, span)) => compile_error(&error, span),
+    }
+}
+
+/// A struct deriving ScriptType.
+struct ScriptStruct {
+    name: String,
+    fields: Vec<(String, String)>, // The name and type of each field.
+    methods: Vec<String>,          // The methods listed in #[script(methods(...))].
+}
+
+impl ScriptStruct {
+    fn parse(input: TokenStream) -> Result<ScriptStruct, (String, Span)> {
+        let mut tokens = input.into_iter().peekable();
+        let mut methods = vec![];
+        // Attributes and the visibility.
+        while let Some(token) = tokens.next_if(|token| !is_ident(token, "struct")) {
+            if let TokenTree::Group(group) = &token {
+                if group.delimiter() == Delimiter::Bracket {
+                    methods.extend(script_methods(group.stream())?);
+                }
+            }
+            if is_ident(&token, "enum") || is_ident(&token, "union") {
+                return Err((
+                    "ScriptType can only be derived for structs.".into(),
+                    token.span(),
+                ));
+            }
+        }
+        tokens.next();
+        let Some(TokenTree::Ident(name)) = tokens.next() else {
+            unreachable!() // Derive macros are only passed structs, enums and unions.
+        };
+        match tokens.next() {
+            Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Brace => {
+                Ok(ScriptStruct {
+                    name: name.to_string(),
+                    fields: fields(group.stream()),
+                    methods,
+                })
+            }
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '<' => Err((
+                "ScriptType can't be derived for generic structs.".into(),
+                punct.span(),
+            )),
+            Some(TokenTree::Ident(ident)) if ident.to_string() == "where" => Err((
+                "ScriptType can't be derived for generic structs.".into(),
+                ident.span(),
+            )),
+            _ => Err((
+                "ScriptType can only be derived for structs with named fields.".into(),
+                name.span(),
+            )),
+        }
+    }
+
+    /// The implementations of ScriptValue and ScriptType.
+    fn implementation(&self) -> String {
+        let name = &self.name;
+        let mut field_types = String::new();
+        let mut field_values = String::new();
+        let mut field_names = String::new();
+        let mut converted_fields = String::new();
+        for (index, (field, field_type)) in self.fields.iter().enumerate() {
+            let script_name = field.strip_prefix("r#").unwrap_or(field);
+            field_types += &format!(
+                "({script_name:?}, <{field_type} as ::decibelle::script_type::ScriptValue>::script_type()),"
+            );
+            field_values += &format!(
+                "({script_name:?}, ::decibelle::script_type::ScriptValue::to_value(&self.{field})),"
+            );
+            field_names += &format!("{script_name:?},");
+            converted_fields += &format!(
+                "{field}: <{field_type} as ::decibelle::script_type::ScriptValue>::from_value(fields[{index}])?,"
+            );
+        }
+        let snake_case_name = snake_case(name);
+        let registered_methods = self
+            .methods
+            .iter()
+            .map(|method| {
+                format!("interpreter.register_method(\"{snake_case_name}_{method}\", {name}::{method});")
+            })
+            .collect::<String>();
+        format!(
+            "impl ::decibelle::script_type::ScriptValue for {name} {{
+                fn script_type() -> ::decibelle::tokenizer::Type {{
+                    ::decibelle::script_type::record_type(::std::vec![{field_types}])
+                }}
+
+                fn to_value(&self) -> ::decibelle::tokenizer::Value {{
+                    ::decibelle::script_type::record(::std::vec![{field_values}])
+                }}
+
+                #[allow(unused_variables)]
+                fn from_value(value: &::decibelle::tokenizer::Value) -> ::core::option::Option<Self> {{
+                    let fields = ::decibelle::script_type::record_fields(value, &[{field_names}])?;
+                    ::core::option::Option::Some({name} {{ {converted_fields} }})
+                }}
+            }}
+
+            impl ::decibelle::script_type::ScriptType for {name} {{
+                fn register_methods(interpreter: &mut ::decibelle::Interpreter) {{
+                    {registered_methods}
+                }}
+            }}"
+        )
+    }
+}
+
+fn is_ident(token: &TokenTree, name: &str) -> bool {
+    matches!(token, TokenTree::Ident(ident) if ident.to_string() == name)
+}
+
+/// The methods listed in an attribute if it's #[script(methods(...))], from the part in brackets.
+fn script_methods(attribute: TokenStream) -> Result<Vec<String>, (String, Span)> {
+    let tokens = attribute.into_iter().collect::<Vec<_>>();
+    let [script, TokenTree::Group(arguments)] = &tokens[..] else {
+        return Ok(vec![]);
+    };
+    if !is_ident(script, "script") {
+        return Ok(vec![]);
+    }
+    let arguments = arguments.stream().into_iter().collect::<Vec<_>>();
+    let [methods, TokenTree::Group(names)] = &arguments[..] else {
+        return Err(("Expected #[script(methods(...))].".into(), script.span()));
+    };
+    if !is_ident(methods, "methods") {
+        return Err(("Expected methods(...).".into(), methods.span()));
+    }
+    names
+        .stream()
+        .into_iter()
+        .filter(|token| !matches!(token, TokenTree::Punct(punct) if punct.as_char() == ','))
+        .map(|token| match token {
+            TokenTree::Ident(name) => Ok(name.to_string()),
+            token => Err(("Expected the name of a method.".into(), token.span())),
+        })
+        .collect()
+}
+
+/// The name and type of each field in the braces of a struct.
+fn fields(stream: TokenStream) -> Vec<(String, String)> {
+    let mut fields = vec![];
+    let mut tokens = stream.into_iter().peekable();
+    while tokens.peek().is_some() {
+        // Attributes and the visibility come before the name.
+        let name = loop {
+            match tokens.next() {
+                Some(TokenTree::Ident(ident)) if ident.to_string() != "pub" => {
+                    break ident.to_string()
+                }
+                Some(_) => {}
+                None => return fields,
+            }
+        };
+        tokens.next(); // :
+                       // The type ends at the next comma which isn't between angle brackets, like the one in Result<f64, String>.
+        let mut field_type = TokenStream::new();
+        let mut depth = 0;
+        let mut last_punct = ' ';
+        for token in tokens.by_ref() {
+            if let TokenTree::Punct(punct) = &token {
+                match punct.as_char() {
+                    ',' if depth == 0 => break,
+                    '<' => depth += 1,
+                    '>' if last_punct != '-' => depth -= 1,
+                    _ => {}
+                }
+                last_punct = punct.as_char();
+            } else {
+                last_punct = ' ';
+            }
+            field_type.extend([token]);
+        }
+        fields.push((name, field_type.to_string()));
+    }
+    fields
+}
+
+/// Converts a type name like HttpRequest to http_request.
+fn snake_case(name: &str) -> String {
+    let characters = name.chars().collect::<Vec<_>>();
+    let mut snake_case = String::new();
+    for (index, character) in characters.iter().enumerate() {
+        if character.is_uppercase() && index > 0 {
+            let previous = characters[index - 1];
+            let next_is_lowercase = characters
+                .get(index + 1)
+                .is_some_and(|next| next.is_lowercase());
+            if !previous.is_uppercase() && previous != '_'
+                || previous.is_uppercase() && next_is_lowercase
+            {
+                snake_case.push('_');
+            }
+        }
+        snake_case.extend(character.to_lowercase());
+    }
+    snake_case
+}
+
+/// A compile_error! statement pointing at the span.
+fn compile_error(error: &str, span: Span) -> TokenStream {
+    format!("::core::compile_error!({});", Literal::string(error))
+        .parse::<TokenStream>()
+        .unwrap()
+        .into_iter()
+        .map(|mut token| {
+            token.set_span(span);
+            token
+        })
+        .collect()
+}
+
+/// The program written out from the macro's tokens, with each token on the same line as in the Rust code,
+/// relative to the first token's line.
+#[derive(Default)]
+struct Source {
+    text: String,
+    line: usize,           // The line in the Rust code the text ends on.
+    column: usize,         // The column in the Rust code the text ends at, counting from 1.
+    line_spans: Vec<Span>, // The span of the first token on or after each line of the program.
+    joint: bool, // Was the last token a punctuation character joined to the next, like the first = of ==?
+}
+
+impl Source {
+    fn write_stream(&mut self, stream: TokenStream) {
+        let mut tokens = stream.into_iter().peekable();
+        while let Some(token) = tokens.next() {
+            match token {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.write(open, group.span_open());
+                    self.write_stream(group.stream());
+                    self.write(close, group.span_close());
+                }
+                // Rust turns /// and //! comments into #[doc = "..."] and #![doc = "..."], so they're turned back.
+                TokenTree::Punct(punct) if punct.as_char() == '#' => {
+                    let inner = matches!(tokens.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == '!');
+                    let mut lookahead = tokens.clone();
+                    if inner {
+                        lookahead.next();
+                    }
+                    match lookahead.next().and_then(|token| doc_comment(&token)) {
+                        Some(doc) => {
+                            tokens = lookahead;
+                            let prefix = if inner { "//!" } else { "///" };
+                            self.write(&format!("{prefix}{doc}"), punct.span());
+                        }
+                        None => {
+                            self.write("#", punct.span());
+                            self.joint = punct.spacing() == Spacing::Joint;
+                        }
+                    }
+                }
+                TokenTree::Punct(punct) => {
+                    self.write(&punct.as_char().to_string(), punct.span());
+                    self.joint = punct.spacing() == Spacing::Joint;
+                }
+                TokenTree::Ident(ident) => self.write(&ident.to_string(), ident.span()),
+                TokenTree::Literal(literal) => self.write(&literal.to_string(), literal.span()),
+            }
+        }
+    }
+
+    /// Writes the text of a token at the token's position.
+    fn write(&mut self, text: &str, span: Span) {
+        let (line, column) = (span.line(), span.column());
+        if self.line_spans.is_empty() {
+            self.line_spans.push(span);
+            (self.line, self.column) = (line, 1);
+        }
+        if line > self.line {
+            for _ in self.line..line {
+                self.text.push('\n');
+                self.line_spans.push(span);
+            }
+            (self.line, self.column) = (line, 1);
+        }
+        if column > self.column {
+            self.text.push_str(&" ".repeat(column - self.column));
+        } else if column < self.column && !self.joint {
+            // Tokens from other macros can share a span, so they're kept apart.
+            self.text.push(' ');
+        }
+        self.joint = false;
+        self.text.push_str(text);
+        match text.rsplit_once('\n') {
+            Some((before, after)) => {
+                for _ in 0..before.matches('\n').count() + 1 {
+                    self.line_spans.push(span);
+                }
+                self.line += before.matches('\n').count() + 1;
+                self.column = 1 + column_after(after);
+            }
+            None => self.column = column.max(self.column) + column_after(text),
+        }
+    }
+
+    /// The span of the first token on the line of the program.
+    fn line_span(&self, line: usize) -> Span {
+        self.line_spans
+            .get(line - 1)
+            .copied()
+            .unwrap_or_else(Span::call_site)
+    }
+}
+
+/// How many columns the text takes up.
+fn column_after(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// The text of a doc comment from the bracketed part of its attribute, like [doc = " text"].
+fn doc_comment(token: &TokenTree) -> Option<String> {
+    let TokenTree::Group(group) = token else {
+        return None;
+    };
+    if group.delimiter() != Delimiter::Bracket {
+        return None;
+    }
+    let tokens = group.stream().into_iter().collect::<Vec<_>>();
+    let [TokenTree::Ident(doc), TokenTree::Punct(equals), TokenTree::Literal(text)] = &tokens[..]
+    else {
+        return None;
+    };
+    if doc.to_string() != "doc" || equals.as_char() != '=' {
+        return None;
+    }
+    let text = text.to_string();
+    let text = text.strip_prefix('"')?.strip_suffix('"')?;
+    Some(text.replace("\\\"", "\"").replace("\\\\", "\\"))
+}
@@ -0,0 +1,212 @@
+//! PyO3 bindings for running decibelle programs from Python.
+//!
+//! Build with `maturin develop` inside this directory, then `import decibelle`.
+
+use std::rc::Rc;
+
+use decibelle::{
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+    variable_and_type_check::can_assign,
+};
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyBool, PyDict, PyFloat, PyInt, PyString, PyTuple},
+};
+
+create_exception!(decibelle, DecibelleError, PyException);
+
+/// An interpreter session. Global variables persist between calls to eval.
+#[pyclass(unsendable, name = "Interpreter")]
+struct Interpreter {
+    interpreter: decibelle::Interpreter,
+}
+
+#[pymethods]
+impl Interpreter {
+    #[new]
+    fn new() -> Interpreter {
+        let mut interpreter = decibelle::Interpreter::new();
+        // Send print to sys.stdout so the output shows up in notebooks.
+        interpreter.set_output(|text| {
+            Python::attach(|py| {
+                if let Ok(stdout) = py.import("sys").and_then(|sys| sys.getattr("stdout")) {
+                    let _ = stdout.call_method1("write", (text,));
+                }
+            })
+        });
+        Interpreter { interpreter }
+    }
+
+    /// Runs a program, raising DecibelleError if it has errors.
+    fn eval(&mut self, source: &str) -> PyResult<()> {
+        self.interpreter
+            .eval(source)
+            .map_err(|error| DecibelleError::new_err(error.report(source)))
+    }
+
+    /// Returns the value of a global variable, or None if there is no such variable.
+    fn get_global(&self, py: Python<'_>, name: &str) -> PyResult<Option<Py<PyAny>>> {
+        self.interpreter
+            .get_global(name)
+            .map(|value| to_python(py, &value))
+            .transpose()
+    }
+
+    /// Adds a global function implemented in Python.
+    /// Types are written the same way as in programs, for example "number" or "(string, bool)".
+    #[pyo3(signature = (name, function, parameters, return_type=None))]
+    fn register(
+        &mut self,
+        name: &str,
+        function: Py<PyAny>,
+        parameters: Vec<String>,
+        return_type: Option<&str>,
+    ) -> PyResult<()> {
+        let parameters = parameters
+            .iter()
+            .map(|parameter| parameter.parse::<Type>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(PyValueError::new_err)?;
+        let return_type = match return_type {
+            Some(return_type) => return_type.parse().map_err(PyValueError::new_err)?,
+            None => Type::Void,
+        };
+
+        let expected_type = return_type.clone();
+        self.interpreter
+            .register_fallible_function(name, parameters, return_type, move |values| {
+                Python::attach(|py| {
+                    let result = values
+                        .iter()
+                        .map(|value| to_python(py, value))
+                        .collect::<PyResult<Vec<_>>>()
+                        .and_then(|arguments| PyTuple::new(py, arguments))
+                        .and_then(|arguments| function.call1(py, arguments))
+                        .and_then(|result| from_python(result.bind(py)));
+                    match result {
+                        Ok(_) if expected_type == Type::Void => Ok(Value::Void),
+                        Ok(value) if can_assign(&expected_type, &value.value_type()) => Ok(value),
+                        Ok(value) => Err(format!(
+                            "returned a value of type {}, not {expected_type}",
+                            value.value_type()
+                        )),
+                        Err(error) => Err(format!("raised {error}")),
+                    }
+                })
+            });
+        Ok(())
+    }
+}
+
+/// Runs a program in a fresh interpreter, raising DecibelleError if it has errors.
+#[pyfunction]
+fn eval(source: &str) -> PyResult<()> {
+    Interpreter::new().eval(source)
+}
+
+fn to_python(py: Python<'_>, value: &Value) -> PyResult<Py<PyAny>> {
+    Ok(match value {
+        Value::Void => py.None(),
+        Value::Number(number) => PyFloat::new(py, *number).into_any().unbind(),
+        Value::String(string) => PyString::new(py, string.as_str()).into_any().unbind(),
+        Value::Boolean(boolean) => PyBool::new(py, *boolean).to_owned().into_any().unbind(),
+        Value::Tuple(values) => PyTuple::new(
+            py,
+            values
+                .iter()
+                .map(|value| to_python(py, value))
+                .collect::<PyResult<Vec<_>>>()?,
+        )?
+        .into_any()
+        .unbind(),
+        // Records become dicts, which keep the order of their fields.
+        Value::Record(fields) => {
+            let dict = PyDict::new(py);
+            for (name, value) in fields.iter() {
+                dict.set_item(name.to_string(), to_python(py, value)?)?;
+            }
+            dict.into_any().unbind()
+        }
+        // Ok results become their value, and Err results raise their error.
+        Value::Result(result) => match &**result {
+            Ok(value) => to_python(py, value)?,
+            Err(error) => return Err(DecibelleError::new_err(error.to_string())),
+        },
+        // Options become their value, or None.
+        Value::Option(Some(value)) => to_python(py, value)?,
+        Value::Option(None) => py.None(),
+        Value::Function(_) => {
+            return Err(PyTypeError::new_err(
+                "Functions can't be converted to Python objects.",
+            ))
+        }
+        Value::Reference(_) => {
+            return Err(PyTypeError::new_err(
+                "References can't be converted to Python objects.",
+            ))
+        }
+        Value::Iterator(_) => {
+            return Err(PyTypeError::new_err(
+                "Iterators can't be converted to Python objects.",
+            ))
+        }
+        Value::Channel(_) => {
+            return Err(PyTypeError::new_err(
+                "Channels can't be converted to Python objects.",
+            ))
+        }
+        Value::Task(_) => {
+            return Err(PyTypeError::new_err(
+                "Tasks can't be converted to Python objects.",
+            ))
+        }
+    })
+}
+
+fn from_python(object: &Bound<'_, PyAny>) -> PyResult<Value> {
+    if object.is_none() {
+        Ok(Value::Void)
+    } else if object.is_instance_of::<PyBool>() {
+        // bool is a subclass of int, so it's checked before int.
+        Ok(Value::Boolean(object.extract()?))
+    } else if object.is_instance_of::<PyInt>() || object.is_instance_of::<PyFloat>() {
+        Ok(Value::Number(object.extract()?))
+    } else if object.is_instance_of::<PyString>() {
+        Ok(Value::String(object.extract::<String>()?.into()))
+    } else if let Ok(tuple) = object.cast::<PyTuple>() {
+        Ok(Value::Tuple(Rc::new(
+            tuple
+                .iter()
+                .map(|element| from_python(&element))
+                .collect::<PyResult<_>>()?,
+        )))
+    } else if let Ok(dict) = object.cast::<PyDict>() {
+        Ok(Value::Record(Rc::new(
+            dict.iter()
+                .map(|(name, value)| {
+                    Ok((
+                        Symbol::intern(&name.extract::<String>()?),
+                        from_python(&value)?,
+                    ))
+                })
+                .collect::<PyResult<_>>()?,
+        )))
+    } else {
+        Err(PyTypeError::new_err(format!(
+            "{} can't be converted to a decibelle value.",
+            object.get_type().name()?
+        )))
+    }
+}
+
+#[pymodule]
+#[pyo3(name = "decibelle")]
+fn decibelle_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Interpreter>()?;
+    module.add_function(wrap_pyfunction!(eval, module)?)?;
+    module.add("DecibelleError", module.py().get_type::<DecibelleError>())?;
+    Ok(())
+}
@@ -0,0 +1,52 @@
+//! wasm-bindgen bindings for running decibelle in the browser.
+//!
+//! Build with `wasm-pack build decibelle-wasm --target web`.
+
+use decibelle::Interpreter;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A JavaScript function which receives the output of print.
+    #[wasm_bindgen(typescript_type = "(text: string) => void")]
+    pub type OutputCallback;
+
+    #[wasm_bindgen(method, js_name = call)]
+    fn call(this: &OutputCallback, context: &JsValue, text: &str);
+
+    /// Browsers have no monotonic clock for Rust, so clock uses performance.now().
+    #[wasm_bindgen(js_namespace = performance)]
+    fn now() -> f64;
+}
+
+/// An interpreter session. Global variables persist between calls to eval.
+#[wasm_bindgen(js_name = Interpreter)]
+pub struct WasmInterpreter {
+    interpreter: Interpreter,
+}
+
+#[wasm_bindgen(js_class = Interpreter)]
+impl WasmInterpreter {
+    /// Creates an interpreter which sends the output of print to the given function.
+    #[wasm_bindgen(constructor)]
+    pub fn new(output: OutputCallback) -> WasmInterpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_output(move |text| output.call(&JsValue::NULL, text));
+        interpreter.set_clock(now);
+        WasmInterpreter { interpreter }
+    }
+
+    /// Runs a program. Returns null on success, and the error report otherwise.
+    pub fn eval(&mut self, source: &str) -> JsValue {
+        match self.interpreter.eval(source) {
+            Ok(()) => JsValue::NULL,
+            Err(error) => JsValue::from_str(&error.report(source)),
+        }
+    }
+}
+
+/// Runs a program in a fresh interpreter. Returns null on success, and the error report otherwise.
+#[wasm_bindgen]
+pub fn eval(source: &str, output: OutputCallback) -> JsValue {
+    WasmInterpreter::new(output).eval(source)
+}
@@ -0,0 +1,39 @@
+#![no_main]
+
+use decibelle::{
+    messages::NESTED_TOO_DEEPLY,
+    parser::{parse, Statement},
+    tokenizer::tokenize,
+    Interpreter,
+};
+use libfuzzer_sys::fuzz_target;
+
+/// The most statements a generated program runs, so programs which loop forever finish.
+const STEP_LIMIT: usize = 10_000;
+
+fn print(statements: &[Statement]) -> String {
+    statements
+        .iter()
+        .map(|statement| format!("{statement}\n"))
+        .collect()
+}
+
+// Prints a generated program, parses it back and checks it prints the same way, then runs it.
+// Any panic, in the parser, checker or tree walker, is a bug.
+fuzz_target!(|statements: Vec<Statement>| {
+    let program = print(&statements);
+    let tokens = tokenize(&program)
+        .unwrap_or_else(|errors| panic!("Couldn't tokenize:\n{program}{}", errors[0]));
+    let parsed = match parse(&tokens) {
+        Ok(parsed) => parsed,
+        // Generated programs can nest deeper than programs are allowed to.
+        Err(errors) if errors[0].error.message == &NESTED_TOO_DEEPLY => return,
+        Err(errors) => panic!("Couldn't parse:\n{program}{}", errors[0].error),
+    };
+    assert_eq!(print(&parsed), program);
+
+    let mut interpreter = Interpreter::new();
+    interpreter.set_output(|_| {});
+    interpreter.set_step_limit(Some(STEP_LIMIT));
+    let _ = interpreter.eval(&program);
+});
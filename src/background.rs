@@ -0,0 +1,109 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+use crate::{channel::Message, messages::diagnostic, Backend, EvalError, Interpreter};
+
+/// How a program run in the background finished.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExecResult {
+    Returned(Message), // What the program returned from its top level, or void if it ran to the end.
+    Exited(i32),       // The program called exit with this code.
+    Failed(String),    // The report of the error which stopped the program.
+    Cancelled,
+}
+
+/// A program running in its own interpreter on a worker thread, started with Interpreter::spawn_eval.
+/// The host can poll it, read what it prints as it prints it, wait for it or cancel it, without blocking on it.
+pub struct ExecHandle {
+    output: Receiver<String>,
+    result: Receiver<ExecResult>,
+    finished: Option<ExecResult>, // Set once the result has been received.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ExecHandle {
+    /// Runs the program on a new thread in an interpreter with the backend, after calling setup with it, so hosts
+    /// can register their functions. Interpreters can't leave the thread they were made on, so setup is sent instead.
+    pub fn spawn(
+        backend: Backend,
+        program: String,
+        setup: impl FnOnce(&mut Interpreter) + Send + 'static,
+    ) -> ExecHandle {
+        let (output_sender, output) = mpsc::channel();
+        let (result_sender, result) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        thread::spawn(move || {
+            let run = AssertUnwindSafe(|| {
+                let mut interpreter = Interpreter::with_backend(backend);
+                interpreter.set_output(move |text| {
+                    let _ = output_sender.send(text.to_string());
+                });
+                interpreter.variables.cancelled = Some(worker_cancelled.clone());
+                setup(&mut interpreter);
+                match interpreter.eval(&program) {
+                    Err(_) if worker_cancelled.load(Ordering::Relaxed) => ExecResult::Cancelled,
+                    Ok(()) => match Message::from_value(interpreter.return_value()) {
+                        Ok(message) => ExecResult::Returned(message),
+                        Err(error) => ExecResult::Failed(error.to_string()),
+                    },
+                    Err(EvalError::Exit(code)) => ExecResult::Exited(code),
+                    Err(error) => ExecResult::Failed(error.report(&program)),
+                }
+            });
+            let result = panic::catch_unwind(run)
+                .unwrap_or_else(|_| ExecResult::Failed(diagnostic!(PROGRAM_PANICKED).to_string()));
+            let _ = result_sender.send(result);
+        });
+        ExecHandle {
+            output,
+            result,
+            finished: None,
+            cancelled,
+        }
+    }
+
+    /// Returns how the program finished, or None if it's still running.
+    pub fn poll(&mut self) -> Option<&ExecResult> {
+        if self.finished.is_none() {
+            self.finished = match self.result.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => unreachable!(), // The worker always sends a result.
+            };
+        }
+        self.finished.as_ref()
+    }
+
+    /// Returns what the program has printed since the output was last read, without waiting.
+    pub fn take_output(&self) -> String {
+        self.output.try_iter().collect()
+    }
+
+    /// Waits for the program to print something and returns it, or returns None once it finished and all its output
+    /// has been read.
+    pub fn recv_output(&self) -> Option<String> {
+        self.output.recv().ok()
+    }
+
+    /// Stops the program before its next statement, or with Backend::Vm within a thousand or so instructions.
+    /// Code compiled by the JIT runs until it returns to the tree walker.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Waits for the program to finish and returns how it finished. Its output can still be read afterwards.
+    pub fn join(&mut self) -> ExecResult {
+        if self.finished.is_none() {
+            self.finished = Some(self.result.recv().unwrap());
+        }
+        self.finished.clone().unwrap()
+    }
+}
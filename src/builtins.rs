@@ -0,0 +1,773 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashSet,
+    iter,
+    rc::Rc,
+};
+
+use unicase::UniCase;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    format::{FormatSpec, ValueFormat},
+    messages::{diagnostic, Diagnostic},
+    parser::{Expression, ExpressionType, Statement, StatementType},
+    replay::Replay,
+    source_map::SourceMap,
+    symbol::Symbol,
+    task::Task,
+    tokenizer::{Type, Value},
+    Backend, EvalError, Interpreter,
+};
+
+/// Where the output of print goes.
+pub type Output = Rc<RefCell<Box<dyn FnMut(&str)>>>;
+
+/// Where clock gets the time from, in milliseconds.
+pub type Clock = Rc<RefCell<Box<dyn Fn() -> f64>>>;
+
+/// The parts of an interpreter builtins use.
+#[derive(Clone)]
+pub struct BuiltinContext {
+    pub output: Output,
+    pub garbage_collection_requested: Rc<Cell<bool>>,
+    pub exit_code: Rc<Cell<Option<i32>>>,
+    pub error: Rc<Cell<Option<Diagnostic>>>, // Set to stop the program with a runtime error.
+    pub source_name: Rc<RefCell<Option<String>>>, // Where the program came from, which dbg prints.
+    pub source_map: Rc<RefCell<Option<SourceMap>>>, // Where the lines of an assembled program came from.
+    pub line: Rc<Cell<usize>>,                      // The line the builtin was called on.
+    pub clock: Clock,
+    pub replay: Rc<RefCell<Replay>>, // Records or replays the results of builtins like clock.
+    pub value_format: Rc<Cell<ValueFormat>>, // How print and dbg write values.
+    pub backend: Backend,            // The backend spawned tasks run with.
+    #[cfg(feature = "tracing")]
+    pub log_to_tracing: Rc<Cell<bool>>, // Whether print and dbg log events with tracing instead of writing output.
+}
+
+impl BuiltinContext {
+    /// The source and line the program's line came from, using the source map if the line is in it.
+    pub fn location(&self, line: usize) -> (Option<String>, usize) {
+        let source_map = self.source_map.borrow();
+        match source_map
+            .as_ref()
+            .and_then(|source_map| source_map.original(line))
+        {
+            Some((source_name, line)) => (Some(source_name.into()), line),
+            None => (self.source_name.borrow().clone(), line),
+        }
+    }
+}
+
+/// A global function provided by the interpreter.
+/// Builtins are declared the first time a program refers to them, so creating an interpreter stays cheap
+/// however many there are.
+pub struct Builtin {
+    pub name: &'static str,
+    pub parameters: &'static [Type],
+    pub return_type: Type,
+    pub function: fn(&BuiltinContext, Vec<Value>) -> Value,
+}
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "print",
+        parameters: &[Type::Any],
+        return_type: Type::Void,
+        function: print,
+    },
+    Builtin {
+        name: "gc",
+        parameters: &[],
+        return_type: Type::Void,
+        function: gc,
+    },
+    Builtin {
+        name: "exit",
+        parameters: &[Type::Number],
+        return_type: Type::Void,
+        function: exit,
+    },
+    Builtin {
+        name: "assert",
+        parameters: &[Type::Boolean],
+        return_type: Type::Void,
+        function: assert,
+    },
+    Builtin {
+        name: "len",
+        parameters: &[Type::Any],
+        return_type: Type::Number,
+        function: len,
+    },
+    Builtin {
+        name: "char_len",
+        parameters: &[Type::String],
+        return_type: Type::Number,
+        function: char_len,
+    },
+    Builtin {
+        name: "byte_len",
+        parameters: &[Type::String],
+        return_type: Type::Number,
+        function: byte_len,
+    },
+    Builtin {
+        name: "slice",
+        parameters: &[Type::String, Type::Number, Type::Number],
+        return_type: Type::String,
+        function: slice,
+    },
+    Builtin {
+        name: "char_slice",
+        parameters: &[Type::String, Type::Number, Type::Number],
+        return_type: Type::String,
+        function: char_slice,
+    },
+    Builtin {
+        name: "to_upper",
+        parameters: &[Type::String],
+        return_type: Type::String,
+        function: to_upper,
+    },
+    Builtin {
+        name: "to_lower",
+        parameters: &[Type::String],
+        return_type: Type::String,
+        function: to_lower,
+    },
+    Builtin {
+        name: "casefold_eq",
+        parameters: &[Type::String, Type::String],
+        return_type: Type::Boolean,
+        function: casefold_eq,
+    },
+    Builtin {
+        name: "format",
+        parameters: &[Type::Any, Type::String],
+        return_type: Type::String,
+        function: format,
+    },
+    Builtin {
+        name: "parse_int",
+        parameters: &[Type::String, Type::Number],
+        return_type: Type::Number,
+        function: parse_int,
+    },
+    Builtin {
+        name: "to_hex",
+        parameters: &[Type::Number],
+        return_type: Type::String,
+        function: to_hex,
+    },
+    Builtin {
+        name: "to_bin",
+        parameters: &[Type::Number],
+        return_type: Type::String,
+        function: to_bin,
+    },
+    Builtin {
+        name: "copy",
+        parameters: &[Type::Any],
+        return_type: Type::Any,
+        function: copy,
+    },
+    Builtin {
+        name: "deep_copy",
+        parameters: &[Type::Any],
+        return_type: Type::Any,
+        function: deep_copy,
+    },
+    Builtin {
+        name: "is_some",
+        parameters: &[Type::Any],
+        return_type: Type::Boolean,
+        function: is_some,
+    },
+    Builtin {
+        name: "unwrap_or",
+        parameters: &[Type::Any, Type::Any],
+        return_type: Type::Any,
+        function: unwrap_or,
+    },
+    Builtin {
+        name: "clock",
+        parameters: &[],
+        return_type: Type::Number,
+        function: clock,
+    },
+    Builtin {
+        name: "dbg",
+        parameters: &[Type::Any],
+        return_type: Type::Any,
+        function: dbg,
+    },
+    Builtin {
+        name: "send",
+        parameters: &[Type::Any, Type::Any],
+        return_type: Type::Void,
+        function: send,
+    },
+    Builtin {
+        name: "recv",
+        parameters: &[Type::Any],
+        return_type: Type::Any,
+        function: recv,
+    },
+    Builtin {
+        name: "spawn",
+        parameters: &[Type::Any],
+        return_type: Type::Any,
+        function: spawn,
+    },
+    Builtin {
+        name: "join",
+        parameters: &[Type::Any],
+        return_type: Type::Any,
+        function: join,
+    },
+    Builtin {
+        name: "include",
+        parameters: &[Type::String],
+        return_type: Type::Void,
+        function: include,
+    },
+    Builtin {
+        name: "eval",
+        parameters: &[Type::String],
+        return_type: Type::Any,
+        function: eval,
+    },
+];
+
+/// Builtins which return a value of the same type as their last argument, rather than their return type.
+// TODO: Remove once functions can be generic.
+pub static ARGUMENT_TYPED_BUILTINS: &[&str] = &[
+    "copy",
+    "deep_copy",
+    "dbg",
+    "unwrap_or",
+    "send",
+    "recv",
+    "spawn",
+    "join",
+    "include",
+    "eval",
+];
+
+/// Builtins which return result(return type, string), with Err and a message when they fail.
+/// BUILTINS is a constant, so it can't hold their boxed return types itself.
+pub static FALLIBLE_BUILTINS: &[&str] = &["parse_int", "to_hex", "to_bin"];
+
+fn print(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let text = context.value_format.get().display(&values[0]);
+    #[cfg(feature = "tracing")]
+    if context.log_to_tracing.get() {
+        let (file, line) = context.location(context.line.get());
+        tracing::info!(target: "decibelle", file = file.as_deref(), line, "{text}");
+        return Value::Void;
+    }
+    (context.output.borrow_mut())(&format!("{text}\n"));
+    Value::Void
+}
+
+/// Prints the value with where it is and its source, and returns it.
+/// The checker passes the source of the argument and its line when dbg is called directly.
+fn dbg(context: &BuiltinContext, mut values: Vec<Value>) -> Value {
+    let value = values.remove(0);
+    let text = context.value_format.get().debug(&value);
+    #[cfg(feature = "tracing")]
+    if context.log_to_tracing.get() {
+        let (file, line) = context.location(context.line.get());
+        let message = match &values[..] {
+            [Value::String(source), _] => format!("{source} = {text}"),
+            _ => text,
+        };
+        tracing::debug!(target: "decibelle", file = file.as_deref(), line, "{message}");
+        return value;
+    }
+    let location = match &values[..] {
+        [Value::String(source), Value::Number(line)] => match context.location(*line as usize) {
+            (Some(source_name), line) => format!("[{source_name}:{line}] {source} = "),
+            (None, line) => format!("[line {line}] {source} = "),
+        },
+        _ => String::new(),
+    };
+    (context.output.borrow_mut())(&format!("{location}{text}\n"));
+    value
+}
+
+/// The time in milliseconds, which is only meaningful compared to other times it returns.
+fn clock(context: &BuiltinContext, _: Vec<Value>) -> Value {
+    let time = context
+        .replay
+        .borrow_mut()
+        .result("clock", || (context.clock.borrow())());
+    match time {
+        Ok(time) => Value::Number(time),
+        Err(error) => {
+            context.error.set(Some(error));
+            Value::Number(0.0)
+        }
+    }
+}
+
+fn gc(context: &BuiltinContext, _: Vec<Value>) -> Value {
+    context.garbage_collection_requested.set(true);
+    Value::Void
+}
+
+/// Stops the program. The interpreter reports the code as EvalError::Exit.
+fn exit(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let Value::Number(code) = values[0] else {
+        unreachable!()
+    };
+    context.exit_code.set(Some(code as i32));
+    Value::Void
+}
+
+fn assert(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    if values[0] != Value::Boolean(true) {
+        context.error.set(Some(diagnostic!(ASSERTION_FAILED)));
+    }
+    Value::Void
+}
+
+fn string_argument(value: &Value) -> &str {
+    let Value::String(string) = value else {
+        unreachable!()
+    };
+    string
+}
+
+/// The number of grapheme clusters (user-perceived characters) in a string, or the number of values in a tuple or record.
+// TODO: Check the argument type before running once builtins can take one of several types.
+fn len(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    match &values[0] {
+        Value::String(string) => Value::Number(string.graphemes(true).count() as f64),
+        Value::Tuple(values) => Value::Number(values.len() as f64),
+        Value::Record(fields) => Value::Number(fields.len() as f64),
+        value => {
+            context
+                .error
+                .set(Some(diagnostic!(LEN_NEEDS_SEQUENCE, value.value_type())));
+            Value::Number(0.0)
+        }
+    }
+}
+
+/// The number of Unicode code points in the string.
+fn char_len(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    Value::Number(string_argument(&values[0]).chars().count() as f64)
+}
+
+/// The number of bytes in the UTF-8 encoding of the string.
+fn byte_len(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    Value::Number(string_argument(&values[0]).len() as f64)
+}
+
+/// The grapheme clusters from the start index up to the end index.
+fn slice(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let string = string_argument(&values[0]);
+    let boundaries = string.grapheme_indices(true).map(|(index, _)| index);
+    slice_at_boundaries(context, "slice", string, boundaries, &values[1..])
+}
+
+/// The code points from the start index up to the end index.
+fn char_slice(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let string = string_argument(&values[0]);
+    let boundaries = string.char_indices().map(|(index, _)| index);
+    slice_at_boundaries(context, "char_slice", string, boundaries, &values[1..])
+}
+
+/// Slices the string between the start and end indices, counted in the units which start at the byte offsets.
+/// Indices which aren't whole numbers or are out of range stop the program, so slices never split a unit.
+fn slice_at_boundaries(
+    context: &BuiltinContext,
+    name: &str,
+    string: &str,
+    boundaries: impl Iterator<Item = usize>,
+    range: &[Value],
+) -> Value {
+    let boundaries = boundaries
+        .chain(iter::once(string.len()))
+        .collect::<Vec<_>>();
+    let (&Value::Number(start), &Value::Number(end)) = (&range[0], &range[1]) else {
+        unreachable!()
+    };
+    let length = boundaries.len() - 1;
+    let is_index = |index: f64| index.fract() == 0.0 && index >= 0.0 && index <= length as f64;
+    if !is_index(start) || !is_index(end) || start > end {
+        context.error.set(Some(diagnostic!(
+            SUBSTRING_OUT_OF_RANGE,
+            name,
+            start,
+            end,
+            length
+        )));
+        return Value::String("".into());
+    }
+    Value::String(string[boundaries[start as usize]..boundaries[end as usize]].into())
+}
+
+/// Uppercases the string with Unicode's default case mapping, which is the same in every locale, so ß becomes SS.
+fn to_upper(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    Value::String(string_argument(&values[0]).to_uppercase().into())
+}
+
+/// Lowercases the string with Unicode's default case mapping.
+fn to_lower(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    Value::String(string_argument(&values[0]).to_lowercase().into())
+}
+
+/// Are the strings equal ignoring case? Compares their Unicode case foldings, so "Straße" equals "STRASSE".
+fn casefold_eq(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    Value::Boolean(
+        UniCase::unicode(string_argument(&values[0]))
+            == UniCase::unicode(string_argument(&values[1])),
+    )
+}
+
+/// Formats the value with a spec, like ${value:spec} in an interpolated string. The spec can be chosen at runtime,
+/// so invalid specs stop the program.
+fn format(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let spec = string_argument(&values[1])
+        .parse::<FormatSpec>()
+        .and_then(|spec| spec.check(&values[0].value_type()).map(|()| spec));
+    match spec {
+        Ok(spec) => Value::String(spec.format(&values[0]).into()),
+        Err(error) => {
+            context.error.set(Some(error));
+            Value::String("".into())
+        }
+    }
+}
+
+/// Parses a whole number written in a base from 2 to 36, like parse_int("ff", 16) or parse_int("-101", 2).
+/// Returns Err with a message if the base is invalid or the text isn't a number in the base.
+fn parse_int(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    let (text, &Value::Number(radix)) = (string_argument(&values[0]), &values[1]) else {
+        unreachable!()
+    };
+    if radix.fract() != 0.0 || !(2.0..=36.0).contains(&radix) {
+        return error_result(diagnostic!(INVALID_BASE, radix));
+    }
+    match i64::from_str_radix(text, radix as u32) {
+        Ok(number) => Value::Result(Rc::new(Ok(Value::Number(number as f64)))),
+        Err(_) => error_result(diagnostic!(PARSE_INT_FAILED, text, radix)),
+    }
+}
+
+/// Writes a whole number in hex, like ff or -1a.
+fn to_hex(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    to_radix("to_hex", &values[0], |number| format!("{number:x}"))
+}
+
+/// Writes a whole number in binary, like 101 or -11.
+fn to_bin(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    to_radix("to_bin", &values[0], |number| format!("{number:b}"))
+}
+
+/// Writes the magnitude of a whole number with the function, after a - if it's negative.
+/// Returns Err with a message if the number isn't whole or doesn't fit in 64 bits.
+fn to_radix(name: &str, value: &Value, write: fn(u64) -> String) -> Value {
+    let &Value::Number(number) = value else {
+        unreachable!()
+    };
+    if number.fract() != 0.0 || number.abs() >= 2f64.powi(63) {
+        return error_result(diagnostic!(NOT_A_64_BIT_INTEGER, name, number));
+    }
+    let sign = if number < 0.0 { "-" } else { "" };
+    let text = format!("{sign}{}", write((number as i64).unsigned_abs()));
+    Value::Result(Rc::new(Ok(Value::String(text.into()))))
+}
+
+/// The Err value a fallible builtin returns, with the message as its error.
+fn error_result(message: Diagnostic) -> Value {
+    Value::Result(Rc::new(Err(Value::String(message.to_string().into()))))
+}
+
+/// Copies the value. Values are already copied when they're assigned or passed, so this is the same as let copy = v;.
+/// Tuples and records share their memory until one of the copies is changed.
+fn copy(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    values.into_iter().next().unwrap()
+}
+
+/// Copies the value and every tuple and record in it, so the copy shares no memory with the value.
+/// Functions are still shared, along with the variables they capture, and references still refer to the same variables.
+/// Whether an option is Some.
+// TODO: Check the argument type before running once builtins can take options of any type.
+fn is_some(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    match &values[0] {
+        Value::Option(value) => Value::Boolean(value.is_some()),
+        value => {
+            context
+                .error
+                .set(Some(diagnostic!(IS_SOME_NEEDS_OPTION, value.value_type())));
+            Value::Boolean(false)
+        }
+    }
+}
+
+/// The value of an option, or the default if it's None. The checker makes sure the default has the option's value type.
+fn unwrap_or(_: &BuiltinContext, mut values: Vec<Value>) -> Value {
+    let default = values.pop().unwrap();
+    match values.pop() {
+        Some(Value::Option(Some(value))) => (*value).clone(),
+        _ => default,
+    }
+}
+
+/// Sends the value over the channel or to the task's mailbox, stopping the program if it can't be sent.
+// Calls which don't name send or recv directly aren't checked, so the channel is checked here.
+fn send(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    match &values[0] {
+        Value::Channel(channel) => {
+            if let Err(error) = channel.send(&values[1]) {
+                context.error.set(Some(error));
+            }
+        }
+        Value::Task(task) => {
+            if let Err(error) = task.send(&values[1]) {
+                context.error.set(Some(error));
+            }
+        }
+        value => context.error.set(Some(diagnostic!(
+            NOT_A_CHANNEL_OR_TASK,
+            "send",
+            value.value_type()
+        ))),
+    }
+    Value::Void
+}
+
+/// Waits for a value from the channel or the task's mailbox. Returns None once the other end is closed.
+fn recv(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    match &values[0] {
+        Value::Channel(channel) => Value::Option(channel.recv().map(Rc::new)),
+        Value::Task(task) => Value::Option(task.recv().map(Rc::new)),
+        value => {
+            context.error.set(Some(diagnostic!(
+                NOT_A_CHANNEL_OR_TASK,
+                "recv",
+                value.value_type()
+            )));
+            Value::Option(None)
+        }
+    }
+}
+
+/// Starts running the function in its own interpreter on another thread, and returns its task.
+fn spawn(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let task = match &values[0] {
+        Value::Function(function) => Task::spawn(function, context.backend),
+        value => Err(diagnostic!(SPAWN_NEEDS_FUNCTION, value.value_type())),
+    };
+    match task {
+        Ok(task) => Value::Task(Rc::new(task)),
+        Err(error) => {
+            context.error.set(Some(error));
+            Value::Void
+        }
+    }
+}
+
+/// Waits for the task to finish, and returns its result, or an error saying why it failed.
+fn join(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    match &values[0] {
+        Value::Task(task) => Value::Result(Rc::new(match task.join() {
+            Ok(message) => Ok(message.into_value()),
+            Err(error) => Err(Value::String(error.into())),
+        })),
+        value => {
+            context
+                .error
+                .set(Some(diagnostic!(JOIN_NOT_TASK, value.value_type())));
+            Value::Void
+        }
+    }
+}
+
+fn deep_copy(_: &BuiltinContext, values: Vec<Value>) -> Value {
+    fn copy_value(value: &Value) -> Value {
+        match value {
+            Value::Tuple(values) => Value::Tuple(Rc::new(values.iter().map(copy_value).collect())),
+            Value::Record(fields) => Value::Record(Rc::new(
+                fields
+                    .iter()
+                    .map(|(name, value)| (*name, copy_value(value)))
+                    .collect(),
+            )),
+            Value::Result(result) => Value::Result(Rc::new(match &**result {
+                Ok(value) => Ok(copy_value(value)),
+                Err(error) => Err(copy_value(error)),
+            })),
+            Value::Option(Some(value)) => Value::Option(Some(Rc::new(copy_value(value)))),
+            value => value.clone(),
+        }
+    }
+    copy_value(&values[0])
+}
+
+/// Adds the names of the variables the statement refers to.
+pub fn add_variable_names(statement: &Statement, names: &mut HashSet<Symbol>) {
+    match &statement.statement {
+        StatementType::VariableDeclaration { value, .. } => {
+            add_expression_variable_names(value, names)
+        }
+        StatementType::FunctionDeclaration { body, .. } => add_variable_names(body, names),
+        StatementType::Return(expression) | StatementType::Break(expression) => {
+            if let Some(expression) = expression {
+                add_expression_variable_names(expression, names);
+            }
+        }
+        StatementType::Expression(expression) | StatementType::Raise(expression) => {
+            add_expression_variable_names(expression, names)
+        }
+        StatementType::Block(statements) => {
+            for statement in statements {
+                add_variable_names(statement, names);
+            }
+        }
+        StatementType::If {
+            expression,
+            then_statement,
+            else_statement,
+        } => {
+            add_expression_variable_names(expression, names);
+            add_variable_names(then_statement, names);
+            if let Some(else_statement) = else_statement {
+                add_variable_names(else_statement, names);
+            }
+        }
+        StatementType::While {
+            expression,
+            statement,
+        }
+        | StatementType::For {
+            expression,
+            statement,
+            ..
+        } => {
+            add_expression_variable_names(expression, names);
+            add_variable_names(statement, names);
+        }
+        StatementType::Try {
+            statement,
+            catch_statement,
+            ..
+        } => {
+            add_variable_names(statement, names);
+            add_variable_names(catch_statement, names);
+        }
+        StatementType::Match { expression, arms } => {
+            add_expression_variable_names(expression, names);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    add_expression_variable_names(guard, names);
+                }
+                add_variable_names(&arm.statement, names);
+            }
+        }
+        StatementType::Test { body, .. } => add_variable_names(body, names),
+        StatementType::ImportNative(_) => {}
+    }
+}
+
+fn add_expression_variable_names(expression: &Expression, names: &mut HashSet<Symbol>) {
+    match &expression.expression_type {
+        ExpressionType::Variable { name, .. } => {
+            names.insert(*name);
+        }
+        ExpressionType::Unary { expression, .. }
+        | ExpressionType::Grouping(expression)
+        | ExpressionType::TupleAccess { expression, .. }
+        | ExpressionType::Ok(expression)
+        | ExpressionType::Err(expression)
+        | ExpressionType::Propagate(expression)
+        | ExpressionType::Some(expression) => add_expression_variable_names(expression, names),
+        ExpressionType::Binary {
+            left_expression,
+            right_expression,
+            ..
+        } => {
+            add_expression_variable_names(left_expression, names);
+            add_expression_variable_names(right_expression, names);
+        }
+        ExpressionType::Tuple(expressions)
+        | ExpressionType::Interpolation {
+            values: expressions,
+            ..
+        } => {
+            for expression in expressions {
+                add_expression_variable_names(expression, names);
+            }
+        }
+        ExpressionType::Record(fields) => {
+            for (_, expression) in fields {
+                add_expression_variable_names(expression, names);
+            }
+        }
+        ExpressionType::FunctionCall {
+            function,
+            arguments,
+        } => {
+            add_expression_variable_names(function, names);
+            for argument in arguments {
+                add_expression_variable_names(argument, names);
+            }
+        }
+        ExpressionType::Block { statements, value } => {
+            for statement in statements {
+                add_variable_names(statement, names);
+            }
+            add_expression_variable_names(value, names);
+        }
+        ExpressionType::If {
+            condition,
+            then_expression,
+            else_expression,
+        } => {
+            add_expression_variable_names(condition, names);
+            add_expression_variable_names(then_expression, names);
+            add_expression_variable_names(else_expression, names);
+        }
+        ExpressionType::Loop(body) => add_variable_names(body, names),
+        ExpressionType::Literal(_) => {}
+    }
+}
+
+/// Includes are replaced with the files they name before programs are checked, so this only runs when include is
+/// called some other way, like through a variable.
+fn include(context: &BuiltinContext, _: Vec<Value>) -> Value {
+    context
+        .error
+        .set(Some(diagnostic!(INCLUDE_NOT_AT_TOP_LEVEL)));
+    Value::Void
+}
+
+/// Runs the source in a new interpreter with the same backend and output, and returns its value: the value of the
+/// source if it's an expression, or else what it returns from its top level with return, or void.
+/// Returns an error with the report of why the source couldn't run, and exits the program if the source exits.
+fn eval(context: &BuiltinContext, values: Vec<Value>) -> Value {
+    let source = string_argument(&values[0]);
+    let mut interpreter = Interpreter::with_backend(context.backend);
+    interpreter.builtin_context.clock = context.clock.clone();
+    interpreter.builtin_context.replay = context.replay.clone();
+    interpreter.builtin_context.value_format = context.value_format.clone();
+    let output = context.output.clone();
+    interpreter.set_output(move |text| (output.borrow_mut())(text));
+    let expression = format!("return ({source});");
+    let (program, result) = match interpreter.check(&expression) {
+        Ok(()) => (expression.as_str(), interpreter.eval(&expression)),
+        Err(_) => (source, interpreter.eval(source)),
+    };
+    let result = match result {
+        Ok(()) => Ok(interpreter.return_value().clone()),
+        Err(EvalError::Exit(code)) => {
+            context.exit_code.set(Some(code));
+            return Value::Void;
+        }
+        Err(error) => Err(Value::String(error.report(program).trim_end().into())),
+    };
+    Value::Result(Rc::new(result))
+}
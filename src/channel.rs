@@ -0,0 +1,167 @@
+use std::{
+    fmt::Debug,
+    rc::Rc,
+    sync::mpsc::{self, Receiver, Sender},
+};
+
+use crate::{
+    messages::{diagnostic, Diagnostic},
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+};
+
+/// A value which can be sent to another thread. Values share their parts with Rc and name fields with symbols,
+/// which are interned per thread, so they're copied into messages to be sent. Functions, references, iterators
+/// and channels belong to the interpreter which made them, so they can't be sent.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Void,
+    Number(f64),
+    String(String),
+    Boolean(bool),
+    Tuple(Vec<Message>),
+    Record(Vec<(String, Message)>),
+    Result(Box<Result<Message, Message>>),
+    Option(Option<Box<Message>>),
+}
+
+impl Message {
+    /// Copies the value into a message, or returns an error if part of it can't be sent.
+    pub fn from_value(value: &Value) -> Result<Message, Diagnostic> {
+        Ok(match value {
+            Value::Void => Message::Void,
+            Value::Number(number) => Message::Number(*number),
+            Value::String(string) => Message::String(string.as_str().into()),
+            Value::Boolean(boolean) => Message::Boolean(*boolean),
+            Value::Tuple(values) => Message::Tuple(
+                values
+                    .iter()
+                    .map(Message::from_value)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Record(fields) => Message::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.to_string(), Message::from_value(value)?)))
+                    .collect::<Result<_, Diagnostic>>()?,
+            ),
+            Value::Result(result) => Message::Result(Box::new(match &**result {
+                Ok(value) => Ok(Message::from_value(value)?),
+                Err(error) => Err(Message::from_value(error)?),
+            })),
+            Value::Option(value) => Message::Option(match value {
+                Some(value) => Some(Box::new(Message::from_value(value)?)),
+                None => None,
+            }),
+            Value::Function(_) => return Err(diagnostic!(FUNCTION_NOT_SENDABLE)),
+            Value::Reference(_) => return Err(diagnostic!(REFERENCE_NOT_SENDABLE)),
+            Value::Iterator(_) => return Err(diagnostic!(ITERATOR_NOT_SENDABLE)),
+            Value::Channel(_) => return Err(diagnostic!(CHANNEL_NOT_SENDABLE)),
+            Value::Task(_) => return Err(diagnostic!(TASK_NOT_SENDABLE)),
+        })
+    }
+
+    /// Turns the message back into a value on the thread which receives it.
+    pub fn into_value(self) -> Value {
+        match self {
+            Message::Void => Value::Void,
+            Message::Number(number) => Value::Number(number),
+            Message::String(string) => Value::String(string.into()),
+            Message::Boolean(boolean) => Value::Boolean(boolean),
+            Message::Tuple(messages) => Value::Tuple(Rc::new(
+                messages.into_iter().map(Message::into_value).collect(),
+            )),
+            Message::Record(fields) => Value::Record(Rc::new(
+                fields
+                    .into_iter()
+                    .map(|(name, message)| (Symbol::intern(&name), message.into_value()))
+                    .collect(),
+            )),
+            Message::Result(result) => Value::Result(Rc::new(match *result {
+                Ok(message) => Ok(message.into_value()),
+                Err(message) => Err(message.into_value()),
+            })),
+            Message::Option(message) => {
+                Value::Option(message.map(|message| Rc::new(message.into_value())))
+            }
+        }
+    }
+}
+
+/// Whether values of the type can be sent to another thread.
+pub fn can_send(value_type: &Type) -> bool {
+    match value_type {
+        Type::Void | Type::Number | Type::String | Type::Boolean | Type::Never => true,
+        Type::Tuple(types) => types.iter().all(can_send),
+        Type::Record(fields) => fields.iter().all(|(_, field_type)| can_send(field_type)),
+        Type::Result { value, error } => can_send(value) && can_send(error),
+        Type::Option(value_type) => can_send(value_type),
+        // Values of type any may be functions.
+        Type::Any
+        | Type::Reference(_)
+        | Type::Function { .. }
+        | Type::Iterator(_)
+        | Type::Channel(_)
+        | Type::Task { .. } => false,
+    }
+}
+
+/// A program's end of a channel, which send and recv use. Both ends send messages of the same type.
+pub struct Channel {
+    pub message_type: Type,
+    sender: Sender<Message>,
+    receiver: Receiver<Message>,
+}
+
+impl Channel {
+    /// Makes a channel for messages of the type. Returns the program's end as a value of type channel(message type),
+    /// and the other end, which can be moved to another thread.
+    pub fn pair(message_type: Type) -> (Value, Sender<Message>, Receiver<Message>) {
+        let (sender, other_receiver) = mpsc::channel();
+        let (other_sender, receiver) = mpsc::channel();
+        let channel = Channel::new(message_type, sender, receiver);
+        (
+            Value::Channel(Rc::new(channel)),
+            other_sender,
+            other_receiver,
+        )
+    }
+
+    /// Makes a channel from the ends of a pair of channels, like ones moved from the thread which made them.
+    pub fn new(
+        message_type: Type,
+        sender: Sender<Message>,
+        receiver: Receiver<Message>,
+    ) -> Channel {
+        Channel {
+            message_type,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Sends the value to the other end, or returns an error if it can't be sent or the other end was dropped.
+    pub fn send(&self, value: &Value) -> Result<(), Diagnostic> {
+        self.sender
+            .send(Message::from_value(value)?)
+            .map_err(|_| diagnostic!(CHANNEL_CLOSED))
+    }
+
+    /// Waits for the next value from the other end, or returns None if the other end was dropped.
+    pub fn recv(&self) -> Option<Value> {
+        self.receiver.recv().ok().map(Message::into_value)
+    }
+}
+
+impl Debug for Channel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "channel({:?})", self.message_type)
+    }
+}
+
+/// Channels are equal if they're the same channel.
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
@@ -0,0 +1,817 @@
+use std::rc::Rc;
+
+use crate::{
+    format::FormatSpec,
+    parser::{
+        field_path, BinaryOperation, Expression, ExpressionType, Pattern, Statement, StatementType,
+        UnaryOperation,
+    },
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Constant(usize), // Index into the chunk's constants.
+    Pop,
+    GetGlobal(usize), // Slot of the global.
+    SetGlobal(usize),
+    StoreGlobal(usize),  // Sets the global and pops the value, unlike SetGlobal.
+    DefineGlobal(usize), // Index into the chunk's globals.
+    // path is an index into the chunk's paths.
+    SetGlobalField {
+        slot: usize,
+        path: usize,
+    },
+    GetLocal {
+        depth: usize,
+        slot: usize,
+    },
+    SetLocal {
+        depth: usize,
+        slot: usize,
+    },
+    StoreLocal {
+        depth: usize,
+        slot: usize,
+    },
+    DefineLocal(usize),
+    SetLocalField {
+        depth: usize,
+        slot: usize,
+        path: usize,
+    },
+    ReferenceGlobal(usize),
+    ReferenceLocal {
+        depth: usize,
+        slot: usize,
+    },
+    Dereference,
+    SetReference, // Pops the reference, and sets the referenced variable to the value on top of the stack.
+    SetReferenceField(usize), // Same as SetReference, but sets a field. The operand is an index into the chunk's paths.
+    InitializeStatic(usize),  // Pops the value of a static variable into its global slot.
+    PushScope(usize),         // Number of slots in the scope.
+    PopScope,
+    Negate,
+    Not,
+    BitwiseNot,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
+    Jump(usize), // Index of the instruction to jump to.
+    JumpIfFalse(usize),
+    JumpIfFalseOrPop(usize),
+    JumpIfTrueOrPop(usize),
+    // Jumps over the initialization of a static variable which has already been initialized.
+    JumpIfInitialized {
+        slot: usize,
+        target: usize,
+    },
+    // Pushes the next value of the for loop over the string or iterator below the byte position on top of the stack,
+    // advancing the position, or jumps if there are no more values.
+    Next(usize),
+    // If the result on top of the stack is Ok, replaces it with its value and jumps.
+    JumpIfOk(usize),
+    // Pushes the values of the variables of the chunk's pattern if the value on top of the stack matches it,
+    // and jumps otherwise.
+    Match {
+        pattern: usize,
+        target: usize,
+    },
+    // Pops and compares the top two values, jumping if the comparison is false.
+    JumpUnless {
+        comparison: Comparison,
+        target: usize,
+    },
+    Tuple(usize),  // Number of elements.
+    Record(usize), // Index into the chunk's records. Pops one value for each field.
+    TupleGet(usize),
+    Ok,                 // Wraps the value on top of the stack in Ok.
+    Err,                // Wraps the value on top of the stack in Err.
+    Some,               // Wraps the value on top of the stack in Some.
+    Interpolate(usize), // Index into the chunk's interpolations. Pops one value for each of its specs.
+    Closure(usize),     // Index into the chunk's functions.
+    Call(usize),        // Number of arguments.
+    Return,
+    Try(usize), // Starts a try statement whose catch statement starts at the index.
+    EndTry,     // Ends the innermost try statement, whose body finished or is being left.
+    Raise, // Pops the error and jumps to the catch statement of the innermost try statement with it.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl Comparison {
+    pub fn from_instruction(instruction: Instruction) -> Option<Comparison> {
+        match instruction {
+            Instruction::Equal => Some(Comparison::Equal),
+            Instruction::NotEqual => Some(Comparison::NotEqual),
+            Instruction::Less => Some(Comparison::Less),
+            Instruction::LessEqual => Some(Comparison::LessEqual),
+            Instruction::Greater => Some(Comparison::Greater),
+            Instruction::GreaterEqual => Some(Comparison::GreaterEqual),
+            _ => None,
+        }
+    }
+
+    pub fn instruction(self) -> Instruction {
+        match self {
+            Comparison::Equal => Instruction::Equal,
+            Comparison::NotEqual => Instruction::NotEqual,
+            Comparison::Less => Instruction::Less,
+            Comparison::LessEqual => Instruction::LessEqual,
+            Comparison::Greater => Instruction::Greater,
+            Comparison::GreaterEqual => Instruction::GreaterEqual,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub instructions: Vec<Instruction>,
+    pub lines: Vec<usize>, // Line of each instruction.
+    pub constants: Vec<Value>,
+    pub globals: Vec<(Symbol, usize, usize)>, // Name, shadow_id and slot of each global declared.
+    pub paths: Vec<Vec<usize>>, // Tuple indices used by field assignments, outermost first.
+    pub interpolations: Vec<(Vec<String>, Vec<FormatSpec>)>, // The strings and specs of interpolated strings.
+    pub records: Vec<Vec<Symbol>>,                           // The field names of record literals.
+    pub patterns: Vec<Pattern>,                              // The patterns of match arms.
+    pub functions: Vec<Rc<Function>>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<(Symbol, Option<usize>, Type)>, // Option<usize> is the shadow_id of the parameter.
+    pub return_type: Type,
+    pub chunk: Chunk,
+    pub body: Option<Rc<Statement>>, // The statement it was compiled from, which spawn sends as source.
+}
+
+/// Where a variable lives at runtime.
+enum Location {
+    Global(usize), // Slot in the global environment.
+    // depth is the number of environments to go up from the current environment.
+    Local { depth: usize, slot: usize },
+}
+
+/// A loop which break statements jump out of.
+struct Loop {
+    scope_count: usize,  // The number of scopes outside the loop.
+    is_expression: bool, // Breaks out of loop expressions leave a value, which is void if the break has none.
+    breaks: Vec<usize>,  // The jumps to patch to the end of the loop.
+    try_count: usize,    // The number of try statements outside the loop.
+}
+
+struct Compiler {
+    chunk: Chunk,
+    // Scopes from the variable and type check, excluding the global scope.
+    // Scopes without variables don't create an environment at runtime, so we only store whether each one has an environment.
+    scopes: Vec<bool>,
+    loops: Vec<Loop>, // The loops being compiled, innermost last.
+    try_count: usize, // The number of try statements the code being compiled is in.
+}
+
+/// Compiles type checked statements into a function which runs them.
+pub fn compile(statements: &[Statement]) -> Rc<Function> {
+    let mut compiler = Compiler {
+        chunk: Chunk::default(),
+        scopes: vec![],
+        loops: vec![],
+        try_count: 0,
+    };
+
+    for statement in statements {
+        compiler.statement(statement);
+    }
+    let line = statements.last().map_or(1, |statement| statement.lines.1);
+    compiler.constant(Value::Void, line);
+    compiler.emit(Instruction::Return, line);
+
+    Rc::new(Function {
+        name: "<script>".into(),
+        parameters: vec![],
+        return_type: Type::Void,
+        chunk: compiler.chunk,
+        body: None,
+    })
+}
+
+impl Compiler {
+    fn emit(&mut self, instruction: Instruction, line: usize) -> usize {
+        self.chunk.instructions.push(instruction);
+        self.chunk.lines.push(line);
+        self.chunk.instructions.len() - 1
+    }
+
+    fn constant(&mut self, value: Value, line: usize) {
+        self.chunk.constants.push(value);
+        self.emit(Instruction::Constant(self.chunk.constants.len() - 1), line);
+    }
+
+    /// Makes the jump at the given index jump to the next instruction.
+    fn patch_jump(&mut self, index: usize) {
+        let target = self.chunk.instructions.len();
+        match &mut self.chunk.instructions[index] {
+            Instruction::Jump(jump_target)
+            | Instruction::JumpIfFalse(jump_target)
+            | Instruction::JumpIfFalseOrPop(jump_target)
+            | Instruction::JumpIfTrueOrPop(jump_target)
+            | Instruction::Next(jump_target)
+            | Instruction::JumpIfOk(jump_target)
+            | Instruction::Try(jump_target)
+            | Instruction::Match {
+                target: jump_target,
+                ..
+            }
+            | Instruction::JumpIfInitialized {
+                target: jump_target,
+                ..
+            } => *jump_target = target,
+            _ => unreachable!("patch_jump called on a non jump instruction"),
+        }
+    }
+
+    fn resolve(&self, parent_height: usize, slot: usize) -> Location {
+        if parent_height == self.scopes.len() {
+            return Location::Global(slot);
+        }
+
+        let scope_index = self.scopes.len() - 1 - parent_height;
+        let depth = self.scopes[scope_index + 1..]
+            .iter()
+            .filter(|has_environment| **has_environment)
+            .count();
+        Location::Local { depth, slot }
+    }
+
+    fn define(&mut self, name: Symbol, shadow_id: usize, slot: usize, line: usize) {
+        if self.scopes.is_empty() {
+            self.chunk.globals.push((name, shadow_id, slot));
+            self.emit(
+                Instruction::DefineGlobal(self.chunk.globals.len() - 1),
+                line,
+            );
+        } else {
+            self.emit(Instruction::DefineLocal(slot), line);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let line = statement.lines.0;
+        match &statement.statement {
+            StatementType::Expression(expression) => {
+                self.expression(expression);
+                self.emit(Instruction::Pop, line);
+            }
+            StatementType::VariableDeclaration {
+                value,
+                is_static: true,
+                slot,
+                ..
+            } => {
+                let skip_jump = self.emit(
+                    Instruction::JumpIfInitialized {
+                        slot: slot.unwrap(),
+                        target: 0,
+                    },
+                    line,
+                );
+                self.expression(value);
+                self.emit(Instruction::InitializeStatic(slot.unwrap()), line);
+                self.patch_jump(skip_jump);
+            }
+            StatementType::VariableDeclaration {
+                variable,
+                value,
+                shadow_id,
+                slot,
+                ..
+            } => {
+                self.expression(value);
+                self.define(*variable, shadow_id.unwrap(), slot.unwrap(), line);
+            }
+            StatementType::FunctionDeclaration {
+                name,
+                parameters,
+                return_type,
+                body,
+                shadow_id,
+                slot,
+                ..
+            } => {
+                let function = self.function(*name, parameters, return_type, body);
+                self.chunk.functions.push(function.into());
+                self.emit(Instruction::Closure(self.chunk.functions.len() - 1), line);
+                self.define(*name, shadow_id.unwrap(), slot.unwrap(), line);
+            }
+            StatementType::Return(expression) => {
+                match expression {
+                    Some(expression) => self.expression(expression),
+                    None => self.constant(Value::Void, line),
+                }
+                self.emit(Instruction::Return, line);
+            }
+            StatementType::Block(statements) => self.block(statements, None, statement.lines),
+            StatementType::If {
+                expression,
+                then_statement,
+                else_statement,
+            } => {
+                self.expression(expression);
+                let else_jump = self.emit(Instruction::JumpIfFalse(0), line);
+                self.statement(then_statement);
+                match else_statement {
+                    Some(else_statement) => {
+                        let end_jump = self.emit(Instruction::Jump(0), line);
+                        self.patch_jump(else_jump);
+                        self.statement(else_statement);
+                        self.patch_jump(end_jump);
+                    }
+                    None => self.patch_jump(else_jump),
+                }
+            }
+            StatementType::While {
+                expression,
+                statement,
+            } => {
+                let start = self.chunk.instructions.len();
+                self.expression(expression);
+                let end_jump = self.emit(Instruction::JumpIfFalse(0), line);
+                self.start_loop(false);
+                self.statement(statement);
+                self.emit(Instruction::Jump(start), line);
+                self.patch_jump(end_jump);
+                self.end_loop();
+            }
+            StatementType::For {
+                expression,
+                statement,
+                ..
+            } => {
+                // The string or iterator and the position in it stay on the stack while the loop runs.
+                self.expression(expression);
+                self.constant(Value::Number(0.0), line);
+                let start = self.chunk.instructions.len();
+                let end_jump = self.emit(Instruction::Next(0), line);
+                self.start_loop(false);
+                self.scopes.push(true);
+                self.emit(Instruction::PushScope(1), line);
+                self.emit(Instruction::DefineLocal(0), line);
+                self.statement(statement);
+                self.emit(Instruction::PopScope, line);
+                self.scopes.pop();
+                self.emit(Instruction::Jump(start), line);
+                self.patch_jump(end_jump);
+                self.end_loop();
+                self.emit(Instruction::Pop, line);
+                self.emit(Instruction::Pop, line);
+            }
+            StatementType::Break(expression) => {
+                let innermost_loop = self.loops.last().unwrap();
+                match expression {
+                    Some(expression) => self.expression(expression),
+                    None if innermost_loop.is_expression => self.constant(Value::Void, line),
+                    None => {}
+                }
+                // Leave the environments of the scopes inside the loop.
+                let environments = self.scopes[self.loops.last().unwrap().scope_count..]
+                    .iter()
+                    .filter(|has_environment| **has_environment)
+                    .count();
+                for _ in 0..environments {
+                    self.emit(Instruction::PopScope, line);
+                }
+                for _ in self.loops.last().unwrap().try_count..self.try_count {
+                    self.emit(Instruction::EndTry, line);
+                }
+                let jump = self.emit(Instruction::Jump(0), line);
+                self.loops.last_mut().unwrap().breaks.push(jump);
+            }
+            StatementType::Raise(expression) => {
+                self.expression(expression);
+                self.emit(Instruction::Raise, line);
+            }
+            StatementType::Try {
+                statement: body,
+                catch_statement,
+                ..
+            } => {
+                let catch_jump = self.emit(Instruction::Try(0), line);
+                self.try_count += 1;
+                self.statement(body);
+                self.try_count -= 1;
+                self.emit(Instruction::EndTry, line);
+                let end_jump = self.emit(Instruction::Jump(0), line);
+                // The error is on the stack when the catch statement starts.
+                self.patch_jump(catch_jump);
+                self.scopes.push(true);
+                self.emit(Instruction::PushScope(1), line);
+                self.emit(Instruction::DefineLocal(0), line);
+                self.statement(catch_statement);
+                self.emit(Instruction::PopScope, line);
+                self.scopes.pop();
+                self.patch_jump(end_jump);
+            }
+            StatementType::Match { expression, arms } => {
+                // The value stays on the stack until an arm matches it.
+                self.expression(expression);
+                let mut end_jumps = vec![];
+                for arm in arms {
+                    let line = arm.lines.0;
+                    self.chunk.patterns.push(arm.pattern.clone());
+                    let next_jump = self.emit(
+                        Instruction::Match {
+                            pattern: self.chunk.patterns.len() - 1,
+                            target: 0,
+                        },
+                        line,
+                    );
+                    let variable_count = arm.pattern.variables().len();
+                    self.scopes.push(variable_count > 0);
+                    if variable_count > 0 {
+                        self.emit(Instruction::PushScope(variable_count), line);
+                        for slot in (0..variable_count).rev() {
+                            self.emit(Instruction::DefineLocal(slot), line);
+                        }
+                    }
+                    // The guard is checked with the pattern's variables set, and the value still on the stack.
+                    let guard_jump = arm.guard.as_ref().map(|guard| {
+                        self.expression(guard);
+                        self.emit(Instruction::JumpIfFalse(0), line)
+                    });
+                    self.emit(Instruction::Pop, line);
+                    self.statement(&arm.statement);
+                    if variable_count > 0 {
+                        self.emit(Instruction::PopScope, arm.lines.1);
+                    }
+                    end_jumps.push(self.emit(Instruction::Jump(0), arm.lines.1));
+                    if let Some(guard_jump) = guard_jump {
+                        self.patch_jump(guard_jump);
+                        if variable_count > 0 {
+                            self.emit(Instruction::PopScope, line);
+                        }
+                    }
+                    self.scopes.pop();
+                    self.patch_jump(next_jump);
+                }
+                self.emit(Instruction::Pop, statement.lines.1);
+                for jump in end_jumps {
+                    self.patch_jump(jump);
+                }
+            }
+            StatementType::Test { .. } => unreachable!(), // Tests are removed before checking.
+            StatementType::ImportNative(_) => unreachable!(), // Imports are removed before checking.
+        }
+    }
+
+    fn start_loop(&mut self, is_expression: bool) {
+        self.loops.push(Loop {
+            scope_count: self.scopes.len(),
+            is_expression,
+            breaks: vec![],
+            try_count: self.try_count,
+        });
+    }
+
+    /// Makes the breaks out of the innermost loop jump to the next instruction.
+    fn end_loop(&mut self) {
+        for jump in self.loops.pop().unwrap().breaks {
+            self.patch_jump(jump);
+        }
+    }
+
+    /// Compiles a block, and the value at its end if it's a block expression.
+    fn block(
+        &mut self,
+        statements: &[Statement],
+        value: Option<&Expression>,
+        lines: (usize, usize),
+    ) {
+        let size = statements
+            .iter()
+            .filter(|statement| {
+                matches!(
+                    statement.statement,
+                    StatementType::VariableDeclaration {
+                        is_static: false,
+                        ..
+                    } | StatementType::FunctionDeclaration { .. }
+                )
+            })
+            .count();
+        self.scopes.push(size > 0);
+        if size > 0 {
+            self.emit(Instruction::PushScope(size), lines.0);
+        }
+        for statement in statements {
+            self.statement(statement);
+        }
+        if let Some(value) = value {
+            self.expression(value);
+        }
+        if size > 0 {
+            self.emit(Instruction::PopScope, lines.1);
+        }
+        self.scopes.pop();
+    }
+
+    fn function(
+        &mut self,
+        name: Symbol,
+        parameters: &[(Symbol, Option<usize>, Type)],
+        return_type: &Type,
+        body: &Rc<Statement>,
+    ) -> Function {
+        let enclosing_chunk = std::mem::take(&mut self.chunk);
+        self.scopes.push(!parameters.is_empty());
+
+        self.statement(body);
+        // Functions without a return statement return void.
+        self.constant(Value::Void, body.lines.1);
+        self.emit(Instruction::Return, body.lines.1);
+
+        self.scopes.pop();
+        let chunk = std::mem::replace(&mut self.chunk, enclosing_chunk);
+        Function {
+            name: name.to_string(),
+            parameters: parameters.to_vec(),
+            return_type: return_type.clone(),
+            chunk,
+            body: Some(body.clone()),
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        let line = expression.lines.0;
+        match &expression.expression_type {
+            ExpressionType::Literal(value) => self.constant(value.clone(), line),
+            ExpressionType::Variable {
+                parent_height,
+                slot,
+                ..
+            } => {
+                let instruction = match self.resolve(parent_height.unwrap(), slot.unwrap()) {
+                    Location::Global(slot) => Instruction::GetGlobal(slot),
+                    Location::Local { depth, slot } => Instruction::GetLocal { depth, slot },
+                };
+                self.emit(instruction, line);
+            }
+            ExpressionType::Grouping(expression) => self.expression(expression),
+            ExpressionType::Ok(value) => {
+                self.expression(value);
+                self.emit(Instruction::Ok, line);
+            }
+            ExpressionType::Err(error) => {
+                self.expression(error);
+                self.emit(Instruction::Err, line);
+            }
+            ExpressionType::Some(value) => {
+                self.expression(value);
+                self.emit(Instruction::Some, line);
+            }
+            ExpressionType::Propagate(result) => {
+                self.expression(result);
+                let ok_jump = self.emit(Instruction::JumpIfOk(0), line);
+                self.emit(Instruction::Return, line);
+                self.patch_jump(ok_jump);
+            }
+            ExpressionType::Interpolation {
+                strings,
+                values,
+                specs,
+            } => {
+                for value in values {
+                    self.expression(value);
+                }
+                self.chunk
+                    .interpolations
+                    .push((strings.clone(), specs.clone()));
+                self.emit(
+                    Instruction::Interpolate(self.chunk.interpolations.len() - 1),
+                    line,
+                );
+            }
+            ExpressionType::Block { statements, value } => {
+                self.block(statements, Some(value), expression.lines)
+            }
+            ExpressionType::Loop(body) => {
+                let start = self.chunk.instructions.len();
+                self.start_loop(true);
+                self.statement(body);
+                self.emit(Instruction::Jump(start), line);
+                self.end_loop();
+            }
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => {
+                self.expression(condition);
+                let else_jump = self.emit(Instruction::JumpIfFalse(0), line);
+                self.expression(then_expression);
+                let end_jump = self.emit(Instruction::Jump(0), line);
+                self.patch_jump(else_jump);
+                self.expression(else_expression);
+                self.patch_jump(end_jump);
+            }
+            ExpressionType::Tuple(expressions) => {
+                for expression in expressions {
+                    self.expression(expression);
+                }
+                self.emit(Instruction::Tuple(expressions.len()), line);
+            }
+            ExpressionType::Record(fields) => {
+                for (_, expression) in fields {
+                    self.expression(expression);
+                }
+                self.chunk
+                    .records
+                    .push(fields.iter().map(|(name, _)| *name).collect());
+                self.emit(Instruction::Record(self.chunk.records.len() - 1), line);
+            }
+            ExpressionType::TupleAccess { expression, field } => {
+                self.expression(expression);
+                self.emit(Instruction::TupleGet(field.index()), line);
+            }
+            ExpressionType::Unary {
+                operation: UnaryOperation::Reference,
+                expression,
+            } => {
+                let ExpressionType::Variable {
+                    parent_height,
+                    slot,
+                    ..
+                } = expression.expression_type
+                else {
+                    unreachable!()
+                };
+                let instruction = match self.resolve(parent_height.unwrap(), slot.unwrap()) {
+                    Location::Global(slot) => Instruction::ReferenceGlobal(slot),
+                    Location::Local { depth, slot } => Instruction::ReferenceLocal { depth, slot },
+                };
+                self.emit(instruction, line);
+            }
+            ExpressionType::Unary {
+                operation,
+                expression,
+            } => {
+                self.expression(expression);
+                let instruction = match operation {
+                    UnaryOperation::Minus => Instruction::Negate,
+                    UnaryOperation::Not => Instruction::Not,
+                    UnaryOperation::BitwiseNot => Instruction::BitwiseNot,
+                    UnaryOperation::Dereference => Instruction::Dereference,
+                    UnaryOperation::Reference => unreachable!(),
+                };
+                self.emit(instruction, line);
+            }
+            ExpressionType::FunctionCall {
+                function,
+                arguments,
+            } => {
+                self.expression(function);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                self.emit(Instruction::Call(arguments.len()), line);
+            }
+            ExpressionType::Binary {
+                operation,
+                left_expression,
+                right_expression,
+            } => match operation {
+                BinaryOperation::And | BinaryOperation::Or => {
+                    self.expression(left_expression);
+                    let instruction = if *operation == BinaryOperation::And {
+                        Instruction::JumpIfFalseOrPop(0)
+                    } else {
+                        Instruction::JumpIfTrueOrPop(0)
+                    };
+                    let end_jump = self.emit(instruction, line);
+                    self.expression(right_expression);
+                    self.patch_jump(end_jump);
+                }
+                BinaryOperation::Assignment => {
+                    self.assignment(left_expression, right_expression, line)
+                }
+                _ => {
+                    self.expression(left_expression);
+                    self.expression(right_expression);
+                    let instruction = match operation {
+                        BinaryOperation::Add => Instruction::Add,
+                        BinaryOperation::Subtract => Instruction::Subtract,
+                        BinaryOperation::Multiply => Instruction::Multiply,
+                        BinaryOperation::Divide => Instruction::Divide,
+                        BinaryOperation::Equal => Instruction::Equal,
+                        BinaryOperation::NotEqual => Instruction::NotEqual,
+                        BinaryOperation::Less => Instruction::Less,
+                        BinaryOperation::LessEqual => Instruction::LessEqual,
+                        BinaryOperation::Greater => Instruction::Greater,
+                        BinaryOperation::GreaterEqual => Instruction::GreaterEqual,
+                        BinaryOperation::BitwiseAnd => Instruction::BitwiseAnd,
+                        BinaryOperation::BitwiseOr => Instruction::BitwiseOr,
+                        BinaryOperation::BitwiseXor => Instruction::BitwiseXor,
+                        BinaryOperation::ShiftLeft => Instruction::ShiftLeft,
+                        BinaryOperation::ShiftRight => Instruction::ShiftRight,
+                        BinaryOperation::And
+                        | BinaryOperation::Or
+                        | BinaryOperation::Assignment => unreachable!(),
+                    };
+                    self.emit(instruction, line);
+                }
+            },
+        }
+    }
+
+    fn assignment(
+        &mut self,
+        left_expression: &Expression,
+        right_expression: &Expression,
+        line: usize,
+    ) {
+        match &left_expression.expression_type {
+            ExpressionType::Variable {
+                parent_height,
+                slot,
+                ..
+            } => {
+                self.expression(right_expression);
+                let instruction = match self.resolve(parent_height.unwrap(), slot.unwrap()) {
+                    Location::Global(slot) => Instruction::SetGlobal(slot),
+                    Location::Local { depth, slot } => Instruction::SetLocal { depth, slot },
+                };
+                self.emit(instruction, line);
+            }
+            ExpressionType::Unary {
+                operation: UnaryOperation::Dereference,
+                expression,
+            } => {
+                self.expression(right_expression);
+                self.expression(expression);
+                self.emit(Instruction::SetReference, line);
+            }
+            ExpressionType::TupleAccess { expression, .. } => {
+                // Same as the tree walking interpreter: we mutate the tuple field if the tuple is in a variable
+                // or a referenced variable.
+                let (indices, root) = field_path(left_expression);
+                match &root.expression_type {
+                    ExpressionType::Variable {
+                        parent_height,
+                        slot,
+                        ..
+                    } => {
+                        self.expression(right_expression);
+                        self.chunk.paths.push(indices);
+                        let path = self.chunk.paths.len() - 1;
+                        let instruction = match self.resolve(parent_height.unwrap(), slot.unwrap())
+                        {
+                            Location::Global(slot) => Instruction::SetGlobalField { slot, path },
+                            Location::Local { depth, slot } => {
+                                Instruction::SetLocalField { depth, slot, path }
+                            }
+                        };
+                        self.emit(instruction, line);
+                    }
+                    ExpressionType::Unary {
+                        operation: UnaryOperation::Dereference,
+                        expression: reference,
+                    } => {
+                        self.expression(right_expression);
+                        self.expression(reference);
+                        self.chunk.paths.push(indices);
+                        self.emit(
+                            Instruction::SetReferenceField(self.chunk.paths.len() - 1),
+                            line,
+                        );
+                    }
+                    _ => {
+                        self.expression(right_expression);
+                        self.expression(expression);
+                        self.emit(Instruction::Pop, line);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::rc::Rc;
+
+use crate::{
+    format::interpolate,
+    interpreter::{binary_operation, unary_operation},
+    parser::{BinaryOperation, Expression, ExpressionType, Statement, StatementType},
+    tokenizer::Value,
+};
+
+/// Replaces operations on literals with their result, for example 2 * 60 * 60 with 7200.
+/// Needs to run after variable and type checking.
+pub fn fold_constants(statements: &mut [Statement]) {
+    for statement in statements {
+        fold_statement(statement);
+    }
+}
+
+fn fold_statement(statement: &mut Statement) {
+    match &mut statement.statement {
+        StatementType::VariableDeclaration { value, .. } => fold_expression(value),
+        StatementType::FunctionDeclaration { body, .. } => {
+            // The body is only shared with the program cache until the program runs, so this rarely copies it.
+            fold_statement(Rc::make_mut(body));
+        }
+        StatementType::Return(expression) | StatementType::Break(expression) => {
+            if let Some(expression) = expression {
+                fold_expression(expression);
+            }
+        }
+        StatementType::Expression(expression) | StatementType::Raise(expression) => {
+            fold_expression(expression)
+        }
+        StatementType::Block(statements) => fold_constants(statements),
+        StatementType::If {
+            expression,
+            then_statement,
+            else_statement,
+        } => {
+            fold_expression(expression);
+            fold_statement(then_statement);
+            if let Some(else_statement) = else_statement {
+                fold_statement(else_statement);
+            }
+        }
+        StatementType::While {
+            expression,
+            statement,
+        }
+        | StatementType::For {
+            expression,
+            statement,
+            ..
+        } => {
+            fold_expression(expression);
+            fold_statement(statement);
+        }
+        StatementType::Try {
+            statement,
+            catch_statement,
+            ..
+        } => {
+            fold_statement(statement);
+            fold_statement(catch_statement);
+        }
+        StatementType::Match { expression, arms } => {
+            fold_expression(expression);
+            for arm in arms {
+                if let Some(guard) = &mut arm.guard {
+                    fold_expression(guard);
+                }
+                fold_statement(&mut arm.statement);
+            }
+        }
+        StatementType::Test { body, .. } => fold_statement(body),
+        StatementType::ImportNative(_) => {}
+    }
+}
+
+fn literal(expression: &Expression) -> Option<&Value> {
+    match &expression.expression_type {
+        ExpressionType::Literal(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn fold_expression(expression: &mut Expression) {
+    // The expression to replace this one with.
+    let folded_expression = match &mut expression.expression_type {
+        ExpressionType::Unary {
+            operation,
+            expression,
+        } => {
+            fold_expression(expression);
+            literal(expression)
+                .map(|value| ExpressionType::Literal(unary_operation(*operation, value.clone())))
+        }
+        ExpressionType::Binary {
+            operation: BinaryOperation::Assignment,
+            right_expression,
+            ..
+        } => {
+            // The left side is a place, not a value, so it's left as is.
+            fold_expression(right_expression);
+            None
+        }
+        ExpressionType::Binary {
+            operation: operation @ (BinaryOperation::And | BinaryOperation::Or),
+            left_expression,
+            right_expression,
+        } => {
+            fold_expression(left_expression);
+            fold_expression(right_expression);
+            let short_circuit_value = Value::Boolean(*operation == BinaryOperation::Or);
+            match literal(left_expression) {
+                // true || x is true and false && x is false.
+                Some(value) if *value == short_circuit_value => {
+                    Some(ExpressionType::Literal(value.clone()))
+                }
+                // true && x and false || x are x.
+                Some(_) => Some(std::mem::replace(
+                    &mut right_expression.expression_type,
+                    ExpressionType::Tuple(vec![]),
+                )),
+                None => None,
+            }
+        }
+        ExpressionType::Binary {
+            operation,
+            left_expression,
+            right_expression,
+        } => {
+            fold_expression(left_expression);
+            fold_expression(right_expression);
+            match (literal(left_expression), literal(right_expression)) {
+                (Some(left_value), Some(right_value)) => Some(ExpressionType::Literal(
+                    binary_operation(*operation, left_value, right_value),
+                )),
+                _ => None,
+            }
+        }
+        ExpressionType::Literal(_) | ExpressionType::Variable { .. } => None,
+        ExpressionType::Grouping(inner_expression) => {
+            fold_expression(inner_expression);
+            literal(inner_expression)
+                .cloned()
+                .map(ExpressionType::Literal)
+        }
+        ExpressionType::Ok(inner_expression) => {
+            fold_expression(inner_expression);
+            literal(inner_expression)
+                .map(|value| ExpressionType::Literal(Value::Result(Ok(value.clone()).into())))
+        }
+        ExpressionType::Err(inner_expression) => {
+            fold_expression(inner_expression);
+            literal(inner_expression)
+                .map(|value| ExpressionType::Literal(Value::Result(Err(value.clone()).into())))
+        }
+        ExpressionType::Some(inner_expression) => {
+            fold_expression(inner_expression);
+            literal(inner_expression)
+                .map(|value| ExpressionType::Literal(Value::Option(Some(value.clone().into()))))
+        }
+        ExpressionType::Propagate(inner_expression) => {
+            fold_expression(inner_expression);
+            None
+        }
+        ExpressionType::Tuple(expressions) => {
+            for expression in expressions.iter_mut() {
+                fold_expression(expression);
+            }
+            expressions
+                .iter()
+                .map(|expression| literal(expression).cloned())
+                .collect::<Option<Vec<_>>>()
+                .map(|values| ExpressionType::Literal(Value::Tuple(values.into())))
+        }
+        ExpressionType::Record(fields) => {
+            for (_, expression) in fields.iter_mut() {
+                fold_expression(expression);
+            }
+            fields
+                .iter()
+                .map(|(name, expression)| Some((*name, literal(expression)?.clone())))
+                .collect::<Option<Vec<_>>>()
+                .map(|fields| ExpressionType::Literal(Value::Record(fields.into())))
+        }
+        ExpressionType::Interpolation {
+            strings,
+            values,
+            specs,
+        } => {
+            for value in values.iter_mut() {
+                fold_expression(value);
+            }
+            values
+                .iter()
+                .map(|value| literal(value).cloned())
+                .collect::<Option<Vec<_>>>()
+                .map(|values| {
+                    ExpressionType::Literal(Value::String(
+                        interpolate(strings, specs, &values).into(),
+                    ))
+                })
+        }
+        ExpressionType::TupleAccess { expression, field } => {
+            fold_expression(expression);
+            literal(expression)
+                .map(|value| ExpressionType::Literal(value.field(field.index()).clone()))
+        }
+        ExpressionType::FunctionCall {
+            function,
+            arguments,
+        } => {
+            fold_expression(function);
+            for argument in arguments {
+                fold_expression(argument);
+            }
+            None
+        }
+        ExpressionType::If {
+            condition,
+            then_expression,
+            else_expression,
+        } => {
+            fold_expression(condition);
+            fold_expression(then_expression);
+            fold_expression(else_expression);
+            // The branch taken by a constant condition replaces the if.
+            match literal(condition) {
+                Some(Value::Boolean(true)) => Some(std::mem::replace(
+                    &mut then_expression.expression_type,
+                    ExpressionType::Tuple(vec![]),
+                )),
+                Some(Value::Boolean(false)) => Some(std::mem::replace(
+                    &mut else_expression.expression_type,
+                    ExpressionType::Tuple(vec![]),
+                )),
+                _ => None,
+            }
+        }
+        ExpressionType::Loop(body) => {
+            fold_statement(body);
+            None
+        }
+        ExpressionType::Block { statements, value } => {
+            fold_constants(statements);
+            fold_expression(value);
+            match statements.is_empty() {
+                true => literal(value).cloned().map(ExpressionType::Literal),
+                false => None,
+            }
+        }
+    };
+
+    if let Some(folded_expression) = folded_expression {
+        expression.expression_type = folded_expression;
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write,
+    mem::{discriminant, Discriminant},
+};
+
+use crate::{
+    highlight::escape_html,
+    parser::{block_statements, parse, Statement, StatementType},
+    tokenizer::tokenize,
+    EvalError,
+};
+
+/// How often each statement ran, by its lines and kind. The kind tells apart statements on one line, like an if and
+/// the statements in its block. Blocks and declarations of functions and tests aren't counted,
+/// as running them doesn't run the code inside.
+#[derive(Debug, Default, Clone)]
+pub struct Coverage {
+    pub hits: HashMap<((usize, usize), Discriminant<StatementType>), usize>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    pub fn hit(&mut self, statement: &Statement) {
+        if is_counted(statement) {
+            *self.hits.entry(key(statement)).or_default() += 1;
+        }
+    }
+
+    /// Adds the hits of another run of the same program.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (lines, hits) in &other.hits {
+            *self.hits.entry(*lines).or_default() += hits;
+        }
+    }
+}
+
+fn key(statement: &Statement) -> ((usize, usize), Discriminant<StatementType>) {
+    (statement.lines, discriminant(&statement.statement))
+}
+
+fn is_counted(statement: &Statement) -> bool {
+    !matches!(
+        statement.statement,
+        StatementType::Block(_)
+            | StatementType::FunctionDeclaration { .. }
+            | StatementType::Test { .. }
+            | StatementType::ImportNative(_)
+    )
+}
+
+/// The coverage of each line of a program.
+#[derive(Debug, Clone)]
+pub struct FileCoverage {
+    pub source_name: String,
+    pub source: String,
+    /// The hits of each line which starts a statement. A line with several statements has the most hits among them.
+    pub lines: BTreeMap<usize, usize>,
+}
+
+impl FileCoverage {
+    /// Finds the statements of the program and how often each ran.
+    pub fn new(
+        source_name: &str,
+        program: &str,
+        coverage: &Coverage,
+    ) -> Result<FileCoverage, EvalError> {
+        let tokens = tokenize(program).map_err(EvalError::Tokenizer)?;
+        let statements = parse(&tokens).map_err(EvalError::Compiler)?;
+        let mut lines = BTreeMap::new();
+        for statement in &statements {
+            add_lines(statement, coverage, &mut lines);
+        }
+        Ok(FileCoverage {
+            source_name: source_name.to_string(),
+            source: program.to_string(),
+            lines,
+        })
+    }
+
+    pub fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+}
+
+fn add_lines(statement: &Statement, coverage: &Coverage, lines: &mut BTreeMap<usize, usize>) {
+    if is_counted(statement) {
+        let hits = coverage.hits.get(&key(statement)).copied().unwrap_or(0);
+        let line_hits = lines.entry(statement.lines.0).or_default();
+        *line_hits = hits.max(*line_hits);
+    }
+    match &statement.statement {
+        StatementType::FunctionDeclaration { body, .. } => add_lines(body, coverage, lines),
+        StatementType::Block(statements) => {
+            for statement in statements {
+                add_lines(statement, coverage, lines);
+            }
+        }
+        StatementType::If {
+            expression,
+            then_statement,
+            else_statement,
+        } => {
+            for statement in block_statements(expression) {
+                add_lines(statement, coverage, lines);
+            }
+            add_lines(then_statement, coverage, lines);
+            if let Some(else_statement) = else_statement {
+                add_lines(else_statement, coverage, lines);
+            }
+        }
+        StatementType::While {
+            expression,
+            statement,
+        }
+        | StatementType::For {
+            expression,
+            statement,
+            ..
+        } => {
+            for statement in block_statements(expression) {
+                add_lines(statement, coverage, lines);
+            }
+            add_lines(statement, coverage, lines);
+        }
+        StatementType::Try {
+            statement,
+            catch_statement,
+            ..
+        } => {
+            add_lines(statement, coverage, lines);
+            add_lines(catch_statement, coverage, lines);
+        }
+        StatementType::Match { expression, arms } => {
+            for statement in block_statements(expression) {
+                add_lines(statement, coverage, lines);
+            }
+            for arm in arms {
+                for statement in arm.guard.iter().flat_map(block_statements) {
+                    add_lines(statement, coverage, lines);
+                }
+                add_lines(&arm.statement, coverage, lines);
+            }
+        }
+        StatementType::Test { body, .. } => add_lines(body, coverage, lines),
+        StatementType::VariableDeclaration {
+            value: expression, ..
+        }
+        | StatementType::Return(Some(expression))
+        | StatementType::Break(Some(expression))
+        | StatementType::Raise(expression)
+        | StatementType::Expression(expression) => {
+            for statement in block_statements(expression) {
+                add_lines(statement, coverage, lines);
+            }
+        }
+        StatementType::Return(None)
+        | StatementType::Break(None)
+        | StatementType::ImportNative(_) => {}
+    }
+}
+
+/// Formats the coverage in the lcov tracefile format, with a record for each file.
+pub fn to_lcov(files: &[FileCoverage]) -> String {
+    let mut lcov = String::new();
+    for file in files {
+        writeln!(lcov, "TN:\nSF:{}", file.source_name).unwrap();
+        for (line, hits) in &file.lines {
+            writeln!(lcov, "DA:{line},{hits}").unwrap();
+        }
+        writeln!(
+            lcov,
+            "LF:{}\nLH:{}\nend_of_record",
+            file.lines.len(),
+            file.lines_hit()
+        )
+        .unwrap();
+    }
+    lcov
+}
+
+/// Formats the coverage as a standalone HTML page showing the source of each file,
+/// with the lines which ran in green and the lines which didn't in red.
+pub fn to_html(files: &[FileCoverage]) -> String {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Coverage</title>\n<style>\n\
+         .hit { background: #dfd; }\n.missed { background: #fdd; }\n\
+         td.line, td.hits { color: #888; text-align: right; padding-right: 1em; }\n</style>\n</head>\n<body>\n",
+    );
+    for file in files {
+        html.push_str("<h2>");
+        escape_html(&mut html, &file.source_name);
+        html.push_str("</h2>\n");
+        let percentage = match file.lines.len() {
+            0 => 100.0,
+            lines => file.lines_hit() as f64 * 100.0 / lines as f64,
+        };
+        writeln!(
+            html,
+            "<p>{} of {} lines covered ({percentage:.1}%)</p>",
+            file.lines_hit(),
+            file.lines.len()
+        )
+        .unwrap();
+        html.push_str("<table>\n");
+        for (index, text) in file.source.lines().enumerate() {
+            let line = index + 1;
+            let (class, hits) = match file.lines.get(&line) {
+                Some(0) => (" class=\"missed\"", "0".to_string()),
+                Some(hits) => (" class=\"hit\"", hits.to_string()),
+                None => ("", String::new()),
+            };
+            write!(
+                html,
+                "<tr{class}><td class=\"line\">{line}</td><td class=\"hits\">{hits}</td><td><pre>"
+            )
+            .unwrap();
+            escape_html(&mut html, text);
+            html.push_str("</pre></td></tr>\n");
+        }
+        html.push_str("</table>\n");
+    }
+    html.push_str("</body>\n</html>\n");
+    html
+}
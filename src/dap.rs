@@ -0,0 +1,543 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::BTreeSet,
+    fs,
+    io::{self, BufRead, Read, Write},
+    rc::Rc,
+};
+
+use serde_json::{json, Value as Json};
+
+use crate::{
+    debugger::{format_value, is_native_function, parse_value, DebugCommand, Debugger, Pause},
+    tokenizer::Value,
+    EvalError, Interpreter, OptLevel,
+};
+
+/// The only thread, as programs are single threaded.
+const THREAD_ID: i64 = 1;
+/// The variables reference of the paused statement's variables. Values can't be expanded, so it's the only one.
+const VARIABLES_REFERENCE: i64 = 1;
+
+/// Reads and writes Debug Adapter Protocol messages, which are JSON with a Content-Length header.
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: i64, // The sequence number of the last message sent.
+}
+
+impl Connection {
+    /// Reads the next message, or returns None at the end of the input.
+    fn read(&mut self) -> io::Result<Option<Json>> {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            if self.input.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(length) = header.strip_prefix("Content-Length:") {
+                content_length = length.trim().parse::<usize>().ok();
+            }
+        }
+        let Some(content_length) = content_length else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Expected a Content-Length header.",
+            ));
+        };
+        let mut content = vec![0; content_length];
+        self.input.read_exact(&mut content)?;
+        serde_json::from_slice(&content)
+            .map(Some)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+
+    fn send(&mut self, mut message: Json) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        let content = message.to_string();
+        write!(
+            self.output,
+            "Content-Length: {}\r\n\r\n{content}",
+            content.len()
+        )?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn respond_error(&mut self, request: &Json, message: &str) -> io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+}
+
+/// What the launch request asked for.
+struct Launch {
+    program: String, // The path of the program.
+    stop_on_entry: bool,
+}
+
+/// Runs a debug adapter which reads requests from the input and writes responses and events to the output,
+/// until the client disconnects. It launches one program, which is run with the tree walker.
+/// Requests are only read while the program is paused, so breakpoints set while it runs apply from the next pause.
+// TODO: Read requests on another thread, so running programs can be paused.
+pub fn serve(input: Box<dyn BufRead>, output: Box<dyn Write>) -> io::Result<()> {
+    let connection = Rc::new(RefCell::new(Connection {
+        input,
+        output,
+        seq: 0,
+    }));
+    let disconnected = Rc::new(Cell::new(false));
+    let mut launch = None;
+    let mut breakpoints = BTreeSet::new();
+    while !disconnected.get() {
+        let Some(request) = connection.borrow_mut().read()? else {
+            return Ok(());
+        };
+        let mut connection_ref = connection.borrow_mut();
+        match request["command"].as_str().unwrap_or("") {
+            "initialize" => {
+                connection_ref.respond(
+                    &request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsSetVariable": true,
+                    }),
+                )?;
+                connection_ref.event("initialized", json!({}))?;
+            }
+            "launch" => {
+                let arguments = &request["arguments"];
+                let Some(program) = arguments["program"].as_str() else {
+                    connection_ref.respond_error(&request, "Expected the path of a program.")?;
+                    continue;
+                };
+                launch = Some(Launch {
+                    program: program.to_string(),
+                    stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+                });
+                connection_ref.respond(&request, json!({}))?;
+            }
+            "setBreakpoints" => {
+                let body;
+                (breakpoints, body) = set_breakpoints(&request);
+                connection_ref.respond(&request, body)?;
+            }
+            "configurationDone" => {
+                connection_ref.respond(&request, json!({}))?;
+                drop(connection_ref);
+                let Some(launch) = launch.take() else {
+                    continue;
+                };
+                run(
+                    &launch,
+                    std::mem::take(&mut breakpoints),
+                    &connection,
+                    &disconnected,
+                )?;
+            }
+            "threads" => connection_ref.respond(&request, threads_body())?,
+            "disconnect" | "terminate" => {
+                connection_ref.respond(&request, json!({}))?;
+                disconnected.set(true);
+            }
+            command => {
+                connection_ref.respond_error(&request, &format!("{command} isn't supported."))?
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs the launched program, handling requests while it's paused, then reports how it exited.
+fn run(
+    launch: &Launch,
+    breakpoints: BTreeSet<usize>,
+    connection: &Rc<RefCell<Connection>>,
+    disconnected: &Rc<Cell<bool>>,
+) -> io::Result<()> {
+    let program = match fs::read_to_string(&launch.program) {
+        Ok(program) => program,
+        Err(_) => {
+            let mut connection = connection.borrow_mut();
+            let output = format!("Couldn't read {}.\n", launch.program);
+            connection.event("output", json!({ "category": "stderr", "output": output }))?;
+            connection.event("exited", json!({ "exitCode": 74 }))?;
+            return connection.event("terminated", json!({}));
+        }
+    };
+
+    let mut interpreter = Interpreter::new();
+    // Statements are kept as written, so they line up with the source.
+    interpreter.set_opt_level(OptLevel::None);
+    let output_connection = connection.clone();
+    interpreter.set_output(move |text| {
+        // Output can't be reported if the client has gone, and the program should still finish.
+        let _ = output_connection
+            .borrow_mut()
+            .event("output", json!({ "category": "stdout", "output": text }));
+    });
+
+    let source_lines = program.lines().map(String::from).collect::<Vec<_>>();
+    let pause_connection = connection.clone();
+    let pause_disconnected = disconnected.clone();
+    let path = launch.program.clone();
+    let mut entry = launch.stop_on_entry;
+    let mut debugger = Debugger::new(move |pause| {
+        let reason = if std::mem::take(&mut entry) {
+            "entry"
+        } else if pause.breakpoints.contains(&pause.lines.0) {
+            "breakpoint"
+        } else {
+            "step"
+        };
+        let mut connection = pause_connection.borrow_mut();
+        handle_pause(pause, reason, &mut connection, &path, &source_lines).unwrap_or_else(|_| {
+            pause_disconnected.set(true);
+            DebugCommand::Quit
+        })
+    });
+    debugger.breakpoints = breakpoints;
+    if !launch.stop_on_entry {
+        debugger.command = DebugCommand::Continue;
+    }
+    interpreter.set_debugger(debugger);
+
+    let result = interpreter.eval(&program);
+    if disconnected.get() {
+        return Ok(());
+    }
+    let mut connection = connection.borrow_mut();
+    let exit_code = match &result {
+        Ok(()) => match interpreter.return_value() {
+            Value::Number(code) => *code as i32,
+            _ => 0,
+        },
+        Err(EvalError::Exit(code)) => *code,
+        Err(error) => {
+            let output = error.report_in(&launch.program, &program);
+            connection.event("output", json!({ "category": "stderr", "output": output }))?;
+            match error {
+                EvalError::Runtime(_) => 70,
+                _ => 65,
+            }
+        }
+    };
+    connection.event("exited", json!({ "exitCode": exit_code }))?;
+    connection.event("terminated", json!({}))
+}
+
+/// Tells the client the program stopped, then answers its requests until one resumes the program.
+fn handle_pause(
+    pause: &mut Pause,
+    reason: &str,
+    connection: &mut Connection,
+    path: &str,
+    source_lines: &[String],
+) -> io::Result<DebugCommand> {
+    connection.event(
+        "stopped",
+        json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+    )?;
+    loop {
+        let Some(request) = connection.read()? else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+        let command = match request["command"].as_str().unwrap_or("") {
+            "threads" => {
+                connection.respond(&request, threads_body())?;
+                continue;
+            }
+            "stackTrace" => {
+                // The paused statement, then the calls which led to it.
+                let lines = [pause.lines]
+                    .into_iter()
+                    .chain(pause.call_stack.iter().rev().copied());
+                let frames = lines
+                    .enumerate()
+                    .map(|(id, lines)| {
+                        let name = source_lines.get(lines.0 - 1).map_or("", |line| line.trim());
+                        json!({
+                            "id": id,
+                            "name": name,
+                            "line": lines.0,
+                            "column": 1,
+                            "source": { "path": path },
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                let total_frames = frames.len();
+                connection.respond(
+                    &request,
+                    json!({ "stackFrames": frames, "totalFrames": total_frames }),
+                )?;
+                continue;
+            }
+            "scopes" => {
+                // Only the paused statement's variables can be found, as environments don't record their callers.
+                let scopes = if request["arguments"]["frameId"].as_i64() == Some(0) {
+                    json!([{
+                        "name": "Variables",
+                        "variablesReference": VARIABLES_REFERENCE,
+                        "expensive": false,
+                    }])
+                } else {
+                    json!([])
+                };
+                connection.respond(&request, json!({ "scopes": scopes }))?;
+                continue;
+            }
+            "variables" => {
+                let variables = pause
+                    .visible_variables()
+                    .into_iter()
+                    .filter(|(_, value)| !is_native_function(value))
+                    .map(|(name, value)| {
+                        json!({
+                            "name": name.as_str(),
+                            "value": format_value(&value),
+                            "type": value.value_type().to_string(),
+                            "variablesReference": 0,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                connection.respond(&request, json!({ "variables": variables }))?;
+                continue;
+            }
+            "setVariable" => {
+                let arguments = &request["arguments"];
+                let name = arguments["name"].as_str().unwrap_or("");
+                let result = parse_value(arguments["value"].as_str().unwrap_or(""))
+                    .and_then(|value| pause.set_variable(name, value.clone()).map(|()| value));
+                match result {
+                    Ok(value) => {
+                        connection.respond(&request, json!({ "value": format_value(&value) }))?
+                    }
+                    Err(error) => connection.respond_error(&request, &error)?,
+                }
+                continue;
+            }
+            "setBreakpoints" => {
+                let body;
+                (*pause.breakpoints, body) = set_breakpoints(&request);
+                connection.respond(&request, body)?;
+                continue;
+            }
+            "continue" => DebugCommand::Continue,
+            "next" => DebugCommand::Next,
+            "stepIn" => DebugCommand::Step,
+            "stepOut" => DebugCommand::StepOut,
+            "disconnect" | "terminate" => {
+                connection.respond(&request, json!({}))?;
+                return Err(io::ErrorKind::ConnectionAborted.into());
+            }
+            command => {
+                connection.respond_error(&request, &format!("{command} isn't supported."))?;
+                continue;
+            }
+        };
+        connection.respond(&request, json!({ "allThreadsContinued": true }))?;
+        return Ok(command);
+    }
+}
+
+fn threads_body() -> Json {
+    json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })
+}
+
+/// Returns the lines of a setBreakpoints request and the body of its response, which lists the breakpoints in the
+/// order they were requested. Breakpoints are only set in the launched program, whatever the source.
+fn set_breakpoints(request: &Json) -> (BTreeSet<usize>, Json) {
+    let lines = request["arguments"]["breakpoints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|breakpoint| breakpoint["line"].as_u64())
+        .map(|line| line as usize)
+        .collect::<Vec<_>>();
+    let breakpoints = lines
+        .iter()
+        .map(|line| json!({ "verified": true, "line": line }))
+        .collect::<Vec<_>>();
+    (
+        lines.into_iter().collect(),
+        json!({ "breakpoints": breakpoints }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        fs,
+        io::{self, Write},
+        rc::Rc,
+    };
+
+    use serde_json::{json, Value as Json};
+
+    use super::serve;
+
+    /// Collects what the adapter writes, so the test can read it after the adapter returns.
+    #[derive(Clone, Default)]
+    struct Output(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Sends the requests to an adapter in order and returns the messages it sent back.
+    fn exchange(requests: &[(&str, Json)]) -> Vec<Json> {
+        let mut input = String::new();
+        for (seq, (command, arguments)) in requests.iter().enumerate() {
+            let content = json!({
+                "seq": seq + 1,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            input += &format!("Content-Length: {}\r\n\r\n{content}", content.len());
+        }
+        let output = Output::default();
+        serve(
+            Box::new(io::Cursor::new(input.into_bytes())),
+            Box::new(output.clone()),
+        )
+        .unwrap();
+
+        let output = String::from_utf8(output.0.take()).unwrap();
+        let mut messages = vec![];
+        let mut rest = output.as_str();
+        while let Some(header_end) = rest.find("\r\n\r\n") {
+            let length = rest["Content-Length: ".len()..header_end]
+                .parse::<usize>()
+                .unwrap();
+            let content = &rest[header_end + 4..header_end + 4 + length];
+            messages.push(serde_json::from_str(content).unwrap());
+            rest = &rest[header_end + 4 + length..];
+        }
+        messages
+    }
+
+    /// The name of each event, and the command of each response with whether it succeeded.
+    fn summary(messages: &[Json]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|message| match message["type"].as_str().unwrap() {
+                "event" => format!("event {}", message["event"].as_str().unwrap()),
+                _ => format!(
+                    "{} {}",
+                    message["command"].as_str().unwrap(),
+                    message["success"]
+                ),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn debugs_a_program_from_launch_to_exit() {
+        let path = std::env::temp_dir().join(format!("decibelle-dap-{}.db", std::process::id()));
+        fs::write(
+            &path,
+            "fn f(x: number) -> number {\n    let y = x + 1;\n    return y;\n}\nprint(f(1));\n",
+        )
+        .unwrap();
+        let messages = exchange(&[
+            ("initialize", json!({})),
+            ("launch", json!({ "program": path.to_str().unwrap() })),
+            ("setBreakpoints", json!({ "breakpoints": [{ "line": 3 }] })),
+            ("configurationDone", json!({})),
+            ("stackTrace", json!({ "threadId": 1 })),
+            ("variables", json!({ "variablesReference": 1 })),
+            ("setVariable", json!({ "name": "y", "value": "\"no\"" })),
+            ("setVariable", json!({ "name": "y", "value": "41" })),
+            ("continue", json!({ "threadId": 1 })),
+            ("disconnect", json!({})),
+        ]);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            summary(&messages),
+            [
+                "initialize true",
+                "event initialized",
+                "launch true",
+                "setBreakpoints true",
+                "configurationDone true",
+                "event stopped",
+                "stackTrace true",
+                "variables true",
+                "setVariable false",
+                "setVariable true",
+                "continue true",
+                "event output",
+                "event exited",
+                "event terminated",
+                "disconnect true",
+            ]
+        );
+        assert_eq!(messages[5]["body"]["reason"], "breakpoint");
+        let frames = &messages[6]["body"]["stackFrames"];
+        assert_eq!(frames[0]["line"], 3);
+        assert_eq!(frames[0]["name"], "return y;");
+        assert_eq!(frames[1]["line"], 5);
+        let variables = messages[7]["body"]["variables"].as_array().unwrap();
+        assert_eq!(
+            variables[0],
+            json!({ "name": "y", "value": "2", "type": "number", "variablesReference": 0 })
+        );
+        assert_eq!(messages[11]["body"]["output"], "41\n");
+        assert_eq!(messages[12]["body"]["exitCode"], 0);
+    }
+
+    #[test]
+    fn reports_programs_which_cannot_be_read() {
+        let messages = exchange(&[
+            ("launch", json!({ "program": "/no/such/program.db" })),
+            ("configurationDone", json!({})),
+            ("evaluate", json!({ "expression": "1" })),
+        ]);
+        assert_eq!(
+            summary(&messages),
+            [
+                "launch true",
+                "configurationDone true",
+                "event output",
+                "event exited",
+                "event terminated",
+                "evaluate false",
+            ]
+        );
+        assert_eq!(messages[3]["body"]["exitCode"], 74);
+    }
+}
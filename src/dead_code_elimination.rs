@@ -0,0 +1,273 @@
+use std::rc::Rc;
+
+use crate::{
+    parser::{BinaryOperation, Expression, ExpressionType, Statement, StatementType},
+    tokenizer::Value,
+};
+
+/// Removes statements which can't run or don't do anything:
+/// statements after a return, branches of ifs with a literal condition,
+/// while loops with a false condition and expression statements without side effects.
+/// Needs to run after constant folding, so that conditions like 1 > 2 are literals.
+pub fn eliminate_dead_code(statements: &mut Vec<Statement>) {
+    let mut index = 0;
+    while index < statements.len() {
+        if !eliminate_statement(&mut statements[index]) {
+            statements.remove(index);
+            continue;
+        }
+
+        index += 1;
+        if always_returns(&statements[index - 1]) {
+            statements.truncate(index);
+        }
+    }
+}
+
+fn empty_block(lines: (usize, usize)) -> Statement {
+    Statement {
+        statement: StatementType::Block(vec![]),
+        lines,
+    }
+}
+
+/// Simplifies a statement. Returns false if the statement can be removed.
+fn eliminate_statement(statement: &mut Statement) -> bool {
+    let lines = statement.lines;
+    match &mut statement.statement {
+        StatementType::Expression(expression) => has_side_effects(expression),
+        StatementType::VariableDeclaration { .. }
+        | StatementType::Return(_)
+        | StatementType::Break(_)
+        | StatementType::Raise(_) => true,
+        StatementType::FunctionDeclaration { body, .. } => {
+            // The body is only shared with the program cache until the program runs, so this rarely copies it.
+            eliminate_statement(Rc::make_mut(body));
+            true
+        }
+        StatementType::Block(statements) => {
+            eliminate_dead_code(statements);
+            true
+        }
+        StatementType::If {
+            expression,
+            then_statement,
+            else_statement,
+        } => match expression.expression_type {
+            ExpressionType::Literal(Value::Boolean(true)) => {
+                *statement = std::mem::replace(then_statement, empty_block(lines));
+                eliminate_statement(statement)
+            }
+            ExpressionType::Literal(Value::Boolean(false)) => match else_statement.take() {
+                Some(else_statement) => {
+                    *statement = *else_statement;
+                    eliminate_statement(statement)
+                }
+                None => false,
+            },
+            _ => {
+                if !eliminate_statement(then_statement) {
+                    **then_statement = empty_block(then_statement.lines);
+                }
+                if let Some(else_branch) = else_statement {
+                    if !eliminate_statement(else_branch) {
+                        *else_statement = None;
+                    }
+                }
+                true
+            }
+        },
+        StatementType::While {
+            expression,
+            statement: body,
+        } => {
+            if expression.expression_type == ExpressionType::Literal(Value::Boolean(false)) {
+                return false;
+            }
+            if !eliminate_statement(body) {
+                **body = empty_block(body.lines);
+            }
+            true
+        }
+        StatementType::For {
+            statement: body, ..
+        } => {
+            if !eliminate_statement(body) {
+                **body = empty_block(body.lines);
+            }
+            true
+        }
+        StatementType::Try {
+            statement: body,
+            catch_statement,
+            ..
+        } => {
+            if !eliminate_statement(body) {
+                return false;
+            }
+            if !eliminate_statement(catch_statement) {
+                **catch_statement = empty_block(catch_statement.lines);
+            }
+            true
+        }
+        StatementType::Match { arms, .. } => {
+            // Arms whose guard is always false never run, and guards which are always true can be left out.
+            arms.retain(|arm| {
+                arm.guard.as_ref().map(|guard| &guard.expression_type)
+                    != Some(&ExpressionType::Literal(Value::Boolean(false)))
+            });
+            for arm in arms {
+                if arm.guard.as_ref().map(|guard| &guard.expression_type)
+                    == Some(&ExpressionType::Literal(Value::Boolean(true)))
+                {
+                    arm.guard = None;
+                }
+                if !eliminate_statement(&mut arm.statement) {
+                    arm.statement = empty_block(arm.statement.lines);
+                }
+            }
+            true
+        }
+        StatementType::Test { body, .. } => {
+            eliminate_statement(body);
+            true
+        }
+        StatementType::ImportNative(_) => true,
+    }
+}
+
+/// Will the statement necessarily return?
+fn always_returns(statement: &Statement) -> bool {
+    match &statement.statement {
+        StatementType::Return(_) | StatementType::Raise(_) => true,
+        StatementType::Block(statements) => statements.iter().any(always_returns),
+        StatementType::If {
+            then_statement,
+            else_statement: Some(else_statement),
+            ..
+        } => always_returns(then_statement) && always_returns(else_statement),
+        StatementType::Try {
+            statement,
+            catch_statement,
+            ..
+        } => always_returns(statement) && always_returns(catch_statement),
+        // The arms after one which matches everything never run.
+        StatementType::Match { arms, .. } => arms
+            .iter()
+            .position(|arm| arm.matches_everything())
+            .is_some_and(|last_arm| {
+                arms[..=last_arm]
+                    .iter()
+                    .all(|arm| always_returns(&arm.statement))
+            }),
+        _ => false,
+    }
+}
+
+fn has_side_effects(expression: &Expression) -> bool {
+    match &expression.expression_type {
+        ExpressionType::FunctionCall { .. }
+        | ExpressionType::Loop(_)
+        | ExpressionType::Propagate(_)
+        | ExpressionType::Binary {
+            operation: BinaryOperation::Assignment,
+            ..
+        } => true,
+        ExpressionType::Unary { expression, .. }
+        | ExpressionType::Grouping(expression)
+        | ExpressionType::TupleAccess { expression, .. }
+        | ExpressionType::Ok(expression)
+        | ExpressionType::Err(expression)
+        | ExpressionType::Some(expression) => has_side_effects(expression),
+        ExpressionType::Binary {
+            left_expression,
+            right_expression,
+            ..
+        } => has_side_effects(left_expression) || has_side_effects(right_expression),
+        ExpressionType::Tuple(expressions)
+        | ExpressionType::Interpolation {
+            values: expressions,
+            ..
+        } => expressions.iter().any(has_side_effects),
+        ExpressionType::Record(fields) => fields
+            .iter()
+            .any(|(_, expression)| has_side_effects(expression)),
+        ExpressionType::Block { statements, value } => {
+            !statements.is_empty() || has_side_effects(value)
+        }
+        ExpressionType::If {
+            condition,
+            then_expression,
+            else_expression,
+        } => {
+            has_side_effects(condition)
+                || has_side_effects(then_expression)
+                || has_side_effects(else_expression)
+        }
+        ExpressionType::Literal(_) | ExpressionType::Variable { .. } => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eliminate_dead_code;
+    use crate::{parser::parse, tokenizer::tokenize};
+
+    /// Eliminates the dead code in the program and writes what's left one statement per line.
+    fn eliminate(program: &str) -> String {
+        let mut statements = parse(&tokenize(program).unwrap()).unwrap();
+        eliminate_dead_code(&mut statements);
+        statements
+            .iter()
+            .map(|statement| format!("{statement}\n"))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_the_branch_a_literal_condition_takes() {
+        assert_eq!(
+            eliminate("if true { print(1); } else { print(2); }"),
+            "{ print(1); }\n"
+        );
+        assert_eq!(
+            eliminate("if false { print(1); }\nprint(2);"),
+            "print(2);\n"
+        );
+        assert_eq!(
+            eliminate("let a = 1;\nif a > 0 { 1; } else { print(2); }"),
+            "let a = 1;\nif a > 0 {} else { print(2); }\n"
+        );
+    }
+
+    #[test]
+    fn removes_statements_after_a_return() {
+        assert_eq!(
+            eliminate("fn f() -> number { return 1; print(2); }"),
+            "fn f() -> number { return 1; }\n"
+        );
+        assert_eq!(
+            eliminate("fn f() { if true { return; } else { return; } print(1); }"),
+            "fn f() { { return; } }\n"
+        );
+    }
+
+    #[test]
+    fn removes_loops_and_expressions_which_do_nothing() {
+        assert_eq!(eliminate("while false { print(1); }"), "");
+        assert_eq!(
+            eliminate("let a = 1;\n1 + 2;\na;\nprint(a);"),
+            "let a = 1;\nprint(a);\n"
+        );
+        assert_eq!(eliminate("let a = 1;\na = 2;"), "let a = 1;\na = 2;\n");
+    }
+
+    #[test]
+    fn removes_match_arms_whose_guard_is_false() {
+        assert_eq!(
+            eliminate(
+                "let a = 1;\nmatch a { case n if false => { print(n); } case n if true => { print(n); } case _ => { 1; } }"
+            ),
+            "let a = 1;\nmatch a { case n => { print(n); } case _ => {} }\n"
+        );
+    }
+}
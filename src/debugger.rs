@@ -0,0 +1,268 @@
+use std::collections::BTreeSet;
+
+use crate::{
+    format::ValueFormat,
+    interpreter::Variables,
+    symbol::Symbol,
+    tokenizer::{tokenize, FunctionBody, TokenType, Value},
+};
+
+/// What a paused program does next.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// Pause at the next statement, including in the functions it calls.
+    Step,
+    /// Pause at the next statement in the current function, or the function it returns to.
+    Next,
+    /// Pause at the next statement after the current function returns.
+    StepOut,
+    /// Run until a breakpoint.
+    Continue,
+    /// Stop the program, as if it called exit(0).
+    Quit,
+}
+
+/// The function which decides what a paused program does next.
+pub type PauseHandler = Box<dyn FnMut(&mut Pause) -> DebugCommand>;
+
+/// Pauses the tree walker before statements on breakpoints or while stepping, and lets a handler
+/// inspect and change the program's variables while it's paused.
+/// Statements which start on the same line are one stop, so each line pauses once per visit.
+pub struct Debugger {
+    pub breakpoints: BTreeSet<usize>, // Lines to pause on.
+    handler: PauseHandler,
+    pub command: DebugCommand, // What to do until the next pause. Starts as Step, to pause at the first statement.
+    pause_depth: usize,        // The call depth at the last pause.
+    last_statement: Option<(usize, usize)>, // The line and call depth of the last statement.
+}
+
+impl Debugger {
+    /// Creates a debugger which pauses at the first statement, so the handler can set breakpoints.
+    pub fn new(handler: impl FnMut(&mut Pause) -> DebugCommand + 'static) -> Debugger {
+        Debugger {
+            breakpoints: BTreeSet::new(),
+            handler: Box::new(handler),
+            command: DebugCommand::Step,
+            pause_depth: 0,
+            last_statement: None,
+        }
+    }
+
+    /// Should the program pause before the statement which starts on the line, at the call depth?
+    pub fn should_pause(&mut self, line: usize, depth: usize) -> bool {
+        if self.last_statement.replace((line, depth)) == Some((line, depth)) {
+            return false;
+        }
+        match self.command {
+            DebugCommand::Step => true,
+            DebugCommand::Next if depth <= self.pause_depth => true,
+            DebugCommand::StepOut if depth < self.pause_depth => true,
+            _ => self.breakpoints.contains(&line),
+        }
+    }
+
+    /// Pauses the program before the statement on the lines, until the handler says what to do next.
+    pub fn pause(
+        &mut self,
+        lines: (usize, usize),
+        call_stack: &[(usize, usize)],
+        variables: &mut Variables,
+    ) -> DebugCommand {
+        let mut pause = Pause {
+            lines,
+            call_stack,
+            breakpoints: &mut self.breakpoints,
+            variables,
+        };
+        self.command = (self.handler)(&mut pause);
+        self.pause_depth = call_stack.len();
+        self.command
+    }
+}
+
+/// A program paused before a statement.
+pub struct Pause<'a> {
+    pub lines: (usize, usize), // The lines of the statement about to run.
+    pub call_stack: &'a [(usize, usize)], // The lines of the calls to the running functions, outermost first.
+    pub breakpoints: &'a mut BTreeSet<usize>,
+    pub variables: &'a mut Variables,
+}
+
+impl Pause<'_> {
+    /// The variables the statement can use, innermost first. Shadowed variables aren't included.
+    pub fn visible_variables(&self) -> Vec<(Symbol, Value)> {
+        self.variables
+            .visible_variables()
+            .into_iter()
+            .map(|(name, value)| (name, value.clone()))
+            .collect()
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<Value> {
+        let name = Symbol::get(name)?;
+        self.visible_variables()
+            .into_iter()
+            .find(|(variable, _)| *variable == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Changes the value of a variable the statement can use. The value must have the variable's type.
+    pub fn set_variable(&mut self, name: &str, value: Value) -> Result<(), String> {
+        let variable = Symbol::get(name)
+            .and_then(|name| self.variables.visible_variable_mut(name))
+            .ok_or_else(|| format!("There's no variable called {name} here."))?;
+        let (variable_type, value_type) = (variable.value_type(), value.value_type());
+        if variable_type != value_type {
+            return Err(format!(
+                "{name} has type {variable_type}, so it can't be set to a value of type {value_type}."
+            ));
+        }
+        *variable = value;
+        Ok(())
+    }
+}
+
+/// Is the value a function implemented in Rust, like a builtin? Debuggers hide them from the variables they list.
+pub fn is_native_function(value: &Value) -> bool {
+    matches!(value, Value::Function(function) if matches!(function.body, FunctionBody::RustClosure { .. }))
+}
+
+/// Formats a value for a debugger, with strings in quotes and functions written as their type.
+pub fn format_value(value: &Value) -> String {
+    ValueFormat::default().debug(value)
+}
+
+/// Parses a number, string or bool literal entered in a debugger.
+pub fn parse_value(text: &str) -> Result<Value, String> {
+    let tokens = tokenize(text).map_err(|_| format!("{text} isn't a value."))?;
+    let token_types = tokens
+        .iter()
+        .map(|token| &token.token_type)
+        .collect::<Vec<_>>();
+    match token_types[..] {
+        [TokenType::Literal(value)] => Ok(value.clone()),
+        [TokenType::Minus, TokenType::Literal(Value::Number(number))] => Ok(Value::Number(-number)),
+        _ => Err(format!(
+            "{text} isn't a value. Only numbers, strings and bools can be set."
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{parse_value, DebugCommand, Debugger};
+    use crate::{tokenizer::Value, Backend, EvalError, Interpreter, OptLevel};
+
+    const PROGRAM: &str = "fn add(x: number) -> number {
+    let y = x + 1;
+    return y;
+}
+let a = add(1);
+let b = add(a);
+print(b);";
+
+    /// Runs the program, answering each pause with the next command and recording the paused line and call depth.
+    /// Returns the pauses and what the program printed.
+    fn debug(commands: Vec<DebugCommand>, breakpoints: &[usize]) -> (Vec<(usize, usize)>, String) {
+        let pauses = Rc::new(RefCell::new(vec![]));
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut interpreter = Interpreter::with_backend(Backend::TreeWalker);
+        interpreter.set_opt_level(OptLevel::None);
+        let sink = output.clone();
+        interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+        let (recorded, mut commands) = (pauses.clone(), commands.into_iter());
+        let breakpoints = breakpoints.to_vec();
+        interpreter.set_debugger(Debugger::new(move |pause| {
+            pause.breakpoints.extend(&breakpoints);
+            recorded
+                .borrow_mut()
+                .push((pause.lines.0, pause.call_stack.len()));
+            commands.next().unwrap_or(DebugCommand::Continue)
+        }));
+        // Quitting stops the program as exit(0) does.
+        match interpreter.eval(PROGRAM) {
+            Ok(()) | Err(EvalError::Exit(0)) => {}
+            Err(error) => panic!("{error:?}"),
+        }
+        (pauses.take(), output.take())
+    }
+
+    #[test]
+    fn steps_into_and_over_calls() {
+        use DebugCommand::*;
+        let (pauses, output) = debug(vec![Step; 10], &[]);
+        assert_eq!(
+            pauses,
+            [
+                (1, 0),
+                (5, 0),
+                (2, 1),
+                (3, 1),
+                (6, 0),
+                (2, 1),
+                (3, 1),
+                (7, 0)
+            ]
+        );
+        assert_eq!(output, "3\n");
+
+        let (pauses, _) = debug(vec![Next; 10], &[]);
+        assert_eq!(pauses, [(1, 0), (5, 0), (6, 0), (7, 0)]);
+
+        let (pauses, _) = debug(vec![Step, Step, StepOut, Next], &[]);
+        assert_eq!(pauses, [(1, 0), (5, 0), (2, 1), (6, 0), (7, 0)]);
+    }
+
+    #[test]
+    fn continues_to_breakpoints() {
+        let (pauses, _) = debug(vec![DebugCommand::Continue; 10], &[3]);
+        assert_eq!(pauses, [(1, 0), (3, 1), (3, 1)]);
+    }
+
+    #[test]
+    fn quits_without_running_the_rest() {
+        let (pauses, output) = debug(vec![DebugCommand::Quit], &[]);
+        assert_eq!(pauses, [(1, 0)]);
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn changes_variables_while_paused() {
+        let results = Rc::new(RefCell::new(vec![]));
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut interpreter = Interpreter::with_backend(Backend::TreeWalker);
+        let sink = output.clone();
+        interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+        let recorded = results.clone();
+        let mut debugger = Debugger::new(move |pause| {
+            recorded.borrow_mut().extend([
+                pause.set_variable("y", Value::String("no".into())),
+                pause.set_variable("y", Value::Number(10.0)),
+                pause.set_variable("z", Value::Number(1.0)),
+            ]);
+            assert_eq!(pause.get_variable("y"), Some(Value::Number(10.0)));
+            DebugCommand::Continue
+        });
+        debugger.breakpoints.insert(3);
+        debugger.command = DebugCommand::Continue;
+        interpreter.set_debugger(debugger);
+        interpreter
+            .eval("fn f() -> number {\n    let y = 1;\n    return y;\n}\nprint(f());")
+            .unwrap();
+        let results = results.take();
+        assert!(results[0].is_err());
+        assert_eq!(results[1], Ok(()));
+        assert!(results[2].is_err());
+        assert_eq!(output.take(), "10\n");
+    }
+
+    #[test]
+    fn parses_values() {
+        assert_eq!(parse_value("-1.5"), Ok(Value::Number(-1.5)));
+        assert_eq!(parse_value("true"), Ok(Value::Boolean(true)));
+        assert!(parse_value("1 + 2").is_err());
+        assert!(parse_value("\"open").is_err());
+    }
+}
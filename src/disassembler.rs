@@ -0,0 +1,142 @@
+use std::{collections::HashSet, fmt::Write};
+
+use crate::{
+    compiler::{Chunk, Function, Instruction},
+    debugger::format_value,
+    format::FormatSpec,
+};
+
+/// Formats the bytecode of a compiled program and the functions it declares, one instruction per line.
+/// Each run of instructions is preceded by the source line it was compiled from, jump targets are marked with >
+/// and operands which refer to constants, globals, functions or other instructions are explained after a ;.
+pub fn disassemble(function: &Function, program: &str) -> String {
+    let source_lines = program.lines().collect::<Vec<_>>();
+    let mut disassembly = String::new();
+    let mut functions = vec![function];
+    while let Some(function) = functions.pop() {
+        if !disassembly.is_empty() {
+            disassembly.push('\n');
+        }
+        disassemble_function(&mut disassembly, function, &source_lines);
+        // Functions are listed in the order they're declared.
+        functions.extend(
+            function
+                .chunk
+                .functions
+                .iter()
+                .rev()
+                .map(|function| &**function),
+        );
+    }
+    disassembly
+}
+
+fn disassemble_function(disassembly: &mut String, function: &Function, source_lines: &[&str]) {
+    let chunk = &function.chunk;
+    writeln!(disassembly, "== {} ==", function.name).unwrap();
+    let jump_targets = chunk
+        .instructions
+        .iter()
+        .filter_map(|instruction| jump_target(*instruction))
+        .collect::<HashSet<_>>();
+
+    let mut previous_line = None;
+    for (index, instruction) in chunk.instructions.iter().enumerate() {
+        let line = chunk.lines[index];
+        if previous_line != Some(line) {
+            let source = source_lines
+                .get(line - 1)
+                .map_or("", |source| source.trim());
+            writeln!(disassembly, "     | {line:>4}  {source}").unwrap();
+            previous_line = Some(line);
+        }
+        let marker = if jump_targets.contains(&index) {
+            '>'
+        } else {
+            ' '
+        };
+        let instruction_text = format!("{instruction:?}");
+        match explain(*instruction, chunk) {
+            Some(explanation) => writeln!(
+                disassembly,
+                "{marker}{index:04}  {instruction_text:<40} ; {explanation}"
+            ),
+            None => writeln!(disassembly, "{marker}{index:04}  {instruction_text}"),
+        }
+        .unwrap();
+    }
+}
+
+fn jump_target(instruction: Instruction) -> Option<usize> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::JumpIfFalse(target)
+        | Instruction::JumpIfFalseOrPop(target)
+        | Instruction::JumpIfTrueOrPop(target)
+        | Instruction::Next(target)
+        | Instruction::JumpIfOk(target)
+        | Instruction::Match { target, .. }
+        | Instruction::Try(target)
+        | Instruction::JumpUnless { target, .. }
+        | Instruction::JumpIfInitialized { target, .. } => Some(target),
+        _ => None,
+    }
+}
+
+/// What the instruction's operands refer to, if they refer to anything.
+fn explain(instruction: Instruction, chunk: &Chunk) -> Option<String> {
+    // Globals declared by earlier programs and builtins aren't in the chunk, so they can't be named.
+    let global_name = |slot: usize| {
+        chunk
+            .globals
+            .iter()
+            .find(|(_, _, global_slot)| *global_slot == slot)
+            .map(|(name, _, _)| name.to_string())
+    };
+    let path = |path: usize| {
+        chunk.paths[path]
+            .iter()
+            .map(|index| format!(".{index}"))
+            .collect::<String>()
+    };
+    match instruction {
+        Instruction::Constant(index) => Some(format_value(&chunk.constants[index])),
+        Instruction::GetGlobal(slot)
+        | Instruction::SetGlobal(slot)
+        | Instruction::StoreGlobal(slot)
+        | Instruction::ReferenceGlobal(slot) => global_name(slot),
+        Instruction::DefineGlobal(index) => Some(chunk.globals[index].0.to_string()),
+        Instruction::SetGlobalField { slot, path: index } => {
+            Some(format!("{}{}", global_name(slot)?, path(index)))
+        }
+        Instruction::SetLocalField { path: index, .. } | Instruction::SetReferenceField(index) => {
+            Some(path(index))
+        }
+        Instruction::Closure(index) => Some(format!("fn {}", chunk.functions[index].name)),
+        Instruction::Record(index) => Some(
+            chunk.records[index]
+                .iter()
+                .map(|name| format!("{name}:"))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        Instruction::Match { pattern, target } => Some(format!(
+            "case {} else -> {target:04}",
+            chunk.patterns[pattern]
+        )),
+        Instruction::Interpolate(index) => {
+            let (strings, specs) = &chunk.interpolations[index];
+            let mut template = format!("\"{}", strings[0]);
+            for (spec, string) in specs.iter().zip(&strings[1..]) {
+                if *spec == FormatSpec::default() {
+                    template.push_str(&format!("${{}}{string}"));
+                } else {
+                    template.push_str(&format!("${{:{spec}}}{string}"));
+                }
+            }
+            template.push('"');
+            Some(template)
+        }
+        instruction => jump_target(instruction).map(|target| format!("-> {target:04}")),
+    }
+}
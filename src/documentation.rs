@@ -0,0 +1,179 @@
+use std::fmt::Write;
+
+use crate::{
+    highlight::escape_html,
+    parser::StatementType,
+    syntax_tree::{SyntaxTree, TriviaKind},
+    tokenizer::Type,
+    EvalError,
+};
+
+/// The documentation of a program, from its doc comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Documentation {
+    pub doc: Option<String>, // From the //! comments at the start of the program.
+    pub items: Vec<DocItem>,
+}
+
+/// A top level declaration. Functions are always documented, and variables if they have doc comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocItem {
+    pub name: String,
+    pub is_function: bool,
+    pub signature: String, // The declaration without its body, like fn add(a: number, b: number) -> number.
+    pub doc: Option<String>,
+    pub line: usize,
+}
+
+/// Collects the documentation of the program, without running it.
+pub fn document(program: &str) -> Result<Documentation, EvalError> {
+    let syntax_tree = SyntaxTree::parse(program)?;
+
+    // Module documentation is the //! comments before the first token.
+    let leading_trivia = syntax_tree
+        .tokens
+        .first()
+        .map_or(&syntax_tree.trailing_trivia, |token| &token.leading_trivia);
+    let module_doc_lines = leading_trivia
+        .iter()
+        .filter(|trivia| trivia.kind == TriviaKind::Comment)
+        .filter_map(|trivia| trivia.text.trim_end().strip_prefix("//!"))
+        .collect::<Vec<_>>();
+    let doc = (!module_doc_lines.is_empty()).then(|| doc_text(&module_doc_lines.join("\n")));
+
+    let mut items = vec![];
+    for statement in &syntax_tree.statements {
+        match &statement.statement {
+            StatementType::FunctionDeclaration {
+                doc,
+                name,
+                parameters,
+                return_type,
+                ..
+            } => {
+                let parameters = parameters
+                    .iter()
+                    .map(|(name, _, parameter_type)| format!("{name}: {parameter_type}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let mut signature = format!("fn {name}({parameters})");
+                if *return_type != Type::Void {
+                    write!(signature, " -> {return_type}").unwrap();
+                }
+                items.push(DocItem {
+                    name: name.to_string(),
+                    is_function: true,
+                    signature,
+                    doc: doc.as_deref().map(doc_text),
+                    line: statement.lines.0,
+                });
+            }
+            StatementType::VariableDeclaration {
+                doc: Some(doc),
+                variable,
+                variable_type,
+                value,
+                ..
+            } => {
+                let signature = match variable_type {
+                    Some(variable_type) => format!("let {variable}: {variable_type} = {value}"),
+                    None => format!("let {variable} = {value}"),
+                };
+                items.push(DocItem {
+                    name: variable.to_string(),
+                    is_function: false,
+                    signature,
+                    doc: Some(doc_text(doc)),
+                    line: statement.lines.0,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Documentation { doc, items })
+}
+
+/// Removes the space after /// from each line of a doc comment.
+fn doc_text(doc: &str) -> String {
+    doc.split('\n')
+        .map(|line| line.strip_prefix(' ').unwrap_or(line).trim_end())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Documentation {
+    /// Renders the documentation as Markdown, under a heading with the title.
+    /// Doc comments are included as they are, so they can use Markdown.
+    pub fn to_markdown(&self, title: &str) -> String {
+        let mut markdown = format!("# {title}\n");
+        if let Some(doc) = &self.doc {
+            write!(markdown, "\n{doc}\n").unwrap();
+        }
+        for (heading, is_function) in [("Functions", true), ("Variables", false)] {
+            let items = self
+                .items
+                .iter()
+                .filter(|item| item.is_function == is_function);
+            for (index, item) in items.enumerate() {
+                if index == 0 {
+                    write!(markdown, "\n## {heading}\n").unwrap();
+                }
+                write!(
+                    markdown,
+                    "\n### {}\n\n```\n{}\n```\n",
+                    item.name, item.signature
+                )
+                .unwrap();
+                if let Some(doc) = &item.doc {
+                    write!(markdown, "\n{doc}\n").unwrap();
+                }
+            }
+        }
+        markdown
+    }
+
+    /// Renders the documentation as a standalone HTML page. Each paragraph of a doc comment becomes a <p>.
+    pub fn to_html(&self, title: &str) -> String {
+        let mut html =
+            String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+        escape_html(&mut html, title);
+        html.push_str("</title>\n</head>\n<body>\n<h1>");
+        escape_html(&mut html, title);
+        html.push_str("</h1>\n");
+        if let Some(doc) = &self.doc {
+            write_paragraphs(&mut html, doc);
+        }
+        for (heading, is_function) in [("Functions", true), ("Variables", false)] {
+            let items = self
+                .items
+                .iter()
+                .filter(|item| item.is_function == is_function);
+            for (index, item) in items.enumerate() {
+                if index == 0 {
+                    writeln!(html, "<h2>{heading}</h2>").unwrap();
+                }
+                // Names are identifiers, so they don't need escaping.
+                write!(html, "<h3 id=\"{0}\">{0}</h3>\n<pre><code>", item.name).unwrap();
+                escape_html(&mut html, &item.signature);
+                html.push_str("</code></pre>\n");
+                if let Some(doc) = &item.doc {
+                    write_paragraphs(&mut html, doc);
+                }
+            }
+        }
+        html.push_str("</body>\n</html>\n");
+        html
+    }
+}
+
+fn write_paragraphs(html: &mut String, text: &str) {
+    for paragraph in text
+        .split("\n\n")
+        .filter(|paragraph| !paragraph.trim().is_empty())
+    {
+        html.push_str("<p>");
+        escape_html(html, paragraph.trim());
+        html.push_str("</p>\n");
+    }
+}
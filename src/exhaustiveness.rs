@@ -0,0 +1,307 @@
+use crate::{parser::Pattern, tokenizer::Value};
+
+/// An example of a value which none of the patterns match, written as a pattern, or None if they match every value.
+/// Which values exist is worked out from the patterns, so a match on a value of type any may not cover every value
+/// even if this returns None.
+pub fn missing_pattern(patterns: &[&Pattern]) -> Option<String> {
+    let wildcard = Pattern::Wildcard;
+    let matrix = patterns
+        .iter()
+        .map(|pattern| vec![*pattern])
+        .collect::<Vec<_>>();
+    useful(&matrix, &[&wildcard], &wildcard).map(|mut witness| witness.remove(0))
+}
+
+/// Whether the pattern matches a value which none of the earlier patterns match.
+pub fn is_useful(earlier_patterns: &[&Pattern], pattern: &Pattern) -> bool {
+    let wildcard = Pattern::Wildcard;
+    let matrix = earlier_patterns
+        .iter()
+        .map(|pattern| vec![*pattern])
+        .collect::<Vec<_>>();
+    useful(&matrix, &[pattern], &wildcard).is_some()
+}
+
+/// A kind of value, whose parts are matched by the patterns inside a pattern for it.
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    Tuple(usize),
+    Some,
+    None,
+    Ok,
+    Err,
+    Boolean(bool),
+    Number(Interval),
+    Literal(Value), // Any other literal, like a string. There are too many values to list them all.
+}
+
+impl Constructor {
+    /// How many parts values of this kind have.
+    fn arity(&self) -> usize {
+        match self {
+            Constructor::Tuple(length) => *length,
+            Constructor::Some | Constructor::Ok | Constructor::Err => 1,
+            _ => 0,
+        }
+    }
+
+    /// Writes a value of this kind with the parts, as a pattern.
+    fn write(&self, parts: &[String]) -> String {
+        match self {
+            Constructor::Tuple(_) => format!("({})", parts.join(", ")),
+            Constructor::Some => format!("Some({})", parts[0]),
+            Constructor::None => "None".into(),
+            Constructor::Ok => format!("Ok({})", parts[0]),
+            Constructor::Err => format!("Err({})", parts[0]),
+            Constructor::Boolean(boolean) => boolean.to_string(),
+            Constructor::Number(interval) => interval.example(),
+            Constructor::Literal(value) => Pattern::Literal(value.clone()).to_string(),
+        }
+    }
+}
+
+/// Numbers which either all match or all don't match each number pattern in the same position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interval {
+    Point(f64),
+    Between(f64, f64), // Doesn't include the ends.
+}
+
+impl Interval {
+    /// Whether the number pattern matches the numbers in the interval.
+    fn is_matched_by(self, pattern: &Pattern) -> bool {
+        match (self, pattern) {
+            (Interval::Point(number), Pattern::Literal(Value::Number(literal))) => {
+                number == *literal
+            }
+            (Interval::Point(number), Pattern::Range { start, end }) => {
+                *start <= number && number < *end
+            }
+            (Interval::Between(low, high), Pattern::Range { start, end }) => {
+                *start <= low && high <= *end
+            }
+            _ => false,
+        }
+    }
+
+    /// A number in the interval, preferring whole numbers.
+    fn example(self) -> String {
+        let number = match self {
+            Interval::Point(number) => number,
+            Interval::Between(f64::NEG_INFINITY, f64::INFINITY) => return "_".into(),
+            Interval::Between(f64::NEG_INFINITY, high) => high.ceil() - 1.0,
+            Interval::Between(low, f64::INFINITY) => low.floor() + 1.0,
+            Interval::Between(low, high) if low.floor() + 1.0 < high => low.floor() + 1.0,
+            Interval::Between(low, high) => (low + high) / 2.0,
+        };
+        Value::Number(number).to_string()
+    }
+}
+
+/// The kinds of values patterns can be written for.
+#[derive(PartialEq)]
+enum Family {
+    Tuple(usize),
+    Option,
+    Result,
+    Boolean,
+    Number,
+    Literal,
+}
+
+/// The family of the values the pattern matches, or None if it matches values of any family.
+fn family(pattern: &Pattern) -> Option<Family> {
+    match pattern {
+        Pattern::Wildcard | Pattern::Variable { .. } | Pattern::Or(_) => None,
+        Pattern::Tuple(patterns) => Some(Family::Tuple(patterns.len())),
+        Pattern::Some(_) | Pattern::Literal(Value::Option(None)) => Some(Family::Option),
+        Pattern::Ok(_) | Pattern::Err(_) => Some(Family::Result),
+        Pattern::Literal(Value::Boolean(_)) => Some(Family::Boolean),
+        Pattern::Literal(Value::Number(_)) | Pattern::Range { .. } => Some(Family::Number),
+        Pattern::Literal(_) => Some(Family::Literal),
+    }
+}
+
+/// Splits the numbers at the ends of the number patterns, so each interval is either all matched or all not matched
+/// by each pattern.
+fn intervals(patterns: &[&Pattern]) -> Vec<Interval> {
+    let mut ends = vec![];
+    for pattern in patterns {
+        match pattern {
+            Pattern::Literal(Value::Number(number)) => ends.push(*number),
+            Pattern::Range { start, end } => ends.extend([*start, *end]),
+            _ => {}
+        }
+    }
+    ends.sort_by(f64::total_cmp);
+    ends.dedup();
+    let mut intervals = vec![];
+    let mut low = f64::NEG_INFINITY;
+    for end in ends {
+        intervals.push(Interval::Between(low, end));
+        intervals.push(Interval::Point(end));
+        low = end;
+    }
+    intervals.push(Interval::Between(low, f64::INFINITY));
+    intervals
+}
+
+/// Every constructor of the family the patterns are from, or None if there are too many to list or the patterns are
+/// from different families.
+fn all_constructors(patterns: &[&Pattern]) -> Option<Vec<Constructor>> {
+    let first_family = family(patterns.first()?)?;
+    if patterns
+        .iter()
+        .any(|pattern| family(pattern).as_ref() != Some(&first_family))
+    {
+        return None;
+    }
+    match first_family {
+        Family::Tuple(length) => Some(vec![Constructor::Tuple(length)]),
+        Family::Option => Some(vec![Constructor::Some, Constructor::None]),
+        Family::Result => Some(vec![Constructor::Ok, Constructor::Err]),
+        Family::Boolean => Some(vec![
+            Constructor::Boolean(true),
+            Constructor::Boolean(false),
+        ]),
+        Family::Number => Some(
+            intervals(patterns)
+                .into_iter()
+                .map(Constructor::Number)
+                .collect(),
+        ),
+        Family::Literal => None,
+    }
+}
+
+/// The constructors of the values the pattern matches, which isn't a wildcard or an or-pattern.
+/// The intervals of number patterns are split at the ends of the other patterns.
+fn constructors(pattern: &Pattern, other_patterns: &[&Pattern]) -> Vec<Constructor> {
+    match pattern {
+        Pattern::Tuple(patterns) => vec![Constructor::Tuple(patterns.len())],
+        Pattern::Some(_) => vec![Constructor::Some],
+        Pattern::Ok(_) => vec![Constructor::Ok],
+        Pattern::Err(_) => vec![Constructor::Err],
+        Pattern::Literal(Value::Option(None)) => vec![Constructor::None],
+        Pattern::Literal(Value::Boolean(boolean)) => vec![Constructor::Boolean(*boolean)],
+        Pattern::Literal(Value::Number(_)) | Pattern::Range { .. } => {
+            let mut patterns = other_patterns.to_vec();
+            patterns.push(pattern);
+            intervals(&patterns)
+                .into_iter()
+                .filter(|interval| interval.is_matched_by(pattern))
+                .map(Constructor::Number)
+                .collect()
+        }
+        Pattern::Literal(value) => vec![Constructor::Literal(value.clone())],
+        Pattern::Wildcard | Pattern::Variable { .. } | Pattern::Or(_) => unreachable!(),
+    }
+}
+
+/// The row for the values of the constructor's kind, with the first pattern replaced by the patterns for the parts,
+/// or None if the first pattern doesn't match values of that kind.
+fn specialize<'a>(
+    row: &[&'a Pattern],
+    constructor: &Constructor,
+    wildcard: &'a Pattern,
+) -> Option<Vec<&'a Pattern>> {
+    let mut specialized_row = match (row[0], constructor) {
+        (Pattern::Wildcard | Pattern::Variable { .. }, _) => vec![wildcard; constructor.arity()],
+        (Pattern::Tuple(patterns), Constructor::Tuple(length)) if patterns.len() == *length => {
+            patterns.iter().collect()
+        }
+        (Pattern::Some(pattern), Constructor::Some)
+        | (Pattern::Ok(pattern), Constructor::Ok)
+        | (Pattern::Err(pattern), Constructor::Err) => vec![&**pattern],
+        (Pattern::Literal(Value::Option(None)), Constructor::None) => vec![],
+        (Pattern::Literal(Value::Boolean(boolean)), Constructor::Boolean(other))
+            if boolean == other =>
+        {
+            vec![]
+        }
+        (
+            pattern @ (Pattern::Literal(Value::Number(_)) | Pattern::Range { .. }),
+            Constructor::Number(interval),
+        ) if interval.is_matched_by(pattern) => vec![],
+        (Pattern::Literal(value), Constructor::Literal(other)) if value == other => vec![],
+        _ => return None,
+    };
+    specialized_row.extend_from_slice(&row[1..]);
+    Some(specialized_row)
+}
+
+/// Replaces each row starting with an or-pattern with a row for each of its patterns.
+fn expand_or_patterns<'a>(matrix: &[Vec<&'a Pattern>]) -> Vec<Vec<&'a Pattern>> {
+    let mut expanded_matrix = vec![];
+    for row in matrix {
+        if let Pattern::Or(patterns) = row[0] {
+            let rows = patterns
+                .iter()
+                .map(|pattern| {
+                    let mut row = row.clone();
+                    row[0] = pattern;
+                    row
+                })
+                .collect::<Vec<_>>();
+            expanded_matrix.extend(expand_or_patterns(&rows));
+        } else {
+            expanded_matrix.push(row.clone());
+        }
+    }
+    expanded_matrix
+}
+
+/// Finds values matched by the row of patterns but by none of the rows of the matrix, which all have the same length.
+/// Returns one of them written as a row of patterns, or None if there aren't any.
+fn useful<'a>(
+    matrix: &[Vec<&'a Pattern>],
+    row: &[&'a Pattern],
+    wildcard: &'a Pattern,
+) -> Option<Vec<String>> {
+    let Some(&first_pattern) = row.first() else {
+        return matrix.is_empty().then(Vec::new);
+    };
+    if let Pattern::Or(patterns) = first_pattern {
+        return patterns.iter().find_map(|pattern| {
+            let mut row = row.to_vec();
+            row[0] = pattern;
+            useful(matrix, &row, wildcard)
+        });
+    }
+    let matrix = expand_or_patterns(matrix);
+    let first_patterns = matrix
+        .iter()
+        .map(|row| row[0])
+        .filter(|pattern| !matches!(pattern, Pattern::Wildcard | Pattern::Variable { .. }))
+        .collect::<Vec<_>>();
+
+    let constructors = match first_pattern {
+        Pattern::Wildcard | Pattern::Variable { .. } => match all_constructors(&first_patterns) {
+            Some(constructors) => constructors,
+            None => {
+                // Some values aren't matched by any of the first patterns, so only the rows starting with a wildcard
+                // can match them.
+                let default_matrix = matrix
+                    .iter()
+                    .filter(|row| matches!(row[0], Pattern::Wildcard | Pattern::Variable { .. }))
+                    .map(|row| row[1..].to_vec())
+                    .collect::<Vec<_>>();
+                let mut witness = useful(&default_matrix, &row[1..], wildcard)?;
+                witness.insert(0, "_".into());
+                return Some(witness);
+            }
+        },
+        pattern => constructors(pattern, &first_patterns),
+    };
+    constructors.into_iter().find_map(|constructor| {
+        let specialized_matrix = matrix
+            .iter()
+            .filter_map(|row| specialize(row, &constructor, wildcard))
+            .collect::<Vec<_>>();
+        let specialized_row = specialize(row, &constructor, wildcard).unwrap();
+        let mut witness = useful(&specialized_matrix, &specialized_row, wildcard)?;
+        let parts = witness.drain(..constructor.arity()).collect::<Vec<_>>();
+        witness.insert(0, constructor.write(&parts));
+        Some(witness)
+    })
+}
@@ -0,0 +1,325 @@
+use std::{
+    fmt::{Display, Write},
+    str::FromStr,
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    messages::{diagnostic, Diagnostic},
+    tokenizer::{quote, Type, Value},
+};
+
+/// How a value is formatted in an interpolated string or by format(), written like 8.2, >10, 08 or x.
+/// The syntax is [[fill]align][0][width][.precision][kind].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FormatSpec {
+    pub fill: Option<char>, // Pads with spaces if None.
+    pub align: Option<Align>,
+    pub zero: bool, // Pad numbers with zeros after their sign.
+    pub width: usize,
+    pub precision: Option<usize>, // Digits after the point of numbers, or the most grapheme clusters of strings.
+    pub kind: FormatKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum FormatKind {
+    #[default]
+    Display,
+    Hex,
+    UpperHex,
+    Binary,
+    Octal,
+    Exponent,
+}
+
+impl FromStr for FormatSpec {
+    type Err = Diagnostic;
+
+    fn from_str(source: &str) -> Result<FormatSpec, Diagnostic> {
+        let error = || diagnostic!(INVALID_FORMAT_SPEC, source);
+        let align = |ch| match ch {
+            '<' => Some(Align::Left),
+            '>' => Some(Align::Right),
+            '^' => Some(Align::Center),
+            _ => None,
+        };
+        let digits = |rest: &mut &str| -> Result<Option<usize>, Diagnostic> {
+            let length = rest
+                .find(|ch: char| !ch.is_ascii_digit())
+                .unwrap_or(rest.len());
+            if length == 0 {
+                return Ok(None);
+            }
+            let number = rest[..length].parse().map_err(|_| error())?;
+            *rest = &rest[length..];
+            Ok(Some(number))
+        };
+
+        let mut spec = FormatSpec::default();
+        let mut rest = source;
+        let mut chars = rest.chars();
+        let (first, second) = (chars.next(), chars.next());
+        if let Some(alignment) = second.and_then(align) {
+            spec.fill = first;
+            spec.align = Some(alignment);
+            rest = &rest[first.unwrap().len_utf8() + 1..];
+        } else if let Some(alignment) = first.and_then(align) {
+            spec.align = Some(alignment);
+            rest = &rest[1..];
+        }
+        if let Some(after_zero) = rest.strip_prefix('0') {
+            spec.zero = true;
+            rest = after_zero;
+        }
+        spec.width = digits(&mut rest)?.unwrap_or(0);
+        if let Some(after_point) = rest.strip_prefix('.') {
+            rest = after_point;
+            spec.precision = Some(digits(&mut rest)?.ok_or_else(error)?);
+        }
+        spec.kind = match rest {
+            "" => FormatKind::Display,
+            "x" => FormatKind::Hex,
+            "X" => FormatKind::UpperHex,
+            "b" => FormatKind::Binary,
+            "o" => FormatKind::Octal,
+            "e" => FormatKind::Exponent,
+            _ => return Err(error()),
+        };
+        Ok(spec)
+    }
+}
+
+/// Writes the spec back the way it's written in programs.
+impl Display for FormatSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(fill) = self.fill {
+            write!(f, "{fill}")?;
+        }
+        match self.align {
+            Some(Align::Left) => write!(f, "<")?,
+            Some(Align::Right) => write!(f, ">")?,
+            Some(Align::Center) => write!(f, "^")?,
+            None => {}
+        }
+        if self.zero {
+            write!(f, "0")?;
+        }
+        if self.width > 0 {
+            write!(f, "{}", self.width)?;
+        }
+        if let Some(precision) = self.precision {
+            write!(f, ".{precision}")?;
+        }
+        let kind = match self.kind {
+            FormatKind::Display => "",
+            FormatKind::Hex => "x",
+            FormatKind::UpperHex => "X",
+            FormatKind::Binary => "b",
+            FormatKind::Octal => "o",
+            FormatKind::Exponent => "e",
+        };
+        write!(f, "{kind}")
+    }
+}
+
+impl FormatSpec {
+    /// Checks that values of the type can be formatted with the spec.
+    /// Zero padding and kinds other than the default are only for numbers, and precision is only for numbers and strings.
+    pub fn check(&self, value_type: &Type) -> Result<(), Diagnostic> {
+        let number_only = self.zero || self.kind != FormatKind::Display;
+        match value_type {
+            Type::Number | Type::Any => Ok(()),
+            Type::String if !number_only => Ok(()),
+            _ if !number_only && self.precision.is_none() => Ok(()),
+            value_type => Err(diagnostic!(FORMAT_SPEC_TYPE_MISMATCH, self, value_type)),
+        }
+    }
+
+    /// Formats the value, which must have a type the spec can be used with.
+    /// Numbers formatted as hex, binary or octal are formatted as whole numbers, with a - if they're negative.
+    pub fn format(&self, value: &Value) -> String {
+        let (sign, body) = match value {
+            &Value::Number(number) => {
+                let whole = (number as i64).unsigned_abs();
+                let body = match (self.kind, self.precision) {
+                    (FormatKind::Display, Some(precision)) => {
+                        format!("{:.precision$}", number.abs())
+                    }
+                    (FormatKind::Display, None) => number.abs().to_string(),
+                    (FormatKind::Hex, _) => format!("{whole:x}"),
+                    (FormatKind::UpperHex, _) => format!("{whole:X}"),
+                    (FormatKind::Binary, _) => format!("{whole:b}"),
+                    (FormatKind::Octal, _) => format!("{whole:o}"),
+                    (FormatKind::Exponent, Some(precision)) => {
+                        format!("{:.precision$e}", number.abs())
+                    }
+                    (FormatKind::Exponent, None) => format!("{:e}", number.abs()),
+                };
+                let negative = match self.kind {
+                    FormatKind::Display | FormatKind::Exponent => {
+                        number.is_sign_negative() && number != 0.0
+                    }
+                    _ => (number as i64) < 0,
+                };
+                (if negative { "-" } else { "" }, body)
+            }
+            Value::String(string) => match self.precision {
+                Some(precision) => ("", string.graphemes(true).take(precision).collect()),
+                None => ("", string.to_string()),
+            },
+            value => ("", value.to_string()),
+        };
+
+        let length = sign.len() + body.graphemes(true).count();
+        let padding = self.width.saturating_sub(length);
+        if self.zero && self.align.is_none() {
+            return format!("{sign}{}{body}", "0".repeat(padding));
+        }
+        let align = self.align.unwrap_or(match value {
+            Value::Number(_) => Align::Right,
+            _ => Align::Left,
+        });
+        let (before, after) = match align {
+            Align::Left => (0, padding),
+            Align::Right => (padding, 0),
+            Align::Center => (padding / 2, padding - padding / 2),
+        };
+        let fill = self.fill.unwrap_or(' ').to_string();
+        format!("{}{sign}{body}{}", fill.repeat(before), fill.repeat(after))
+    }
+}
+
+/// Joins the text of an interpolated string with its values, formatted with their specs.
+/// There's one more string than values, as the text before the first value and after the last can be empty.
+pub fn interpolate(strings: &[String], specs: &[FormatSpec], values: &[Value]) -> String {
+    let mut interpolated = strings[0].clone();
+    for ((spec, value), string) in specs.iter().zip(values).zip(&strings[1..]) {
+        interpolated.push_str(&spec.format(value));
+        interpolated.push_str(string);
+    }
+    interpolated
+}
+
+/// How print and dbg write values. Tuples, records, results and options nested more than the max depth deep are
+/// written as ..., and only the first max width fields of a tuple or record are written, followed by ...
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ValueFormat {
+    pub max_depth: Option<usize>,
+    pub max_width: Option<usize>,
+}
+
+impl ValueFormat {
+    /// Writes the value as print does. Strings inside other values are quoted, so ("a, b", "c") can be told apart
+    /// from ("a", "b", "c"), but a string on its own is written as it is.
+    pub fn display(&self, value: &Value) -> String {
+        let mut text = String::new();
+        match value {
+            Value::String(string) => text.push_str(string),
+            value => self.write(&mut text, value, 0, false),
+        }
+        text
+    }
+
+    /// Writes the value as dbg and the debugger do, with strings quoted even on their own, and
+    /// functions, references, iterators, channels and tasks written as their type, like fn(number) -> number.
+    pub fn debug(&self, value: &Value) -> String {
+        let mut text = String::new();
+        self.write(&mut text, value, 0, true);
+        text
+    }
+
+    fn write(&self, text: &mut String, value: &Value, depth: usize, debug: bool) {
+        let nested = matches!(
+            value,
+            Value::Tuple(_) | Value::Record(_) | Value::Result(_) | Value::Option(Some(_))
+        );
+        if nested && self.max_depth.is_some_and(|max_depth| depth >= max_depth) {
+            text.push_str("...");
+            return;
+        }
+        match value {
+            Value::String(string) => text.push_str(&quote(string)),
+            Value::Tuple(values) => {
+                self.write_fields(text, values.iter().map(|value| (None, value)), depth, debug)
+            }
+            Value::Record(fields) => self.write_fields(
+                text,
+                fields
+                    .iter()
+                    .map(|(name, value)| (Some(name.as_str()), value)),
+                depth,
+                debug,
+            ),
+            Value::Result(result) => {
+                let (name, value) = match &**result {
+                    Ok(value) => ("Ok", value),
+                    Err(error) => ("Err", error),
+                };
+                text.push_str(name);
+                text.push('(');
+                self.write(text, value, depth + 1, debug);
+                text.push(')');
+            }
+            Value::Option(Some(value)) => {
+                text.push_str("Some(");
+                self.write(text, value, depth + 1, debug);
+                text.push(')');
+            }
+            Value::Reference(_)
+            | Value::Function(_)
+            | Value::Iterator(_)
+            | Value::Channel(_)
+            | Value::Task(_)
+                if debug =>
+            {
+                write!(text, "{}", value.value_type()).unwrap()
+            }
+            Value::Void => text.push_str("void"),
+            Value::Number(number) => write!(text, "{number}").unwrap(),
+            Value::Boolean(boolean) => write!(text, "{boolean}").unwrap(),
+            Value::Option(None) => text.push_str("None"),
+            Value::Reference(_) => text.push_str("reference"),
+            Value::Function(_) => text.push_str("function"),
+            Value::Iterator(_) => text.push_str("iterator"),
+            Value::Channel(_) => text.push_str("channel"),
+            Value::Task(_) => text.push_str("task"),
+        }
+    }
+
+    /// Writes the fields of a tuple or record, with the names of a record's fields.
+    fn write_fields<'a>(
+        &self,
+        text: &mut String,
+        fields: impl ExactSizeIterator<Item = (Option<&'a str>, &'a Value)>,
+        depth: usize,
+        debug: bool,
+    ) {
+        let count = fields.len();
+        let shown = self
+            .max_width
+            .map_or(count, |max_width| max_width.min(count));
+        text.push('(');
+        for (index, (name, value)) in fields.take(shown).enumerate() {
+            if index > 0 {
+                text.push_str(", ");
+            }
+            if let Some(name) = name {
+                write!(text, "{name}: ").unwrap();
+            }
+            self.write(text, value, depth + 1, debug);
+        }
+        if shown < count {
+            text.push_str(if shown > 0 { ", ...)" } else { "...)" });
+        } else {
+            text.push(')');
+        }
+    }
+}
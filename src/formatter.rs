@@ -0,0 +1,479 @@
+use std::{collections::VecDeque, fmt::Write};
+
+use crate::{
+    parser::{Expression, ExpressionType, Statement, StatementType},
+    syntax_tree::{SyntaxTree, TriviaKind},
+    tokenizer::Type,
+    EvalError,
+};
+
+/// How many spaces each level of blocks is indented by.
+const INDENT: &str = "    ";
+
+/// Reprints a program with canonical indentation and spacing: one statement per line, blocks broken over lines
+/// and single spaces around binary operators. Comments and single blank lines between statements are kept.
+// TODO: Break expressions which are too long over several lines.
+pub fn format_program(program: &str) -> Result<String, EvalError> {
+    let syntax_tree = SyntaxTree::parse(program)?;
+
+    let mut formatter = Formatter {
+        formatted: String::new(),
+        comments: syntax_tree
+            .comments()
+            .into_iter()
+            .map(|(comment, trailing)| Comment {
+                text: comment.text.trim_end().to_string(),
+                line: comment.line,
+                trailing,
+            })
+            .collect(),
+        last_line: None,
+    };
+    if let Some(shebang) = syntax_tree
+        .tokens
+        .first()
+        .map_or(&syntax_tree.trailing_trivia, |token| &token.leading_trivia)
+        .iter()
+        .find(|trivia| trivia.kind == TriviaKind::Shebang)
+    {
+        formatter.formatted.push_str(shebang.text.trim_end());
+        formatter.formatted.push('\n');
+        formatter.last_line = Some(shebang.line);
+    }
+    formatter.write_statements(&syntax_tree.statements, 0, None);
+    formatter.write_comments_before(usize::MAX, 0);
+    Ok(formatter.formatted)
+}
+
+struct Comment {
+    text: String,
+    line: usize,
+    trailing: bool, // Does the comment follow code on the same line?
+}
+
+struct Formatter {
+    formatted: String,
+    comments: VecDeque<Comment>, // The comments which haven't been written yet.
+    last_line: Option<usize>,    // The last source line written, or None at the start of a block.
+}
+
+impl Formatter {
+    /// Starts a new line at the depth, keeping a blank line before it if the source had one.
+    fn start_line(&mut self, line: usize, depth: usize) {
+        if self.last_line.is_some_and(|last_line| line > last_line + 1) {
+            self.formatted.push('\n');
+        }
+        self.formatted.push_str(&INDENT.repeat(depth));
+    }
+
+    /// Writes the comments before the line on lines of their own.
+    fn write_comments_before(&mut self, line: usize, depth: usize) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|comment| comment.line < line)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            self.start_line(comment.line, depth);
+            self.formatted.push_str(&comment.text);
+            self.formatted.push('\n');
+            self.last_line = Some(comment.line);
+        }
+    }
+
+    /// Ends the line, after the comment which followed code on it.
+    fn end_line(&mut self, line: usize) {
+        if self
+            .comments
+            .front()
+            .is_some_and(|comment| comment.trailing && comment.line == line)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            write!(self.formatted, " {}", comment.text).unwrap();
+        }
+        self.formatted.push('\n');
+        self.last_line = Some(line);
+    }
+
+    /// Writes the doc comments of the declaration which starts on the line.
+    fn write_doc(&mut self, doc: &str, line: usize, depth: usize) {
+        let doc_lines = doc.split('\n').collect::<Vec<_>>();
+        self.start_line(line.saturating_sub(doc_lines.len()), depth);
+        for (index, doc_line) in doc_lines.into_iter().enumerate() {
+            if index > 0 {
+                self.formatted.push_str(&INDENT.repeat(depth));
+            }
+            writeln!(self.formatted, "///{}", doc_line.trim_end()).unwrap();
+        }
+        self.last_line = Some(line - 1);
+    }
+
+    /// Writes the statements of a block which ends on the line, or of the program.
+    fn write_statements(
+        &mut self,
+        statements: &[Statement],
+        depth: usize,
+        block_end_line: Option<usize>,
+    ) {
+        for (index, statement) in statements.iter().enumerate() {
+            self.write_comments_before(statement.lines.0, depth);
+            if let StatementType::VariableDeclaration { doc: Some(doc), .. }
+            | StatementType::FunctionDeclaration { doc: Some(doc), .. } = &statement.statement
+            {
+                self.write_doc(doc, statement.lines.0, depth);
+            }
+            self.start_line(statement.lines.0, depth);
+            self.write_statement(statement, depth);
+            let end_line = end_line(statement);
+            // A comment after several statements on a line belongs after the last one.
+            let next_line = statements
+                .get(index + 1)
+                .map_or(block_end_line, |next_statement| {
+                    Some(next_statement.lines.0)
+                });
+            if next_line == Some(end_line) {
+                self.formatted.push('\n');
+                self.last_line = Some(end_line);
+            } else {
+                self.end_line(end_line);
+            }
+        }
+    }
+
+    /// Writes the expression of a statement. Block and if expressions are broken over lines like block and if statements.
+    // TODO: Break block expressions inside other expressions over lines too.
+    fn write_expression(&mut self, expression: &Expression, depth: usize) {
+        let (statements, value) = match &expression.expression_type {
+            ExpressionType::Block { statements, value } => (statements, value),
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => {
+                write!(self.formatted, "if {condition} ").unwrap();
+                self.write_expression(then_expression, depth);
+                self.formatted.push_str(" else ");
+                self.write_expression(else_expression, depth);
+                return;
+            }
+            ExpressionType::Loop(body) => {
+                self.formatted.push_str("loop ");
+                self.write_statement(body, depth);
+                return;
+            }
+            _ => {
+                write!(self.formatted, "{expression}").unwrap();
+                return;
+            }
+        };
+        let (start_line, end_line) = expression.lines;
+        self.formatted.push('{');
+        let first_line = statements
+            .first()
+            .map_or(value.lines.0, |statement| statement.lines.0);
+        if first_line > start_line {
+            self.end_line(start_line);
+        } else {
+            self.formatted.push('\n');
+        }
+        self.last_line = None;
+        self.write_statements(statements, depth + 1, Some(value.lines.0));
+        self.write_comments_before(value.lines.0, depth + 1);
+        self.start_line(value.lines.0, depth + 1);
+        self.write_expression(value, depth + 1);
+        if value.lines.1 == end_line {
+            self.formatted.push('\n');
+            self.last_line = Some(end_line);
+        } else {
+            self.end_line(value.lines.1);
+        }
+        self.write_comments_before(end_line, depth + 1);
+        self.formatted.push_str(&INDENT.repeat(depth));
+        self.formatted.push('}');
+    }
+
+    /// Writes the statement, without indenting its first line or ending its last.
+    fn write_statement(&mut self, statement: &Statement, depth: usize) {
+        match &statement.statement {
+            StatementType::VariableDeclaration {
+                variable,
+                variable_type,
+                value,
+                is_static,
+                ..
+            } => {
+                if *is_static {
+                    write!(self.formatted, "static ").unwrap();
+                }
+                write!(self.formatted, "let {variable}").unwrap();
+                if let Some(variable_type) = variable_type {
+                    write!(self.formatted, ": {variable_type}").unwrap();
+                }
+                self.formatted.push_str(" = ");
+                self.write_expression(value, depth);
+                self.formatted.push(';');
+            }
+            StatementType::FunctionDeclaration {
+                name,
+                parameters,
+                return_type,
+                body,
+                ..
+            } => {
+                let parameters = parameters
+                    .iter()
+                    .map(|(name, _, parameter_type)| format!("{name}: {parameter_type}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(self.formatted, "fn {name}({parameters})").unwrap();
+                if *return_type != Type::Void {
+                    write!(self.formatted, " -> {return_type}").unwrap();
+                }
+                self.formatted.push(' ');
+                self.write_statement(body, depth);
+            }
+            StatementType::Return(Some(expression)) => {
+                self.formatted.push_str("return ");
+                self.write_expression(expression, depth);
+                self.formatted.push(';');
+            }
+            StatementType::Return(None) => self.formatted.push_str("return;"),
+            StatementType::Break(Some(expression)) => {
+                self.formatted.push_str("break ");
+                self.write_expression(expression, depth);
+                self.formatted.push(';');
+            }
+            StatementType::Break(None) => self.formatted.push_str("break;"),
+            StatementType::Raise(expression) => {
+                self.formatted.push_str("raise ");
+                self.write_expression(expression, depth);
+                self.formatted.push(';');
+            }
+            StatementType::Expression(expression) => {
+                self.write_expression(expression, depth);
+                // Like while statements, loop statements don't need a semicolon.
+                if !matches!(expression.expression_type, ExpressionType::Loop(_)) {
+                    self.formatted.push(';');
+                }
+            }
+            StatementType::Block(statements) => {
+                let has_comments = self
+                    .comments
+                    .front()
+                    .is_some_and(|comment| comment.line < statement.lines.1);
+                if statements.is_empty() && !has_comments {
+                    self.formatted.push_str("{}");
+                    return;
+                }
+                self.formatted.push('{');
+                let first_line = statements
+                    .first()
+                    .map_or(statement.lines.1, |statement| statement.lines.0);
+                if first_line > statement.lines.0 {
+                    self.end_line(statement.lines.0);
+                } else {
+                    self.formatted.push('\n');
+                }
+                self.last_line = None;
+                self.write_statements(statements, depth + 1, Some(statement.lines.1));
+                self.write_comments_before(statement.lines.1, depth + 1);
+                self.formatted.push_str(&INDENT.repeat(depth));
+                self.formatted.push('}');
+            }
+            StatementType::If {
+                expression,
+                then_statement,
+                else_statement,
+            } => {
+                write!(self.formatted, "if {expression} ").unwrap();
+                self.write_statement(then_statement, depth);
+                if let Some(else_statement) = else_statement {
+                    self.formatted.push_str(" else ");
+                    self.write_statement(else_statement, depth);
+                }
+            }
+            StatementType::While {
+                expression,
+                statement,
+            } => {
+                write!(self.formatted, "while {expression} ").unwrap();
+                self.write_statement(statement, depth);
+            }
+            StatementType::For {
+                variable,
+                expression,
+                statement,
+                ..
+            } => {
+                write!(self.formatted, "for {variable} in {expression} ").unwrap();
+                self.write_statement(statement, depth);
+            }
+            StatementType::Try {
+                statement,
+                variable,
+                catch_statement,
+                ..
+            } => {
+                self.formatted.push_str("try ");
+                self.write_statement(statement, depth);
+                write!(self.formatted, " catch {variable} ").unwrap();
+                self.write_statement(catch_statement, depth);
+            }
+            StatementType::Match { expression, arms } => {
+                write!(self.formatted, "match {expression} {{").unwrap();
+                let first_line = arms.first().map_or(statement.lines.1, |arm| arm.lines.0);
+                if first_line > statement.lines.0 {
+                    self.end_line(statement.lines.0);
+                } else {
+                    self.formatted.push('\n');
+                }
+                self.last_line = None;
+                for (index, arm) in arms.iter().enumerate() {
+                    self.write_comments_before(arm.lines.0, depth + 1);
+                    self.start_line(arm.lines.0, depth + 1);
+                    write!(self.formatted, "{arm} ").unwrap();
+                    self.write_statement(&arm.statement, depth + 1);
+                    let next_line = arms
+                        .get(index + 1)
+                        .map_or(statement.lines.1, |next_arm| next_arm.lines.0);
+                    if next_line == arm.lines.1 {
+                        self.formatted.push('\n');
+                        self.last_line = Some(arm.lines.1);
+                    } else {
+                        self.end_line(arm.lines.1);
+                    }
+                }
+                self.write_comments_before(statement.lines.1, depth + 1);
+                self.formatted.push_str(&INDENT.repeat(depth));
+                self.formatted.push('}');
+            }
+            StatementType::Test { name, body } => {
+                write!(self.formatted, "test \"{name}\" ").unwrap();
+                self.write_statement(body, depth);
+            }
+            StatementType::ImportNative(name) => {
+                write!(self.formatted, "import native \"{name}\";").unwrap();
+            }
+        }
+    }
+}
+
+/// The last line of the statement. The lines of while and for statements stop before their body.
+fn end_line(statement: &Statement) -> usize {
+    match &statement.statement {
+        StatementType::While { statement, .. } | StatementType::For { statement, .. } => {
+            end_line(statement)
+        }
+        _ => statement.lines.1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_program;
+    use crate::{parser::parse, tokenizer::tokenize};
+
+    /// Writes the program's statements one per line, leaving out their lines and formatting.
+    fn statements(program: &str) -> String {
+        parse(&tokenize(program).unwrap())
+            .unwrap()
+            .iter()
+            .map(|statement| format!("{statement}\n"))
+            .collect()
+    }
+
+    const MESSY: &str = "let   a=1+2*3;
+fn add(x:number,y:number)->number{return x+y;}
+if a>3{print(add(a,1));}else{print(\"small\");}
+
+
+while a<10 {a=a+1;}
+let t = (1,\"two\");
+match t { case (1, s) => { print(s); } case _ => {} }
+";
+
+    #[test]
+    fn formats_spacing_and_blocks() {
+        assert_eq!(
+            format_program(MESSY).unwrap(),
+            "let a = 1 + 2 * 3;
+fn add(x: number, y: number) -> number {
+    return x + y;
+}
+if a > 3 {
+    print(add(a, 1));
+} else {
+    print(\"small\");
+}
+
+while a < 10 {
+    a = a + 1;
+}
+let t = (1, \"two\");
+match t {
+    case (1, s) => {
+        print(s);
+    }
+    case _ => {}
+}
+"
+        );
+    }
+
+    #[test]
+    fn formatting_twice_changes_nothing() {
+        let formatted = format_program(MESSY).unwrap();
+        assert_eq!(format_program(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn keeps_the_meaning_of_programs() {
+        for program in [
+            MESSY,
+            "let a = -(1 - 2) - 3; let b = !true || false && a < 2;",
+            "fn f() -> number { let x = loop { break 1; }; return if x > 0 { x } else { 0 }; }",
+            "let r = (x: 1, y: \"a\\n\"); print(\"{r.x} and {r.y}\");",
+        ] {
+            let formatted = format_program(program).unwrap();
+            assert_eq!(statements(&formatted), statements(program), "{formatted}");
+        }
+    }
+
+    #[test]
+    fn does_not_format_programs_which_do_not_parse() {
+        assert!(format_program("if true { print(1);").is_err());
+    }
+
+    #[test]
+    fn keeps_comments_and_blank_lines() {
+        let program = "#!/usr/bin/env busheye
+// Counts up.
+let a=0; // Start.
+
+
+/// Adds one.
+fn up(x:number)->number{
+// Inside.
+return x+1; // Done.
+}
+while a<3{a=up(a);}
+// The end.
+";
+        let formatted = "#!/usr/bin/env busheye
+// Counts up.
+let a = 0; // Start.
+
+/// Adds one.
+fn up(x: number) -> number {
+    // Inside.
+    return x + 1; // Done.
+}
+while a < 3 {
+    a = up(a);
+}
+// The end.
+";
+        assert_eq!(format_program(program).unwrap(), formatted);
+        assert_eq!(format_program(formatted).unwrap(), formatted);
+    }
+}
@@ -0,0 +1,349 @@
+use std::rc::Rc;
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{
+    format::FormatSpec,
+    parser::{
+        BinaryOperation, Expression, ExpressionType, Field, MatchArm, Pattern, Statement,
+        StatementType, UnaryOperation,
+    },
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+};
+
+/// How deeply generated statements and expressions nest, so programs stay small enough to run quickly.
+const MAX_DEPTH: usize = 4;
+
+// Generated programs only use a few names, so they often refer to variables and functions they declared.
+const NAMES: &[&str] = &["a", "b", "c", "f", "g", "print", "len"];
+const PARAMETERS: &[&str] = &["p", "q"];
+const FIELDS: &[&str] = &["x", "y"];
+// Strings without quotes, escapes or interpolations, which are written the same way they're printed.
+const STRINGS: &[&str] = &["", "s", "hello world", "é🦀"];
+
+const UNARY_OPERATIONS: &[UnaryOperation] = &[
+    UnaryOperation::Minus,
+    UnaryOperation::Not,
+    UnaryOperation::BitwiseNot,
+    UnaryOperation::Reference,
+    UnaryOperation::Dereference,
+];
+const BINARY_OPERATIONS: &[BinaryOperation] = &[
+    BinaryOperation::Add,
+    BinaryOperation::Subtract,
+    BinaryOperation::Multiply,
+    BinaryOperation::Divide,
+    BinaryOperation::Equal,
+    BinaryOperation::NotEqual,
+    BinaryOperation::Less,
+    BinaryOperation::LessEqual,
+    BinaryOperation::Greater,
+    BinaryOperation::GreaterEqual,
+    BinaryOperation::And,
+    BinaryOperation::Or,
+    BinaryOperation::BitwiseAnd,
+    BinaryOperation::BitwiseOr,
+    BinaryOperation::BitwiseXor,
+    BinaryOperation::ShiftLeft,
+    BinaryOperation::ShiftRight,
+];
+
+/// Generates expressions which print as source the parser reads back as the same expression.
+/// Names are unresolved, as they are straight after parsing.
+impl<'a> Arbitrary<'a> for Expression {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Expression> {
+        expression(u, 0)
+    }
+}
+
+/// Generates statements which print as source the parser reads back as the same statement.
+impl<'a> Arbitrary<'a> for Statement {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Statement> {
+        statement(u, 0)
+    }
+}
+
+fn name(u: &mut Unstructured, names: &[&str]) -> Result<Symbol> {
+    Ok(Symbol::intern(u.choose(names)?))
+}
+
+fn new_expression(expression_type: ExpressionType) -> Expression {
+    Expression {
+        expression_type,
+        lines: (1, 1),
+    }
+}
+
+fn new_statement(statement: StatementType) -> Statement {
+    Statement {
+        statement,
+        lines: (1, 1),
+    }
+}
+
+fn literal(u: &mut Unstructured) -> Result<Value> {
+    Ok(match u.int_in_range(0..=3)? {
+        // Quarters are printed exactly.
+        0 => Value::Number(u.int_in_range(0..=400)? as f64 / 4.0),
+        1 => Value::String((*u.choose(STRINGS)?).into()),
+        2 => Value::Boolean(u.arbitrary()?),
+        _ => Value::Option(None),
+    })
+}
+
+fn variable(u: &mut Unstructured) -> Result<Expression> {
+    Ok(new_expression(ExpressionType::Variable {
+        name: name(u, NAMES)?,
+        shadow_id: None,
+        parent_height: None,
+        slot: None,
+    }))
+}
+
+/// An expression which can be an operand, the callee of a call or a condition. Expressions which would be read
+/// back differently there, like binary operations and blocks, are put in parentheses.
+fn operand(u: &mut Unstructured, depth: usize) -> Result<Expression> {
+    let expression = expression(u, depth)?;
+    Ok(match expression.expression_type {
+        ExpressionType::Unary { .. }
+        | ExpressionType::Binary { .. }
+        | ExpressionType::Block { .. }
+        | ExpressionType::If { .. }
+        | ExpressionType::Loop(_)
+        | ExpressionType::Propagate(_) => {
+            new_expression(ExpressionType::Grouping(Box::new(expression)))
+        }
+        _ => expression,
+    })
+}
+
+fn block_expression(u: &mut Unstructured, depth: usize) -> Result<Expression> {
+    Ok(new_expression(ExpressionType::Block {
+        statements: statements(u, depth)?,
+        value: Box::new(operand(u, depth)?),
+    }))
+}
+
+fn expression(u: &mut Unstructured, depth: usize) -> Result<Expression> {
+    if depth >= MAX_DEPTH {
+        return match u.arbitrary()? {
+            true => Ok(new_expression(ExpressionType::Literal(literal(u)?))),
+            false => variable(u),
+        };
+    }
+    let depth = depth + 1;
+    let boxed = |u: &mut Unstructured| operand(u, depth).map(Box::new);
+    Ok(new_expression(match u.int_in_range(0..=16)? {
+        0 => ExpressionType::Literal(literal(u)?),
+        1 => return variable(u),
+        2 => ExpressionType::Unary {
+            operation: *u.choose(UNARY_OPERATIONS)?,
+            expression: boxed(u)?,
+        },
+        3 => ExpressionType::Binary {
+            operation: *u.choose(BINARY_OPERATIONS)?,
+            left_expression: boxed(u)?,
+            right_expression: boxed(u)?,
+        },
+        4 => ExpressionType::Grouping(Box::new(expression(u, depth)?)),
+        // Tuples have at least two elements.
+        5 => ExpressionType::Tuple(
+            (0..u.int_in_range(2..=3)?)
+                .map(|_| expression(u, depth))
+                .collect::<Result<_>>()?,
+        ),
+        6 => ExpressionType::Record(
+            FIELDS
+                .iter()
+                .map(|field| Ok((Symbol::intern(field), expression(u, depth)?)))
+                .collect::<Result<_>>()?,
+        ),
+        // A field of a field would be read as a number, like a.0.1, so fields are only taken of variables.
+        7 => ExpressionType::TupleAccess {
+            expression: Box::new(variable(u)?),
+            field: match u.arbitrary()? {
+                true => Field::Index(u.int_in_range(0..=2)?),
+                false => Field::Name {
+                    name: name(u, FIELDS)?,
+                    index: None,
+                },
+            },
+        },
+        8 => ExpressionType::FunctionCall {
+            function: Box::new(variable(u)?),
+            arguments: (0..u.int_in_range(0..=2)?)
+                .map(|_| expression(u, depth))
+                .collect::<Result<_>>()?,
+        },
+        // Values with colons, braces or quotes in them can't be interpolated, as they end the value or start its spec.
+        9 => {
+            let values = (0..u.int_in_range(1..=2)?)
+                .map(|_| variable(u))
+                .collect::<Result<Vec<_>>>()?;
+            ExpressionType::Interpolation {
+                strings: (0..=values.len())
+                    .map(|_| Ok((*u.choose(STRINGS)?).into()))
+                    .collect::<Result<_>>()?,
+                specs: vec![FormatSpec::default(); values.len()],
+                values,
+            }
+        }
+        10 => return block_expression(u, depth),
+        11 => ExpressionType::If {
+            condition: boxed(u)?,
+            then_expression: Box::new(block_expression(u, depth)?),
+            else_expression: Box::new(block_expression(u, depth)?),
+        },
+        12 => ExpressionType::Loop(Box::new(block(u, depth)?)),
+        13 => ExpressionType::Ok(Box::new(expression(u, depth)?)),
+        14 => ExpressionType::Err(Box::new(expression(u, depth)?)),
+        15 => ExpressionType::Propagate(boxed(u)?),
+        _ => ExpressionType::Some(Box::new(expression(u, depth)?)),
+    }))
+}
+
+fn statements(u: &mut Unstructured, depth: usize) -> Result<Vec<Statement>> {
+    if depth >= MAX_DEPTH {
+        return Ok(vec![]);
+    }
+    (0..u.int_in_range(0..=3)?)
+        .map(|_| statement(u, depth))
+        .collect()
+}
+
+fn block(u: &mut Unstructured, depth: usize) -> Result<Statement> {
+    Ok(new_statement(StatementType::Block(statements(u, depth)?)))
+}
+
+fn simple_type(u: &mut Unstructured) -> Result<Type> {
+    Ok(match u.int_in_range(0..=3)? {
+        0 => Type::Number,
+        1 => Type::String,
+        2 => Type::Boolean,
+        _ => Type::Option(Box::new(Type::Number)),
+    })
+}
+
+fn pattern(u: &mut Unstructured, depth: usize) -> Result<Pattern> {
+    if depth >= MAX_DEPTH {
+        return Ok(Pattern::Wildcard);
+    }
+    let depth = depth + 1;
+    Ok(match u.int_in_range(0..=7)? {
+        0 => Pattern::Wildcard,
+        1 => Pattern::Variable {
+            name: name(u, PARAMETERS)?,
+            shadow_id: None,
+        },
+        2 => Pattern::Literal(literal(u)?),
+        3 => Pattern::Tuple(vec![pattern(u, depth)?, pattern(u, depth)?]),
+        4 => Pattern::Some(Box::new(pattern(u, depth)?)),
+        5 => Pattern::Ok(Box::new(pattern(u, depth)?)),
+        6 => Pattern::Err(Box::new(pattern(u, depth)?)),
+        _ => {
+            let start = u.int_in_range(0..=10)?;
+            Pattern::Range {
+                start: start as f64,
+                end: u.int_in_range(start..=20)? as f64,
+            }
+        }
+    })
+}
+
+fn statement(u: &mut Unstructured, depth: usize) -> Result<Statement> {
+    let depth = depth + 1;
+    Ok(new_statement(match u.int_in_range(0..=11)? {
+        0 => StatementType::VariableDeclaration {
+            doc: None,
+            variable: name(u, NAMES)?,
+            variable_type: match u.arbitrary()? {
+                true => Some(simple_type(u)?),
+                false => None,
+            },
+            value: expression(u, depth)?,
+            is_static: false,
+            shadow_id: None,
+            slot: None,
+        },
+        1 => {
+            let parameter_count = u.int_in_range(0..=PARAMETERS.len())?;
+            StatementType::FunctionDeclaration {
+                doc: None,
+                name: name(u, NAMES)?,
+                parameters: PARAMETERS[..parameter_count]
+                    .iter()
+                    .map(|parameter| Ok((Symbol::intern(parameter), None, simple_type(u)?)))
+                    .collect::<Result<_>>()?,
+                return_type: match u.arbitrary()? {
+                    true => simple_type(u)?,
+                    false => Type::Void,
+                },
+                body: Rc::new(block(u, depth)?),
+                shadow_id: None,
+                slot: None,
+            }
+        }
+        2 => StatementType::Return(match u.arbitrary()? {
+            true => Some(expression(u, depth)?),
+            false => None,
+        }),
+        // Expression statements which start with { or if would be read as statements.
+        3 => StatementType::Expression(match u.arbitrary()? {
+            true => new_expression(ExpressionType::FunctionCall {
+                function: Box::new(variable(u)?),
+                arguments: (0..u.int_in_range(0..=2)?)
+                    .map(|_| expression(u, depth))
+                    .collect::<Result<_>>()?,
+            }),
+            false => new_expression(ExpressionType::Binary {
+                operation: BinaryOperation::Assignment,
+                left_expression: Box::new(variable(u)?),
+                right_expression: Box::new(expression(u, depth)?),
+            }),
+        }),
+        4 => return block(u, depth),
+        5 => StatementType::If {
+            expression: operand(u, depth)?,
+            then_statement: Box::new(block(u, depth)?),
+            else_statement: match u.arbitrary()? {
+                true => Some(Box::new(block(u, depth)?)),
+                false => None,
+            },
+        },
+        6 => StatementType::While {
+            expression: operand(u, depth)?,
+            statement: Box::new(block(u, depth)?),
+        },
+        7 => StatementType::For {
+            variable: name(u, NAMES)?,
+            shadow_id: None,
+            expression: operand(u, depth)?,
+            statement: Box::new(block(u, depth)?),
+        },
+        8 => StatementType::Break(None),
+        9 => StatementType::Raise(expression(u, depth)?),
+        10 => StatementType::Try {
+            statement: Box::new(block(u, depth)?),
+            variable: name(u, NAMES)?,
+            shadow_id: None,
+            catch_statement: Box::new(block(u, depth)?),
+        },
+        _ => StatementType::Match {
+            expression: operand(u, depth)?,
+            arms: (0..u.int_in_range(1..=3)?)
+                .map(|_| {
+                    Ok(MatchArm {
+                        pattern: pattern(u, depth)?,
+                        guard: match u.arbitrary()? {
+                            true => Some(operand(u, depth)?),
+                            false => None,
+                        },
+                        statement: block(u, depth)?,
+                        lines: (1, 1),
+                    })
+                })
+                .collect::<Result<_>>()?,
+        },
+    }))
+}
@@ -0,0 +1,118 @@
+use std::{cell::Cell, rc::Rc};
+
+/// The fewest environments allocated between two collections by default.
+pub const DEFAULT_GARBAGE_COLLECTION_THRESHOLD: usize = 100;
+
+/// Decides when a backend frees unreachable environments.
+/// Functions refer to the environment they were defined in by ID instead of an Rc,
+/// so cycles (like a recursive function stored in the environment it closes over)
+/// are freed by tracing from the roots, which reference counting couldn't do.
+pub struct GarbageCollectionSchedule {
+    threshold: usize,
+    countdown: usize,          // Allocations left until the next collection.
+    requested: Rc<Cell<bool>>, // Set by gc() to collect as soon as possible.
+}
+
+impl Default for GarbageCollectionSchedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GarbageCollectionSchedule {
+    pub fn new() -> GarbageCollectionSchedule {
+        GarbageCollectionSchedule {
+            threshold: DEFAULT_GARBAGE_COLLECTION_THRESHOLD,
+            countdown: DEFAULT_GARBAGE_COLLECTION_THRESHOLD,
+            requested: Rc::new(Cell::new(false)),
+        }
+    }
+
+    /// Sets the fewest allocations between two collections. A threshold of 1 collects on every allocation.
+    pub fn set_threshold(&mut self, threshold: usize) {
+        self.threshold = threshold.max(1);
+        self.countdown = self.countdown.min(self.threshold);
+    }
+
+    /// Returns a flag which requests a collection when set.
+    pub fn request_handle(&self) -> Rc<Cell<bool>> {
+        self.requested.clone()
+    }
+
+    pub fn requested(&self) -> bool {
+        self.requested.get()
+    }
+
+    /// Counts an allocation. Returns true if the garbage collector should run before it.
+    pub fn allocate(&mut self) -> bool {
+        self.countdown = self.countdown.saturating_sub(1);
+        self.countdown == 0 || self.requested.get()
+    }
+
+    /// Called after a collection with the number of environments still alive.
+    /// We don't collect again until we've allocated as many environments as are alive,
+    /// so the time spent collecting stays proportional to the time spent allocating.
+    pub fn collected(&mut self, live_environments: usize) {
+        self.countdown = live_environments.max(self.threshold);
+        self.requested.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GarbageCollectionSchedule;
+    use crate::{Backend, Interpreter};
+
+    #[test]
+    fn collects_after_the_threshold_and_as_many_allocations_as_are_alive() {
+        let mut schedule = GarbageCollectionSchedule::new();
+        schedule.set_threshold(3);
+        assert!(!schedule.allocate());
+        assert!(!schedule.allocate());
+        assert!(schedule.allocate());
+        schedule.collected(5);
+        for _ in 0..4 {
+            assert!(!schedule.allocate());
+        }
+        assert!(schedule.allocate());
+    }
+
+    #[test]
+    fn gc_requests_a_collection() {
+        let mut schedule = GarbageCollectionSchedule::new();
+        schedule.request_handle().set(true);
+        assert!(schedule.allocate());
+        schedule.collected(0);
+        assert!(!schedule.requested());
+        assert!(!schedule.allocate());
+    }
+
+    /// Each block declares a recursive function, which refers to the block's environment while the environment
+    /// holds it, so only tracing frees them.
+    const CYCLES: &str = "let i = 0;
+while i < 200 {
+    {
+        fn count(n: number) -> number {
+            if n == 0 { return 0; }
+            return count(n - 1);
+        }
+        count(3);
+    }
+    i = i + 1;
+}";
+
+    #[test]
+    fn frees_cycles() {
+        for backend in [Backend::TreeWalker, Backend::Vm] {
+            let mut interpreter = Interpreter::with_backend(backend);
+            interpreter.set_gc_threshold(10);
+            interpreter.eval(CYCLES).unwrap();
+            assert!(interpreter.stats().garbage_collections > 0, "{backend:?}");
+        }
+        let mut interpreter = Interpreter::new();
+        interpreter.set_gc_threshold(10);
+        interpreter.eval(CYCLES).unwrap();
+        interpreter.collect_garbage();
+        assert_eq!(interpreter.variables.environments.len(), 1);
+    }
+}
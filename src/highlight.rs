@@ -0,0 +1,220 @@
+use std::{fmt::Write, ops::Range};
+
+use crate::{
+    syntax_tree::{tokenize_lossless, Trivia, TriviaKind},
+    tokenizer::{TokenType, Value},
+    EvalError,
+};
+
+/// What a span of source is, for syntax highlighting.
+/// The kinds other than Punctuation match semantic token types of the Language Server Protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SemanticTokenKind {
+    Keyword,
+    Type,
+    Function,
+    Parameter,
+    Variable,
+    Property,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment,
+}
+
+impl SemanticTokenKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SemanticTokenKind::Keyword => "keyword",
+            SemanticTokenKind::Type => "type",
+            SemanticTokenKind::Function => "function",
+            SemanticTokenKind::Parameter => "parameter",
+            SemanticTokenKind::Variable => "variable",
+            SemanticTokenKind::Property => "property",
+            SemanticTokenKind::Number => "number",
+            SemanticTokenKind::String => "string",
+            SemanticTokenKind::Operator => "operator",
+            SemanticTokenKind::Punctuation => "punctuation",
+            SemanticTokenKind::Comment => "comment",
+        }
+    }
+}
+
+/// A classified span of source.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SemanticToken {
+    pub kind: SemanticTokenKind,
+    pub range: Range<usize>, // The byte offsets of the span in the program.
+    pub line: usize,         // The line the span starts on.
+}
+
+/// Classifies the tokens and comments of the program, in order, without parsing or running it,
+/// so programs can be highlighted while they're being written.
+pub fn semantic_tokens(program: &str) -> Result<Vec<SemanticToken>, EvalError> {
+    let (tokens, trailing_trivia) = tokenize_lossless(program)?;
+
+    let mut semantic_tokens = vec![];
+    let mut offset = 0;
+    // Parameters are the names followed by a colon in the parentheses after fn and the function's name.
+    let mut in_parameters = false;
+    for (index, token) in tokens.iter().enumerate() {
+        add_comments(&mut semantic_tokens, &token.leading_trivia, &mut offset);
+
+        let previous_token_type = index
+            .checked_sub(1)
+            .map(|index| &tokens[index].token.token_type);
+        let next_token_type = tokens.get(index + 1).map(|token| &token.token.token_type);
+        let kind = match &token.token.token_type {
+            TokenType::Let
+            | TokenType::Static
+            | TokenType::If
+            | TokenType::Else
+            | TokenType::While
+            | TokenType::Loop
+            | TokenType::For
+            | TokenType::In
+            | TokenType::Break
+            | TokenType::Raise
+            | TokenType::Try
+            | TokenType::Catch
+            | TokenType::Match
+            | TokenType::Case
+            | TokenType::Ok
+            | TokenType::Err
+            | TokenType::Some
+            | TokenType::None
+            | TokenType::Fn
+            | TokenType::Return
+            | TokenType::Literal(Value::Boolean(_)) => SemanticTokenKind::Keyword,
+            TokenType::Number
+            | TokenType::String
+            | TokenType::Bool
+            | TokenType::Result
+            | TokenType::Option => SemanticTokenKind::Type,
+            TokenType::Literal(Value::String(_)) | TokenType::InterpolatedString(_) => {
+                SemanticTokenKind::String
+            }
+            TokenType::Literal(_) => SemanticTokenKind::Number,
+            // test is only a keyword before the name of a test.
+            TokenType::Variable(name)
+                if name.as_str() == "test"
+                    && matches!(next_token_type, Some(TokenType::Literal(Value::String(_)))) =>
+            {
+                SemanticTokenKind::Keyword
+            }
+            TokenType::Variable(_) if previous_token_type == Some(&TokenType::Fn) => {
+                in_parameters = next_token_type == Some(&TokenType::LeftParenthesis);
+                SemanticTokenKind::Function
+            }
+            TokenType::Variable(_)
+                if in_parameters && next_token_type == Some(&TokenType::Colon) =>
+            {
+                SemanticTokenKind::Parameter
+            }
+            // Record fields, where they're named in a record and where they're accessed.
+            TokenType::Variable(_)
+                if (next_token_type == Some(&TokenType::Colon)
+                    && previous_token_type != Some(&TokenType::Let))
+                    || previous_token_type == Some(&TokenType::Dot) =>
+            {
+                SemanticTokenKind::Property
+            }
+            TokenType::Variable(_) if next_token_type == Some(&TokenType::LeftParenthesis) => {
+                SemanticTokenKind::Function
+            }
+            TokenType::Variable(_) => SemanticTokenKind::Variable,
+            TokenType::LeftParenthesis
+            | TokenType::RightParenthesis
+            | TokenType::LeftBrace
+            | TokenType::RightBrace
+            | TokenType::Colon
+            | TokenType::Semicolon
+            | TokenType::Comma => {
+                if token.token.token_type == TokenType::LeftBrace {
+                    in_parameters = false;
+                }
+                SemanticTokenKind::Punctuation
+            }
+            TokenType::Plus
+            | TokenType::Minus
+            | TokenType::PlusPlus
+            | TokenType::MinusMinus
+            | TokenType::Star
+            | TokenType::Slash
+            | TokenType::DoubleAnd
+            | TokenType::DoubleOr
+            | TokenType::Ampersand
+            | TokenType::Pipe
+            | TokenType::Caret
+            | TokenType::Tilde
+            | TokenType::DoubleLess
+            | TokenType::DoubleGreater
+            | TokenType::Equal
+            | TokenType::DoubleEqual
+            | TokenType::Exclamation
+            | TokenType::ExclamationEqual
+            | TokenType::Less
+            | TokenType::LessEqual
+            | TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Arrow
+            | TokenType::FatArrow
+            | TokenType::Dot
+            | TokenType::DotDot
+            | TokenType::Question => SemanticTokenKind::Operator,
+            TokenType::DocComment(_) => SemanticTokenKind::Comment,
+        };
+        semantic_tokens.push(SemanticToken {
+            kind,
+            range: offset..offset + token.text.len(),
+            line: token.token.lines.0,
+        });
+        offset += token.text.len();
+    }
+    add_comments(&mut semantic_tokens, &trailing_trivia, &mut offset);
+    Ok(semantic_tokens)
+}
+
+/// Adds the comments among the trivia, which starts at the offset, and moves the offset past the trivia.
+fn add_comments(semantic_tokens: &mut Vec<SemanticToken>, trivia: &[Trivia], offset: &mut usize) {
+    for trivia in trivia {
+        if trivia.kind != TriviaKind::Whitespace {
+            semantic_tokens.push(SemanticToken {
+                kind: SemanticTokenKind::Comment,
+                range: *offset..*offset + trivia.text.len(),
+                line: trivia.line,
+            });
+        }
+        *offset += trivia.text.len();
+    }
+}
+
+/// Formats the program as HTML, with each token in a span whose class is the name of its kind.
+pub fn to_html(program: &str) -> Result<String, EvalError> {
+    let mut html = String::from("<pre class=\"decibelle\"><code>");
+    let mut offset = 0;
+    for token in semantic_tokens(program)? {
+        escape_html(&mut html, &program[offset..token.range.start]);
+        write!(html, "<span class=\"{}\">", token.kind.name()).unwrap();
+        escape_html(&mut html, &program[token.range.clone()]);
+        html.push_str("</span>");
+        offset = token.range.end;
+    }
+    escape_html(&mut html, &program[offset..]);
+    html.push_str("</code></pre>\n");
+    Ok(html)
+}
+
+/// Adds the text to the HTML, escaping the characters which have a meaning in HTML.
+pub fn escape_html(html: &mut String, text: &str) {
+    for ch in text.chars() {
+        match ch {
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '&' => html.push_str("&amp;"),
+            '"' => html.push_str("&quot;"),
+            ch => html.push(ch),
+        }
+    }
+}
@@ -0,0 +1,406 @@
+use std::{ops::Range, rc::Rc};
+
+use crate::{
+    messages::OMITTED_SEMICOLON,
+    parser::{
+        drop_errors_after_nesting, parse_statement, Expression, ExpressionType, Statement,
+        StatementType,
+    },
+    tokenizer::{tokenize_from_line, Token, TokenType},
+    EvalError,
+};
+
+/// Replaces the bytes in range of a program's source with the replacement.
+#[derive(Debug, Clone)]
+pub struct TextEdit {
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+/// A program's source and its parse, which is kept up to date through edits by
+/// reparsing only the top level statements an edit touches.
+#[derive(Debug)]
+pub struct ParsedProgram {
+    source: String,
+    parse: Result<Parse, EvalError>,
+}
+
+#[derive(Debug, Default)]
+struct Parse {
+    statements: Vec<Statement>,
+    // The lines of the first and last token of each statement.
+    spans: Vec<(usize, usize)>,
+    // Whether each statement could continue on the line after it, because it left out its semicolon.
+    open: Vec<bool>,
+}
+
+impl ParsedProgram {
+    pub fn new(source: String) -> ParsedProgram {
+        let parse = parse_lines(&source, 1);
+        ParsedProgram { source, parse }
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn statements(&self) -> Result<&[Statement], &EvalError> {
+        match &self.parse {
+            Ok(parse) => Ok(&parse.statements),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Applies the edit to the source and updates the parse.
+    /// Panics if the range is out of bounds or not on character boundaries.
+    pub fn edit(&mut self, edit: &TextEdit) -> Result<&[Statement], &EvalError> {
+        let first_line = line_of(&self.source, edit.range.start);
+        let last_line = line_of(&self.source, edit.range.end);
+        let delta =
+            edit.replacement.matches('\n').count() as isize - (last_line - first_line) as isize;
+        self.source
+            .replace_range(edit.range.clone(), &edit.replacement);
+
+        // Without a previous parse there's nothing to reuse.
+        if self.parse.is_err() {
+            self.parse = parse_lines(&self.source, 1);
+            return self.statements();
+        }
+        let Ok(parse) = &mut self.parse else {
+            unreachable!()
+        };
+
+        // Reparse the statements on the edited lines, along with any statements sharing a line with them,
+        // since lines are the smallest unit we can retokenize.
+        let mut start = parse.spans.partition_point(|span| span.1 < first_line);
+        let mut end = parse.spans.partition_point(|span| span.0 <= last_line);
+        let mut lines = (first_line, last_line);
+        if start < end {
+            lines.0 = lines.0.min(parse.spans[start].0);
+            lines.1 = lines.1.max(parse.spans[end - 1].1);
+        }
+        // A statement which left out its semicolon would have continued into the edited lines if they
+        // continue its expression, like a line starting with - does.
+        if start > 0 && parse.open[start - 1] {
+            start -= 1;
+            lines.0 = parse.spans[start].0;
+        }
+        while start > 0 && parse.spans[start - 1].1 >= lines.0 {
+            start -= 1;
+            lines.0 = parse.spans[start].0;
+        }
+        while end < parse.spans.len() && parse.spans[end].0 <= lines.1 {
+            lines.1 = parse.spans[end].1;
+            end += 1;
+        }
+
+        let reparsed = loop {
+            let new_last_line = (lines.1 as isize + delta) as usize;
+            let region = &self.source
+                [line_start(&self.source, lines.0)..line_start(&self.source, new_last_line + 1)];
+            let Ok(reparsed) = parse_lines(region, lines.0) else {
+                // The edit may have joined the region with the statements around it, for example by removing
+                // a closing brace, so the errors are only reliable for the whole program.
+                self.parse = parse_lines(&self.source, 1);
+                return self.statements();
+            };
+
+            // Likewise the region's last statement may continue into the statement after it. It can't if it's
+            // the statement which ended the region before the edit, as that statement was followed by the same lines.
+            let continues = reparsed.open.last() == Some(&true)
+                && end < parse.spans.len()
+                && !(end > start && parse.open[end - 1] && {
+                    let mut statement = parse.statements[end - 1].clone();
+                    shift_statement(&mut statement, delta);
+                    reparsed.statements.last() == Some(&statement)
+                });
+            if !continues {
+                break reparsed;
+            }
+            lines.1 = parse.spans[end].1;
+            end += 1;
+        };
+
+        if delta != 0 {
+            for statement in &mut parse.statements[end..] {
+                shift_statement(statement, delta);
+            }
+            for span in &mut parse.spans[end..] {
+                *span = shift(*span, delta);
+            }
+        }
+        parse.statements.splice(start..end, reparsed.statements);
+        parse.spans.splice(start..end, reparsed.spans);
+        parse.open.splice(start..end, reparsed.open);
+        self.statements()
+    }
+}
+
+/// Parses a part of a program made of whole statements, which starts at the given line.
+fn parse_lines(source: &str, first_line: usize) -> Result<Parse, EvalError> {
+    let tokens = tokenize_from_line(source, first_line).map_err(EvalError::Tokenizer)?;
+    let mut current_token = 0;
+    let mut errors = vec![];
+    let mut parse = Parse::default();
+
+    while current_token < tokens.len() {
+        let start_token = current_token;
+        let Some(statement) = parse_statement(&tokens, &mut current_token, &mut errors) else {
+            continue;
+        };
+        parse
+            .open
+            .push(is_open(&statement, &tokens[current_token - 1]));
+        parse.statements.push(statement);
+        parse.spans.push((
+            tokens[start_token].lines.0,
+            tokens[current_token - 1].lines.1,
+        ));
+    }
+
+    drop_errors_after_nesting(&mut errors);
+    // Semicolons can be left out, as they can in programs run with eval.
+    errors.retain(|error| error.error.message != &OMITTED_SEMICOLON);
+    if errors.is_empty() {
+        Ok(parse)
+    } else {
+        Err(EvalError::Compiler(errors))
+    }
+}
+
+/// Could the statement, whose last token is given, continue on the next line? Statements which end with a block,
+/// like if statements, can't, but others without a semicolon can, even if their expression ends with a block.
+fn is_open(statement: &Statement, last_token: &Token) -> bool {
+    last_token.token_type != TokenType::Semicolon
+        && matches!(
+            statement.statement,
+            StatementType::VariableDeclaration { .. }
+                | StatementType::Expression(_)
+                | StatementType::Return(_)
+                | StatementType::Break(_)
+                | StatementType::Raise(_)
+        )
+}
+
+/// The line containing the byte at index.
+fn line_of(source: &str, index: usize) -> usize {
+    source[..index].matches('\n').count() + 1
+}
+
+/// The index of the first byte of the line, or the length of the source if it has fewer lines.
+fn line_start(source: &str, line: usize) -> usize {
+    if line == 1 {
+        return 0;
+    }
+    source
+        .match_indices('\n')
+        .nth(line - 2)
+        .map_or(source.len(), |(index, _)| index + 1)
+}
+
+fn shift(lines: (usize, usize), delta: isize) -> (usize, usize) {
+    (
+        (lines.0 as isize + delta) as usize,
+        (lines.1 as isize + delta) as usize,
+    )
+}
+
+fn shift_statement(statement: &mut Statement, delta: isize) {
+    statement.lines = shift(statement.lines, delta);
+    match &mut statement.statement {
+        StatementType::VariableDeclaration { value, .. } => shift_expression(value, delta),
+        StatementType::FunctionDeclaration { body, .. } => {
+            shift_statement(Rc::make_mut(body), delta)
+        }
+        StatementType::Return(expression) | StatementType::Break(expression) => {
+            if let Some(expression) = expression {
+                shift_expression(expression, delta);
+            }
+        }
+        StatementType::Expression(expression) | StatementType::Raise(expression) => {
+            shift_expression(expression, delta)
+        }
+        StatementType::Block(statements) => {
+            for statement in statements {
+                shift_statement(statement, delta);
+            }
+        }
+        StatementType::If {
+            expression,
+            then_statement,
+            else_statement,
+        } => {
+            shift_expression(expression, delta);
+            shift_statement(then_statement, delta);
+            if let Some(else_statement) = else_statement {
+                shift_statement(else_statement, delta);
+            }
+        }
+        StatementType::While {
+            expression,
+            statement,
+        }
+        | StatementType::For {
+            expression,
+            statement,
+            ..
+        } => {
+            shift_expression(expression, delta);
+            shift_statement(statement, delta);
+        }
+        StatementType::Try {
+            statement,
+            catch_statement,
+            ..
+        } => {
+            shift_statement(statement, delta);
+            shift_statement(catch_statement, delta);
+        }
+        StatementType::Match { expression, arms } => {
+            shift_expression(expression, delta);
+            for arm in arms {
+                arm.lines = shift(arm.lines, delta);
+                if let Some(guard) = &mut arm.guard {
+                    shift_expression(guard, delta);
+                }
+                shift_statement(&mut arm.statement, delta);
+            }
+        }
+        StatementType::Test { body, .. } => shift_statement(body, delta),
+        StatementType::ImportNative(_) => {}
+    }
+}
+
+fn shift_expression(expression: &mut Expression, delta: isize) {
+    expression.lines = shift(expression.lines, delta);
+    match &mut expression.expression_type {
+        ExpressionType::Unary { expression, .. }
+        | ExpressionType::Grouping(expression)
+        | ExpressionType::TupleAccess { expression, .. }
+        | ExpressionType::Ok(expression)
+        | ExpressionType::Err(expression)
+        | ExpressionType::Propagate(expression)
+        | ExpressionType::Some(expression) => shift_expression(expression, delta),
+        ExpressionType::Binary {
+            left_expression,
+            right_expression,
+            ..
+        } => {
+            shift_expression(left_expression, delta);
+            shift_expression(right_expression, delta);
+        }
+        ExpressionType::Tuple(expressions)
+        | ExpressionType::Interpolation {
+            values: expressions,
+            ..
+        } => {
+            for expression in expressions {
+                shift_expression(expression, delta);
+            }
+        }
+        ExpressionType::Record(fields) => {
+            for (_, expression) in fields {
+                shift_expression(expression, delta);
+            }
+        }
+        ExpressionType::FunctionCall {
+            function,
+            arguments,
+        } => {
+            shift_expression(function, delta);
+            for argument in arguments {
+                shift_expression(argument, delta);
+            }
+        }
+        ExpressionType::Block { statements, value } => {
+            for statement in statements {
+                shift_statement(statement, delta);
+            }
+            shift_expression(value, delta);
+        }
+        ExpressionType::If {
+            condition,
+            then_expression,
+            else_expression,
+        } => {
+            shift_expression(condition, delta);
+            shift_expression(then_expression, delta);
+            shift_expression(else_expression, delta);
+        }
+        ExpressionType::Loop(body) => shift_statement(body, delta),
+        ExpressionType::Literal(_) | ExpressionType::Variable { .. } => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParsedProgram, TextEdit};
+
+    /// Replaces the first occurrence of target in the program, both through an edit and by parsing the
+    /// result from scratch, and checks the two parses agree.
+    fn replace(program: &mut ParsedProgram, target: &str, replacement: &str) {
+        let start = program.source().find(target).unwrap();
+        let edit = TextEdit {
+            range: start..start + target.len(),
+            replacement: replacement.to_string(),
+        };
+        let edited = format!("{:?}", program.edit(&edit));
+        let fresh = ParsedProgram::new(program.source().to_string());
+        assert_eq!(edited, format!("{:?}", fresh.statements()));
+    }
+
+    #[test]
+    fn reparses_edits_within_a_line() {
+        let mut program = ParsedProgram::new("let a = 1;\nlet b = 2;\nprint(a + b);\n".to_string());
+        replace(&mut program, "2", "3");
+        replace(&mut program, "a + b", "a * b");
+        replace(&mut program, "let a = 1;", "let a = 1; let c = 4;");
+        replace(&mut program, "let c = 4;", "");
+        assert_eq!(program.statements().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn shifts_the_statements_after_edits_which_add_or_remove_lines() {
+        let mut program = ParsedProgram::new(
+            "fn f(x: number) -> number {\n    return x;\n}\nlet a = f(1);\nprint(a);\n".to_string(),
+        );
+        replace(&mut program, "return x;", "let y = x + 1;\n    return y;");
+        replace(&mut program, "let a = f(1);\n", "");
+        replace(&mut program, "print(a);", "let a = 1;\n\n\nprint(f(a));");
+        assert_eq!(program.statements().unwrap()[2].lines, (8, 8));
+    }
+
+    #[test]
+    fn recovers_from_edits_which_break_the_program() {
+        let mut program =
+            ParsedProgram::new("if true {\n    print(1);\n}\nprint(2);\n".to_string());
+        replace(&mut program, "}", "");
+        assert!(program.statements().is_err());
+        replace(&mut program, "print(1);", "print(1);\n}");
+        assert_eq!(program.statements().unwrap().len(), 2);
+        replace(&mut program, "print(2);", "print(\"2);");
+        assert!(program.statements().is_err());
+        replace(&mut program, "\"2);", "\"2\");");
+        assert!(program.statements().is_ok());
+    }
+
+    #[test]
+    fn reparses_statements_which_continue_onto_the_next_line() {
+        let mut program = ParsedProgram::new("let a = 1\nprint(a)\n".to_string());
+        replace(&mut program, "print(a)", "-3");
+        assert_eq!(program.statements().unwrap().len(), 1);
+        replace(&mut program, "-3", "print(a)");
+        assert_eq!(program.statements().unwrap().len(), 2);
+
+        let mut program = ParsedProgram::new("let a = 1;\nlet b = 2;\nprint(b);\n".to_string());
+        replace(&mut program, "2;", "2");
+        replace(&mut program, "print(b);", "* 3\nprint(b)");
+        assert_eq!(program.statements().unwrap().len(), 3);
+        replace(&mut program, "1;", "1");
+        replace(&mut program, "let b = 2", "+ 4");
+        replace(&mut program, "* 3", "print(a)");
+        replace(&mut program, "print(a)", "let c = if true { 1 } else { 2 }");
+        replace(&mut program, "print(b)", "- 5\nprint(c)");
+        assert_eq!(program.statements().unwrap().len(), 3);
+    }
+}
@@ -1,41 +1,125 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::{
+    cell::Cell,
+    cmp::Reverse,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
 
+#[cfg(feature = "jit")]
+use crate::jit::Jit;
 use crate::{
+    coverage::Coverage,
+    debugger::{DebugCommand, Debugger},
+    format::interpolate,
+    garbage_collection::GarbageCollectionSchedule,
+    messages::{diagnostic, Diagnostic},
     parser::{
-        BinaryOperation, Expression, ExpressionType, Statement, StatementType, UnaryOperation,
+        block_statements, field_path, BinaryOperation, Expression, ExpressionType, Pattern,
+        Statement, StatementType, UnaryOperation,
     },
-    tokenizer::{FunctionBody, Value},
+    profiler::{Profiler, SpanKind},
+    stats::Stats,
+    symbol::Symbol,
+    tokenizer::{FunctionBody, FunctionValue, Reference, Type, Value},
+    trace::{TraceEvent, Tracer},
+    variable_and_type_check::HostOperator,
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 pub struct Environment {
     parent: Option<usize>,
-    pub variables: HashMap<(String, usize), Value>,
+    pub slots: Vec<Value>, // Variables are indexed by the slot assigned during variable and type checking.
+    names: Vec<Symbol>, // The name of the variable in each slot, except in the global environment.
 }
 
 pub struct Variables {
     current_environment: usize,
     pub environments: HashMap<usize, Environment>,
+    pub global_slots: HashMap<(Symbol, usize), usize>, // Slot of each global variable by name and shadow_id.
+    pub argument_typed_slots: HashSet<usize>, // Slots of the ARGUMENT_TYPED_BUILTINS which have been declared.
+    pub operators: Vec<HostOperator>,         // Registered with Interpreter::register_operator.
+    pub initialized_statics: HashSet<usize>, // Global slots of the static variables whose declarations have run.
     current_environments: Vec<usize>,
-    garbage_collection_counter: usize,
+    pub garbage_collection: GarbageCollectionSchedule,
+    pub profiler: Option<Profiler>, // Only the tree walker records spans.
+    pub coverage: Option<Coverage>, // Only the tree walker records coverage.
+    pub tracer: Option<Tracer>,     // Only the tree walker traces programs.
+    pub debugger: Option<Debugger>, // Only the tree walker can be debugged.
+    pub stats: Stats,
+    pub step_limit: Option<(usize, usize)>, // The limit, and the statements_executed at which programs stop.
+    pub cancelled: Option<Arc<AtomicBool>>, // Set from another thread to stop the program.
+    pub exit_code: Rc<Cell<Option<i32>>>,   // Set by exit() to stop the program.
+    pub return_value: Value, // What the program returned from its top level, or void if it ran to the end.
+    pub returned: bool,      // Whether the last program returned from its top level.
+    pub builtin_error: Rc<Cell<Option<Diagnostic>>>, // Set by builtins which fail, like assert.
+    pub builtin_line: Rc<Cell<usize>>, // The line of the builtin call which is running, set before calling it.
+    #[cfg(feature = "jit")]
+    pub jit: Option<Jit>,
     last_id: usize,
 }
 
+impl Default for Variables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Variables {
+    /// The error to stop the program with if a builtin called exit or failed.
+    pub fn take_builtin_error(&self) -> Option<Diagnostic> {
+        match self.exit_code.get() {
+            Some(code) => Some(diagnostic!(EXITED, code)),
+            None => self.builtin_error.take(),
+        }
+    }
+
+    /// Has the program been cancelled from another thread? The tree walker checks before each statement,
+    /// and the VM every CANCEL_CHECK_INTERVAL instructions.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .as_ref()
+            .is_some_and(|cancelled| cancelled.load(Ordering::Relaxed))
+    }
+
     pub fn new() -> Variables {
         let mut environments = HashMap::new();
         environments.insert(
             0,
             Environment {
                 parent: None,
-                variables: HashMap::new(),
+                slots: vec![],
+                names: vec![],
             },
         );
         Variables {
             current_environment: 0,
             environments,
+            global_slots: HashMap::new(),
+            argument_typed_slots: HashSet::new(),
+            operators: vec![],
+            initialized_statics: HashSet::new(),
             last_id: 0,
             current_environments: vec![],
-            garbage_collection_counter: 100,
+            garbage_collection: GarbageCollectionSchedule::new(),
+            profiler: None,
+            coverage: None,
+            tracer: None,
+            debugger: None,
+            stats: Stats::default(),
+            step_limit: None,
+            cancelled: None,
+            exit_code: Rc::new(Cell::new(None)),
+            return_value: Value::Void,
+            returned: false,
+            builtin_error: Rc::new(Cell::new(None)),
+            builtin_line: Rc::new(Cell::new(0)),
+            #[cfg(feature = "jit")]
+            jit: None,
         }
     }
 
@@ -45,16 +129,20 @@ impl Variables {
         value: &Value,
     ) {
         match value {
-            Value::Function {
-                parent_environment, ..
-            } => {
-                if !queued_environments.contains(parent_environment) {
-                    environment_queue.push_back(*parent_environment);
-                    queued_environments.insert(*parent_environment);
-                }
+            Value::Function(function)
+                if !queued_environments.contains(&function.parent_environment) =>
+            {
+                environment_queue.push_back(function.parent_environment);
+                queued_environments.insert(function.parent_environment);
+            }
+            Value::Reference(reference)
+                if !queued_environments.contains(&reference.environment) =>
+            {
+                environment_queue.push_back(reference.environment);
+                queued_environments.insert(reference.environment);
             }
             Value::Tuple(values) => {
-                for value in values {
+                for value in values.iter() {
                     Variables::add_reachable_environments(
                         queued_environments,
                         environment_queue,
@@ -62,14 +150,47 @@ impl Variables {
                     );
                 }
             }
+            Value::Record(fields) => {
+                for (_, value) in fields.iter() {
+                    Variables::add_reachable_environments(
+                        queued_environments,
+                        environment_queue,
+                        value,
+                    );
+                }
+            }
+            Value::Option(Some(value)) => {
+                Variables::add_reachable_environments(
+                    queued_environments,
+                    environment_queue,
+                    value,
+                );
+            }
+            Value::Result(result) => {
+                let (Ok(value) | Err(value)) = &**result;
+                Variables::add_reachable_environments(
+                    queued_environments,
+                    environment_queue,
+                    value,
+                );
+            }
             _ => {}
         }
     }
 
-    fn collect_garbage(&mut self) {
+    /// Frees the environments which can't be reached from the current environments or the given values.
+    pub fn collect_garbage(&mut self, roots: &[Value]) {
         let mut queued_environments = HashSet::new();
         let mut environment_queue = VecDeque::new();
 
+        for value in roots {
+            Variables::add_reachable_environments(
+                &mut queued_environments,
+                &mut environment_queue,
+                value,
+            );
+        }
+
         environment_queue.extend(self.current_environments.iter());
         queued_environments.extend(self.current_environments.iter());
 
@@ -86,7 +207,7 @@ impl Variables {
                 }
             }
 
-            for value in environment.variables.values() {
+            for value in environment.slots.iter() {
                 Variables::add_reachable_environments(
                     &mut queued_environments,
                     &mut environment_queue,
@@ -105,26 +226,67 @@ impl Variables {
             self.environments.remove(&environment_id);
         }
 
-        self.garbage_collection_counter = 100;
+        self.garbage_collection.collected(self.environments.len());
+        self.stats.garbage_collections += 1;
     }
 
-    fn push_environment(&mut self) {
+    /// Creates an environment for a block or function call.
+    /// Values held by the evaluator but not stored in a variable must be passed as roots.
+    fn push_environment(&mut self, roots: &[Value]) {
         self.environments.insert(
             self.last_id + 1,
             Environment {
                 parent: Some(self.current_environment),
-                variables: HashMap::new(),
+                slots: vec![],
+                names: vec![],
             },
         );
         self.current_environment = self.last_id + 1;
         self.last_id += 1;
+        self.stats.environment_pushes += 1;
 
-        self.garbage_collection_counter -= 1;
-        if self.garbage_collection_counter == 0 {
-            self.collect_garbage();
+        if self.garbage_collection.allocate() {
+            self.collect_garbage(roots);
         }
     }
 
+    /// Returns the bodies of the functions stored in variables.
+    fn function_bodies(&self) -> Vec<Rc<Statement>> {
+        fn add_function_bodies(value: &Value, bodies: &mut Vec<Rc<Statement>>) {
+            match value {
+                Value::Function(function) => {
+                    if let FunctionBody::Statement(body) = &function.body {
+                        bodies.push(body.clone());
+                    }
+                }
+                Value::Tuple(values) => {
+                    for value in values.iter() {
+                        add_function_bodies(value, bodies);
+                    }
+                }
+                Value::Record(fields) => {
+                    for (_, value) in fields.iter() {
+                        add_function_bodies(value, bodies);
+                    }
+                }
+                Value::Result(result) => {
+                    let (Ok(value) | Err(value)) = &**result;
+                    add_function_bodies(value, bodies);
+                }
+                Value::Option(Some(value)) => add_function_bodies(value, bodies),
+                _ => {}
+            }
+        }
+
+        let mut bodies = vec![];
+        for environment in self.environments.values() {
+            for value in &environment.slots {
+                add_function_bodies(value, &mut bodies);
+            }
+        }
+        bodies
+    }
+
     fn pop_environment(&mut self) {
         self.current_environment = self.environments[&self.current_environment].parent.unwrap();
     }
@@ -138,431 +300,1622 @@ impl Variables {
         self.current_environment = self.current_environments.pop().unwrap();
     }
 
-    fn get_variable(
+    fn get_variable(&self, parent_height: usize, slot: usize) -> Option<&Value> {
+        self.get_variable_from(self.current_environment, parent_height, slot)
+    }
+
+    /// Gets a variable, counting the parent height from the given environment.
+    fn get_variable_from(
         &self,
-        variable: &str,
-        shadow_id: usize,
+        environment: usize,
         parent_height: usize,
-    ) -> Option<Value> {
-        let mut current_environment = self.current_environment;
+        slot: usize,
+    ) -> Option<&Value> {
+        let mut current_environment = environment;
         for _ in 0..parent_height {
-            if let Some(environment) = self.environments[&current_environment].parent {
-                current_environment = environment;
-            } else {
-                return None;
-            }
+            current_environment = self.environments[&current_environment].parent?;
         }
 
-        self.environments[&current_environment]
-            .variables
-            .get(&(variable.to_owned(), shadow_id))
-            .cloned()
+        self.environments[&current_environment].slots.get(slot)
     }
 
-    /// Sets a variable. Doesn't create a new one.
-    fn set_variable(
-        &mut self,
-        variable: &str,
-        shadow_id: usize,
-        parent_height: usize,
-        value: Value,
-    ) -> Result<(), ()> {
+    fn get_variable_mut(&mut self, parent_height: usize, slot: usize) -> Option<&mut Value> {
         let mut current_environment = self.current_environment;
         for _ in 0..parent_height {
-            if let Some(environment) = self.environments[&current_environment].parent {
-                current_environment = environment;
-            } else {
-                return Err(());
-            }
+            current_environment = self.environments[&current_environment].parent?;
         }
 
-        if self.environments[&current_environment]
-            .variables
-            .contains_key(&(variable.to_owned(), shadow_id))
-        {
-            self.environments
-                .get_mut(&current_environment)
-                .unwrap()
-                .variables
-                .insert((variable.to_owned(), shadow_id), value);
-            Ok(())
-        } else {
-            Err(())
+        self.environments
+            .get_mut(&current_environment)
+            .unwrap()
+            .slots
+            .get_mut(slot)
+    }
+
+    /// Makes a reference to a variable.
+    fn reference(&self, parent_height: usize, slot: usize) -> Reference {
+        let mut environment = self.current_environment;
+        for _ in 0..parent_height {
+            environment = self.environments[&environment].parent.unwrap();
+        }
+        Reference {
+            environment,
+            slot,
+            value_type: self.environments[&environment].slots[slot].value_type(),
         }
     }
 
-    fn create_variable(&mut self, variable: &str, shadow_id: usize, value: Value) {
-        self.environments
-            .get_mut(&self.current_environment)
+    /// The variable a reference refers to.
+    fn dereference(&self, reference: &Reference) -> &Value {
+        &self.environments[&reference.environment].slots[reference.slot]
+    }
+
+    fn dereference_mut(&mut self, reference: &Reference) -> &mut Value {
+        &mut self
+            .environments
+            .get_mut(&reference.environment)
             .unwrap()
-            .variables
-            .insert((variable.to_owned(), shadow_id), value);
+            .slots[reference.slot]
+    }
+
+    /// Sets a variable. Doesn't create a new one.
+    fn set_variable(&mut self, parent_height: usize, slot: usize, value: Value) -> Result<(), ()> {
+        *self.get_variable_mut(parent_height, slot).ok_or(())? = value;
+        Ok(())
+    }
+
+    pub fn global(&self, slot: usize) -> &Value {
+        &self.environments[&0].slots[slot]
+    }
+
+    pub fn global_mut(&mut self, slot: usize) -> &mut Value {
+        &mut self.environments.get_mut(&0).unwrap().slots[slot]
+    }
+
+    /// Takes the values of the global variables out, leaving the global environment empty until they're restored.
+    pub fn take_globals(&mut self) -> Vec<Value> {
+        std::mem::take(&mut self.environments.get_mut(&0).unwrap().slots)
+    }
+
+    pub fn restore_globals(&mut self, globals: Vec<Value>) {
+        self.environments.get_mut(&0).unwrap().slots = globals;
+    }
+
+    /// Creates a global variable in the slot assigned to it during variable and type checking.
+    pub fn define_global(&mut self, variable: Symbol, shadow_id: usize, slot: usize, value: Value) {
+        let slots = &mut self.environments.get_mut(&0).unwrap().slots;
+        if slots.len() <= slot {
+            slots.resize(slot + 1, Value::Void);
+        }
+        slots[slot] = value;
+        self.global_slots.insert((variable, shadow_id), slot);
+    }
+
+    /// Makes room for the given number of globals, so new globals aren't given the slots of earlier ones.
+    pub fn reserve_globals(&mut self, count: usize) {
+        let slots = &mut self.environments.get_mut(&0).unwrap().slots;
+        if slots.len() < count {
+            slots.resize(count, Value::Void);
+        }
+    }
+
+    /// Creates a global variable, shadowing any existing global with the same name.
+    pub fn declare_global(&mut self, variable: &str, value: Value) {
+        let variable = Symbol::intern(variable);
+        let shadow_id = self
+            .global_slots
+            .keys()
+            .filter(|(name, _)| *name == variable)
+            .map(|(_, shadow_id)| shadow_id + 1)
+            .max()
+            .unwrap_or(0);
+        let slot = self.environments[&0].slots.len();
+        self.define_global(variable, shadow_id, slot, value);
+    }
+
+    /// Gets the most recently declared global variable with the given name.
+    pub fn get_global(&self, variable: &str) -> Option<&Value> {
+        let variable = Symbol::get(variable)?;
+        self.global_slots
+            .iter()
+            .filter(|((name, _), _)| *name == variable)
+            .max_by_key(|((_, shadow_id), _)| *shadow_id)
+            .map(|(_, slot)| self.global(*slot))
+    }
+
+    fn create_variable(&mut self, variable: Symbol, shadow_id: usize, slot: usize, value: Value) {
+        if self.current_environment == 0 {
+            self.define_global(variable, shadow_id, slot, value);
+            return;
+        }
+
+        // Variables in an environment are declared in slot order.
+        let environment = self
+            .environments
+            .get_mut(&self.current_environment)
+            .unwrap();
+        environment.slots.push(value);
+        environment.names.push(variable);
+    }
+
+    /// Returns the environments the current one can use variables from, from the innermost to the global environment,
+    /// with the slot of each name which isn't shadowed by an inner variable.
+    fn visible_slots(&self) -> Vec<(Symbol, usize, usize)> {
+        let mut visible_slots: Vec<(Symbol, usize, usize)> = vec![];
+        let mut environment_id = Some(self.current_environment);
+        while let Some(id) = environment_id {
+            let environment = &self.environments[&id];
+            if id == 0 {
+                let mut globals = self.global_slots.iter().collect::<Vec<_>>();
+                // Later declarations shadow earlier ones.
+                globals.sort_by_key(|((_, shadow_id), slot)| (Reverse(*shadow_id), **slot));
+                for ((name, _), slot) in globals {
+                    if !visible_slots.iter().any(|(visible, _, _)| visible == name) {
+                        visible_slots.push((*name, id, *slot));
+                    }
+                }
+            } else {
+                for (slot, name) in environment.names.iter().enumerate().rev() {
+                    if !visible_slots.iter().any(|(visible, _, _)| visible == name) {
+                        visible_slots.push((*name, id, slot));
+                    }
+                }
+            }
+            environment_id = environment.parent;
+        }
+        visible_slots
+    }
+
+    /// Returns the variables the current environment can use, innermost first.
+    pub fn visible_variables(&self) -> Vec<(Symbol, &Value)> {
+        self.visible_slots()
+            .into_iter()
+            .map(|(name, environment, slot)| (name, &self.environments[&environment].slots[slot]))
+            .collect()
+    }
+
+    pub fn visible_variable_mut(&mut self, name: Symbol) -> Option<&mut Value> {
+        let (_, environment, slot) = self
+            .visible_slots()
+            .into_iter()
+            .find(|(visible, _, _)| *visible == name)?;
+        Some(&mut self.environments.get_mut(&environment).unwrap().slots[slot])
     }
 }
 
-pub fn interpret(statements: &Vec<Statement>, variables: &mut Variables) {
+/// The most nested function calls a program can make before it's stopped.
+pub const MAX_CALL_DEPTH: usize = 10_000;
+
+/// An error which stops a program while it's running.
+#[derive(Debug)]
+pub struct RuntimeError {
+    pub lines: (usize, usize),
+    pub error: Diagnostic,
+}
+
+/// Work left to do by the evaluator. Tasks which produce a value push it on the value stack.
+enum Task<'a> {
+    Statement(&'a Statement),
+    Expression(&'a Expression),
+    PopValue,
+    PopEnvironment,
+    Declare {
+        variable: Symbol,
+        shadow_id: usize,
+        slot: usize,
+    },
+    // Sets a static variable the first time its declaration runs.
+    InitializeStatic {
+        variable: Symbol,
+        slot: usize,
+    },
+    Branch {
+        then_statement: &'a Statement,
+        else_statement: Option<&'a Statement>,
+    },
+    // Evaluates a branch of an if expression.
+    Select {
+        then_expression: &'a Expression,
+        else_expression: &'a Expression,
+    },
+    Loop(&'a Statement), // The while statement, run again if the condition is true.
+    Repeat(&'a Statement), // The body of a loop expression, run again until a break.
+    Iterate(&'a Statement), // Starts the for statement over the string or iterator on the value stack.
+    // Runs the body of the for statement for the next grapheme cluster of the string, starting at the byte position,
+    // or the next value of the iterator, if there is one.
+    ForEach {
+        for_statement: &'a Statement,
+        sequence: Value,
+        position: usize,
+    },
+    // Skips the rest of the innermost loop. A loop expression's value is on the value stack if it's broken with a value.
+    Break {
+        has_value: bool,
+    },
+    Return,
+    Ok,
+    Err,
+    Propagate, // Replaces the result on the value stack with its value if it's Ok, or returns it if it's Err.
+    Raise((usize, usize)), // Leaves everything up to the innermost try statement with the error on the value stack.
+    // Marks the end of a try statement's body. The value stack is cut back to its height if an error is caught.
+    Catch {
+        try_statement: &'a Statement,
+        value_height: usize,
+    },
+    // Runs the first arm of the match statement from the index whose pattern matches the value on the value stack.
+    Match {
+        match_statement: &'a Statement,
+        arm: usize,
+    },
+    // Runs the arm of the match statement if its guard is true, with the guard's value above the matched value.
+    // Otherwise goes on to the next arm.
+    Guard {
+        match_statement: &'a Statement,
+        arm: usize,
+    },
+    Some,
+    Unary(UnaryOperation),
+    Binary(BinaryOperation),
+    ShortCircuit {
+        operation: BinaryOperation,
+        right_expression: &'a Expression,
+    },
+    Assign {
+        parent_height: usize,
+        slot: usize,
+    },
+    Store {
+        // Like Assign, but moves the value out of the value stack instead of leaving a copy.
+        parent_height: usize,
+        slot: usize,
+    },
+    AssignField(&'a Expression), // The tuple access being assigned to.
+    TraceAssignment(&'a Expression), // Traces the assignment to the expression, whose value is on the value stack.
+    Tuple(usize),
+    Record(&'a Expression), // The record literal, whose values are on the value stack.
+    AssignReference, // Assigns the value below the reference on the value stack to the referenced variable.
+    TupleGet(usize),
+    Interpolate(&'a Expression), // The interpolated string, whose values are on the value stack.
+    Call {
+        argument_count: usize,
+        lines: (usize, usize),
+    },
+    EndCall(usize), // The height of the value stack below the call, which a return goes back to.
+    EndSpan {
+        kind: SpanKind,
+        lines: (usize, usize),
+        start: Instant,
+    },
+}
+
+/// Evaluates statements with explicit task and value stacks instead of Rust recursion,
+/// so deeply nested programs can't overflow the native stack.
+struct Evaluator<'a, 'v> {
+    variables: &'v mut Variables,
+    bodies: HashMap<*const Statement, &'a Statement>, // Bodies of the functions which can be called, by address.
+    tasks: Vec<Task<'a>>,
+    values: Vec<Value>,
+    call_depth: usize,
+    call_lines: Vec<(usize, usize)>, // The lines of the calls to the running functions, outermost first.
+}
+
+pub fn interpret(statements: &[Statement], variables: &mut Variables) -> Result<(), RuntimeError> {
+    // Tasks borrow the statements they run, so we borrow every function body
+    // that can be called up front: those in this program and those in existing function values.
+    let previous_bodies = variables.function_bodies();
+    let mut bodies = HashMap::new();
     for statement in statements {
-        interpret_statement(statement, variables);
+        add_function_bodies(statement, &mut bodies);
     }
+    for body in &previous_bodies {
+        bodies.insert(Rc::as_ptr(body), &**body);
+        add_function_bodies(body, &mut bodies);
+    }
+
+    let mut evaluator = Evaluator {
+        variables,
+        bodies,
+        tasks: statements.iter().rev().map(Task::Statement).collect(),
+        values: vec![],
+        call_depth: 0,
+        call_lines: vec![],
+    };
+    let result = evaluator.run();
+    if result.is_err() {
+        // End the spans which were running, so the profiler counts them as finished.
+        while let Some(task) = evaluator.tasks.pop() {
+            if let Task::EndSpan { kind, lines, start } = task {
+                evaluator.end_span(kind, lines, start);
+            }
+        }
+        // Leave the functions we were in so the next program starts in the global environment.
+        variables.current_environments.clear();
+        variables.current_environment = 0;
+    }
+    result
 }
 
-fn interpret_statement(statement: &Statement, variables: &mut Variables) -> Option<Value> {
-    // Return value
+fn add_function_bodies<'a>(
+    statement: &'a Statement,
+    bodies: &mut HashMap<*const Statement, &'a Statement>,
+) {
     match &statement.statement {
-        StatementType::Expression(expression) => {
-            interpret_expression(expression, variables);
-            None
-        }
-        StatementType::VariableDeclaration {
-            variable,
-            value,
-            shadow_id,
-            ..
-        } => {
-            let value = interpret_expression(value, variables);
-            variables.create_variable(variable, shadow_id.unwrap(), value);
-            None
+        StatementType::FunctionDeclaration { body, .. } => {
+            bodies.insert(Rc::as_ptr(body), body);
+            add_function_bodies(body, bodies);
         }
         StatementType::Block(statements) => {
-            variables.push_environment();
             for statement in statements {
-                if let Some(value) = interpret_statement(statement, variables) {
-                    variables.pop_environment();
-                    return Some(value);
-                }
+                add_function_bodies(statement, bodies);
             }
-            variables.pop_environment();
-            None
         }
         StatementType::If {
             expression,
             then_statement,
             else_statement,
         } => {
-            let Value::Boolean(value) = interpret_expression(expression, variables) else {
-                unreachable!();
-            };
-
-            if value {
-                if let Some(value) = interpret_statement(then_statement, variables) {
-                    return Some(value);
-                }
-            } else if let Some(else_statement) = else_statement {
-                if let Some(value) = interpret_statement(else_statement, variables) {
-                    return Some(value);
-                }
+            for statement in block_statements(expression) {
+                add_function_bodies(statement, bodies);
+            }
+            add_function_bodies(then_statement, bodies);
+            if let Some(else_statement) = else_statement {
+                add_function_bodies(else_statement, bodies);
             }
-
-            None
         }
         StatementType::While {
             expression,
             statement,
-        } => loop {
-            let Value::Boolean(run_loop) = interpret_expression(expression, variables) else {
-                    unreachable!();
-                };
-
-            if run_loop {
-                if let Some(value) = interpret_statement(statement, variables) {
-                    return Some(value);
-                }
-            } else {
-                return None;
+        }
+        | StatementType::For {
+            expression,
+            statement,
+            ..
+        } => {
+            for statement in block_statements(expression) {
+                add_function_bodies(statement, bodies);
             }
-        },
-        StatementType::FunctionDeclaration {
-            name,
-            parameters,
-            return_type,
-            body,
-            shadow_id,
+            add_function_bodies(statement, bodies);
+        }
+        StatementType::Try {
+            statement,
+            catch_statement,
+            ..
         } => {
-            variables.create_variable(
-                name,
-                shadow_id.unwrap(),
-                Value::Function {
-                    parameters: parameters.clone(),
-                    return_type: return_type.clone(),
-                    body: FunctionBody::Statement(body.clone()),
-                    parent_environment: variables.current_environment,
-                },
-            );
-            None
+            add_function_bodies(statement, bodies);
+            add_function_bodies(catch_statement, bodies);
         }
-        StatementType::Return(expression) => {
-            if let Some(expression) = expression {
-                Some(interpret_expression(expression, variables))
-            } else {
-                Some(Value::Void)
+        StatementType::Match { expression, arms } => {
+            for statement in block_statements(expression) {
+                add_function_bodies(statement, bodies);
             }
+            for arm in arms {
+                for statement in arm.guard.iter().flat_map(block_statements) {
+                    add_function_bodies(statement, bodies);
+                }
+                add_function_bodies(&arm.statement, bodies);
+            }
+        }
+        StatementType::Test { body, .. } => add_function_bodies(body, bodies),
+        StatementType::VariableDeclaration {
+            value: expression, ..
         }
+        | StatementType::Return(Some(expression))
+        | StatementType::Break(Some(expression))
+        | StatementType::Raise(expression)
+        | StatementType::Expression(expression) => {
+            for statement in block_statements(expression) {
+                add_function_bodies(statement, bodies);
+            }
+        }
+        StatementType::Return(None)
+        | StatementType::Break(None)
+        | StatementType::ImportNative(_) => {}
     }
 }
 
-fn interpret_expression(expression: &Expression, variables: &mut Variables) -> Value {
-    match &expression.expression_type {
-        ExpressionType::Unary {
-            operation,
-            expression,
-        } => match operation {
-            UnaryOperation::Minus => {
-                let expression_value = interpret_expression(expression, variables);
-                if let Value::Number(number) = expression_value {
-                    Value::Number(-number)
-                } else {
-                    unreachable!()
+impl<'a> Evaluator<'a, '_> {
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        while let Some(task) = self.tasks.pop() {
+            match task {
+                Task::Statement(statement) => {
+                    self.variables.stats.statements_executed += 1;
+                    if let Some((limit, last_step)) = self.variables.step_limit {
+                        if self.variables.stats.statements_executed > last_step {
+                            return Err(RuntimeError {
+                                lines: statement.lines,
+                                error: diagnostic!(STEP_LIMIT_EXCEEDED, limit),
+                            });
+                        }
+                    }
+                    if self.variables.is_cancelled() {
+                        return Err(RuntimeError {
+                            lines: statement.lines,
+                            error: diagnostic!(CANCELLED),
+                        });
+                    }
+                    self.start_span(SpanKind::Statement, statement.lines);
+                    if let Some(coverage) = &mut self.variables.coverage {
+                        coverage.hit(statement);
+                    }
+                    if !matches!(statement.statement, StatementType::Block(_)) {
+                        self.trace(TraceEvent::Statement {
+                            lines: statement.lines,
+                        });
+                        if self.variables.debugger.is_some() {
+                            self.debug(statement.lines)?;
+                        }
+                    }
+                    self.statement(statement);
                 }
-            }
-            UnaryOperation::Not => {
-                let expression_value = interpret_expression(expression, variables);
-                if let Value::Boolean(boolean) = expression_value {
-                    Value::Boolean(!boolean)
-                } else {
-                    unreachable!()
+                Task::Expression(expression) => self.expression(expression),
+                Task::PopValue => {
+                    self.values.pop();
                 }
-            }
-        },
-        ExpressionType::Binary {
-            operation,
-            left_expression,
-            right_expression,
-        } => match operation {
-            BinaryOperation::Add => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Number(left + right),
-                    (Value::String(left), Value::String(right)) => Value::String(left + &right),
-                    _ => {
-                        unreachable!()
+                Task::PopEnvironment => self.variables.pop_environment(),
+                Task::Declare {
+                    variable,
+                    shadow_id,
+                    slot,
+                } => {
+                    let value = self.values.pop().unwrap();
+                    if self.variables.tracer.is_some() {
+                        self.trace(TraceEvent::Assignment {
+                            target: variable.to_string(),
+                            value: value.clone(),
+                        });
                     }
+                    self.variables
+                        .create_variable(variable, shadow_id, slot, value);
                 }
-            }
-            BinaryOperation::Subtract => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Number(left - right),
-                    _ => {
-                        unreachable!()
+                Task::InitializeStatic { variable, slot } => {
+                    let value = self.values.pop().unwrap();
+                    if self.variables.tracer.is_some() {
+                        self.trace(TraceEvent::Assignment {
+                            target: variable.to_string(),
+                            value: value.clone(),
+                        });
                     }
+                    *self.variables.global_mut(slot) = value;
+                    self.variables.initialized_statics.insert(slot);
                 }
-            }
-            BinaryOperation::Multiply => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Number(left * right),
-                    _ => {
-                        unreachable!()
+                Task::Branch {
+                    then_statement,
+                    else_statement,
+                } => {
+                    let Some(Value::Boolean(condition)) = self.values.pop() else {
+                        unreachable!();
+                    };
+                    if condition {
+                        self.tasks.push(Task::Statement(then_statement));
+                    } else if let Some(else_statement) = else_statement {
+                        self.tasks.push(Task::Statement(else_statement));
                     }
                 }
-            }
-            BinaryOperation::Divide => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Number(left / right),
-                    _ => {
-                        unreachable!()
+                Task::Select {
+                    then_expression,
+                    else_expression,
+                } => {
+                    let Some(Value::Boolean(condition)) = self.values.pop() else {
+                        unreachable!();
+                    };
+                    if condition {
+                        self.tasks.push(Task::Expression(then_expression));
+                    } else {
+                        self.tasks.push(Task::Expression(else_expression));
                     }
                 }
-            }
-            BinaryOperation::Equal => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                Value::Boolean(left_value == right_value)
-            }
-            BinaryOperation::NotEqual => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                Value::Boolean(left_value != right_value)
-            }
-            BinaryOperation::Less => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Boolean(left < right),
-                    (Value::String(left), Value::String(right)) => Value::Boolean(left < right),
-                    _ => {
-                        unreachable!()
+                Task::Loop(while_statement) => {
+                    let Some(Value::Boolean(run_loop)) = self.values.pop() else {
+                        unreachable!();
+                    };
+                    let StatementType::While {
+                        expression,
+                        statement,
+                    } = &while_statement.statement
+                    else {
+                        unreachable!();
+                    };
+                    if run_loop {
+                        self.tasks.push(Task::Loop(while_statement));
+                        self.tasks.push(Task::Expression(expression));
+                        self.tasks.push(Task::Statement(statement));
                     }
                 }
-            }
-            BinaryOperation::LessEqual => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Boolean(left <= right),
-                    (Value::String(left), Value::String(right)) => Value::Boolean(left <= right),
-                    _ => {
-                        unreachable!()
+                Task::Iterate(for_statement) => {
+                    let sequence = self.values.pop().unwrap();
+                    self.tasks.push(Task::ForEach {
+                        for_statement,
+                        sequence,
+                        position: 0,
+                    });
+                }
+                Task::ForEach {
+                    for_statement,
+                    sequence,
+                    mut position,
+                } => {
+                    let StatementType::For {
+                        variable,
+                        shadow_id,
+                        statement,
+                        ..
+                    } = &for_statement.statement
+                    else {
+                        unreachable!();
+                    };
+                    let Some(value) = next_item(&sequence, &mut position) else {
+                        continue;
+                    };
+                    self.tasks.push(Task::ForEach {
+                        for_statement,
+                        sequence,
+                        position,
+                    });
+                    if self.variables.tracer.is_some() {
+                        self.trace(TraceEvent::Assignment {
+                            target: variable.to_string(),
+                            value: value.clone(),
+                        });
                     }
+                    self.variables.push_environment(&self.values);
+                    self.variables
+                        .create_variable(*variable, shadow_id.unwrap(), 0, value);
+                    self.tasks.push(Task::PopEnvironment);
+                    self.tasks.push(Task::Statement(statement));
                 }
-            }
-            BinaryOperation::Greater => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Boolean(left > right),
-                    (Value::String(left), Value::String(right)) => Value::Boolean(left > right),
-                    _ => {
+                Task::Repeat(body) => {
+                    self.tasks.push(Task::Repeat(body));
+                    self.tasks.push(Task::Statement(body));
+                }
+                Task::Break { has_value } => {
+                    while let Some(task) = self.tasks.pop() {
+                        match task {
+                            Task::Loop(_) | Task::ForEach { .. } => break,
+                            Task::Repeat(_) => {
+                                if !has_value {
+                                    self.values.push(Value::Void);
+                                }
+                                break;
+                            }
+                            Task::PopEnvironment => self.variables.pop_environment(),
+                            Task::EndSpan { kind, lines, start } => {
+                                self.end_span(kind, lines, start);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                Task::Return if self.call_depth == 0 => {
+                    // Skip the rest of the program, leaving the environments of the blocks it's in.
+                    while let Some(task) = self.tasks.pop() {
+                        match task {
+                            Task::PopEnvironment => self.variables.pop_environment(),
+                            Task::EndSpan { kind, lines, start } => {
+                                self.end_span(kind, lines, start);
+                            }
+                            _ => {}
+                        }
+                    }
+                    self.variables.return_value = self.values.pop().unwrap();
+                    self.variables.returned = true;
+                }
+                Task::Return => {
+                    // Skip the rest of the function, leaving the return value on the value stack.
+                    while let Some(task) = self.tasks.pop() {
+                        match task {
+                            Task::EndCall(value_height) => {
+                                // Returning with ? leaves the values of the expression it's in.
+                                let value = self.values.pop().unwrap();
+                                self.values.truncate(value_height);
+                                self.values.push(value);
+                                break;
+                            }
+                            Task::EndSpan { kind, lines, start } => {
+                                self.end_span(kind, lines, start);
+                            }
+                            _ => {}
+                        }
+                    }
+                    self.end_call();
+                }
+                Task::Raise(lines) => {
+                    self.variables.stats.errors_raised += 1;
+                    // Leave the blocks and functions inside the innermost try statement.
+                    loop {
+                        match self.tasks.pop() {
+                            Some(Task::Catch {
+                                try_statement,
+                                value_height,
+                            }) => {
+                                self.catch(try_statement, value_height);
+                                break;
+                            }
+                            Some(Task::PopEnvironment) => self.variables.pop_environment(),
+                            Some(Task::EndCall(_)) => self.end_call(),
+                            Some(Task::EndSpan { kind, lines, start }) => {
+                                self.end_span(kind, lines, start);
+                            }
+                            Some(_) => {}
+                            None => return Err(uncaught_error(self.values.last().unwrap(), lines)),
+                        }
+                    }
+                }
+                Task::Catch { .. } => {} // The try statement's body finished without raising an error.
+                Task::Match {
+                    match_statement,
+                    arm,
+                } => self.match_arm(match_statement, arm),
+                Task::Guard {
+                    match_statement,
+                    arm,
+                } => {
+                    let StatementType::Match { arms, .. } = &match_statement.statement else {
+                        unreachable!();
+                    };
+                    if self.values.pop() == Some(Value::Boolean(true)) {
+                        self.values.pop();
+                        self.tasks.push(Task::Statement(&arms[arm].statement));
+                    } else {
+                        let Some(Task::PopEnvironment) = self.tasks.pop() else {
+                            unreachable!();
+                        };
+                        self.variables.pop_environment();
+                        self.tasks.push(Task::Match {
+                            match_statement,
+                            arm: arm + 1,
+                        });
+                    }
+                }
+                Task::Some => {
+                    let value = self.values.pop().unwrap();
+                    self.values.push(Value::Option(Some(value.into())));
+                }
+                Task::Ok => {
+                    let value = self.values.pop().unwrap();
+                    self.values.push(Value::Result(Ok(value).into()));
+                }
+                Task::Err => {
+                    let error = self.values.pop().unwrap();
+                    self.values.push(Value::Result(Err(error).into()));
+                }
+                Task::Propagate => {
+                    let Some(Value::Result(result)) = self.values.pop() else {
                         unreachable!()
+                    };
+                    match &*result {
+                        Ok(value) => self.values.push(value.clone()),
+                        Err(_) => {
+                            self.values.push(Value::Result(result));
+                            self.tasks.push(Task::Return);
+                        }
                     }
                 }
-            }
-            BinaryOperation::GreaterEqual => {
-                let left_value = interpret_expression(left_expression, variables);
-                let right_value = interpret_expression(right_expression, variables);
-                match (left_value, right_value) {
-                    (Value::Number(left), Value::Number(right)) => Value::Boolean(left >= right),
-                    (Value::String(left), Value::String(right)) => Value::Boolean(left >= right),
-                    _ => {
+                Task::Unary(UnaryOperation::Dereference) => {
+                    let Some(Value::Reference(reference)) = self.values.pop() else {
                         unreachable!()
+                    };
+                    let value = self.variables.dereference(&reference).clone();
+                    self.values.push(value);
+                }
+                Task::Unary(operation) => {
+                    let value = self.values.pop().unwrap();
+                    self.values.push(unary_operation(operation, value));
+                }
+                Task::Binary(operation) => {
+                    let right_value = self.values.pop().unwrap();
+                    let left_value = self.values.pop().unwrap();
+                    self.push_binary_result(binary_operation(operation, &left_value, &right_value));
+                }
+                Task::ShortCircuit {
+                    operation,
+                    right_expression,
+                } => {
+                    // The left value is the result if it's true for or, or false for and.
+                    let short_circuit_value = Value::Boolean(operation == BinaryOperation::Or);
+                    if self.values.last() != Some(&short_circuit_value) {
+                        self.values.pop();
+                        self.tasks.push(Task::Expression(right_expression));
+                    }
+                }
+                Task::Assign {
+                    parent_height,
+                    slot,
+                } => {
+                    let value = self.values.last().unwrap().clone();
+                    self.variables
+                        .set_variable(parent_height, slot, value)
+                        .unwrap();
+                }
+                Task::Store {
+                    parent_height,
+                    slot,
+                } => {
+                    let value = self.values.pop().unwrap();
+                    self.variables
+                        .set_variable(parent_height, slot, value)
+                        .unwrap();
+                }
+                Task::AssignField(left_expression) => {
+                    let (indices, root) = field_path(left_expression);
+                    // The field is changed in place, so only tuples shared with other values are copied.
+                    let mut lvalue = match &root.expression_type {
+                        ExpressionType::Variable {
+                            parent_height,
+                            slot,
+                            ..
+                        } => self
+                            .variables
+                            .get_variable_mut(parent_height.unwrap(), slot.unwrap())
+                            .unwrap(),
+                        // The reference was evaluated after the value.
+                        _ => {
+                            let Some(Value::Reference(reference)) = self.values.pop() else {
+                                unreachable!()
+                            };
+                            self.variables.dereference_mut(&reference)
+                        }
+                    };
+                    let value = self.values.last().unwrap().clone();
+                    for index in indices {
+                        lvalue = lvalue.field_mut(index);
                     }
+                    *lvalue = value;
                 }
+                Task::AssignReference => {
+                    let Some(Value::Reference(reference)) = self.values.pop() else {
+                        unreachable!()
+                    };
+                    let value = self.values.last().unwrap().clone();
+                    *self.variables.dereference_mut(&reference) = value;
+                }
+                Task::TraceAssignment(target) => {
+                    let value = self.values.last().unwrap().clone();
+                    self.trace(TraceEvent::Assignment {
+                        target: target.to_string(),
+                        value,
+                    });
+                }
+                Task::Tuple(size) => {
+                    let values = self.values.split_off(self.values.len() - size);
+                    self.values.push(Value::Tuple(values.into()));
+                    self.variables.stats.allocations += 1;
+                }
+                Task::Record(expression) => {
+                    let ExpressionType::Record(fields) = &expression.expression_type else {
+                        unreachable!()
+                    };
+                    let values = self.values.split_off(self.values.len() - fields.len());
+                    let fields = fields
+                        .iter()
+                        .map(|(name, _)| *name)
+                        .zip(values)
+                        .collect::<Vec<_>>();
+                    self.values.push(Value::Record(fields.into()));
+                    self.variables.stats.allocations += 1;
+                }
+                Task::Interpolate(expression) => {
+                    let ExpressionType::Interpolation { strings, specs, .. } =
+                        &expression.expression_type
+                    else {
+                        unreachable!()
+                    };
+                    let values = self.values.split_off(self.values.len() - specs.len());
+                    let string = interpolate(strings, specs, &values);
+                    self.push_binary_result(Value::String(string.into()));
+                }
+                Task::TupleGet(index) => {
+                    // A tuple which nothing else refers to is consumed, so its field is moved out instead of copied.
+                    let value = match self.values.pop() {
+                        Some(Value::Tuple(values)) => match Rc::try_unwrap(values) {
+                            Ok(mut values) => values.swap_remove(index),
+                            Err(values) => values[index].clone(),
+                        },
+                        Some(Value::Record(fields)) => match Rc::try_unwrap(fields) {
+                            Ok(mut fields) => fields.swap_remove(index).1,
+                            Err(fields) => fields[index].1.clone(),
+                        },
+                        _ => unreachable!(),
+                    };
+                    self.values.push(value);
+                }
+                Task::Call {
+                    argument_count,
+                    lines,
+                } => self.call(argument_count, lines)?,
+                Task::EndCall(_) => {
+                    // The function finished without a return statement.
+                    self.values.push(Value::Void);
+                    self.end_call();
+                }
+                Task::EndSpan { kind, lines, start } => self.end_span(kind, lines, start),
+            }
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self, statement: &'a Statement) {
+        match &statement.statement {
+            StatementType::Expression(Expression {
+                expression_type:
+                    ExpressionType::Binary {
+                        operation: BinaryOperation::Assignment,
+                        left_expression,
+                        right_expression,
+                    },
+                ..
+            }) if matches!(
+                left_expression.expression_type,
+                ExpressionType::Variable { .. }
+            ) && self.variables.tracer.is_none() =>
+            {
+                // The value of the assignment is unused, so it's moved into the variable.
+                let ExpressionType::Variable {
+                    parent_height,
+                    slot,
+                    ..
+                } = left_expression.expression_type
+                else {
+                    unreachable!()
+                };
+                self.tasks.push(Task::Store {
+                    parent_height: parent_height.unwrap(),
+                    slot: slot.unwrap(),
+                });
+                self.tasks.push(Task::Expression(right_expression));
+            }
+            StatementType::Expression(expression) => {
+                self.tasks.push(Task::PopValue);
+                self.tasks.push(Task::Expression(expression));
+            }
+            StatementType::VariableDeclaration {
+                variable,
+                value,
+                is_static: true,
+                slot,
+                ..
+            } => {
+                let slot = slot.unwrap();
+                if !self.variables.initialized_statics.contains(&slot) {
+                    self.tasks.push(Task::InitializeStatic {
+                        variable: *variable,
+                        slot,
+                    });
+                    self.tasks.push(Task::Expression(value));
+                }
+            }
+            StatementType::VariableDeclaration {
+                variable,
+                value,
+                shadow_id,
+                slot,
+                ..
+            } => {
+                self.tasks.push(Task::Declare {
+                    variable: *variable,
+                    shadow_id: shadow_id.unwrap(),
+                    slot: slot.unwrap(),
+                });
+                self.tasks.push(Task::Expression(value));
+            }
+            StatementType::Block(statements) => {
+                self.variables.push_environment(&self.values);
+                self.tasks.push(Task::PopEnvironment);
+                self.tasks
+                    .extend(statements.iter().rev().map(Task::Statement));
+            }
+            StatementType::If {
+                expression,
+                then_statement,
+                else_statement,
+            } => {
+                self.tasks.push(Task::Branch {
+                    then_statement,
+                    else_statement: else_statement.as_deref(),
+                });
+                self.tasks.push(Task::Expression(expression));
+            }
+            StatementType::While { expression, .. } => {
+                self.tasks.push(Task::Loop(statement));
+                self.tasks.push(Task::Expression(expression));
             }
-            BinaryOperation::Or => {
-                let left_value = interpret_expression(left_expression, variables);
-                if left_value == Value::Boolean(true) {
-                    left_value
-                } else {
-                    interpret_expression(right_expression, variables)
+            StatementType::For { expression, .. } => {
+                self.tasks.push(Task::Iterate(statement));
+                self.tasks.push(Task::Expression(expression));
+            }
+            StatementType::Try {
+                statement: body, ..
+            } => {
+                self.tasks.push(Task::Catch {
+                    try_statement: statement,
+                    value_height: self.values.len(),
+                });
+                self.tasks.push(Task::Statement(body));
+            }
+            StatementType::Match { expression, .. } => {
+                self.tasks.push(Task::Match {
+                    match_statement: statement,
+                    arm: 0,
+                });
+                self.tasks.push(Task::Expression(expression));
+            }
+            StatementType::Test { .. } => unreachable!(), // Tests are removed before checking.
+            StatementType::ImportNative(_) => unreachable!(), // Imports are removed before checking.
+            StatementType::FunctionDeclaration {
+                name,
+                parameters,
+                return_type,
+                body,
+                shadow_id,
+                slot,
+                ..
+            } => {
+                let function = Value::Function(Rc::new(FunctionValue {
+                    parameters: parameters.clone(),
+                    return_type: return_type.clone(),
+                    body: FunctionBody::Statement(body.clone()),
+                    parent_environment: self.variables.current_environment,
+                }));
+                self.variables.stats.allocations += 1;
+                self.variables
+                    .create_variable(*name, shadow_id.unwrap(), slot.unwrap(), function);
+            }
+            StatementType::Break(expression) => {
+                self.tasks.push(Task::Break {
+                    has_value: expression.is_some(),
+                });
+                if let Some(expression) = expression {
+                    self.tasks.push(Task::Expression(expression));
                 }
             }
-            BinaryOperation::And => {
-                let left_value = interpret_expression(left_expression, variables);
-                if left_value == Value::Boolean(false) {
-                    left_value
-                } else {
-                    interpret_expression(right_expression, variables)
+            StatementType::Raise(expression) => {
+                self.tasks.push(Task::Raise(statement.lines));
+                self.tasks.push(Task::Expression(expression));
+            }
+            StatementType::Return(expression) => {
+                self.tasks.push(Task::Return);
+                match expression {
+                    Some(expression) => self.tasks.push(Task::Expression(expression)),
+                    None => self.values.push(Value::Void),
                 }
             }
-            BinaryOperation::Assignment => {
-                let value = interpret_expression(right_expression, variables);
+        }
+    }
+
+    fn expression(&mut self, expression: &'a Expression) {
+        match &expression.expression_type {
+            ExpressionType::Unary {
+                operation: UnaryOperation::Reference,
+                expression,
+            } => {
+                let ExpressionType::Variable {
+                    parent_height,
+                    slot,
+                    ..
+                } = expression.expression_type
+                else {
+                    unreachable!()
+                };
+                let reference = self
+                    .variables
+                    .reference(parent_height.unwrap(), slot.unwrap());
+                self.values.push(Value::Reference(reference.into()));
+                self.variables.stats.allocations += 1;
+            }
+            ExpressionType::Unary {
+                operation,
+                expression,
+            } => {
+                self.tasks.push(Task::Unary(*operation));
+                self.tasks.push(Task::Expression(expression));
+            }
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => {
+                self.tasks.push(Task::Select {
+                    then_expression,
+                    else_expression,
+                });
+                self.tasks.push(Task::Expression(condition));
+            }
+            ExpressionType::Loop(body) => {
+                self.tasks.push(Task::Repeat(body));
+                self.tasks.push(Task::Statement(body));
+            }
+            ExpressionType::Block { statements, value } => {
+                self.variables.push_environment(&self.values);
+                self.tasks.push(Task::PopEnvironment);
+                self.tasks.push(Task::Expression(value));
+                self.tasks
+                    .extend(statements.iter().rev().map(Task::Statement));
+            }
+            ExpressionType::Binary {
+                operation: operation @ (BinaryOperation::And | BinaryOperation::Or),
+                left_expression,
+                right_expression,
+            } => {
+                self.tasks.push(Task::ShortCircuit {
+                    operation: *operation,
+                    right_expression,
+                });
+                self.tasks.push(Task::Expression(left_expression));
+            }
+            ExpressionType::Binary {
+                operation: BinaryOperation::Assignment,
+                left_expression,
+                right_expression,
+            } => {
+                if self.variables.tracer.is_some() {
+                    self.tasks.push(Task::TraceAssignment(left_expression));
+                }
                 match &left_expression.expression_type {
                     ExpressionType::Variable {
-                        name,
-                        shadow_id,
                         parent_height,
+                        slot,
+                        ..
+                    } => self.tasks.push(Task::Assign {
+                        parent_height: parent_height.unwrap(),
+                        slot: slot.unwrap(),
+                    }),
+                    ExpressionType::Unary {
+                        operation: UnaryOperation::Dereference,
+                        expression,
                     } => {
-                        variables
-                            .set_variable(
-                                name,
-                                shadow_id.unwrap(),
-                                parent_height.unwrap(),
-                                value.clone(),
-                            )
-                            .unwrap();
-                        value
+                        self.tasks.push(Task::AssignReference);
+                        self.tasks.push(Task::Expression(expression));
                     }
-                    ExpressionType::TupleAccess { expression, index } => {
-                        // We access the tuple field and mutate it if the field is in a variable.
-                        let mut current_expression = expression;
-                        let mut indices = vec![*index];
-                        loop {
-                            match &current_expression.expression_type {
-                                ExpressionType::TupleAccess { expression, index } => {
-                                    current_expression = expression;
-                                    indices.push(*index);
-                                }
-                                ExpressionType::Variable {
-                                    name,
-                                    shadow_id,
-                                    parent_height,
-                                } => {
-                                    let mut variable_value = variables
-                                        .get_variable(
-                                            name,
-                                            shadow_id.unwrap(),
-                                            parent_height.unwrap(),
-                                        )
-                                        .unwrap();
-                                    let mut lvalue = &mut variable_value;
-                                    for &index in indices.iter().rev() {
-                                        let Value::Tuple(values) = lvalue else { unreachable!() };
-                                        lvalue = &mut values[index];
-                                    }
-                                    *lvalue = value.clone();
-                                    variables
-                                        .set_variable(
-                                            name,
-                                            shadow_id.unwrap(),
-                                            parent_height.unwrap(),
-                                            variable_value,
-                                        )
-                                        .unwrap();
-                                    break;
-                                }
-                                _ => {
-                                    // NOTE: may change when I add pointers.
-                                    interpret_expression(expression, variables);
-                                    break;
-                                }
+                    ExpressionType::TupleAccess { expression, .. } => {
+                        // We mutate the tuple field if the tuple is in a variable or a referenced variable.
+                        match &field_path(expression).1.expression_type {
+                            ExpressionType::Variable { .. } => {
+                                self.tasks.push(Task::AssignField(left_expression));
+                            }
+                            ExpressionType::Unary {
+                                operation: UnaryOperation::Dereference,
+                                expression: reference,
+                            } => {
+                                self.tasks.push(Task::AssignField(left_expression));
+                                self.tasks.push(Task::Expression(reference));
+                            }
+                            _ => {
+                                self.tasks.push(Task::PopValue);
+                                self.tasks.push(Task::Expression(expression));
                             }
                         }
-                        value
-                    }
-                    _ => {
-                        unreachable!()
                     }
+                    _ => unreachable!(),
                 }
+                self.tasks.push(Task::Expression(right_expression));
             }
-        },
-        ExpressionType::Variable {
-            name,
+            ExpressionType::Binary {
+                operation,
+                left_expression,
+                right_expression,
+            } => {
+                // Operands which can be borrowed are used in place, so comparing strings or tuples doesn't copy them.
+                if can_borrow(left_expression) && can_borrow(right_expression) {
+                    let value = binary_operation(
+                        *operation,
+                        self.borrow(left_expression),
+                        self.borrow(right_expression),
+                    );
+                    self.push_binary_result(value);
+                    return;
+                }
+                self.tasks.push(Task::Binary(*operation));
+                self.tasks.push(Task::Expression(right_expression));
+                self.tasks.push(Task::Expression(left_expression));
+            }
+            ExpressionType::Literal(_) | ExpressionType::Variable { .. } => {
+                self.values.push(self.borrow(expression).clone());
+            }
+            ExpressionType::Grouping(expression) => {
+                self.tasks.push(Task::Expression(expression));
+            }
+            ExpressionType::Ok(value) => {
+                self.tasks.push(Task::Ok);
+                self.tasks.push(Task::Expression(value));
+            }
+            ExpressionType::Err(error) => {
+                self.tasks.push(Task::Err);
+                self.tasks.push(Task::Expression(error));
+            }
+            ExpressionType::Some(value) => {
+                self.tasks.push(Task::Some);
+                self.tasks.push(Task::Expression(value));
+            }
+            ExpressionType::Propagate(result) => {
+                self.tasks.push(Task::Propagate);
+                self.tasks.push(Task::Expression(result));
+            }
+            ExpressionType::Tuple(expressions) => {
+                self.tasks.push(Task::Tuple(expressions.len()));
+                self.tasks
+                    .extend(expressions.iter().rev().map(Task::Expression));
+            }
+            ExpressionType::Record(fields) => {
+                self.tasks.push(Task::Record(expression));
+                self.tasks.extend(
+                    fields
+                        .iter()
+                        .rev()
+                        .map(|(_, expression)| Task::Expression(expression)),
+                );
+            }
+            ExpressionType::Interpolation { values, .. } => {
+                self.tasks.push(Task::Interpolate(expression));
+                self.tasks.extend(values.iter().rev().map(Task::Expression));
+            }
+            ExpressionType::TupleAccess { .. } if can_borrow(expression) => {
+                // Only the field is copied, not the tuples it's in.
+                self.values.push(self.borrow(expression).clone());
+            }
+            ExpressionType::TupleAccess { expression, field } => {
+                self.tasks.push(Task::TupleGet(field.index()));
+                self.tasks.push(Task::Expression(expression));
+            }
+            ExpressionType::FunctionCall {
+                function,
+                arguments,
+            } => {
+                self.tasks.push(Task::Call {
+                    argument_count: arguments.len(),
+                    lines: expression.lines,
+                });
+                self.tasks
+                    .extend(arguments.iter().rev().map(Task::Expression));
+                self.tasks.push(Task::Expression(function));
+            }
+        }
+    }
+
+    /// Returns the value of an expression which can_borrow, without evaluating it.
+    fn borrow<'b>(&'b self, expression: &'b Expression) -> &'b Value {
+        match &expression.expression_type {
+            ExpressionType::Literal(value) => value,
+            ExpressionType::Variable {
+                parent_height,
+                slot,
+                ..
+            } => self
+                .variables
+                .get_variable(parent_height.unwrap(), slot.unwrap())
+                .unwrap(),
+            ExpressionType::Grouping(expression) => self.borrow(expression),
+            ExpressionType::TupleAccess { expression, field } => {
+                self.borrow(expression).field(field.index())
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn push_binary_result(&mut self, value: Value) {
+        if matches!(&value, Value::String(string) if !string.is_inline())
+            || matches!(value, Value::Tuple(_))
+        {
+            self.variables.stats.allocations += 1;
+        }
+        self.values.push(value);
+    }
+
+    /// Calls the function below the arguments on the value stack.
+    fn call(&mut self, argument_count: usize, lines: (usize, usize)) -> Result<(), RuntimeError> {
+        let function_index = self.values.len() - argument_count - 1;
+        let Value::Function(function) = &self.values[function_index] else {
+            unreachable!();
+        };
+        let function = function.clone();
+
+        match &function.body {
+            FunctionBody::Statement(body) => {
+                #[cfg(feature = "jit")]
+                if self.call_native(&function, body, function_index, lines)? {
+                    return Ok(());
+                }
+
+                if self.call_depth == MAX_CALL_DEPTH {
+                    return Err(RuntimeError {
+                        lines,
+                        error: diagnostic!(CALL_DEPTH_EXCEEDED, MAX_CALL_DEPTH),
+                    });
+                }
+                self.call_depth += 1;
+                self.call_lines.push(lines);
+                let stats = &mut self.variables.stats;
+                stats.max_call_depth = stats.max_call_depth.max(self.call_depth);
+                self.start_span(SpanKind::Function, body.lines);
+
+                // The environment is created while the function and arguments are still on the value stack,
+                // so that they are kept if the garbage collector runs.
+                self.variables
+                    .push_function_environment(function.parent_environment);
+                self.variables.push_environment(&self.values);
+                let arguments = self.values.split_off(function_index + 1);
+                self.values.pop();
+                for (slot, ((parameter, shadow_id, _), value)) in
+                    function.parameters.iter().zip(arguments).enumerate()
+                {
+                    self.variables
+                        .create_variable(*parameter, shadow_id.unwrap(), slot, value);
+                }
+
+                self.tasks.push(Task::EndCall(function_index));
+                // The body is run directly, as the function's span already covers it.
+                self.statement(self.bodies[&Rc::as_ptr(body)]);
+            }
+            FunctionBody::RustClosure { closure, .. } => {
+                let arguments = self.values.split_off(function_index + 1);
+                self.values.pop();
+                self.variables.builtin_line.set(lines.0);
+                self.values.push(closure(arguments));
+                if let Some(error) = self.variables.take_builtin_error() {
+                    return Err(RuntimeError { lines, error });
+                }
+                if self.variables.garbage_collection.requested() {
+                    self.variables.collect_garbage(&self.values);
+                }
+            }
+            FunctionBody::Bytecode(_) => {
+                unreachable!("bytecode functions are only created by the VM")
+            }
+        }
+        Ok(())
+    } // TODO: Handle return types
+
+    /// Runs the function as native code if it's hot and can be compiled.
+    /// Returns false if the function should be interpreted.
+    #[cfg(feature = "jit")]
+    fn call_native(
+        &mut self,
+        function: &FunctionValue,
+        body: &Rc<Statement>,
+        function_index: usize,
+        lines: (usize, usize),
+    ) -> Result<bool, RuntimeError> {
+        // Profiled programs are interpreted so that every span is recorded, and likewise for coverage,
+        // tracing and debugging.
+        if self.variables.profiler.is_some()
+            || self.variables.coverage.is_some()
+            || self.variables.tracer.is_some()
+            || self.variables.debugger.is_some()
+        {
+            return Ok(false);
+        }
+        let Some(jit) = &mut self.variables.jit else {
+            return Ok(false);
+        };
+        let Some(compiled_function) =
+            jit.function(body, &function.parameters, &function.return_type)
+        else {
+            return Ok(false);
+        };
+        if let Some((parent_height, slot)) = compiled_function.recursive_variable {
+            let recursive_function =
+                self.variables
+                    .get_variable_from(function.parent_environment, parent_height, slot);
+            let calls_itself = matches!(
+                recursive_function,
+                Some(Value::Function(recursive_function))
+                    if matches!(&recursive_function.body, FunctionBody::Statement(recursive_body) if Rc::ptr_eq(recursive_body, body))
+            );
+            if !calls_itself {
+                return Ok(false);
+            }
+        }
+
+        let value = compiled_function
+            .call(
+                &self.values[function_index + 1..],
+                self.call_depth,
+                self.variables,
+            )
+            .map_err(|(error, stopped_at)| RuntimeError {
+                lines: stopped_at.unwrap_or(lines),
+                error,
+            })?;
+        self.values.truncate(function_index);
+        self.values.push(value);
+        Ok(true)
+    }
+
+    /// Records a hit of the span if the profiler is on, and times it until the tasks pushed after this finish.
+    fn start_span(&mut self, kind: SpanKind, lines: (usize, usize)) {
+        if let Some(profiler) = &mut self.variables.profiler {
+            let start = profiler.enter(kind, lines);
+            self.tasks.push(Task::EndSpan { kind, lines, start });
+        }
+    }
+
+    fn trace(&mut self, event: TraceEvent) {
+        if let Some(tracer) = &mut self.variables.tracer {
+            tracer(&event);
+        }
+    }
+
+    fn end_span(&mut self, kind: SpanKind, lines: (usize, usize), start: Instant) {
+        if let Some(profiler) = &mut self.variables.profiler {
+            profiler.exit(kind, lines, start);
+        }
+    }
+
+    /// Runs the catch statement of the try statement with the error on top of the value stack.
+    fn catch(&mut self, try_statement: &'a Statement, value_height: usize) {
+        let StatementType::Try {
+            variable,
             shadow_id,
-            parent_height,
-        } => variables
-            .get_variable(name, shadow_id.unwrap(), parent_height.unwrap())
-            .unwrap(),
-        ExpressionType::Literal(value) => value.clone(),
-        ExpressionType::Grouping(expression) => interpret_expression(expression, variables),
-        ExpressionType::Tuple(expressions) => Value::Tuple(
-            expressions
-                .iter()
-                .map(|expression| interpret_expression(expression, variables))
-                .collect(),
-        ),
-        ExpressionType::TupleAccess { expression, index } => {
-            let Value::Tuple(values) = interpret_expression(expression, variables) else {
-                unreachable!()
-            };
-            values[*index].clone()
-        }
-        ExpressionType::FunctionCall {
-            function,
-            arguments,
-        } => {
-            let Value::Function { parameters, body, parent_environment , ..} = interpret_expression(function, variables) else {
-                unreachable!();
-            };
-
-            let mut argument_values = vec![];
-            for argument in arguments {
-                argument_values.push(interpret_expression(argument, variables));
-            }
-
-            match body {
-                FunctionBody::Statement(statement) => {
-                    variables.push_function_environment(parent_environment);
-                    variables.push_environment();
-                    for ((parameter, shadow_id), value) in parameters
-                        .into_iter()
-                        .map(|(parameter, shadow_id, _)| (parameter, shadow_id))
-                        .zip(argument_values.into_iter())
-                    {
-                        variables.create_variable(&parameter, shadow_id.unwrap(), value);
-                    }
-                    let return_value =
-                        interpret_statement(&statement, variables).unwrap_or(Value::Void);
-                    variables.pop_environment();
-                    variables.pop_function_environment();
-                    return_value
+            catch_statement,
+            ..
+        } = &try_statement.statement
+        else {
+            unreachable!();
+        };
+        let error = self.values.pop().unwrap();
+        self.values.truncate(value_height);
+        if self.variables.tracer.is_some() {
+            self.trace(TraceEvent::Assignment {
+                target: variable.to_string(),
+                value: error.clone(),
+            });
+        }
+        // The error stays on the value stack while the environment is created, so it's kept if the garbage collector runs.
+        self.values.push(error);
+        self.variables.push_environment(&self.values);
+        let error = self.values.pop().unwrap();
+        self.variables
+            .create_variable(*variable, shadow_id.unwrap(), 0, error);
+        self.tasks.push(Task::PopEnvironment);
+        self.tasks.push(Task::Statement(catch_statement));
+    }
+
+    fn match_arm(&mut self, match_statement: &'a Statement, first_arm: usize) {
+        let StatementType::Match { arms, .. } = &match_statement.statement else {
+            unreachable!();
+        };
+        let value = self.values.last().unwrap();
+        let mut values = vec![];
+        let Some((index, arm)) = arms.iter().enumerate().skip(first_arm).find(|(_, arm)| {
+            values.clear();
+            match_pattern(&arm.pattern, value, &mut values)
+        }) else {
+            // No arm matches the value.
+            self.values.pop();
+            return;
+        };
+        // The value stays on the value stack while the environment is created, so the variables' values are kept
+        // if the garbage collector runs. It's kept until the guard is checked, for the arms after this one.
+        self.variables.push_environment(&self.values);
+        for (slot, ((variable, shadow_id), value)) in
+            arm.pattern.variables().into_iter().zip(values).enumerate()
+        {
+            if self.variables.tracer.is_some() {
+                self.trace(TraceEvent::Assignment {
+                    target: variable.to_string(),
+                    value: value.clone(),
+                });
+            }
+            self.variables
+                .create_variable(variable, shadow_id.unwrap(), slot, value);
+        }
+        self.tasks.push(Task::PopEnvironment);
+        match &arm.guard {
+            Some(guard) => {
+                self.tasks.push(Task::Guard {
+                    match_statement,
+                    arm: index,
+                });
+                self.tasks.push(Task::Expression(guard));
+            }
+            None => {
+                self.values.pop();
+                self.tasks.push(Task::Statement(&arm.statement));
+            }
+        }
+    }
+
+    fn end_call(&mut self) {
+        self.variables.pop_function_environment();
+        self.call_depth -= 1;
+        self.call_lines.pop();
+    }
+
+    /// Lets the debugger pause the program before the statement.
+    fn debug(&mut self, lines: (usize, usize)) -> Result<(), RuntimeError> {
+        // The debugger is taken out of the variables so that the pause can borrow them.
+        let mut debugger = self.variables.debugger.take().unwrap();
+        let mut result = Ok(());
+        if debugger.should_pause(lines.0, self.call_lines.len())
+            && debugger.pause(lines, &self.call_lines, self.variables) == DebugCommand::Quit
+        {
+            self.variables.exit_code.set(Some(0));
+            result = Err(RuntimeError {
+                lines,
+                error: diagnostic!(STOPPED_BY_DEBUGGER),
+            });
+        }
+        self.variables.debugger = Some(debugger);
+        result
+    }
+}
+
+/// Can the value of the expression be borrowed instead of evaluated?
+/// True for literals, variables and fields of them.
+fn can_borrow(expression: &Expression) -> bool {
+    match &expression.expression_type {
+        ExpressionType::Literal(_) | ExpressionType::Variable { .. } => true,
+        ExpressionType::Grouping(expression) | ExpressionType::TupleAccess { expression, .. } => {
+            can_borrow(expression)
+        }
+        _ => false,
+    }
+}
+
+pub fn unary_operation(operation: UnaryOperation, value: Value) -> Value {
+    match (operation, value) {
+        (UnaryOperation::Minus, Value::Number(number)) => Value::Number(-number),
+        (UnaryOperation::Not, Value::Boolean(boolean)) => Value::Boolean(!boolean),
+        (UnaryOperation::BitwiseNot, Value::Number(number)) => {
+            Value::Number(!(number as i64) as f64)
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// The type of the errors which can be raised and caught.
+pub fn error_type() -> Type {
+    Type::Record(vec![
+        (Symbol::intern("message"), Type::String),
+        (Symbol::intern("code"), Type::Number),
+    ])
+}
+
+/// The runtime error which stops the program when an error raised by the statement on the lines isn't caught.
+pub fn uncaught_error(error: &Value, lines: (usize, usize)) -> RuntimeError {
+    let (Value::String(message), Value::Number(code)) = (error.field(0), error.field(1)) else {
+        unreachable!()
+    };
+    RuntimeError {
+        lines,
+        error: diagnostic!(UNCAUGHT_ERROR, code, message),
+    }
+}
+
+/// Whether the value matches the pattern. If it does, the values of the pattern's variables are added in order.
+pub fn match_pattern(pattern: &Pattern, value: &Value, variables: &mut Vec<Value>) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Variable { .. }, _) => {
+            variables.push(value.clone());
+            true
+        }
+        (Pattern::Literal(literal), _) => literal == value,
+        (Pattern::Tuple(patterns), Value::Tuple(values)) => {
+            patterns.len() == values.len()
+                && patterns
+                    .iter()
+                    .zip(values.iter())
+                    .all(|(pattern, value)| match_pattern(pattern, value, variables))
+        }
+        (Pattern::Some(pattern), Value::Option(Some(value))) => {
+            match_pattern(pattern, value, variables)
+        }
+        (Pattern::Ok(pattern), Value::Result(result)) => match &**result {
+            Ok(value) => match_pattern(pattern, value, variables),
+            Err(_) => false,
+        },
+        (Pattern::Err(pattern), Value::Result(result)) => match &**result {
+            Ok(_) => false,
+            Err(error) => match_pattern(pattern, error, variables),
+        },
+        (Pattern::Or(patterns), _) => patterns.iter().enumerate().any(|(index, pattern)| {
+            if index == 0 {
+                let length = variables.len();
+                if match_pattern(pattern, value, variables) {
+                    return true;
                 }
-                FunctionBody::RustClosure { closure, .. } => closure(argument_values),
-            } // TODO: Handle return types
+                // Remove the variables of a tuple which only partly matched.
+                variables.truncate(length);
+                return false;
+            }
+            let mut alternative_variables = vec![];
+            if !match_pattern(pattern, value, &mut alternative_variables) {
+                return false;
+            }
+            // The slots of the variables are in the order of the first pattern, which may not be the order of this one.
+            let names = pattern.variables();
+            for (name, _) in patterns[0].variables() {
+                let index = names.iter().position(|(other, _)| *other == name).unwrap();
+                variables.push(alternative_variables[index].clone());
+            }
+            true
+        }),
+        (Pattern::Range { start, end }, Value::Number(number)) => start <= number && number < end,
+        _ => false,
+    }
+}
+
+/// Returns the grapheme cluster of the string which starts at the byte position, if it isn't at the end.
+pub fn next_grapheme(string: &str, position: usize) -> Option<&str> {
+    string[position..].graphemes(true).next()
+}
+
+/// Takes the next value a for loop runs its body for from the string or iterator. For strings, it's the grapheme
+/// cluster at the byte position, and the position is advanced past it.
+pub fn next_item(sequence: &Value, position: &mut usize) -> Option<Value> {
+    match sequence {
+        Value::String(string) => {
+            let grapheme = next_grapheme(string, *position)?;
+            *position += grapheme.len();
+            Some(Value::String(grapheme.into()))
         }
+        Value::Iterator(iterator) => iterator.next(),
+        _ => unreachable!(),
+    }
+}
+
+/// Applies a bitwise operation to the whole number parts of the numbers, as 64 bit integers.
+/// Numbers outside the range of 64 bit integers are clamped to it, and shifts only use the lowest 6 bits of the amount.
+pub fn bitwise_operation(operation: BinaryOperation, left: f64, right: f64) -> f64 {
+    let (left, right) = (left as i64, right as i64);
+    (match operation {
+        BinaryOperation::BitwiseAnd => left & right,
+        BinaryOperation::BitwiseOr => left | right,
+        BinaryOperation::BitwiseXor => left ^ right,
+        BinaryOperation::ShiftLeft => left.wrapping_shl(right as u32),
+        BinaryOperation::ShiftRight => left.wrapping_shr(right as u32),
+        _ => unreachable!(),
+    }) as f64
+}
+
+/// Applies an operation other than and, or and assignment, which the evaluator handles itself.
+pub fn binary_operation(
+    operation: BinaryOperation,
+    left_value: &Value,
+    right_value: &Value,
+) -> Value {
+    match (operation, left_value, right_value) {
+        (BinaryOperation::Equal, left, right) => Value::Boolean(left == right),
+        (BinaryOperation::NotEqual, left, right) => Value::Boolean(left != right),
+        (BinaryOperation::Add, Value::String(left), Value::String(right)) => {
+            Value::String(left.concat(right))
+        }
+        (BinaryOperation::Add, Value::Tuple(left), Value::Tuple(right)) => Value::Tuple(
+            left.iter()
+                .chain(right.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        (BinaryOperation::Less, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left < right)
+        }
+        (BinaryOperation::LessEqual, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left <= right)
+        }
+        (BinaryOperation::Greater, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left > right)
+        }
+        (BinaryOperation::GreaterEqual, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left >= right)
+        }
+        (operation, &Value::Number(left), &Value::Number(right)) => match operation {
+            BinaryOperation::Add => Value::Number(left + right),
+            BinaryOperation::Subtract => Value::Number(left - right),
+            BinaryOperation::Multiply => Value::Number(left * right),
+            BinaryOperation::Divide => Value::Number(left / right),
+            BinaryOperation::Less => Value::Boolean(left < right),
+            BinaryOperation::LessEqual => Value::Boolean(left <= right),
+            BinaryOperation::Greater => Value::Boolean(left > right),
+            BinaryOperation::GreaterEqual => Value::Boolean(left >= right),
+            BinaryOperation::BitwiseAnd
+            | BinaryOperation::BitwiseOr
+            | BinaryOperation::BitwiseXor
+            | BinaryOperation::ShiftLeft
+            | BinaryOperation::ShiftRight => {
+                Value::Number(bitwise_operation(operation, left, right))
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
     }
 }
@@ -0,0 +1,881 @@
+use std::{
+    collections::HashMap,
+    mem::{offset_of, ManuallyDrop},
+    rc::Rc,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use cranelift_codegen::{
+    entity::EntityRef,
+    ir::{
+        condcodes::{FloatCC, IntCC},
+        types, AbiParam, FuncRef, InstBuilder, MemFlags, Signature, Value as NativeValue,
+    },
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Module};
+
+use crate::{
+    interpreter::{Variables, MAX_CALL_DEPTH},
+    messages::{diagnostic, Diagnostic},
+    parser::{
+        BinaryOperation, Expression, ExpressionType, Statement, StatementType, UnaryOperation,
+    },
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+};
+
+/// How many times a function is called before it's compiled to native code.
+pub const JIT_THRESHOLD: usize = 1000;
+
+/// Takes the arguments, the call depth and the context.
+/// Numbers and booleans are passed as f64, with booleans being 0.0 or 1.0.
+type EntryPoint = unsafe extern "C" fn(*const f64, i64, *mut NativeContext) -> f64;
+
+/// Why compiled code stopped before returning, in NativeContext::stop.
+const STOPPED_AT_CALL_DEPTH: i64 = 1;
+const STOPPED_AT_STEP_LIMIT: i64 = 2;
+const STOPPED_WHEN_CANCELLED: i64 = 3;
+
+/// What compiled code shares with the interpreter during a call.
+#[repr(C)]
+struct NativeContext {
+    statements_executed: u64, // Counted like the tree walker counts them.
+    last_step: u64,           // The code stops before running a statement past this.
+    cancelled: *const AtomicBool,
+    stop: i64,            // Why the code stopped, or 0 if it hasn't.
+    stop_lines: [u64; 2], // The statement or call the code stopped at, or 0 if it stopped before running anything.
+}
+
+/// Never set, for programs which can't be cancelled.
+static NOT_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// A function compiled to native code.
+#[derive(Clone, Copy)]
+pub struct CompiledFunction {
+    entry_point: EntryPoint,
+    returns_boolean: bool,
+    /// The variable recursive calls go through, by parent height from the environment the function
+    /// was defined in and slot. The native code calls itself directly, so this must hold the function.
+    pub recursive_variable: Option<(usize, usize)>,
+}
+
+impl CompiledFunction {
+    /// Runs the function, counting its statements in the stats like the tree walker does.
+    /// If the maximum call depth is exceeded, the step limit is reached or the program is cancelled, returns the error
+    /// and the lines of the statement or call it happened at, or None if it happened before the function started.
+    pub fn call(
+        &self,
+        arguments: &[Value],
+        call_depth: usize,
+        variables: &mut Variables,
+    ) -> Result<Value, (Diagnostic, Option<(usize, usize)>)> {
+        let arguments = arguments
+            .iter()
+            .map(|argument| match argument {
+                Value::Number(number) => *number,
+                Value::Boolean(boolean) => f64::from(u8::from(*boolean)),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let mut context = NativeContext {
+            statements_executed: variables.stats.statements_executed as u64,
+            last_step: variables
+                .step_limit
+                .map_or(u64::MAX, |(_, last_step)| last_step as u64),
+            cancelled: variables
+                .cancelled
+                .as_ref()
+                .map_or(&NOT_CANCELLED, Arc::as_ptr),
+            stop: 0,
+            stop_lines: [0, 0],
+        };
+        // The function was compiled for these parameter types, which the type checker enforces.
+        let result =
+            unsafe { (self.entry_point)(arguments.as_ptr(), call_depth as i64, &mut context) };
+        variables.stats.statements_executed = context.statements_executed as usize;
+        let error = match context.stop {
+            0 if self.returns_boolean => return Ok(Value::Boolean(result != 0.0)),
+            0 => return Ok(Value::Number(result)),
+            STOPPED_AT_CALL_DEPTH => diagnostic!(CALL_DEPTH_EXCEEDED, MAX_CALL_DEPTH),
+            STOPPED_AT_STEP_LIMIT => {
+                diagnostic!(STEP_LIMIT_EXCEEDED, variables.step_limit.unwrap().0)
+            }
+            _ => diagnostic!(CANCELLED),
+        };
+        let [start, end] = context.stop_lines;
+        Err((
+            error,
+            (start != 0).then_some((start as usize, end as usize)),
+        ))
+    }
+}
+
+enum JitState {
+    Counting(usize),
+    Compiled(CompiledFunction),
+    Unsupported,
+}
+
+/// Compiles hot functions which only use numbers and booleans to native code with Cranelift.
+/// Functions can call themselves, but calling other functions or using variables from outside
+/// the function makes it run in the tree walker.
+pub struct Jit {
+    module: ManuallyDrop<JITModule>,
+    functions: HashMap<*const Statement, JitState>, // By the address of their body.
+    bodies: Vec<Rc<Statement>>, // Bodies which were compiled, kept so their address isn't reused by another function.
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Jit {
+    pub fn new() -> Jit {
+        let mut flags = settings::builder();
+        flags.set("opt_level", "speed").unwrap();
+        let isa = cranelift_native::builder()
+            .unwrap()
+            .finish(settings::Flags::new(flags))
+            .unwrap();
+        Jit {
+            module: ManuallyDrop::new(JITModule::new(JITBuilder::with_isa(
+                isa,
+                default_libcall_names(),
+            ))),
+            functions: HashMap::new(),
+            bodies: vec![],
+        }
+    }
+
+    /// Counts a call of a function, compiling it once it's hot.
+    /// Returns the compiled function if there is one.
+    pub fn function(
+        &mut self,
+        body: &Rc<Statement>,
+        parameters: &[(Symbol, Option<usize>, Type)],
+        return_type: &Type,
+    ) -> Option<CompiledFunction> {
+        let state = self
+            .functions
+            .entry(Rc::as_ptr(body))
+            .or_insert(JitState::Counting(0));
+        match state {
+            JitState::Counting(count) if *count + 1 < JIT_THRESHOLD => {
+                *count += 1;
+                None
+            }
+            JitState::Counting(_) => {
+                let compiled_function = self.compile(body, parameters, return_type);
+                let state = match compiled_function {
+                    Some(compiled_function) => JitState::Compiled(compiled_function),
+                    None => JitState::Unsupported,
+                };
+                self.functions.insert(Rc::as_ptr(body), state);
+                self.bodies.push(body.clone());
+                compiled_function
+            }
+            JitState::Compiled(compiled_function) => Some(*compiled_function),
+            JitState::Unsupported => None,
+        }
+    }
+
+    fn compile(
+        &mut self,
+        body: &Statement,
+        parameters: &[(Symbol, Option<usize>, Type)],
+        return_type: &Type,
+    ) -> Option<CompiledFunction> {
+        let returns_boolean = match return_type {
+            Type::Number => false,
+            Type::Boolean => true,
+            _ => return None,
+        };
+        if parameters
+            .iter()
+            .any(|(_, _, parameter_type)| !matches!(parameter_type, Type::Number | Type::Boolean))
+        {
+            return None;
+        }
+
+        let pointer_type = self.module.target_config().pointer_type();
+        let call_conv = self.module.target_config().default_call_conv;
+
+        // The function takes its arguments, the call depth and the pointer to the context.
+        let mut signature = Signature::new(call_conv);
+        for _ in parameters {
+            signature.params.push(AbiParam::new(types::F64));
+        }
+        signature.params.push(AbiParam::new(types::I64));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.returns.push(AbiParam::new(types::F64));
+        let function_id = self.module.declare_anonymous_function(&signature).unwrap();
+
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let this = self.module.declare_func_in_func(function_id, builder.func);
+
+        let entry_block = builder.create_block();
+        builder.append_block_params_for_function_params(entry_block);
+        builder.switch_to_block(entry_block);
+        builder.seal_block(entry_block);
+        let block_parameters = builder.block_params(entry_block).to_vec();
+        let call_depth = block_parameters[parameters.len()];
+        let context_pointer = block_parameters[parameters.len() + 1];
+
+        let body_block = builder.create_block();
+        let exceeded_block = builder.create_block();
+        let exceeded = builder.ins().icmp_imm(
+            IntCC::SignedGreaterThanOrEqual,
+            call_depth,
+            MAX_CALL_DEPTH as i64,
+        );
+        builder
+            .ins()
+            .brif(exceeded, exceeded_block, &[], body_block, &[]);
+        builder.switch_to_block(exceeded_block);
+        builder.seal_block(exceeded_block);
+        let stop = builder.ins().iconst(types::I64, STOPPED_AT_CALL_DEPTH);
+        builder.ins().store(
+            MemFlags::trusted(),
+            stop,
+            context_pointer,
+            offset_of!(NativeContext, stop) as i32,
+        );
+        let zero = builder.ins().f64const(0.0);
+        builder.ins().return_(&[zero]);
+
+        builder.switch_to_block(body_block);
+        builder.seal_block(body_block);
+        let mut compiler = FunctionCompiler {
+            builder,
+            scopes: vec![vec![]],
+            variable_count: 0,
+            this,
+            call_depth,
+            context: context_pointer,
+            pointer_type,
+            recursive_variable: None,
+        };
+        for parameter in &block_parameters[..parameters.len()] {
+            let variable = compiler.declare_variable(*parameter);
+            compiler.scopes[0].push(variable);
+        }
+        // Functions which can end without returning a value return void, which native code can't.
+        // The body is run directly like in the tree walker, so it isn't counted as a statement.
+        let always_returns = compiler.uncounted_statement(body)?;
+        if !always_returns {
+            return None;
+        }
+        let recursive_variable = compiler.recursive_variable;
+        compiler.builder.finalize();
+        self.module
+            .define_function(function_id, &mut context)
+            .ok()?;
+        self.module.clear_context(&mut context);
+
+        let entry_point_id = self.compile_entry_point(function_id, parameters.len());
+        self.module.finalize_definitions().ok()?;
+        let code = self.module.get_finalized_function(entry_point_id);
+        Some(CompiledFunction {
+            // The entry point was compiled with this signature.
+            entry_point: unsafe { std::mem::transmute::<*const u8, EntryPoint>(code) },
+            returns_boolean,
+            recursive_variable,
+        })
+    }
+
+    /// Compiles a function which loads the arguments from an array and calls the compiled function,
+    /// so functions with any number of parameters can be called from Rust.
+    fn compile_entry_point(
+        &mut self,
+        function_id: cranelift_module::FuncId,
+        parameter_count: usize,
+    ) -> cranelift_module::FuncId {
+        let pointer_type = self.module.target_config().pointer_type();
+        let mut signature = self.module.make_signature();
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.params.push(AbiParam::new(types::I64));
+        signature.params.push(AbiParam::new(pointer_type));
+        signature.returns.push(AbiParam::new(types::F64));
+        let entry_point_id = self.module.declare_anonymous_function(&signature).unwrap();
+
+        let mut context = self.module.make_context();
+        context.func.signature = signature;
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let function = self.module.declare_func_in_func(function_id, builder.func);
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+        let block_parameters = builder.block_params(block).to_vec();
+
+        let mut arguments = (0..parameter_count)
+            .map(|index| {
+                builder.ins().load(
+                    types::F64,
+                    MemFlags::trusted(),
+                    block_parameters[0],
+                    (index * 8) as i32,
+                )
+            })
+            .collect::<Vec<_>>();
+        arguments.extend(&block_parameters[1..]);
+        let call = builder.ins().call(function, &arguments);
+        let result = builder.inst_results(call)[0];
+        builder.ins().return_(&[result]);
+        builder.finalize();
+
+        self.module
+            .define_function(entry_point_id, &mut context)
+            .unwrap();
+        self.module.clear_context(&mut context);
+        entry_point_id
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // The compiled functions can't be called after the Jit is dropped,
+        // as CompiledFunctions are only handed out by the Jit and copied for a single call.
+        unsafe { ManuallyDrop::take(&mut self.module).free_memory() };
+    }
+}
+
+/// Translates a function body to Cranelift IR. Returns None for anything native code doesn't support.
+/// Booleans are represented as 0.0 or 1.0, so every value is an f64.
+struct FunctionCompiler<'a> {
+    builder: FunctionBuilder<'a>,
+    scopes: Vec<Vec<Variable>>, // Variables of the parameter environment and the blocks inside it, by slot.
+    variable_count: usize,
+    this: FuncRef,
+    call_depth: NativeValue,
+    context: NativeValue, // The pointer to the NativeContext.
+    pointer_type: types::Type,
+    recursive_variable: Option<(usize, usize)>,
+}
+
+impl FunctionCompiler<'_> {
+    fn declare_variable(&mut self, value: NativeValue) -> Variable {
+        let variable = Variable::new(self.variable_count);
+        self.variable_count += 1;
+        self.builder.declare_var(variable, types::F64);
+        self.builder.def_var(variable, value);
+        variable
+    }
+
+    /// Returns the variable if it's declared inside the function.
+    fn local_variable(&self, parent_height: usize, slot: usize) -> Option<Variable> {
+        let depth = self.scopes.len() - 1;
+        if parent_height > depth {
+            return None;
+        }
+        Some(self.scopes[depth - parent_height][slot])
+    }
+
+    fn boolean(&mut self, condition: NativeValue) -> NativeValue {
+        let one = self.builder.ins().f64const(1.0);
+        let zero = self.builder.ins().f64const(0.0);
+        self.builder.ins().select(condition, one, zero)
+    }
+
+    fn condition(&mut self, value: NativeValue) -> NativeValue {
+        let zero = self.builder.ins().f64const(0.0);
+        self.builder.ins().fcmp(FloatCC::NotEqual, value, zero)
+    }
+
+    /// Stores why the code stopped and where in the context and returns, if the condition is true.
+    fn stop_if(&mut self, condition: NativeValue, stop: NativeValue, lines: [NativeValue; 2]) {
+        let stop_block = self.builder.create_block();
+        let continue_block = self.builder.create_block();
+        self.builder
+            .ins()
+            .brif(condition, stop_block, &[], continue_block, &[]);
+        self.builder.switch_to_block(stop_block);
+        self.builder.seal_block(stop_block);
+        self.store(stop, offset_of!(NativeContext, stop));
+        self.store(lines[0], offset_of!(NativeContext, stop_lines));
+        self.store(lines[1], offset_of!(NativeContext, stop_lines) + 8);
+        let zero = self.builder.ins().f64const(0.0);
+        self.builder.ins().return_(&[zero]);
+        self.builder.switch_to_block(continue_block);
+        self.builder.seal_block(continue_block);
+    }
+
+    fn load(&mut self, value_type: types::Type, offset: usize) -> NativeValue {
+        self.builder
+            .ins()
+            .load(value_type, MemFlags::trusted(), self.context, offset as i32)
+    }
+
+    fn store(&mut self, value: NativeValue, offset: usize) {
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, self.context, offset as i32);
+    }
+
+    fn lines(&mut self, lines: (usize, usize)) -> [NativeValue; 2] {
+        [
+            self.builder.ins().iconst(types::I64, lines.0 as i64),
+            self.builder.ins().iconst(types::I64, lines.1 as i64),
+        ]
+    }
+
+    /// Counts the statement and checks the step limit and cancellation before it runs, like the tree walker.
+    /// Returns whether the statement always returns.
+    fn statement(&mut self, statement: &Statement) -> Option<bool> {
+        let offset = offset_of!(NativeContext, statements_executed);
+        let statements_executed = self.load(types::I64, offset);
+        let statements_executed = self.builder.ins().iadd_imm(statements_executed, 1);
+        self.store(statements_executed, offset);
+        let last_step = self.load(types::I64, offset_of!(NativeContext, last_step));
+        let past_limit =
+            self.builder
+                .ins()
+                .icmp(IntCC::UnsignedGreaterThan, statements_executed, last_step);
+        let lines = self.lines(statement.lines);
+        let stop = self.builder.ins().iconst(types::I64, STOPPED_AT_STEP_LIMIT);
+        self.stop_if(past_limit, stop, lines);
+        let cancelled = self.load(self.pointer_type, offset_of!(NativeContext, cancelled));
+        let cancelled = self
+            .builder
+            .ins()
+            .atomic_load(types::I8, MemFlags::trusted(), cancelled);
+        let stop = self
+            .builder
+            .ins()
+            .iconst(types::I64, STOPPED_WHEN_CANCELLED);
+        self.stop_if(cancelled, stop, lines);
+        self.uncounted_statement(statement)
+    }
+
+    /// Returns whether the statement always returns.
+    fn uncounted_statement(&mut self, statement: &Statement) -> Option<bool> {
+        match &statement.statement {
+            StatementType::Expression(expression) => {
+                self.expression(expression)?;
+                Some(false)
+            }
+            // Static variables are globals, which compiled functions can't use.
+            StatementType::VariableDeclaration {
+                is_static: true, ..
+            } => None,
+            StatementType::VariableDeclaration { value, .. } => {
+                let value = self.expression(value)?;
+                let variable = self.declare_variable(value);
+                // Variables in an environment are declared in slot order.
+                self.scopes.last_mut().unwrap().push(variable);
+                Some(false)
+            }
+            StatementType::Block(statements) => {
+                self.scopes.push(vec![]);
+                let mut always_returns = false;
+                for statement in statements {
+                    if self.statement(statement)? {
+                        always_returns = true;
+                        break;
+                    }
+                }
+                self.scopes.pop();
+                Some(always_returns)
+            }
+            StatementType::If {
+                expression,
+                then_statement,
+                else_statement,
+            } => {
+                let condition = self.expression(expression)?;
+                let condition = self.condition(condition);
+                let then_block = self.builder.create_block();
+                let else_block = self.builder.create_block();
+                let merge_block = self.builder.create_block();
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+
+                self.builder.switch_to_block(then_block);
+                self.builder.seal_block(then_block);
+                let then_returns = self.statement(then_statement)?;
+                if !then_returns {
+                    self.builder.ins().jump(merge_block, &[]);
+                }
+
+                self.builder.switch_to_block(else_block);
+                self.builder.seal_block(else_block);
+                let else_returns = match else_statement {
+                    Some(else_statement) => self.statement(else_statement)?,
+                    None => false,
+                };
+                if !else_returns {
+                    self.builder.ins().jump(merge_block, &[]);
+                }
+
+                self.builder.switch_to_block(merge_block);
+                self.builder.seal_block(merge_block);
+                Some(then_returns && else_returns)
+            }
+            StatementType::While {
+                expression,
+                statement,
+            } => {
+                let header_block = self.builder.create_block();
+                let body_block = self.builder.create_block();
+                let exit_block = self.builder.create_block();
+                self.builder.ins().jump(header_block, &[]);
+
+                self.builder.switch_to_block(header_block);
+                let condition = self.expression(expression)?;
+                let condition = self.condition(condition);
+                self.builder
+                    .ins()
+                    .brif(condition, body_block, &[], exit_block, &[]);
+
+                self.builder.switch_to_block(body_block);
+                self.builder.seal_block(body_block);
+                if !self.statement(statement)? {
+                    self.builder.ins().jump(header_block, &[]);
+                }
+                self.builder.seal_block(header_block);
+
+                self.builder.switch_to_block(exit_block);
+                self.builder.seal_block(exit_block);
+                Some(false)
+            }
+            StatementType::Return(Some(expression)) => {
+                let value = self.expression(expression)?;
+                self.builder.ins().return_(&[value]);
+                Some(true)
+            }
+            StatementType::Return(None)
+            | StatementType::Break(_)
+            | StatementType::For { .. }
+            | StatementType::Raise(_)
+            | StatementType::Try { .. }
+            | StatementType::Match { .. }
+            | StatementType::FunctionDeclaration { .. } => None,
+            StatementType::Test { .. } => unreachable!(), // Tests are removed before checking.
+            StatementType::ImportNative(_) => unreachable!(), // Imports are removed before checking.
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Option<NativeValue> {
+        match &expression.expression_type {
+            ExpressionType::Literal(Value::Number(number)) => {
+                Some(self.builder.ins().f64const(*number))
+            }
+            ExpressionType::Literal(Value::Boolean(boolean)) => {
+                Some(self.builder.ins().f64const(f64::from(u8::from(*boolean))))
+            }
+            ExpressionType::Variable {
+                parent_height,
+                slot,
+                ..
+            } => {
+                let variable = self.local_variable(parent_height.unwrap(), slot.unwrap())?;
+                Some(self.builder.use_var(variable))
+            }
+            ExpressionType::Grouping(expression) => self.expression(expression),
+            ExpressionType::Loop(_)
+            | ExpressionType::Ok(_)
+            | ExpressionType::Err(_)
+            | ExpressionType::Propagate(_)
+            | ExpressionType::Some(_) => None,
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => {
+                let condition = self.expression(condition)?;
+                let condition = self.condition(condition);
+                let then_block = self.builder.create_block();
+                let else_block = self.builder.create_block();
+                let merge_block = self.builder.create_block();
+                self.builder.append_block_param(merge_block, types::F64);
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+
+                self.builder.switch_to_block(then_block);
+                self.builder.seal_block(then_block);
+                let then_value = self.expression(then_expression)?;
+                self.builder.ins().jump(merge_block, &[then_value]);
+
+                self.builder.switch_to_block(else_block);
+                self.builder.seal_block(else_block);
+                let else_value = self.expression(else_expression)?;
+                self.builder.ins().jump(merge_block, &[else_value]);
+
+                self.builder.switch_to_block(merge_block);
+                self.builder.seal_block(merge_block);
+                Some(self.builder.block_params(merge_block)[0])
+            }
+            ExpressionType::Block { statements, value } => {
+                self.scopes.push(vec![]);
+                // Block expressions can't return, so their statements always continue.
+                for statement in statements {
+                    self.statement(statement)?;
+                }
+                let value = self.expression(value);
+                self.scopes.pop();
+                value
+            }
+            // Variables are kept in registers, so they can't be referenced.
+            ExpressionType::Unary {
+                operation: UnaryOperation::Reference | UnaryOperation::Dereference,
+                ..
+            } => None,
+            ExpressionType::Unary {
+                operation,
+                expression,
+            } => {
+                let value = self.expression(expression)?;
+                Some(match operation {
+                    UnaryOperation::Minus => self.builder.ins().fneg(value),
+                    UnaryOperation::Not => {
+                        let one = self.builder.ins().f64const(1.0);
+                        self.builder.ins().fsub(one, value)
+                    }
+                    UnaryOperation::BitwiseNot => {
+                        let integer = self.builder.ins().fcvt_to_sint_sat(types::I64, value);
+                        let result = self.builder.ins().bnot(integer);
+                        self.builder.ins().fcvt_from_sint(types::F64, result)
+                    }
+                    UnaryOperation::Reference | UnaryOperation::Dereference => unreachable!(),
+                })
+            }
+            ExpressionType::Binary {
+                operation: BinaryOperation::Assignment,
+                left_expression,
+                right_expression,
+            } => {
+                let ExpressionType::Variable {
+                    parent_height,
+                    slot,
+                    ..
+                } = &left_expression.expression_type
+                else {
+                    return None;
+                };
+                let variable = self.local_variable(parent_height.unwrap(), slot.unwrap())?;
+                let value = self.expression(right_expression)?;
+                self.builder.def_var(variable, value);
+                Some(value)
+            }
+            ExpressionType::Binary {
+                operation: operation @ (BinaryOperation::And | BinaryOperation::Or),
+                left_expression,
+                right_expression,
+            } => {
+                let left_value = self.expression(left_expression)?;
+                let condition = self.condition(left_value);
+                let right_block = self.builder.create_block();
+                let merge_block = self.builder.create_block();
+                self.builder.append_block_param(merge_block, types::F64);
+                // The left value is the result if it's true for or, or false for and.
+                if *operation == BinaryOperation::Or {
+                    self.builder.ins().brif(
+                        condition,
+                        merge_block,
+                        &[left_value],
+                        right_block,
+                        &[],
+                    );
+                } else {
+                    self.builder.ins().brif(
+                        condition,
+                        right_block,
+                        &[],
+                        merge_block,
+                        &[left_value],
+                    );
+                }
+
+                self.builder.switch_to_block(right_block);
+                self.builder.seal_block(right_block);
+                let right_value = self.expression(right_expression)?;
+                self.builder.ins().jump(merge_block, &[right_value]);
+
+                self.builder.switch_to_block(merge_block);
+                self.builder.seal_block(merge_block);
+                Some(self.builder.block_params(merge_block)[0])
+            }
+            ExpressionType::Binary {
+                operation,
+                left_expression,
+                right_expression,
+            } => {
+                let left_value = self.expression(left_expression)?;
+                let right_value = self.expression(right_expression)?;
+                let comparison = match operation {
+                    BinaryOperation::Add => {
+                        return Some(self.builder.ins().fadd(left_value, right_value))
+                    }
+                    BinaryOperation::Subtract => {
+                        return Some(self.builder.ins().fsub(left_value, right_value))
+                    }
+                    BinaryOperation::Multiply => {
+                        return Some(self.builder.ins().fmul(left_value, right_value))
+                    }
+                    BinaryOperation::Divide => {
+                        return Some(self.builder.ins().fdiv(left_value, right_value))
+                    }
+                    BinaryOperation::Equal => FloatCC::Equal,
+                    BinaryOperation::NotEqual => FloatCC::NotEqual,
+                    BinaryOperation::Less => FloatCC::LessThan,
+                    BinaryOperation::LessEqual => FloatCC::LessThanOrEqual,
+                    BinaryOperation::Greater => FloatCC::GreaterThan,
+                    BinaryOperation::GreaterEqual => FloatCC::GreaterThanOrEqual,
+                    BinaryOperation::BitwiseAnd
+                    | BinaryOperation::BitwiseOr
+                    | BinaryOperation::BitwiseXor
+                    | BinaryOperation::ShiftLeft
+                    | BinaryOperation::ShiftRight => {
+                        // Like interpreter::bitwise_operation. Cranelift's shifts also only use the lowest 6 bits.
+                        let left = self.builder.ins().fcvt_to_sint_sat(types::I64, left_value);
+                        let right = self.builder.ins().fcvt_to_sint_sat(types::I64, right_value);
+                        let result = match operation {
+                            BinaryOperation::BitwiseAnd => self.builder.ins().band(left, right),
+                            BinaryOperation::BitwiseOr => self.builder.ins().bor(left, right),
+                            BinaryOperation::BitwiseXor => self.builder.ins().bxor(left, right),
+                            BinaryOperation::ShiftLeft => self.builder.ins().ishl(left, right),
+                            _ => self.builder.ins().sshr(left, right),
+                        };
+                        return Some(self.builder.ins().fcvt_from_sint(types::F64, result));
+                    }
+                    BinaryOperation::And | BinaryOperation::Or | BinaryOperation::Assignment => {
+                        unreachable!()
+                    }
+                };
+                let condition = self.builder.ins().fcmp(comparison, left_value, right_value);
+                Some(self.boolean(condition))
+            }
+            ExpressionType::FunctionCall {
+                function,
+                arguments,
+            } => {
+                // Only calls through a variable outside the function are compiled,
+                // as they're the only ones which can be recursive calls.
+                let ExpressionType::Variable {
+                    parent_height,
+                    slot,
+                    ..
+                } = &function.expression_type
+                else {
+                    return None;
+                };
+                let depth = self.scopes.len() - 1;
+                let parent_height = parent_height.unwrap();
+                if parent_height <= depth {
+                    return None;
+                }
+                let variable = (parent_height - depth - 1, slot.unwrap());
+                if *self.recursive_variable.get_or_insert(variable) != variable {
+                    return None;
+                }
+
+                let mut argument_values = vec![];
+                for argument in arguments {
+                    argument_values.push(self.expression(argument)?);
+                }
+                argument_values.push(self.builder.ins().iadd_imm(self.call_depth, 1));
+                argument_values.push(self.context);
+                let call = self.builder.ins().call(self.this, &argument_values);
+                let result = self.builder.inst_results(call)[0];
+
+                // Stop if the call stopped. If it stopped before running anything, which happens when the maximum
+                // call depth is exceeded, it stopped at this call, like in the tree walker.
+                let stop = self.load(types::I64, offset_of!(NativeContext, stop));
+                let stopped = self.builder.ins().icmp_imm(IntCC::NotEqual, stop, 0);
+                let stop_lines = [
+                    self.load(types::I64, offset_of!(NativeContext, stop_lines)),
+                    self.load(types::I64, offset_of!(NativeContext, stop_lines) + 8),
+                ];
+                let call_lines = self.lines(expression.lines);
+                let stopped_before_running =
+                    self.builder.ins().icmp_imm(IntCC::Equal, stop_lines[0], 0);
+                let lines = [0, 1].map(|index| {
+                    self.builder.ins().select(
+                        stopped_before_running,
+                        call_lines[index],
+                        stop_lines[index],
+                    )
+                });
+                self.stop_if(stopped, stop, lines);
+                Some(result)
+            }
+            ExpressionType::Literal(_)
+            | ExpressionType::Tuple(_)
+            | ExpressionType::Record(_)
+            | ExpressionType::TupleAccess { .. }
+            | ExpressionType::Interpolation { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{thread, time::Duration};
+
+    use crate::{background::ExecResult, test_runner::assert_backends_agree, Backend, Interpreter};
+
+    /// Calls the function enough times for it to be compiled, then runs the rest of the program.
+    fn after_compiling(function: &str, warm_up: &str, rest: &str) -> String {
+        format!("{function}\nlet i = 0;\nwhile i < 1000 {{\n    {warm_up};\n    i = i + 1;\n}}\n{rest}\n")
+    }
+
+    const DEPTH: &str = "fn depth(n: number) -> number {
+    if n == 0 {
+        return 0;
+    }
+    return 1 + depth(n - 1);
+}";
+
+    const SPIN: &str = "fn spin(n: number) -> number {
+    let total = 0;
+    while n != 0 {
+        total = total + 1;
+        n = n - 1;
+    }
+    return total;
+}";
+
+    #[test]
+    fn reports_the_call_which_exceeds_the_call_depth() {
+        assert_backends_agree(&after_compiling(
+            DEPTH,
+            "depth(1)",
+            "print(depth(5));\nprint(depth(20000));",
+        ));
+    }
+
+    #[test]
+    fn counts_statements_for_the_step_limit() {
+        let program = after_compiling(SPIN, "spin(1)", "print(spin(100000));");
+        let run = |backend| {
+            let mut interpreter = Interpreter::with_backend(backend);
+            interpreter.set_step_limit(Some(50000));
+            let error = interpreter.eval(&program).unwrap_err().report(&program);
+            (error, interpreter.stats().statements_executed)
+        };
+        assert_eq!(run(Backend::Jit), run(Backend::TreeWalker));
+    }
+
+    #[test]
+    fn compiled_loops_can_be_cancelled() {
+        let mut handle = Interpreter::with_backend(Backend::Jit).spawn_eval(&after_compiling(
+            SPIN,
+            "spin(1)",
+            "print(spin(-1));",
+        ));
+        thread::sleep(Duration::from_millis(200));
+        handle.cancel();
+        assert!(matches!(handle.join(), ExecResult::Cancelled));
+    }
+}
@@ -0,0 +1,127 @@
+use std::rc::Rc;
+
+use serde_json::{Map, Number, Value as Json};
+
+use crate::{
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+};
+
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0; // Whole numbers below this in size are exactly f64s.
+
+impl Value {
+    /// Converts JSON to the value a script would write for it. Arrays become tuples and objects become records,
+    /// with their fields in the order serde_json keeps the keys in, which is sorted unless its preserve_order
+    /// feature is on. Null becomes void.
+    pub fn from_json(json: &Json) -> Value {
+        match json {
+            Json::Null => Value::Void,
+            Json::Bool(boolean) => Value::Boolean(*boolean),
+            // Numbers which don't fit in an f64 are rounded, as in JavaScript.
+            Json::Number(number) => Value::Number(number.as_f64().unwrap_or(f64::NAN)),
+            Json::String(string) => Value::String(string.as_str().into()),
+            Json::Array(values) => {
+                Value::Tuple(Rc::new(values.iter().map(Value::from_json).collect()))
+            }
+            Json::Object(fields) => Value::Record(Rc::new(
+                fields
+                    .iter()
+                    .map(|(name, value)| (Symbol::intern(name), Value::from_json(value)))
+                    .collect(),
+            )),
+        }
+    }
+
+    /// Converts JSON written by to_json back to a value of the type, so options and results come back as they were.
+    /// Returns an error if the JSON doesn't hold a value of the type.
+    pub fn from_json_as(json: &Json, value_type: &Type) -> Result<Value, String> {
+        let mismatch = || format!("{json} isn't a value of type {value_type}.");
+        Ok(match (value_type, json) {
+            (Type::Any, json) => Value::from_json(json),
+            (Type::Void, Json::Null) => Value::Void,
+            (Type::Number, Json::Number(number)) => {
+                Value::Number(number.as_f64().unwrap_or(f64::NAN))
+            }
+            (Type::String, Json::String(string)) => Value::String(string.as_str().into()),
+            (Type::Boolean, Json::Bool(boolean)) => Value::Boolean(*boolean),
+            (Type::Tuple(types), Json::Array(values)) if types.len() == values.len() => {
+                Value::Tuple(Rc::new(
+                    types
+                        .iter()
+                        .zip(values)
+                        .map(|(value_type, value)| Value::from_json_as(value, value_type))
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            (Type::Record(types), Json::Object(fields)) if types.len() == fields.len() => {
+                Value::Record(Rc::new(
+                    types
+                        .iter()
+                        .map(|(name, field_type)| {
+                            let field = fields.get(name.as_str()).ok_or_else(mismatch)?;
+                            Ok((*name, Value::from_json_as(field, field_type)?))
+                        })
+                        .collect::<Result<_, String>>()?,
+                ))
+            }
+            (Type::Option(_), Json::Null) => Value::Option(None),
+            (Type::Option(value_type), json) => {
+                Value::Option(Some(Rc::new(Value::from_json_as(json, value_type)?)))
+            }
+            (Type::Result { value, error }, Json::Object(fields)) if fields.len() == 1 => {
+                let result = match fields.iter().next().unwrap() {
+                    (tag, json) if tag == "Ok" => Ok(Value::from_json_as(json, value)?),
+                    (tag, json) if tag == "Err" => Err(Value::from_json_as(json, error)?),
+                    _ => return Err(mismatch()),
+                };
+                Value::Result(Rc::new(result))
+            }
+            _ => return Err(mismatch()),
+        })
+    }
+
+    /// Converts the value to JSON. Tuples become arrays and records become objects. Void and None become null,
+    /// Some(value) becomes the value, and results become {"Ok": value} or {"Err": error}.
+    /// Returns an error for values JSON can't hold: functions, references, iterators, channels, tasks,
+    /// NaN and infinities.
+    pub fn to_json(&self) -> Result<Json, String> {
+        Ok(match self {
+            Value::Void | Value::Option(None) => Json::Null,
+            // Whole numbers are written without a fraction, like 2 rather than 2.0.
+            Value::Number(number) if number.fract() == 0.0 && number.abs() < MAX_SAFE_INTEGER => {
+                Json::from(*number as i64)
+            }
+            Value::Number(number) => Json::Number(
+                Number::from_f64(*number)
+                    .ok_or_else(|| format!("{number} can't be written as JSON."))?,
+            ),
+            Value::String(string) => Json::String(string.as_str().into()),
+            Value::Boolean(boolean) => Json::Bool(*boolean),
+            Value::Tuple(values) => Json::Array(
+                values
+                    .iter()
+                    .map(Value::to_json)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Record(fields) => Json::Object(
+                fields
+                    .iter()
+                    .map(|(name, value)| Ok((name.to_string(), value.to_json()?)))
+                    .collect::<Result<_, String>>()?,
+            ),
+            Value::Result(result) => {
+                let (tag, value) = match &**result {
+                    Ok(value) => ("Ok", value),
+                    Err(error) => ("Err", error),
+                };
+                Json::Object(Map::from_iter([(tag.into(), value.to_json()?)]))
+            }
+            Value::Option(Some(value)) => value.to_json()?,
+            Value::Function(_) => return Err("Functions can't be written as JSON.".into()),
+            Value::Reference(_) => return Err("References can't be written as JSON.".into()),
+            Value::Iterator(_) => return Err("Iterators can't be written as JSON.".into()),
+            Value::Channel(_) => return Err("Channels can't be written as JSON.".into()),
+            Value::Task(_) => return Err("Tasks can't be written as JSON.".into()),
+        })
+    }
+}
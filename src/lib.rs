@@ -0,0 +1,1201 @@
+pub mod background;
+pub mod builtins;
+pub mod channel;
+pub mod compiler;
+pub mod constant_folding;
+pub mod coverage;
+#[cfg(feature = "dap")]
+pub mod dap;
+pub mod dead_code_elimination;
+pub mod debugger;
+pub mod disassembler;
+pub mod documentation;
+pub mod exhaustiveness;
+pub mod format;
+pub mod formatter;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+pub mod garbage_collection;
+pub mod highlight;
+pub mod incremental;
+pub mod interpreter;
+#[cfg(feature = "jit")]
+pub mod jit;
+#[cfg(feature = "json")]
+pub mod json;
+pub mod linter;
+pub mod messages;
+pub mod native;
+pub mod optimizer;
+pub mod parser;
+pub mod peephole;
+pub mod profiler;
+pub mod program_cache;
+pub mod replay;
+pub mod script_type;
+#[cfg(feature = "json")]
+pub mod session;
+pub mod small_string;
+pub mod source_map;
+pub mod stats;
+pub mod symbol;
+pub mod syntax_tree;
+pub mod task;
+pub mod test_runner;
+pub mod tokenizer;
+pub mod trace;
+pub mod variable_and_type_check;
+pub mod vm;
+
+use std::{
+    cell::{OnceCell, RefCell},
+    collections::HashSet,
+    error::Error,
+    fmt::Write,
+    fs,
+    io::Read,
+    path::{Component, Path, PathBuf},
+    rc::Rc,
+    sync::mpsc::{Receiver, Sender},
+    time::{Duration, Instant},
+};
+
+use background::ExecHandle;
+use builtins::{BuiltinContext, ARGUMENT_TYPED_BUILTINS, BUILTINS, FALLIBLE_BUILTINS};
+use channel::{Channel, Message};
+use coverage::Coverage;
+use debugger::Debugger;
+use format::ValueFormat;
+use interpreter::{RuntimeError, Variables};
+use messages::Diagnostic;
+pub use optimizer::OptLevel;
+use parser::{
+    BinaryOperation, CompilerError, Expression, ExpressionType, Statement, StatementType,
+};
+use profiler::Profiler;
+use program_cache::ProgramCache;
+use replay::{Replay, ReplayLog};
+use script_type::{ScriptMethod, ScriptType};
+use source_map::SourceMap;
+use stats::{EvalMetrics, MetricsSink, Stats, Timings};
+use symbol::Symbol;
+use tokenizer::{FunctionBody, FunctionValue, HostIterator, Type, Value};
+use trace::TraceEvent;
+use variable_and_type_check::{can_assign, check_types, HostOperator};
+use vm::Vm;
+
+/// Functions written in decibelle which every interpreter declares unless it's created without the prelude.
+pub const PRELUDE: &str = include_str!("prelude.db");
+
+/// A program embedded in Rust code with the decibelle! macro from decibelle-macros, which tokenizes and parses it when
+/// the Rust code is compiled, so syntax errors are compiler errors. Syntax trees can't be built in constants,
+/// so only the source is kept, and it's parsed again when it's run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Program {
+    source: &'static str,
+}
+
+impl Program {
+    /// Wraps a source which tokenizes and parses. Use the decibelle! macro instead, which checks that it does.
+    #[doc(hidden)]
+    pub const fn new_unchecked(source: &'static str) -> Program {
+        Program { source }
+    }
+
+    /// The source of the program, laid out on the same lines as in the Rust code relative to its first line.
+    pub fn source(&self) -> &'static str {
+        self.source
+    }
+}
+
+// The statuses the command line interpreter exits with when programs stop with errors, following sysexits.h.
+pub const EXIT_COMPILE_ERROR: u8 = 65; // The program couldn't be tokenized or compiled.
+pub const EXIT_RUNTIME_ERROR: u8 = 70;
+
+/// The function set with Interpreter::continue_after_errors.
+pub type ErrorSink = Box<dyn FnMut(&EvalError)>;
+
+/// Errors which stop a program from being interpreted.
+#[derive(Debug)]
+pub enum EvalError {
+    Tokenizer(Vec<Box<dyn Error>>),
+    Compiler(Vec<CompilerError>),
+    Runtime(RuntimeError),
+    Exit(i32), // The program called exit with this code.
+}
+
+impl EvalError {
+    /// The status the command line interpreter exits with when a program stops with the error.
+    pub fn exit_status(&self) -> u8 {
+        match self {
+            EvalError::Tokenizer(_) | EvalError::Compiler(_) => EXIT_COMPILE_ERROR,
+            EvalError::Runtime(_) => EXIT_RUNTIME_ERROR,
+            // Only the low 8 bits of the code reach the shell.
+            EvalError::Exit(code) => *code as u8,
+        }
+    }
+
+    /// Formats the errors, showing the lines of the program each error refers to.
+    pub fn report(&self, program: &str) -> String {
+        self.report_source(None, None, program)
+    }
+
+    /// Like report, but labels each error with where the program came from, like a filename or <stdin>.
+    pub fn report_in(&self, source_name: &str, program: &str) -> String {
+        self.report_source(Some(source_name), None, program)
+    }
+
+    /// Like report, but labels each error with the source and line its first line came from, and numbers the lines
+    /// shown as in their sources. Tokenizer errors, which name their line in their text, aren't mapped.
+    pub fn report_mapped(&self, program: &str, source_map: &SourceMap) -> String {
+        self.report_source(None, Some(source_map), program)
+    }
+
+    fn report_source(
+        &self,
+        source_name: Option<&str>,
+        source_map: Option<&SourceMap>,
+        program: &str,
+    ) -> String {
+        let mut report = String::new();
+        let locate = |line| match source_map.and_then(|source_map| source_map.original(line)) {
+            Some((source_name, line)) => (Some(source_name), line),
+            None => (source_name, line),
+        };
+        match self {
+            EvalError::Tokenizer(errors) => {
+                for error in errors {
+                    if let Some(source_name) = source_name {
+                        write!(report, "{source_name}: ").unwrap();
+                    }
+                    writeln!(report, "{error}").unwrap();
+                }
+            }
+            EvalError::Compiler(errors) => {
+                for error in errors {
+                    write_error(&mut report, &locate, program, error.lines, &error.error);
+                }
+            }
+            EvalError::Runtime(error) => {
+                write_error(&mut report, &locate, program, error.lines, &error.error);
+            }
+            EvalError::Exit(_) => {}
+        }
+        report
+    }
+}
+
+/// Writes the error after the lines it refers to, which are labelled with the source and line locate gives for them.
+fn write_error<'a>(
+    report: &mut String,
+    locate: &dyn Fn(usize) -> (Option<&'a str>, usize),
+    program: &str,
+    error_lines: (usize, usize),
+    error: &Diagnostic,
+) {
+    if let (Some(source_name), line) = locate(error_lines.0) {
+        writeln!(report, "--> {source_name}:{line}").unwrap();
+    }
+    let lines = program.lines().collect::<Vec<_>>();
+    let widest_line = (error_lines.0..=error_lines.1)
+        .map(|line| locate(line).1)
+        .max()
+        .unwrap();
+    let index_width = widest_line.ilog10() + 1;
+    for (line_index, line) in lines
+        .iter()
+        .enumerate()
+        .take(error_lines.1)
+        .skip(error_lines.0 - 1)
+    {
+        let (_, line_number) = locate(line_index + 1);
+        write!(report, "{line_number}").unwrap();
+        for _ in 0..(index_width - line_number.ilog10()) {
+            report.push(' ');
+        }
+        writeln!(report, "| {}", line).unwrap();
+    }
+    writeln!(report, "{error}").unwrap();
+    writeln!(report).unwrap();
+}
+
+/// How programs are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// Walks the syntax tree.
+    #[default]
+    TreeWalker,
+    /// Compiles the syntax tree to bytecode and runs it on a stack based VM.
+    Vm,
+    /// Compiles hot functions to native code and walks the syntax tree for the rest.
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+/// An interpreter session. Global variables persist between calls to eval.
+pub struct Interpreter {
+    backend: Backend,
+    opt_level: OptLevel,
+    variables: Variables,
+    vm: Vm,
+    builtin_context: BuiltinContext,
+    builtins_declared: Vec<bool>, // Whether each of BUILTINS has been declared.
+    last_closure_id: usize,
+    program_cache: Option<ProgramCache>,
+    timings: Option<Timings>, // Only recorded once enabled, as there's no clock on some platforms.
+    metrics_sink: Option<MetricsSink>, // Gets the metrics of each eval.
+    error_sink: Option<ErrorSink>, // Gets the runtime errors programs continue after.
+    include_directory: Option<PathBuf>, // Where include reads files from, if programs can include files.
+    included_files: Vec<PathBuf>, // The files programs have included, in the order they were first read.
+    sources: String, // The sources run with eval_source, one after another, which the source map's lines are in.
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_backend(Backend::default())
+    }
+
+    /// Creates an interpreter with the prelude's functions declared.
+    pub fn with_backend(backend: Backend) -> Interpreter {
+        let mut interpreter = Interpreter::without_prelude(backend);
+        interpreter.load_prelude();
+        interpreter
+    }
+
+    /// Creates an interpreter without the prelude, so only the builtins are declared.
+    pub fn without_prelude(backend: Backend) -> Interpreter {
+        let variables = Variables::new();
+        let vm = Vm::new();
+        let exit_code = variables.exit_code.clone();
+        let error = variables.builtin_error.clone();
+        let line = variables.builtin_line.clone();
+        let garbage_collection_requested = match backend {
+            Backend::Vm => vm.garbage_collection.request_handle(),
+            _ => variables.garbage_collection.request_handle(),
+        };
+        #[cfg_attr(not(feature = "jit"), allow(unused_mut))]
+        let mut interpreter = Interpreter {
+            backend,
+            opt_level: OptLevel::default(),
+            variables,
+            vm,
+            builtin_context: BuiltinContext {
+                output: Rc::new(RefCell::new(Box::new(|text| print!("{text}")))),
+                garbage_collection_requested,
+                exit_code,
+                error,
+                source_name: Rc::new(RefCell::new(None)),
+                source_map: Rc::new(RefCell::new(None)),
+                line,
+                clock: Rc::new(RefCell::new(Box::new(monotonic_clock()))),
+                replay: Rc::default(),
+                value_format: Rc::default(),
+                backend,
+                #[cfg(feature = "tracing")]
+                log_to_tracing: Rc::default(),
+            },
+            builtins_declared: vec![false; BUILTINS.len()],
+            last_closure_id: 0,
+            program_cache: None,
+            timings: None,
+            metrics_sink: None,
+            error_sink: None,
+            include_directory: None,
+            included_files: vec![],
+            sources: String::new(),
+        };
+        #[cfg(feature = "jit")]
+        if backend == Backend::Jit {
+            interpreter.variables.jit = Some(jit::Jit::new());
+        }
+        interpreter
+    }
+
+    /// Runs the prelude, declaring its functions as globals. It isn't counted in the stats.
+    /// Like other programs run earlier, the profiler and coverage may attribute its statements to the wrong lines.
+    fn load_prelude(&mut self) {
+        self.eval(PRELUDE).expect("the prelude runs without errors");
+        self.variables.stats = Stats::default();
+    }
+
+    /// Replaces the include("path") statements at the top level of the program with the statements of the files
+    /// they name, which can include other files. The files are added to the source map after the sources run before,
+    /// so errors in them are reported in the file.
+    fn include_files(
+        &mut self,
+        statements: &mut Vec<Statement>,
+        including: &mut Vec<PathBuf>, // The files whose includes are being replaced.
+    ) -> Result<(), EvalError> {
+        if !statements
+            .iter()
+            .any(|statement| include_path(statement).is_some())
+        {
+            return Ok(());
+        }
+        // Programs run with eval aren't in the sources, which are padded past their lines so the files' lines come after.
+        let last_line = statements
+            .iter()
+            .map(|statement| statement.lines.1)
+            .max()
+            .unwrap_or(0);
+        for _ in self.sources.lines().count()..last_line {
+            self.sources.push('\n');
+        }
+        let mut errors = vec![];
+        let mut included = vec![];
+        for statement in std::mem::take(statements) {
+            let Some(path) = include_path(&statement) else {
+                included.push(statement);
+                continue;
+            };
+            let lines = statement.lines;
+            let Some(directory) = &self.include_directory else {
+                errors.push(CompilerError {
+                    lines,
+                    error: messages::diagnostic!(INCLUDES_NOT_ALLOWED, path),
+                });
+                continue;
+            };
+            // Only relative paths which don't go up a directory are allowed, so files outside the directory can't be read.
+            let in_directory = Path::new(path)
+                .components()
+                .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+            if !in_directory {
+                errors.push(CompilerError {
+                    lines,
+                    error: messages::diagnostic!(INCLUDE_OUTSIDE_DIRECTORY, path),
+                });
+                continue;
+            }
+            let full_path = directory.join(path);
+            if including.contains(&full_path) {
+                errors.push(CompilerError {
+                    lines,
+                    error: messages::diagnostic!(INCLUDE_CYCLE, path),
+                });
+                continue;
+            }
+            let source = match fs::read_to_string(&full_path) {
+                Ok(source) => source,
+                Err(error) => {
+                    errors.push(CompilerError {
+                        lines,
+                        error: messages::diagnostic!(INCLUDE_READ_FAILED, path, error),
+                    });
+                    continue;
+                }
+            };
+            if !self.included_files.contains(&full_path) {
+                self.included_files.push(full_path.clone());
+            }
+            let mut file_statements = self.parse_source(path, &source)?;
+            including.push(full_path);
+            self.include_files(&mut file_statements, including)?;
+            including.pop();
+            included.extend(file_statements);
+        }
+        *statements = included;
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(EvalError::Compiler(errors))
+        }
+    }
+
+    /// Loads the native modules imported at the top level of the program and declares their functions,
+    /// then removes the imports.
+    fn import_native_modules(&mut self, statements: &mut Vec<Statement>) -> Result<(), EvalError> {
+        let mut errors = vec![];
+        for statement in statements.iter() {
+            let StatementType::ImportNative(name) = &statement.statement else {
+                continue;
+            };
+            #[cfg(all(feature = "native", not(target_family = "wasm")))]
+            match native::load(name) {
+                Ok(module) => {
+                    for function in module.functions {
+                        self.register_function(
+                            &function.name,
+                            function.parameters,
+                            function.return_type,
+                            function.function,
+                        );
+                    }
+                }
+                Err(error) => errors.push(CompilerError {
+                    lines: statement.lines,
+                    error,
+                }),
+            }
+            #[cfg(not(all(feature = "native", not(target_family = "wasm"))))]
+            errors.push(CompilerError {
+                lines: statement.lines,
+                error: messages::diagnostic!(NATIVE_MODULES_UNSUPPORTED, name),
+            });
+        }
+        statements
+            .retain(|statement| !matches!(statement.statement, StatementType::ImportNative(_)));
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(EvalError::Compiler(errors))
+        }
+    }
+
+    /// Declares the builtins the statements refer to which haven't been declared yet.
+    fn declare_builtins(&mut self, statements: &[Statement]) {
+        if !self.builtins_declared.contains(&false) {
+            return;
+        }
+        let mut names = HashSet::new();
+        for statement in statements {
+            builtins::add_variable_names(statement, &mut names);
+        }
+
+        for (index, builtin) in BUILTINS.iter().enumerate() {
+            let referenced = Symbol::get(builtin.name).is_some_and(|name| names.contains(&name));
+            if self.builtins_declared[index] || !referenced {
+                continue;
+            }
+            self.builtins_declared[index] = true;
+            // A global with the same name declared by the host or an earlier program would shadow the builtin anyway.
+            if self.variables.get_global(builtin.name).is_some() {
+                continue;
+            }
+            if ARGUMENT_TYPED_BUILTINS.contains(&builtin.name) {
+                let slot = self.variables.environments[&0].slots.len();
+                self.variables.argument_typed_slots.insert(slot);
+            }
+            let mut return_type = builtin.return_type.clone();
+            if FALLIBLE_BUILTINS.contains(&builtin.name) {
+                return_type = Type::Result {
+                    value: Box::new(return_type),
+                    error: Box::new(Type::String),
+                };
+            }
+            let context = self.builtin_context.clone();
+            let function = builtin.function;
+            self.register_function(
+                builtin.name,
+                builtin.parameters.to_vec(),
+                return_type,
+                move |values| function(&context, values),
+            );
+        }
+    }
+
+    /// Adds a global function implemented in Rust.
+    /// The arguments passed to the function are checked against the parameter types,
+    /// and the function must return a value of the return type.
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        parameters: Vec<Type>,
+        return_type: Type,
+        function: impl Fn(Vec<Value>) -> Value + 'static,
+    ) {
+        let id = self.last_closure_id;
+        self.last_closure_id += 1;
+        self.variables.declare_global(
+            name,
+            Value::Function(Rc::new(FunctionValue {
+                parameters: parameters
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, parameter_type)| {
+                        (
+                            Symbol::intern(&format!("value{index}")),
+                            Some(0),
+                            parameter_type,
+                        )
+                    })
+                    .collect(),
+                return_type,
+                body: FunctionBody::RustClosure {
+                    id,
+                    closure: Rc::new(function),
+                },
+                parent_environment: 0, // Defined in global environment.
+            })),
+        );
+    }
+
+    /// Like register_function, but the function can fail with a message, which stops the program with a runtime
+    /// error.
+    pub fn register_fallible_function(
+        &mut self,
+        name: &str,
+        parameters: Vec<Type>,
+        return_type: Type,
+        function: impl Fn(Vec<Value>) -> Result<Value, String> + 'static,
+    ) {
+        let error = self.builtin_context.error.clone();
+        let function_name = name.to_owned();
+        self.register_function(
+            name,
+            parameters,
+            return_type,
+            move |values| match function(values) {
+                Ok(value) => value,
+                Err(message) => {
+                    error.set(Some(messages::diagnostic!(
+                        HOST_FUNCTION_FAILED,
+                        function_name,
+                        message
+                    )));
+                    Value::Void // Not used, as the error stops the program.
+                }
+            },
+        );
+    }
+
+    /// Adds a global of type iterator(item type), which for loops take values from as they run,
+    /// like `for line in lines { ... }`. Each value is only taken once, so a second loop over it continues where
+    /// the first one stopped. The iterator must only return values of the item type.
+    pub fn register_iterator(
+        &mut self,
+        name: &str,
+        item_type: Type,
+        iterator: impl Iterator<Item = Value> + 'static,
+    ) {
+        self.variables.declare_global(
+            name,
+            Value::Iterator(Rc::new(HostIterator::new(item_type, iterator))),
+        );
+    }
+
+    /// Adds a global of type channel(message type), which the program sends messages to the host over with send
+    /// and waits for the host's messages with recv. Returns the host's end, which can be moved to another thread.
+    /// The host must only send messages of the message type.
+    pub fn register_channel(
+        &mut self,
+        name: &str,
+        message_type: Type,
+    ) -> (Sender<Message>, Receiver<Message>) {
+        let (channel, sender, receiver) = Channel::pair(message_type);
+        self.variables.declare_global(name, channel);
+        (sender, receiver)
+    }
+
+    /// Adds a method of a Rust type as a global function, which takes the value it's called on
+    /// followed by the method's arguments.
+    pub fn register_method<S, Args, M: ScriptMethod<S, Args> + 'static>(
+        &mut self,
+        name: &str,
+        method: M,
+    ) {
+        self.register_function(
+            name,
+            M::parameter_types(),
+            M::return_type(),
+            move |values| method.call(values),
+        );
+    }
+
+    /// Lets programs apply the operation to values of the method's types, when the language doesn't support it
+    /// on them, like + on two records converted from a Rust type. The method is called on the left operand with
+    /// the right operand as its argument. Registering the operation for the same types again replaces it, and = can't be registered.
+    pub fn register_operator<L, R, M: ScriptMethod<L, (R,)> + 'static>(
+        &mut self,
+        operation: BinaryOperation,
+        method: M,
+    ) {
+        let parameters = M::parameter_types();
+        // A name programs can't write, so they can't shadow it.
+        let name = format!("{} {operation:?} {}", parameters[0], parameters[1]);
+        self.variables.operators.push(HostOperator {
+            operation,
+            left_type: parameters[0].clone(),
+            right_type: parameters[1].clone(),
+            function: Symbol::intern(&name),
+        });
+        self.register_method(&name, method);
+    }
+
+    /// Registers the methods of a type derived with #[derive(ScriptType)].
+    pub fn register_type<T: ScriptType>(&mut self) {
+        T::register_methods(self);
+    }
+
+    /// Sets the name dbg prints for where the program came from, like a filename or <stdin>.
+    pub fn set_source_name(&mut self, source_name: &str) {
+        *self.builtin_context.source_name.borrow_mut() = Some(source_name.into());
+    }
+
+    /// Makes dbg print, and tracing events name, the source and line each line of the program came from,
+    /// for programs the host assembled from fragments. Errors are mapped with EvalError::report_mapped.
+    pub fn set_source_map(&mut self, source_map: SourceMap) {
+        *self.builtin_context.source_map.borrow_mut() = Some(source_map);
+    }
+
+    /// Lets programs run files in the directory with include("path"), as if the file's statements were written in
+    /// place of the include. Paths are relative to the directory, and can't leave it.
+    pub fn allow_includes(&mut self, directory: impl Into<PathBuf>) {
+        self.include_directory = Some(directory.into());
+    }
+
+    /// The files programs have included, in the order they were first read.
+    pub fn included_files(&self) -> &[PathBuf] {
+        &self.included_files
+    }
+
+    /// Makes clock return the time from the given function, in milliseconds.
+    /// By default it uses a monotonic clock, which some platforms, like WebAssembly in browsers, don't have.
+    pub fn set_clock(&mut self, clock: impl Fn() -> f64 + 'static) {
+        *self.builtin_context.clock.borrow_mut() = Box::new(clock);
+    }
+
+    /// Starts recording the results of builtins which can differ between runs, like clock, so a later run can
+    /// replay them. Only the calls made on this thread are recorded, not those in spawned tasks.
+    pub fn enable_recording(&mut self) {
+        *self.builtin_context.replay.borrow_mut() = Replay::Recording(ReplayLog::new());
+    }
+
+    /// Returns the results recorded since recording was enabled.
+    pub fn recording(&self) -> Option<ReplayLog> {
+        match &*self.builtin_context.replay.borrow() {
+            Replay::Recording(log) => Some(log.clone()),
+            _ => None,
+        }
+    }
+
+    /// Makes builtins which can differ between runs return the results in the log instead, in order,
+    /// so the program behaves like the run which recorded it. Calls which don't match the log are runtime errors.
+    pub fn replay(&mut self, log: ReplayLog) {
+        *self.builtin_context.replay.borrow_mut() = Replay::Replaying(log, 0);
+    }
+
+    /// Runs the program on a worker thread, in a new interpreter with this one's backend and the prelude, and returns
+    /// a handle to poll, read the output of, wait for or cancel it. The program can't use this interpreter's globals,
+    /// as values can't leave the thread they were made on. Use ExecHandle::spawn to set up the interpreter it runs in.
+    pub fn spawn_eval(&self, program: &str) -> ExecHandle {
+        ExecHandle::spawn(self.backend, program.to_string(), |_| {})
+    }
+
+    /// Sends the output of print to the given function instead of stdout.
+    pub fn set_output(&mut self, output: impl FnMut(&str) + 'static) {
+        *self.builtin_context.output.borrow_mut() = Box::new(output);
+    }
+
+    /// Logs print's output as info events, dbg's as debug events and runtime errors as error events with tracing,
+    /// instead of writing them out. Events have the target decibelle, and file and line fields for where they
+    /// happened. The file is the source name, if one was set.
+    #[cfg(feature = "tracing")]
+    pub fn log_to_tracing(&mut self) {
+        self.builtin_context.log_to_tracing.set(true);
+    }
+
+    /// Sets the fewest environments allocated between two garbage collections.
+    /// Lower thresholds use less memory but collect more often.
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        self.variables.garbage_collection.set_threshold(threshold);
+        self.vm.garbage_collection.set_threshold(threshold);
+    }
+
+    /// Frees the environments which programs can no longer reach, finalizing the host iterators only they held,
+    /// without waiting for the next garbage collection.
+    pub fn collect_garbage(&mut self) {
+        let roots = [self.variables.return_value.clone()];
+        match self.backend {
+            Backend::Vm => self
+                .vm
+                .collect_garbage_between_runs(&roots, &mut self.variables),
+            _ => self.variables.collect_garbage(&roots),
+        }
+    }
+
+    /// Sets how print and dbg write values, like how deep into nested tuples they go.
+    pub fn set_value_format(&mut self, value_format: ValueFormat) {
+        self.builtin_context.value_format.set(value_format);
+    }
+
+    /// Runs the rest of each program after a runtime error in one of its top level statements, calling the sink
+    /// with the error instead of stopping, like a notebook. Exiting, being cancelled or running past the step limit
+    /// still stop the program.
+    pub fn continue_after_errors(&mut self, sink: impl FnMut(&EvalError) + 'static) {
+        self.error_sink = Some(Box::new(sink));
+    }
+
+    /// Stops each program run after this with a runtime error once it has run more than the given number of
+    /// statements, so programs which don't finish can't hang the host. The VM doesn't count statements.
+    pub fn set_step_limit(&mut self, limit: Option<usize>) {
+        self.variables.step_limit = limit.map(|limit| (limit, 0));
+    }
+
+    /// Starts recording how often each statement and function runs and how long it takes.
+    /// Only the tree walker records spans.
+    pub fn enable_profiler(&mut self) {
+        self.variables.profiler.get_or_insert_with(Profiler::new);
+    }
+
+    /// Returns the spans recorded since the profiler was enabled.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.variables.profiler.as_ref()
+    }
+
+    /// Sends each statement to the tracer as it starts, and each variable declared or assigned to with its new value.
+    /// Only the tree walker traces programs.
+    pub fn set_tracer(&mut self, tracer: impl FnMut(&TraceEvent) + 'static) {
+        self.variables.tracer = Some(Box::new(tracer));
+    }
+
+    /// Lets the debugger pause programs before their statements. Only the tree walker can be debugged.
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.variables.debugger = Some(debugger);
+    }
+
+    /// Starts counting how often each statement runs. Only the tree walker counts statements.
+    /// Optimizations can remove or merge statements, so programs should be run with OptLevel::None for exact coverage.
+    pub fn enable_coverage(&mut self) {
+        self.variables.coverage.get_or_insert_with(Coverage::new);
+    }
+
+    /// Returns the statement counts recorded since coverage was enabled.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.variables.coverage.as_ref()
+    }
+
+    /// Returns counts of what programs have done since the interpreter was created.
+    pub fn stats(&self) -> Stats {
+        self.variables.stats
+    }
+
+    /// Starts timing each phase of running programs.
+    pub fn enable_timings(&mut self) {
+        self.timings.get_or_insert_with(Timings::default);
+    }
+
+    /// Returns how long programs have spent in each phase since timing was enabled.
+    pub fn timings(&self) -> Option<Timings> {
+        self.timings
+    }
+
+    fn start_timer(&self) -> Option<Instant> {
+        self.timings.is_some().then(Instant::now)
+    }
+
+    /// Adds the time since the timer started to the phase.
+    fn stop_timer(
+        &mut self,
+        start: Option<Instant>,
+        phase: impl FnOnce(&mut Timings) -> &mut Duration,
+    ) {
+        if let (Some(timings), Some(start)) = (&mut self.timings, start) {
+            *phase(timings) += start.elapsed();
+        }
+    }
+
+    /// Calls the function with the metrics of each call to eval when it finishes, like eval_source or
+    /// eval_statements, for exporting to monitoring. Durations are measured with the clock builtin's clock.
+    pub fn set_metrics_sink(&mut self, sink: impl FnMut(&EvalMetrics) + 'static) {
+        self.metrics_sink = Some(Box::new(sink));
+    }
+
+    /// Runs the eval, and sends its metrics to the sink if there is one.
+    fn measure(
+        &mut self,
+        eval: impl FnOnce(&mut Interpreter) -> Result<(), EvalError>,
+    ) -> Result<(), EvalError> {
+        // The sink is taken out while the eval runs, so evals inside it, like eval_program's, aren't reported twice.
+        let Some(mut sink) = self.metrics_sink.take() else {
+            return eval(self);
+        };
+        let stats = self.variables.stats;
+        let start = (self.builtin_context.clock.borrow())();
+        let result = eval(self);
+        let end = (self.builtin_context.clock.borrow())();
+        let stats = self.variables.stats.since(&stats);
+        sink(&EvalMetrics {
+            stats,
+            duration: Duration::from_secs_f64((end - start).max(0.0) / 1000.0),
+            failed: matches!(&result, Err(error) if !matches!(error, EvalError::Exit(_))),
+            fuel_remaining: self
+                .variables
+                .step_limit
+                .map(|(limit, _)| limit.saturating_sub(stats.statements_executed)),
+        });
+        self.metrics_sink = Some(sink);
+        result
+    }
+
+    /// Starts caching parsed programs, so evaluating the same source again skips tokenizing and parsing.
+    pub fn enable_program_cache(&mut self) {
+        self.program_cache.get_or_insert_with(ProgramCache::new);
+    }
+
+    /// Returns the cache of parsed programs, if it's enabled.
+    pub fn program_cache(&mut self) -> Option<&mut ProgramCache> {
+        self.program_cache.as_mut()
+    }
+
+    /// Sets which optimizations run on programs before they're interpreted.
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level;
+    }
+
+    /// Tokenizes, parses, checks and interprets a program.
+    /// If the program stops with a runtime error, the variables it declared before the error are kept.
+    pub fn eval(&mut self, program: &str) -> Result<(), EvalError> {
+        self.measure(|interpreter| interpreter.parse_and_run(program))
+    }
+
+    fn parse_and_run(&mut self, program: &str) -> Result<(), EvalError> {
+        let statements = if self.program_cache.is_some() {
+            let start = self.start_timer();
+            let program_cache = self.program_cache.as_mut().unwrap();
+            let statements = program_cache.get_or_parse(program).cloned();
+            // The cache tokenizes and parses together.
+            self.stop_timer(start, |timings| &mut timings.parse);
+            statements?
+        } else {
+            let start = self.start_timer();
+            let tokens = tokenizer::tokenize(program).map_err(EvalError::Tokenizer);
+            self.stop_timer(start, |timings| &mut timings.tokenize);
+            let start = self.start_timer();
+            let statements = parser::parse(&tokens?).map_err(EvalError::Compiler);
+            self.stop_timer(start, |timings| &mut timings.parse);
+            statements?
+        };
+        self.check_and_run(statements)
+    }
+
+    /// Like eval, but for one of several named sources, like files, run in the session. Each source's lines are
+    /// numbered after those of the sources run before it and added to the source map, so errors, dbg and tracing
+    /// point at the right source even in functions called from a later one. Use report to format its errors.
+    pub fn eval_source(&mut self, source_name: &str, source: &str) -> Result<(), EvalError> {
+        self.measure(|interpreter| {
+            let statements = interpreter.parse_source(source_name, source)?;
+            interpreter.check_and_run(statements)
+        })
+    }
+
+    /// Tokenizes and parses a source, numbering its lines after the sources before it and adding it to the source map.
+    fn parse_source(
+        &mut self,
+        source_name: &str,
+        source: &str,
+    ) -> Result<Vec<Statement>, EvalError> {
+        let first_line = self.sources.lines().count() + 1;
+        self.builtin_context
+            .source_map
+            .borrow_mut()
+            .get_or_insert_with(SourceMap::new)
+            .append(&mut self.sources, source_name, source);
+        let start = self.start_timer();
+        let tokens = tokenizer::tokenize_from_line(source, first_line).map_err(|_| {
+            // Tokenizer errors name their line in their text, so they're made again with the source's own lines.
+            EvalError::Tokenizer(tokenizer::tokenize(source).unwrap_err())
+        });
+        self.stop_timer(start, |timings| &mut timings.tokenize);
+        let start = self.start_timer();
+        let statements = parser::parse(&tokens?).map_err(EvalError::Compiler);
+        self.stop_timer(start, |timings| &mut timings.parse);
+        statements
+    }
+
+    /// Formats an error from eval_source, labelling it with the name of its source and numbering the lines shown
+    /// as in that source.
+    pub fn report(&self, error: &EvalError) -> String {
+        let source_map = self.builtin_context.source_map.borrow();
+        let Some(source_map) = source_map.as_ref() else {
+            return error.report(&self.sources);
+        };
+        match error {
+            // Tokenizing stops at the first source with errors, so they're in the last one.
+            EvalError::Tokenizer(_) => match source_map.original(self.sources.lines().count()) {
+                Some((source_name, _)) => error.report_in(source_name, &self.sources),
+                None => error.report(&self.sources),
+            },
+            _ => error.report_mapped(&self.sources, source_map),
+        }
+    }
+
+    /// Checks and interprets a program embedded with the decibelle! macro.
+    pub fn eval_program(&mut self, program: &Program) -> Result<(), EvalError> {
+        self.eval(program.source)
+    }
+
+    /// Tokenizes, parses and checks a program without running it.
+    /// The checks take the globals declared by earlier programs into account.
+    pub fn check(&mut self, program: &str) -> Result<(), EvalError> {
+        let tokens = tokenizer::tokenize(program).map_err(EvalError::Tokenizer)?;
+        let mut statements = parser::parse(&tokens).map_err(EvalError::Compiler)?;
+        remove_tests(&mut statements);
+        self.include_files(&mut statements, &mut vec![])?;
+        self.import_native_modules(&mut statements)?;
+        self.declare_builtins(&statements);
+        check_types(&mut statements, &mut self.variables).map_err(EvalError::Compiler)
+    }
+
+    /// Tokenizes, parses, checks and optimizes a program, and compiles it to the bytecode the VM would run,
+    /// without running it.
+    pub fn compile(&mut self, program: &str) -> Result<Rc<compiler::Function>, EvalError> {
+        let tokens = tokenizer::tokenize(program).map_err(EvalError::Tokenizer)?;
+        let mut statements = parser::parse(&tokens).map_err(EvalError::Compiler)?;
+        remove_tests(&mut statements);
+        self.include_files(&mut statements, &mut vec![])?;
+        self.import_native_modules(&mut statements)?;
+        self.declare_builtins(&statements);
+        check_types(&mut statements, &mut self.variables).map_err(EvalError::Compiler)?;
+        optimizer::optimize(&mut statements, self.opt_level);
+        let mut script = compiler::compile(&statements);
+        optimizer::optimize_bytecode(&mut script, self.opt_level);
+        Ok(script)
+    }
+
+    /// Like eval, but tokenizes the program as it's read instead of reading it into a string first.
+    pub fn eval_reader(&mut self, reader: impl Read) -> Result<(), EvalError> {
+        self.measure(|interpreter| {
+            let start = interpreter.start_timer();
+            let tokens = tokenizer::tokenize_reader(reader).map_err(EvalError::Tokenizer);
+            interpreter.stop_timer(start, |timings| &mut timings.tokenize);
+            let start = interpreter.start_timer();
+            let statements = parser::parse(&tokens?).map_err(EvalError::Compiler);
+            interpreter.stop_timer(start, |timings| &mut timings.parse);
+            interpreter.check_and_run(statements?)
+        })
+    }
+
+    /// Checks and interprets a parsed program. Test blocks at the top level are skipped.
+    pub fn eval_statements(&mut self, statements: Vec<Statement>) -> Result<(), EvalError> {
+        self.measure(|interpreter| interpreter.check_and_run(statements))
+    }
+
+    fn check_and_run(&mut self, mut statements: Vec<Statement>) -> Result<(), EvalError> {
+        self.include_files(&mut statements, &mut vec![])?;
+        self.import_native_modules(&mut statements)?;
+        let start = self.start_timer();
+        remove_tests(&mut statements);
+        self.declare_builtins(&statements);
+        // Programs which continue after errors check their statements again after one fails.
+        let unchecked = self.error_sink.as_ref().map(|_| statements.clone());
+        let checked =
+            check_types(&mut statements, &mut self.variables).map_err(EvalError::Compiler);
+        // Programs which continue after errors optimize each statement as it runs instead, as the passes can remove
+        // statements, and each has to stay paired with its unchecked copy.
+        if checked.is_ok() && unchecked.is_none() {
+            optimizer::optimize(&mut statements, self.opt_level);
+        }
+        self.stop_timer(start, |timings| &mut timings.check);
+        checked?;
+        match (self.error_sink.take(), unchecked) {
+            (Some(mut sink), Some(unchecked)) => {
+                let result = self.run_continuing_after_errors(statements, unchecked, &mut sink);
+                self.error_sink = Some(sink);
+                result
+            }
+            _ => self.run_statements(&statements),
+        }
+    }
+
+    /// Calls a global function with the arguments, like a `return function(arguments);` at the end of the program,
+    /// and returns its result. Returns None if there's no function with the name or it can't take the arguments.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Option<Result<Value, EvalError>> {
+        let Some(Value::Function(function)) = self.variables.get_global(name) else {
+            return None;
+        };
+        let takes_arguments = function.parameters.len() == arguments.len()
+            && function.parameters.iter().zip(&arguments).all(
+                |((_, _, parameter_type), argument)| {
+                    can_assign(parameter_type, &argument.value_type())
+                },
+            );
+        if !takes_arguments {
+            return None;
+        }
+        // The call is attributed to the start of the function, which is where a debugger would pause before it.
+        let lines = match &function.body {
+            FunctionBody::Statement(body) => (body.lines.0, body.lines.0),
+            FunctionBody::Bytecode(body) => {
+                let line = body.chunk.lines.first().copied().unwrap_or(1);
+                (line, line)
+            }
+            FunctionBody::RustClosure { .. } => (1, 1),
+        };
+        let literal = |value| Expression {
+            expression_type: ExpressionType::Literal(value),
+            lines,
+        };
+        let call = Expression {
+            expression_type: ExpressionType::FunctionCall {
+                function: Box::new(literal(Value::Function(function.clone()))),
+                arguments: arguments.into_iter().map(literal).collect(),
+            },
+            lines,
+        };
+        let statement = Statement {
+            statement: StatementType::Return(Some(call)),
+            lines,
+        };
+        Some(
+            self.run_statements(&[statement])
+                .map(|()| self.variables.return_value.clone()),
+        )
+    }
+
+    /// Runs checked statements on the backend.
+    fn run_statements(&mut self, statements: &[Statement]) -> Result<(), EvalError> {
+        self.start_run();
+        self.run_on_backend(statements)
+    }
+
+    /// Runs checked statements one at a time, passing the errors of each to the sink and going on to the next.
+    /// Once a statement fails, the variables it declares don't exist, so the statements after it are checked again
+    /// one at a time from their unchecked copies, and those which use its variables fail to check instead of running.
+    fn run_continuing_after_errors(
+        &mut self,
+        statements: Vec<Statement>,
+        unchecked: Vec<Statement>,
+        sink: &mut dyn FnMut(&EvalError),
+    ) -> Result<(), EvalError> {
+        self.start_run();
+        let mut failed = false;
+        for (statement, unchecked) in statements.into_iter().zip(unchecked) {
+            let result = if failed {
+                self.check_statement(unchecked)
+            } else {
+                let mut statements = vec![statement];
+                optimizer::optimize(&mut statements, self.opt_level);
+                Ok(statements)
+            }
+            .and_then(|statements| self.run_on_backend(&statements));
+            match result {
+                Ok(()) if self.variables.returned => break,
+                Ok(()) => {}
+                Err(EvalError::Runtime(error))
+                    if error.error.message != &messages::CANCELLED
+                        && error.error.message != &messages::STEP_LIMIT_EXCEEDED =>
+                {
+                    failed = true;
+                    sink(&EvalError::Runtime(error));
+                }
+                Err(error @ EvalError::Compiler(_)) => sink(&error),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks and optimizes a statement against the globals which have been declared.
+    fn check_statement(&mut self, statement: Statement) -> Result<Vec<Statement>, EvalError> {
+        let mut statements = vec![statement];
+        check_types(&mut statements, &mut self.variables).map_err(EvalError::Compiler)?;
+        optimizer::optimize(&mut statements, self.opt_level);
+        Ok(statements)
+    }
+
+    /// Resets what the last program returned, and the step limit, before running a program.
+    fn start_run(&mut self) {
+        self.variables.return_value = Value::Void;
+        self.variables.returned = false;
+        if let Some((limit, _)) = self.variables.step_limit {
+            let last_step = self.variables.stats.statements_executed + limit;
+            self.variables.step_limit = Some((limit, last_step));
+        }
+    }
+
+    fn run_on_backend(&mut self, statements: &[Statement]) -> Result<(), EvalError> {
+        let start = self.start_timer();
+        let result = match self.backend {
+            Backend::TreeWalker => interpreter::interpret(statements, &mut self.variables),
+            #[cfg(feature = "jit")]
+            Backend::Jit => interpreter::interpret(statements, &mut self.variables),
+            Backend::Vm => {
+                let mut script = compiler::compile(statements);
+                optimizer::optimize_bytecode(&mut script, self.opt_level);
+                self.vm.run(script, &mut self.variables)
+            }
+        };
+        self.stop_timer(start, |timings| &mut timings.execute);
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &result {
+            if self.builtin_context.log_to_tracing.get() && self.variables.exit_code.get().is_none()
+            {
+                let (file, line) = self.builtin_context.location(error.lines.0);
+                tracing::error!(target: "decibelle", file = file.as_deref(), line, "{}", error.error);
+            }
+        }
+        result.map_err(|error| match self.variables.exit_code.take() {
+            Some(code) => EvalError::Exit(code),
+            None => EvalError::Runtime(error),
+        })
+    }
+
+    /// Returns what the last program returned from its top level with `return value;`,
+    /// or void if it ran to the end.
+    pub fn return_value(&self) -> &Value {
+        &self.variables.return_value
+    }
+
+    /// Returns the current value of a global variable.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.variables.get_global(name).cloned()
+    }
+
+    /// Returns the name and current value of each global variable which isn't shadowed, in the order they were declared.
+    /// Builtins are only included once a program has used them.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        let mut slots: Vec<_> = self.variables.global_slots.iter().collect();
+        slots.sort_by_key(|(_, slot)| **slot);
+        slots
+            .iter()
+            .filter(|((name, shadow_id), _)| {
+                !slots
+                    .iter()
+                    .any(|((other, other_id), _)| other == name && other_id > shadow_id)
+            })
+            .map(|((name, _), slot)| (name.to_string(), self.variables.global(**slot).clone()))
+            .collect()
+    }
+
+    /// Declares a global variable with the value, like `let name = value;` at the top level of a program.
+    pub fn declare_global(&mut self, name: &str, value: Value) {
+        self.variables.declare_global(name, value);
+    }
+}
+
+/// The milliseconds since it was first called, so platforms without a clock only fail if clock is used.
+fn monotonic_clock() -> impl Fn() -> f64 {
+    let start = OnceCell::new();
+    move || start.get_or_init(Instant::now).elapsed().as_secs_f64() * 1000.0
+}
+
+/// The path of an include("path") statement.
+fn include_path(statement: &Statement) -> Option<&str> {
+    let StatementType::Expression(Expression {
+        expression_type:
+            ExpressionType::FunctionCall {
+                function,
+                arguments,
+            },
+        ..
+    }) = &statement.statement
+    else {
+        return None;
+    };
+    match (&function.expression_type, &arguments[..]) {
+        (
+            ExpressionType::Variable { name, .. },
+            [Expression {
+                expression_type: ExpressionType::Literal(Value::String(path)),
+                ..
+            }],
+        ) if name.as_str() == "include" => Some(path),
+        _ => None,
+    }
+}
+
+/// Removes the top level test blocks, which only the test runner runs.
+fn remove_tests(statements: &mut Vec<Statement>) {
+    statements.retain(|statement| !matches!(statement.statement, StatementType::Test { .. }));
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
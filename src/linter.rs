@@ -0,0 +1,604 @@
+use std::{collections::HashSet, fmt::Display, str::FromStr};
+
+use crate::{
+    exhaustiveness::{is_useful, missing_pattern},
+    messages::{diagnostic, Diagnostic},
+    parser::{
+        parse, parse_requiring_semicolons, BinaryOperation, Expression, ExpressionType, MatchArm,
+        Pattern, Statement, StatementType,
+    },
+    symbol::Symbol,
+    tokenizer::{tokenize, Value},
+    EvalError,
+};
+
+/// A check for code which is valid but probably a mistake.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// A variable or parameter which is never read. Names starting with _ are allowed to be unused.
+    UnusedVariable,
+    /// A declaration with the same name as a variable in the same or an enclosing scope.
+    Shadowing,
+    /// An if or while condition which is always true or always false. while true is allowed.
+    ConstantCondition,
+    /// Statements after a return.
+    UnreachableCode,
+    /// An if or while condition which is an assignment, usually meant to be ==.
+    AssignmentInCondition,
+    /// A match statement whose cases don't cover every value, so it sometimes does nothing.
+    NonExhaustiveMatch,
+    /// A case, or a pattern in an or-pattern, which only matches values the patterns before it match.
+    UnreachableCase,
+    /// A statement which leaves out its semicolon at the end of its line. Off by default.
+    MissingSemicolon,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 8] = [
+        Rule::UnusedVariable,
+        Rule::Shadowing,
+        Rule::ConstantCondition,
+        Rule::UnreachableCode,
+        Rule::AssignmentInCondition,
+        Rule::NonExhaustiveMatch,
+        Rule::UnreachableCase,
+        Rule::MissingSemicolon,
+    ];
+
+    /// The name used for the rule in flags and config files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::Shadowing => "shadowing",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::UnreachableCode => "unreachable-code",
+            Rule::AssignmentInCondition => "assignment-in-condition",
+            Rule::NonExhaustiveMatch => "non-exhaustive-match",
+            Rule::UnreachableCase => "unreachable-case",
+            Rule::MissingSemicolon => "missing-semicolon",
+        }
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Rule, String> {
+        Rule::ALL
+            .into_iter()
+            .find(|rule| rule.name() == name)
+            .ok_or_else(|| format!("There's no lint rule called {name}."))
+    }
+}
+
+/// Which rules are checked. All of them but missing-semicolon are by default.
+#[derive(Debug, Clone)]
+pub struct LintConfig {
+    pub disabled: HashSet<Rule>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig {
+            disabled: HashSet::from([Rule::MissingSemicolon]),
+        }
+    }
+}
+
+impl LintConfig {
+    pub fn is_enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+
+    pub fn enable(&mut self, rule: Rule) {
+        self.disabled.remove(&rule);
+    }
+
+    pub fn disable(&mut self, rule: Rule) {
+        self.disabled.insert(rule);
+    }
+
+    /// Applies a config file, which has a line like `shadowing = off` or `shadowing = on` for each rule it changes.
+    /// Lines starting with # are ignored.
+    pub fn apply_config_file(&mut self, config: &str) -> Result<(), String> {
+        for (index, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, setting)) = line.split_once('=') else {
+                return Err(format!("Expected rule = on or off at line {}.", index + 1));
+            };
+            let rule = name.trim().parse()?;
+            match setting.trim() {
+                "on" => self.enable(rule),
+                "off" => self.disable(rule),
+                setting => {
+                    return Err(format!(
+                        "Expected on or off for {rule} at line {}, got {setting}.",
+                        index + 1
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A problem found by a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lint {
+    pub rule: Rule,
+    pub lines: (usize, usize),
+    pub message: Diagnostic,
+}
+
+/// Checks the program with the enabled rules, without running or type checking it.
+/// The lints are sorted by line.
+pub fn lint(program: &str, config: &LintConfig) -> Result<Vec<Lint>, EvalError> {
+    let tokens = tokenize(program).map_err(EvalError::Tokenizer)?;
+    let statements = parse(&tokens).map_err(EvalError::Compiler)?;
+
+    let mut linter = Linter {
+        config,
+        scopes: vec![vec![]],
+        lints: vec![],
+    };
+    linter.lint_statements(&statements);
+    linter.end_scope();
+    // The program parsed, so the only errors when semicolons are required are the omitted ones.
+    if let Err(errors) = parse_requiring_semicolons(&tokens) {
+        for error in errors {
+            linter.report(
+                Rule::MissingSemicolon,
+                error.lines,
+                diagnostic!(MISSING_SEMICOLON),
+            );
+        }
+    }
+    let mut lints = linter.lints;
+    lints.sort_by_key(|lint| lint.lines);
+    Ok(lints)
+}
+
+/// A variable declared in a scope.
+struct Declaration {
+    name: Symbol,
+    line: usize,
+    used: bool,
+    checked: bool, // Should the rule for unused variables check this declaration?
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    scopes: Vec<Vec<Declaration>>,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: Rule, lines: (usize, usize), message: Diagnostic) {
+        if self.config.is_enabled(rule) {
+            self.lints.push(Lint {
+                rule,
+                lines,
+                message,
+            });
+        }
+    }
+
+    fn declare(&mut self, name: Symbol, line: usize, checked: bool) {
+        if let Some(declaration) = self
+            .scopes
+            .iter()
+            .flatten()
+            .rev()
+            .find(|declaration| declaration.name == name)
+        {
+            let message = diagnostic!(SHADOWED_VARIABLE, name, declaration.line);
+            self.report(Rule::Shadowing, (line, line), message);
+        }
+        self.scopes.last_mut().unwrap().push(Declaration {
+            name,
+            line,
+            used: false,
+            checked: checked && !name.as_str().starts_with('_'),
+        });
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap();
+        for declaration in scope {
+            if declaration.checked && !declaration.used {
+                let message = diagnostic!(UNUSED_VARIABLE, declaration.name);
+                self.report(
+                    Rule::UnusedVariable,
+                    (declaration.line, declaration.line),
+                    message,
+                );
+            }
+        }
+    }
+
+    fn lint_statements(&mut self, statements: &[Statement]) {
+        let mut returned = false;
+        for statement in statements {
+            if returned {
+                self.report(
+                    Rule::UnreachableCode,
+                    statement.lines,
+                    diagnostic!(UNREACHABLE_CODE),
+                );
+                // Only the first unreachable statement is reported.
+                returned = false;
+            }
+            self.lint_statement(statement);
+            if matches!(
+                statement.statement,
+                StatementType::Return(_) | StatementType::Break(_) | StatementType::Raise(_)
+            ) {
+                returned = true;
+            }
+        }
+    }
+
+    fn lint_statement(&mut self, statement: &Statement) {
+        match &statement.statement {
+            StatementType::VariableDeclaration {
+                variable, value, ..
+            } => {
+                self.lint_expression(value);
+                self.declare(*variable, statement.lines.0, true);
+            }
+            StatementType::FunctionDeclaration {
+                name,
+                parameters,
+                body,
+                ..
+            } => {
+                // Functions can call themselves, so they're declared before their body.
+                self.declare(*name, statement.lines.0, false);
+                self.scopes.push(vec![]);
+                for (parameter, _, _) in parameters {
+                    self.declare(*parameter, statement.lines.0, true);
+                }
+                match &body.statement {
+                    StatementType::Block(statements) => self.lint_statements(statements),
+                    _ => unreachable!(),
+                }
+                self.end_scope();
+            }
+            StatementType::Return(expression) | StatementType::Break(expression) => {
+                if let Some(expression) = expression {
+                    self.lint_expression(expression);
+                }
+            }
+            StatementType::Expression(expression) | StatementType::Raise(expression) => {
+                self.lint_expression(expression)
+            }
+            StatementType::Block(statements) => {
+                self.scopes.push(vec![]);
+                self.lint_statements(statements);
+                self.end_scope();
+            }
+            StatementType::If {
+                expression,
+                then_statement,
+                else_statement,
+            } => {
+                self.lint_condition(expression, false);
+                self.lint_statement(then_statement);
+                if let Some(else_statement) = else_statement {
+                    self.lint_statement(else_statement);
+                }
+            }
+            StatementType::While {
+                expression,
+                statement,
+            } => {
+                self.lint_condition(expression, true);
+                self.lint_statement(statement);
+            }
+            StatementType::For {
+                variable,
+                expression,
+                statement: body,
+                ..
+            } => {
+                self.lint_expression(expression);
+                self.scopes.push(vec![]);
+                self.declare(*variable, statement.lines.0, true);
+                self.lint_statement(body);
+                self.end_scope();
+            }
+            StatementType::Try {
+                statement: body,
+                variable,
+                catch_statement,
+                ..
+            } => {
+                self.lint_statement(body);
+                self.scopes.push(vec![]);
+                self.declare(*variable, catch_statement.lines.0, true);
+                self.lint_statement(catch_statement);
+                self.end_scope();
+            }
+            StatementType::Match { expression, arms } => {
+                self.lint_expression(expression);
+                for arm in arms {
+                    self.scopes.push(vec![]);
+                    for (variable, _) in arm.pattern.variables() {
+                        self.declare(variable, arm.lines.0, true);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.lint_condition(guard, false);
+                    }
+                    self.lint_statement(&arm.statement);
+                    self.end_scope();
+                }
+                self.lint_cases(arms, statement.lines);
+            }
+            StatementType::Test { body, .. } => self.lint_statement(body),
+            StatementType::ImportNative(_) => {}
+        }
+    }
+
+    /// Checks that every value is matched by a case, and that each case matches values the cases before it don't.
+    /// Cases with guards don't stop later cases from matching.
+    fn lint_cases(&mut self, arms: &[MatchArm], lines: (usize, usize)) {
+        let mut patterns = vec![];
+        for arm in arms {
+            if !is_useful(&patterns, &arm.pattern) {
+                self.report(
+                    Rule::UnreachableCase,
+                    arm.lines,
+                    diagnostic!(UNREACHABLE_CASE, arm.pattern),
+                );
+            } else if let Pattern::Or(alternatives) = &arm.pattern {
+                for (index, alternative) in alternatives.iter().enumerate() {
+                    let mut earlier_patterns = patterns.clone();
+                    earlier_patterns.extend(&alternatives[..index]);
+                    if !is_useful(&earlier_patterns, alternative) {
+                        self.report(
+                            Rule::UnreachableCase,
+                            arm.lines,
+                            diagnostic!(UNREACHABLE_ALTERNATIVE, alternative, arm.pattern),
+                        );
+                    }
+                }
+            }
+            if arm.guard.is_none() {
+                patterns.push(&arm.pattern);
+            }
+        }
+        if let Some(missing_pattern) = missing_pattern(&patterns) {
+            self.report(
+                Rule::NonExhaustiveMatch,
+                lines,
+                diagnostic!(NON_EXHAUSTIVE_MATCH, missing_pattern),
+            );
+        }
+    }
+
+    fn lint_condition(&mut self, expression: &Expression, is_loop: bool) {
+        let mut condition = expression;
+        while let ExpressionType::Grouping(inner_expression) = &condition.expression_type {
+            condition = inner_expression;
+        }
+
+        if let ExpressionType::Binary {
+            operation: BinaryOperation::Assignment,
+            ..
+        } = condition.expression_type
+        {
+            self.report(
+                Rule::AssignmentInCondition,
+                expression.lines,
+                diagnostic!(ASSIGNMENT_CONDITION),
+            );
+        }
+
+        let always_true =
+            condition.expression_type == ExpressionType::Literal(Value::Boolean(true));
+        if is_constant(condition) && !(is_loop && always_true) {
+            self.report(
+                Rule::ConstantCondition,
+                expression.lines,
+                diagnostic!(CONSTANT_CONDITION),
+            );
+        }
+
+        self.lint_expression(expression);
+    }
+
+    fn lint_expression(&mut self, expression: &Expression) {
+        match &expression.expression_type {
+            ExpressionType::Variable { name, .. } => {
+                if let Some(declaration) = self
+                    .scopes
+                    .iter_mut()
+                    .flatten()
+                    .rev()
+                    .find(|declaration| declaration.name == *name)
+                {
+                    declaration.used = true;
+                }
+            }
+            ExpressionType::Binary {
+                operation: BinaryOperation::Assignment,
+                left_expression,
+                right_expression,
+            } => {
+                // Assigning to a variable doesn't use it, but assigning to part of a tuple does.
+                if !matches!(
+                    left_expression.expression_type,
+                    ExpressionType::Variable { .. }
+                ) {
+                    self.lint_expression(left_expression);
+                }
+                self.lint_expression(right_expression);
+            }
+            ExpressionType::Binary {
+                left_expression,
+                right_expression,
+                ..
+            } => {
+                self.lint_expression(left_expression);
+                self.lint_expression(right_expression);
+            }
+            ExpressionType::Unary { expression, .. }
+            | ExpressionType::Grouping(expression)
+            | ExpressionType::TupleAccess { expression, .. }
+            | ExpressionType::Ok(expression)
+            | ExpressionType::Err(expression)
+            | ExpressionType::Propagate(expression)
+            | ExpressionType::Some(expression) => self.lint_expression(expression),
+            ExpressionType::Tuple(expressions)
+            | ExpressionType::Interpolation {
+                values: expressions,
+                ..
+            } => {
+                for expression in expressions {
+                    self.lint_expression(expression);
+                }
+            }
+            ExpressionType::Record(fields) => {
+                for (_, expression) in fields {
+                    self.lint_expression(expression);
+                }
+            }
+            ExpressionType::FunctionCall {
+                function,
+                arguments,
+            } => {
+                self.lint_expression(function);
+                for argument in arguments {
+                    self.lint_expression(argument);
+                }
+            }
+            ExpressionType::Block { statements, value } => {
+                self.scopes.push(vec![]);
+                self.lint_statements(statements);
+                self.lint_expression(value);
+                self.end_scope();
+            }
+            ExpressionType::Loop(body) => self.lint_statement(body),
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => {
+                self.lint_condition(condition, false);
+                self.lint_expression(then_expression);
+                self.lint_expression(else_expression);
+            }
+            ExpressionType::Literal(_) => {}
+        }
+    }
+}
+
+/// Is the expression made only of literals, so it always has the same value?
+fn is_constant(expression: &Expression) -> bool {
+    match &expression.expression_type {
+        ExpressionType::Literal(_) => true,
+        ExpressionType::Unary { expression, .. } | ExpressionType::Grouping(expression) => {
+            is_constant(expression)
+        }
+        ExpressionType::Binary {
+            operation: BinaryOperation::Assignment,
+            ..
+        } => false,
+        ExpressionType::Binary {
+            left_expression,
+            right_expression,
+            ..
+        } => is_constant(left_expression) && is_constant(right_expression),
+        ExpressionType::Interpolation { values, .. } => values.iter().all(is_constant),
+        ExpressionType::Variable { .. }
+        | ExpressionType::Block { .. }
+        | ExpressionType::If { .. }
+        | ExpressionType::Loop(_)
+        | ExpressionType::Tuple(_)
+        | ExpressionType::Record(_)
+        | ExpressionType::TupleAccess { .. }
+        | ExpressionType::FunctionCall { .. }
+        | ExpressionType::Ok(_)
+        | ExpressionType::Err(_)
+        | ExpressionType::Propagate(_)
+        | ExpressionType::Some(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, LintConfig, Rule};
+
+    /// The rule and first line of each lint in the program with the default rules.
+    fn lints(program: &str) -> Vec<(Rule, usize)> {
+        lint(program, &LintConfig::default())
+            .unwrap()
+            .into_iter()
+            .map(|lint| (lint.rule, lint.lines.0))
+            .collect()
+    }
+
+    #[test]
+    fn reports_unused_and_shadowed_variables() {
+        assert_eq!(
+            lints("let a = 1;\nfn f(x: number, _y: number) {\n    let a = 2;\n    print(a);\n}\nf(1, 2);"),
+            [
+                (Rule::UnusedVariable, 1),
+                (Rule::UnusedVariable, 2),
+                (Rule::Shadowing, 3),
+            ]
+        );
+        assert_eq!(lints("let a = 1;\nprint(a);"), []);
+    }
+
+    #[test]
+    fn reports_suspicious_conditions() {
+        assert_eq!(
+            lints("let a = 1;\nif true { print(a); }\nwhile (a = 2) {}\nwhile true { print(a); }"),
+            [
+                (Rule::ConstantCondition, 2),
+                (Rule::AssignmentInCondition, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_the_first_unreachable_statement() {
+        assert_eq!(
+            lints("fn f() {\n    return;\n    print(1);\n    print(2);\n}\nf();"),
+            [(Rule::UnreachableCode, 3)]
+        );
+    }
+
+    #[test]
+    fn leaves_out_disabled_rules() {
+        let mut config = LintConfig::default();
+        config
+            .apply_config_file("# Shadowing is fine here.\nshadowing = off\n\nunused-variable=off")
+            .unwrap();
+        assert!(!config.is_enabled(Rule::Shadowing));
+        let lints = lint("let a = 1;\n{ let a = 2; }", &config).unwrap();
+        assert_eq!(lints, []);
+
+        config.enable(Rule::Shadowing);
+        let lints = lint("let a = 1;\n{ let a = 2; }", &config).unwrap();
+        assert_eq!(lints.len(), 1);
+    }
+
+    #[test]
+    fn rejects_bad_config_files() {
+        let mut config = LintConfig::default();
+        assert!(config.apply_config_file("shadowing").is_err());
+        assert!(config.apply_config_file("shadowing = maybe").is_err());
+        assert!(config.apply_config_file("no-such-rule = off").is_err());
+    }
+}
@@ -1,139 +1,1064 @@
-mod interpreter;
-mod parser;
-mod tokenizer;
-mod variable_and_type_check;
-
 use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
     env, fs,
-    io::{self, Write},
+    io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
     rc::Rc,
+    sync::atomic::{AtomicBool, AtomicIsize, Ordering},
+    thread,
+    time::{Duration, SystemTime},
 };
 
-use interpreter::Variables;
-use tokenizer::{FunctionBody, Type, Value};
-use variable_and_type_check::check_types;
+#[cfg(all(feature = "repl", not(target_os = "wasi")))]
+use decibelle::session::Session;
+use decibelle::{
+    coverage::{self, Coverage, FileCoverage},
+    debugger::{format_value, is_native_function, parse_value, DebugCommand, Debugger, Pause},
+    disassembler::disassemble,
+    documentation::document,
+    format::ValueFormat,
+    formatter::format_program,
+    linter::{lint, LintConfig},
+    parser::{format_dot, format_tree, parse},
+    replay::ReplayLog,
+    test_runner::run_tests,
+    tokenizer::{is_incomplete, tokenize, Value},
+    trace::format_event,
+    Backend, EvalError, Interpreter, OptLevel, EXIT_COMPILE_ERROR, EXIT_RUNTIME_ERROR,
+};
+#[cfg(all(feature = "repl", not(target_os = "wasi")))]
+use rustyline::{error::ReadlineError, DefaultEditor};
 
-fn run(program: &str, variables: &mut Variables) {
-    let tokens = match tokenizer::tokenize(program) {
-        Ok(tokens) => tokens,
-        Err(errors) => {
-            for error in errors {
-                println!("{error}");
+/// How --dump-ast prints the syntax tree.
+#[derive(Clone, Copy)]
+enum AstFormat {
+    Text, // An indented tree.
+    Dot,  // A Graphviz graph.
+}
+
+/// Command line flags.
+#[derive(Default)]
+struct Options {
+    backend: Backend,
+    profile: bool,
+    stats: bool,
+    dump_tokens: bool, // Print the tokens of the file instead of running it.
+    dump_ast: Option<AstFormat>, // Print the syntax tree of the file instead of running it.
+    check: bool,       // Check the file for errors without running it.
+    watch: bool,       // Run the file again whenever it changes.
+    coverage: bool,    // Write which lines ran to the coverage files.
+    trace: bool,       // Print each statement and assignment as it runs.
+    debug: bool,       // Run the file in the debugger.
+    time: bool,        // Print how long each phase took and the most heap memory used.
+    no_prelude: bool,  // Don't declare the prelude's functions.
+    keep_going: bool,  // Report runtime errors and run the next top level statement.
+    value_format: ValueFormat, // How much of big values print and dbg write.
+    record: Option<String>, // Where to write the results of builtins like clock, so the run can be replayed.
+    replay: Option<String>, // The log of results which builtins like clock return instead.
+    arguments: Vec<String>, // The arguments after the filename, which are passed to main.
+}
+
+/// Counts the bytes allocated on the heap while --time is given, so it can report the most the program used.
+/// Otherwise allocations only check that they aren't counted, so other runs don't pay for the counting.
+struct CountingAllocator;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+// The bytes allocated since counting started, less the bytes freed since then.
+// Memory allocated before counting started can be freed, so it can be negative.
+static ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+static PEAK_ALLOCATED: AtomicIsize = AtomicIsize::new(0);
+
+fn count_allocation(size: usize) {
+    let allocated = ALLOCATED.fetch_add(size as isize, Ordering::Relaxed) + size as isize;
+    PEAK_ALLOCATED.fetch_max(allocated, Ordering::Relaxed);
+}
+
+fn count_deallocation(size: usize) {
+    ALLOCATED.fetch_sub(size as isize, Ordering::Relaxed);
+}
+
+/// Starts counting allocations from zero.
+fn start_counting_allocations() {
+    ALLOCATED.store(0, Ordering::Relaxed);
+    PEAK_ALLOCATED.store(0, Ordering::Relaxed);
+    COUNTING.store(true, Ordering::Relaxed);
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let pointer = unsafe { System.alloc(layout) };
+        if !pointer.is_null() && COUNTING.load(Ordering::Relaxed) {
+            count_allocation(layout.size());
+        }
+        pointer
+    }
+
+    unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(pointer, layout) };
+        if COUNTING.load(Ordering::Relaxed) {
+            count_deallocation(layout.size());
+        }
+    }
+
+    unsafe fn realloc(&self, pointer: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_pointer = unsafe { System.realloc(pointer, layout, new_size) };
+        if !new_pointer.is_null() && COUNTING.load(Ordering::Relaxed) {
+            count_deallocation(layout.size());
+            count_allocation(new_size);
+        }
+        new_pointer
+    }
+}
+
+#[global_allocator]
+static GLOBAL_ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Exit codes, following sysexits.h, like the ones for programs which stop with errors.
+const EXIT_USAGE: u8 = 64;
+const EXIT_IO_ERROR: u8 = 74;
+
+/// Where --coverage writes the coverage, as an lcov tracefile and as an HTML page.
+const COVERAGE_LCOV_FILENAME: &str = "lcov.info";
+const COVERAGE_HTML_FILENAME: &str = "coverage.html";
+
+/// Writes the coverage files and prints a summary to stderr.
+fn write_coverage(files: &[FileCoverage]) -> Result<(), ExitCode> {
+    for (filename, report) in [
+        (COVERAGE_LCOV_FILENAME, coverage::to_lcov(files)),
+        (COVERAGE_HTML_FILENAME, coverage::to_html(files)),
+    ] {
+        if fs::write(filename, report).is_err() {
+            println!("Couldn't write {filename}.");
+            return Err(ExitCode::from(EXIT_IO_ERROR));
+        }
+    }
+    for file in files {
+        eprintln!(
+            "{}: {} of {} lines covered.",
+            file.source_name,
+            file.lines_hit(),
+            file.lines.len()
+        );
+    }
+    eprintln!("Wrote {COVERAGE_LCOV_FILENAME} and {COVERAGE_HTML_FILENAME}.");
+    Ok(())
+}
+
+/// The exit code for the result of running a program.
+fn exit_code(result: &Result<(), EvalError>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => ExitCode::from(error.exit_status()),
+    }
+}
+
+/// Runs a program entered in the REPL. Returns the exit code if the program called exit.
+fn run(program: &str, interpreter: &mut Interpreter) -> Option<ExitCode> {
+    match interpreter.eval(program) {
+        Err(EvalError::Exit(code)) => Some(ExitCode::from(code as u8)),
+        Err(error) => {
+            print!("{}", error.report(program));
+            None
+        }
+        Ok(()) => None,
+    }
+}
+
+/// Where the REPL keeps the lines entered in earlier sessions.
+#[cfg(all(feature = "repl", not(target_os = "wasi")))]
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".decibelle_history"))
+}
+
+/// Creates an interpreter with the backend, and the prelude unless it's turned off.
+fn new_interpreter(options: &Options) -> Interpreter {
+    let mut interpreter = if options.no_prelude {
+        Interpreter::without_prelude(options.backend)
+    } else {
+        Interpreter::with_backend(options.backend)
+    };
+    interpreter.set_value_format(options.value_format);
+    interpreter
+}
+
+/// Runs a REPL command: `:save path` writes the programs entered and the globals to the file,
+/// and `:restore path` declares the globals saved in the file and adds its programs to the history.
+#[cfg(all(feature = "repl", not(target_os = "wasi")))]
+fn run_command(
+    command: &str,
+    interpreter: &mut Interpreter,
+    editor: &mut DefaultEditor,
+    history: &mut Vec<String>,
+    options: &Options,
+) {
+    match command.split_once(' ') {
+        Some((":save", path)) => {
+            let fresh = new_interpreter(options);
+            let (session, skipped) = Session::capture(interpreter, &fresh, history.clone());
+            let json = serde_json::to_string_pretty(&session.to_json()).unwrap();
+            if let Err(error) = fs::write(path, json) {
+                println!("Couldn't write {path}: {error}");
+                return;
+            }
+            if !skipped.is_empty() {
+                println!(
+                    "Couldn't save {}, as their values can't be written to a file.",
+                    skipped.join(", ")
+                );
+            }
+        }
+        Some((":restore", path)) => {
+            let session = fs::read_to_string(path)
+                .map_err(|error| format!("Couldn't read {path}: {error}"))
+                .and_then(|json| {
+                    serde_json::from_str(&json)
+                        .map_err(|error| format!("Couldn't read {path}: {error}"))
+                })
+                .and_then(|json| Session::from_json(&json));
+            let session = match session {
+                Ok(session) => session,
+                Err(error) => {
+                    println!("{error}");
+                    return;
+                }
+            };
+            for error in session.restore(interpreter) {
+                println!("Couldn't restore {error}");
+            }
+            for program in session.history {
+                let _ = editor.add_history_entry(&program);
+                history.push(program);
             }
-            return;
         }
+        _ => println!("Unknown command. Use :save path or :restore path."),
+    }
+}
+
+/// Runs programs as they're entered, with line editing and history.
+/// Programs which are incomplete continue on the next line, and lines starting with : are commands.
+/// Ctrl-C discards the current program and Ctrl-D exits, as does calling exit.
+#[cfg(all(feature = "repl", not(target_os = "wasi")))]
+fn run_repl(options: &Options) -> ExitCode {
+    let mut interpreter = new_interpreter(options);
+    let Ok(mut editor) = DefaultEditor::new() else {
+        println!("Couldn't start the REPL.");
+        return ExitCode::from(EXIT_IO_ERROR);
     };
+    let history_path = history_path();
+    if let Some(history_path) = &history_path {
+        // There's no history the first time the REPL runs.
+        let _ = editor.load_history(history_path);
+    }
 
-    let mut statements = match parser::parse(&tokens) {
-        Ok(statements) => statements,
-        Err(errors) => {
-            let lines = program.lines().collect::<Vec<_>>();
-            for error in errors {
-                let index_width = error.lines.1.ilog10() + 1;
-                for (line_index, line) in lines
-                    .iter()
-                    .enumerate()
-                    .take(error.lines.1)
-                    .skip(error.lines.0 - 1)
-                {
-                    print!("{}", line_index + 1);
-                    for _ in 0..(index_width - (line_index + 1).ilog10()) {
-                        print!(" ");
-                    }
-                    println!("| {}", line);
+    let mut exit_code = ExitCode::SUCCESS;
+    let mut program = String::new();
+    let mut history = vec![]; // The programs entered in this session, which :save writes out.
+    loop {
+        let prompt = if program.is_empty() { "> " } else { ". " };
+        match editor.readline(prompt) {
+            Ok(line) if program.is_empty() && line.starts_with(':') => {
+                let _ = editor.add_history_entry(&line);
+                run_command(
+                    line.trim_end(),
+                    &mut interpreter,
+                    &mut editor,
+                    &mut history,
+                    options,
+                );
+            }
+            Ok(line) => {
+                program.push_str(&line);
+                program.push('\n');
+                if is_incomplete(&program) {
+                    continue;
+                }
+                let _ = editor.add_history_entry(program.trim_end());
+                history.push(program.trim_end().to_string());
+                if let Some(code) = run(&program, &mut interpreter) {
+                    exit_code = code;
+                    break;
                 }
-                println!("{}", error.error);
-                println!();
+                program.clear();
             }
-            return;
+            Err(ReadlineError::Interrupted) => program.clear(),
+            Err(ReadlineError::Eof) => break,
+            Err(error) => {
+                println!("Couldn't read the line: {error}");
+                exit_code = ExitCode::from(EXIT_IO_ERROR);
+                break;
+            }
+        }
+    }
+
+    if let Some(history_path) = &history_path {
+        let _ = editor.save_history(history_path);
+    }
+    exit_code
+}
+
+#[cfg(not(all(feature = "repl", not(target_os = "wasi"))))]
+fn run_repl(options: &Options) -> ExitCode {
+    let mut interpreter = new_interpreter(options);
+
+    let mut program = String::new();
+    loop {
+        print!("{}", if program.is_empty() { "> " } else { ". " });
+        io::stdout().flush().unwrap();
+        if io::stdin().read_line(&mut program).unwrap() == 0 {
+            return ExitCode::SUCCESS;
+        }
+        if is_incomplete(&program) {
+            continue;
+        }
+        if let Some(code) = run(&program, &mut interpreter) {
+            return code;
         }
+        program.clear();
+    }
+}
+
+fn run_file(filename: &str, options: &Options, included_files: &mut Vec<PathBuf>) -> ExitCode {
+    let Ok(program) = fs::read_to_string(filename) else {
+        // TODO: Handle errors better - check if file doesn't exist.
+        println!("Couldn't read the program.");
+        return ExitCode::from(EXIT_IO_ERROR);
+    };
+    run_program(filename, &program, options, included_files)
+}
+
+/// How often watch mode checks whether the file changed.
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Runs the file, and runs it again whenever it or a file it included is modified, until the process is interrupted.
+/// The screen is cleared before each run when the output is a terminal.
+fn watch_file(filename: &str, options: &Options) -> ExitCode {
+    let modified_times_of = |files: &[PathBuf]| {
+        files
+            .iter()
+            .map(|file| {
+                fs::metadata(file)
+                    .and_then(|metadata| metadata.modified())
+                    .ok()
+            })
+            .collect::<Vec<Option<SystemTime>>>()
     };
+    // The program, followed by the files its last run included.
+    let mut files = vec![PathBuf::from(filename)];
+    let mut last_modified_times = vec![];
+    let mut run_count = 0;
+    loop {
+        let modified_times = modified_times_of(&files);
+        if modified_times[0].is_some() && modified_times != last_modified_times {
+            run_count += 1;
+            if io::stdout().is_terminal() {
+                print!("\x1b[2J\x1b[H");
+            }
+            println!("[watch] Run {run_count} of {filename}");
+            let mut included_files = vec![];
+            let exit_code = run_file(filename, options, &mut included_files);
+            files.truncate(1);
+            files.extend(included_files);
+            last_modified_times = modified_times_of(&files);
+            println!(
+                "[watch] Finished with {}. Waiting for changes.",
+                if exit_code == ExitCode::SUCCESS {
+                    "success"
+                } else {
+                    "errors"
+                }
+            );
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Runs a program piped in, like a file.
+fn run_stdin(options: &Options) -> ExitCode {
+    let mut program = String::new();
+    if io::stdin().read_to_string(&mut program).is_err() {
+        println!("Couldn't read the program.");
+        return ExitCode::from(EXIT_IO_ERROR);
+    }
+    run_program("<stdin>", &program, options, &mut vec![])
+}
+
+/// Calls the program's main function with the command line arguments, either as a string parameter for each
+/// argument or as a tuple of strings. Returns None if main can't take them.
+fn call_main(interpreter: &mut Interpreter, arguments: &[String]) -> Option<Result<(), EvalError>> {
+    let arguments = arguments
+        .iter()
+        .map(|argument| Value::String(argument.as_str().into()))
+        .collect::<Vec<_>>();
+    // Tuples have at least two elements.
+    let tuple = (arguments.len() >= 2).then(|| Value::Tuple(arguments.clone().into()));
+    interpreter
+        .call_function("main", arguments)
+        .or_else(|| interpreter.call_function("main", vec![tuple?]))
+        .map(|result| result.map(|_| ()))
+}
 
-    if let Err(errors) = check_types(&mut statements, &variables.environments[&0]) {
-        let lines = program.lines().collect::<Vec<_>>();
-        for error in errors {
-            let index_width = error.lines.1.ilog10() + 1;
-            for (line_index, line) in lines
-                .iter()
-                .enumerate()
-                .take(error.lines.1)
-                .skip(error.lines.0 - 1)
-            {
-                print!("{}", line_index + 1);
-                for _ in 0..(index_width - (line_index + 1).ilog10()) {
-                    print!(" ");
+/// Runs, or with the inspection flags inspects, a whole program. Errors are labelled with the source name.
+fn run_program(
+    source_name: &str,
+    program: &str,
+    options: &Options,
+    included_files: &mut Vec<PathBuf>, // Set to the files the program included.
+) -> ExitCode {
+    if options.dump_tokens {
+        match tokenize(program) {
+            Ok(tokens) => {
+                for token in tokens {
+                    println!("{token}");
                 }
-                println!("| {}", line);
-            }
-            println!("{}", error.error);
-            println!();
-        }
-        return;
-    }
-
-    interpreter::interpret(&statements, variables);
-}
-
-fn setup_variables(variables: &mut Variables) {
-    variables
-        .environments
-        .get_mut(&0)
-        .unwrap()
-        .variables
-        .insert(
-            ("print".to_string(), 0), // TODO: Prefer setting shadow_id somewhere else.
-            Value::Function {
-                parameters: vec![("value".to_string(), Some(0), Type::Any)],
-                return_type: Type::Void,
-                body: FunctionBody::RustClosure {
-                    id: 0,
-                    closure: Rc::new(|values| {
-                        println!("{}", values[0]);
-                        Value::Void
-                    }),
-                },
-                parent_environment: 0, // Defined in global environment.
+            }
+            Err(errors) => {
+                print!(
+                    "{}",
+                    EvalError::Tokenizer(errors).report_in(source_name, program)
+                );
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    if let Some(format) = options.dump_ast {
+        let tree = tokenize(program)
+            .map_err(EvalError::Tokenizer)
+            .and_then(|tokens| parse(&tokens).map_err(EvalError::Compiler));
+        match tree {
+            Ok(statements) => match format {
+                AstFormat::Text => print!("{}", format_tree(&statements)),
+                AstFormat::Dot => print!("{}", format_dot(&statements)),
             },
+            Err(error) => {
+                print!("{}", error.report_in(source_name, program));
+                return ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let mut interpreter = new_interpreter(options);
+    interpreter.set_source_name(source_name);
+    // Files are included from the program's directory, or the current directory for a program piped in.
+    let directory = Path::new(source_name).parent().unwrap_or(Path::new(""));
+    interpreter.allow_includes(if directory == Path::new("") {
+        Path::new(".")
+    } else {
+        directory
+    });
+    if options.check {
+        let result = interpreter.check(program);
+        *included_files = interpreter.included_files().to_vec();
+        if let Err(error) = &result {
+            print!("{}", error.report_in(source_name, program));
+        }
+        return exit_code(&result);
+    }
+    if options.profile {
+        interpreter.enable_profiler();
+    }
+    if options.trace {
+        let source_lines = program.lines().map(String::from).collect::<Vec<_>>();
+        interpreter.set_tracer(move |event| eprintln!("{}", format_event(event, &source_lines)));
+    }
+    if options.coverage {
+        interpreter.enable_coverage();
+        interpreter.set_opt_level(OptLevel::None);
+    }
+    if options.debug {
+        let source_lines = program.lines().map(String::from).collect::<Vec<_>>();
+        interpreter.set_debugger(Debugger::new(move |pause| {
+            debug_prompt(pause, &source_lines)
+        }));
+        interpreter.set_opt_level(OptLevel::None);
+    }
+    if options.record.is_some() {
+        interpreter.enable_recording();
+    }
+    if let Some(path) = &options.replay {
+        let log = fs::read_to_string(path)
+            .map_err(|error| format!("Couldn't read {path}: {error}"))
+            .and_then(|log| ReplayLog::parse(&log));
+        match log {
+            Ok(log) => interpreter.replay(log),
+            Err(error) => {
+                println!("{error}");
+                return ExitCode::from(EXIT_IO_ERROR);
+            }
+        }
+    }
+    let error_reported = Rc::new(Cell::new(false));
+    if options.keep_going {
+        let (source_name, program, error_reported) = (
+            source_name.to_string(),
+            program.to_string(),
+            error_reported.clone(),
         );
+        interpreter.continue_after_errors(move |error| {
+            print!("{}", error.report_in(&source_name, &program));
+            error_reported.set(true);
+        });
+    }
+    if options.time {
+        interpreter.enable_timings();
+        // Only the memory used while the program runs is reported.
+        start_counting_allocations();
+    }
+    let mut result = interpreter.eval(program);
+    *included_files = interpreter.included_files().to_vec();
+    // A program which returned a value from its top level has already finished.
+    if result.is_ok()
+        && *interpreter.return_value() == Value::Void
+        && matches!(interpreter.get_global("main"), Some(Value::Function(_)))
+    {
+        let Some(main_result) = call_main(&mut interpreter, &options.arguments) else {
+            println!("main must take a string parameter for each argument, or a tuple of strings.");
+            return ExitCode::from(EXIT_USAGE);
+        };
+        result = main_result;
+    }
+    if let Err(error) = &result {
+        print!("{}", error.report_in(source_name, program));
+    }
+    if let Some(timings) = interpreter.timings() {
+        eprint!("{timings}");
+        let peak = PEAK_ALLOCATED.load(Ordering::Relaxed);
+        eprintln!("peak heap: {:>11.1} KiB", peak as f64 / 1024.0);
+    }
+    if let Some(coverage) = interpreter.coverage() {
+        // The program parsed if any of it ran.
+        if let Ok(file) = FileCoverage::new(source_name, program, coverage) {
+            if let Err(exit_code) = write_coverage(&[file]) {
+                return exit_code;
+            }
+        }
+    }
+    if let (Some(path), Some(log)) = (&options.record, interpreter.recording()) {
+        if fs::write(path, log.to_string()).is_err() {
+            println!("Couldn't write {path}.");
+            return ExitCode::from(EXIT_IO_ERROR);
+        }
+    }
+    if let Some(profiler) = interpreter.profiler() {
+        eprint!("{}", profiler.report(program));
+    }
+    if options.stats {
+        eprint!("{}", interpreter.stats());
+    }
+    match (&result, interpreter.return_value()) {
+        // A number returned from the top level of the program is its exit code.
+        (Ok(()), Value::Number(code)) => ExitCode::from(*code as i32 as u8),
+        (Ok(()), _) if error_reported.get() => ExitCode::from(EXIT_RUNTIME_ERROR),
+        _ => exit_code(&result),
+    }
 }
 
-fn run_repl() {
-    let mut variables = Variables::new();
-    setup_variables(&mut variables);
+const DEBUG_HELP: &str = "\
+step, s             Run to the next statement, stepping into calls.
+next, n             Run to the next statement, stepping over calls.
+continue, c         Run to the next breakpoint.
+break, b <line>     Pause whenever the line runs.
+delete, d <line>    Remove the breakpoint on the line.
+print, p <name>     Print the value of a variable.
+set <name> = <value>  Change a variable to a number, string or bool.
+vars                Print the variables in scope.
+backtrace, bt       Print the calls to the running functions.
+list, l             Print the lines around the current one.
+quit, q             Stop the program.";
 
+/// Reads debugger commands from stdin until one resumes the program.
+/// If stdin ends, the breakpoints are removed and the program runs to the end.
+fn debug_prompt(pause: &mut Pause, source_lines: &[String]) -> DebugCommand {
+    let source_line = |line: usize| source_lines.get(line - 1).map_or("", |line| line.trim());
+    println!(
+        "Paused at line {}: {}",
+        pause.lines.0,
+        source_line(pause.lines.0)
+    );
     loop {
-        print!("> ");
+        print!("(debug) ");
         io::stdout().flush().unwrap();
-        let mut line = String::new();
-        io::stdin().read_line(&mut line).unwrap();
-        run(&line, &mut variables);
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+            pause.breakpoints.clear();
+            return DebugCommand::Continue;
+        }
+        let (command, argument) = input
+            .trim()
+            .split_once(' ')
+            .map_or((input.trim(), ""), |(command, argument)| {
+                (command, argument.trim())
+            });
+        match command {
+            "step" | "s" => return DebugCommand::Step,
+            "next" | "n" => return DebugCommand::Next,
+            "continue" | "c" => return DebugCommand::Continue,
+            "quit" | "q" => return DebugCommand::Quit,
+            "break" | "b" | "delete" | "d" => {
+                let Ok(line) = argument.parse::<usize>() else {
+                    println!("Expected a line number.");
+                    continue;
+                };
+                if command.starts_with('b') {
+                    pause.breakpoints.insert(line);
+                    println!("Breakpoint at line {line}: {}", source_line(line));
+                } else if !pause.breakpoints.remove(&line) {
+                    println!("There's no breakpoint at line {line}.");
+                }
+            }
+            "print" | "p" => match pause.get_variable(argument) {
+                Some(value) => println!("{argument} = {}", format_value(&value)),
+                None => println!("There's no variable called {argument} here."),
+            },
+            "set" => {
+                let Some((name, value)) = argument.split_once('=') else {
+                    println!("Expected set <name> = <value>.");
+                    continue;
+                };
+                let result = parse_value(value.trim())
+                    .and_then(|value| pause.set_variable(name.trim(), value));
+                if let Err(error) = result {
+                    println!("{error}");
+                }
+            }
+            "vars" => {
+                for (name, value) in pause.visible_variables() {
+                    // Builtins are globals too, but aren't interesting.
+                    if is_native_function(&value) {
+                        continue;
+                    }
+                    println!("{name} = {}", format_value(&value));
+                }
+            }
+            "backtrace" | "bt" => {
+                println!("line {}: {}", pause.lines.0, source_line(pause.lines.0));
+                for lines in pause.call_stack.iter().rev() {
+                    println!("  called at line {}: {}", lines.0, source_line(lines.0));
+                }
+            }
+            "list" | "l" => {
+                let current_line = pause.lines.0;
+                for line in current_line.saturating_sub(3).max(1)..=current_line + 3 {
+                    let Some(text) = source_lines.get(line - 1) else {
+                        break;
+                    };
+                    let marker = if line == current_line { '>' } else { ' ' };
+                    println!("{marker}{line:>4} | {text}");
+                }
+            }
+            "help" | "h" => println!("{DEBUG_HELP}"),
+            "" => {}
+            command => println!("Unknown command {command}. Enter help to list the commands."),
+        }
+    }
+}
+
+/// Formats the files in place, or with --check lists the files which aren't formatted.
+/// With - instead of filenames, formats stdin to stdout.
+fn run_fmt(arguments: &[String]) -> ExitCode {
+    let check = arguments.iter().any(|argument| argument == "--check");
+    let filenames: Vec<_> = arguments
+        .iter()
+        .filter(|argument| *argument != "--check")
+        .collect();
+    if filenames.is_empty() || filenames.iter().any(|filename| filename.starts_with("--")) {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for filename in filenames {
+        let program = if filename == "-" {
+            let mut program = String::new();
+            io::stdin().read_to_string(&mut program).map(|_| program)
+        } else {
+            fs::read_to_string(filename)
+        };
+        let Ok(program) = program else {
+            println!("Couldn't read {filename}.");
+            exit_code = ExitCode::from(EXIT_IO_ERROR);
+            continue;
+        };
+        let source_name = if filename == "-" { "<stdin>" } else { filename };
+
+        let formatted = match format_program(&program) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                print!("{}", error.report_in(source_name, &program));
+                exit_code = ExitCode::from(EXIT_COMPILE_ERROR);
+                continue;
+            }
+        };
+        if check {
+            if formatted != program {
+                println!("{source_name} isn't formatted.");
+                exit_code = ExitCode::FAILURE;
+            }
+        } else if filename == "-" {
+            print!("{formatted}");
+        } else if formatted != program && fs::write(filename, formatted).is_err() {
+            println!("Couldn't write {filename}.");
+            exit_code = ExitCode::from(EXIT_IO_ERROR);
+        }
     }
+    exit_code
 }
 
-fn run_file(filename: &str) {
+/// Prints the bytecode the VM would run for a file, without running it.
+fn run_disasm(arguments: &[String]) -> ExitCode {
+    let [filename] = arguments else {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    };
+    if filename.starts_with("--") {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    }
+
     let Ok(program) = fs::read_to_string(filename) else {
-        // TODO: Handle errors better - check if file doesn't exist.
-        println!("Couldn't read the program.");
-        return;
+        println!("Couldn't read {filename}.");
+        return ExitCode::from(EXIT_IO_ERROR);
+    };
+    match Interpreter::with_backend(Backend::Vm).compile(&program) {
+        Ok(script) => {
+            print!("{}", disassemble(&script, &program));
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            print!("{}", error.report_in(filename, &program));
+            ExitCode::from(EXIT_COMPILE_ERROR)
+        }
+    }
+}
+
+/// Prints the documentation of a file as Markdown, or HTML with --html.
+fn run_doc(arguments: &[String]) -> ExitCode {
+    let html = arguments.iter().any(|argument| argument == "--html");
+    let filenames: Vec<_> = arguments
+        .iter()
+        .filter(|argument| *argument != "--html")
+        .collect();
+    let [filename] = filenames[..] else {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    };
+    if filename.starts_with("--") {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let Ok(program) = fs::read_to_string(filename) else {
+        println!("Couldn't read {filename}.");
+        return ExitCode::from(EXIT_IO_ERROR);
+    };
+    match document(&program) {
+        Ok(documentation) => {
+            // The title is the file's name without its directory or extension.
+            let title = Path::new(filename)
+                .file_stem()
+                .map_or(filename.as_str(), |stem| stem.to_str().unwrap_or(filename));
+            if html {
+                print!("{}", documentation.to_html(title));
+            } else {
+                print!("{}", documentation.to_markdown(title));
+            }
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            print!("{}", error.report_in(filename, &program));
+            ExitCode::from(EXIT_COMPILE_ERROR)
+        }
+    }
+}
+
+/// The config file lint reads if there's no --config flag.
+const LINT_CONFIG_FILENAME: &str = ".decibellelint";
+
+/// Lints the files, exiting with status 1 if any rule found a problem.
+/// Rules are configured by the config file, then by --enable and --disable flags in order.
+fn run_lint(arguments: &[String]) -> ExitCode {
+    let mut config = LintConfig::default();
+    let mut config_path = None;
+    let mut rule_flags = vec![];
+    let mut filenames = vec![];
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--config" | "--enable" | "--disable" => {
+                let Some(value) = arguments.next() else {
+                    print_usage();
+                    return ExitCode::from(EXIT_USAGE);
+                };
+                if argument == "--config" {
+                    config_path = Some(value.as_str());
+                } else {
+                    rule_flags.push((argument == "--enable", value));
+                }
+            }
+            _ if argument.starts_with("--") => {
+                print_usage();
+                return ExitCode::from(EXIT_USAGE);
+            }
+            _ => filenames.push(argument),
+        }
+    }
+    if filenames.is_empty() {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let config_file = match config_path {
+        Some(config_path) => match fs::read_to_string(config_path) {
+            Ok(config_file) => Some(config_file),
+            Err(_) => {
+                println!("Couldn't read {config_path}.");
+                return ExitCode::from(EXIT_IO_ERROR);
+            }
+        },
+        None => fs::read_to_string(LINT_CONFIG_FILENAME).ok(),
     };
+    if let Some(config_file) = config_file {
+        if let Err(error) = config.apply_config_file(&config_file) {
+            println!("{error}");
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+    for (enable, name) in rule_flags {
+        match name.parse() {
+            Ok(rule) if enable => config.enable(rule),
+            Ok(rule) => config.disable(rule),
+            Err(error) => {
+                println!("{error}");
+                return ExitCode::from(EXIT_USAGE);
+            }
+        }
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    for filename in filenames {
+        let Ok(program) = fs::read_to_string(filename) else {
+            println!("Couldn't read {filename}.");
+            exit_code = ExitCode::from(EXIT_IO_ERROR);
+            continue;
+        };
+        match lint(&program, &config) {
+            Ok(lints) => {
+                for lint in &lints {
+                    println!(
+                        "{filename}:{}: {} [{}]",
+                        lint.lines.0, lint.message, lint.rule
+                    );
+                }
+                if !lints.is_empty() {
+                    exit_code = ExitCode::FAILURE;
+                }
+            }
+            Err(error) => {
+                print!("{}", error.report_in(filename, &program));
+                exit_code = ExitCode::from(EXIT_COMPILE_ERROR);
+            }
+        }
+    }
+    exit_code
+}
+
+/// Runs the test blocks of the files, printing whether each passed and the output and error of each failure.
+/// Exits with status 1 if any test failed.
+fn run_test(arguments: &[String]) -> ExitCode {
+    let coverage = arguments.iter().any(|argument| argument == "--coverage");
+    let filenames: Vec<_> = arguments
+        .iter()
+        .filter(|argument| *argument != "--coverage")
+        .collect();
+    if filenames.is_empty() || filenames.iter().any(|filename| filename.starts_with("--")) {
+        print_usage();
+        return ExitCode::from(EXIT_USAGE);
+    }
+
+    let mut exit_code = ExitCode::SUCCESS;
+    let (mut passed, mut failed) = (0, 0);
+    let mut coverage_files = vec![];
+    for filename in filenames {
+        let Ok(program) = fs::read_to_string(filename) else {
+            println!("Couldn't read {filename}.");
+            exit_code = ExitCode::from(EXIT_IO_ERROR);
+            continue;
+        };
+        let mut file_coverage = coverage.then(Coverage::new);
+        let results = match run_tests(&program, Backend::default(), file_coverage.as_mut()) {
+            Ok(results) => results,
+            Err(error) => {
+                print!("{}", error.report_in(filename, &program));
+                exit_code = ExitCode::from(EXIT_COMPILE_ERROR);
+                continue;
+            }
+        };
+        for result in results {
+            let Some(error) = result.error else {
+                println!("test {} ... ok", result.name);
+                passed += 1;
+                continue;
+            };
+            println!("test {} ... FAILED", result.name);
+            print!("{}", result.output);
+            match error {
+                EvalError::Exit(code) => println!("Exited with code {code}."),
+                error => print!("{}", error.report_in(filename, &program)),
+            }
+            failed += 1;
+        }
+        if let Some(file_coverage) = file_coverage {
+            // The file parsed, as its tests ran.
+            coverage_files.push(FileCoverage::new(filename, &program, &file_coverage).unwrap());
+        }
+    }
+    println!("{passed} passed, {failed} failed");
+    if coverage {
+        if let Err(exit_code) = write_coverage(&coverage_files) {
+            return exit_code;
+        }
+    }
+    if failed > 0 && exit_code == ExitCode::SUCCESS {
+        exit_code = ExitCode::FAILURE;
+    }
+    exit_code
+}
 
-    let mut variables = Variables::new();
-    setup_variables(&mut variables);
-    run(&program, &mut variables);
+fn print_usage() {
+    println!("Incorrect usage.");
+    println!("To run REPL: busheye");
+    println!(
+        "To run a file: busheye [--profile] [--stats] [--time] [--coverage] [--trace] [--no-prelude] [--keep-going] [--max-depth=n] [--max-width=n] [--record=log] [--replay=log] [filename] [arguments]"
+    );
+    println!(
+        "To run a program from stdin: busheye [--profile] [--stats] [--time] [--coverage] [--trace] [--no-prelude] [--keep-going] [--max-depth=n] [--max-width=n] [--record=log] [--replay=log] - [arguments]"
+    );
+    #[cfg(feature = "jit")]
+    println!("To compile hot functions to native code while running: busheye --jit [filename] [arguments]");
+    println!("To print the tokens of a file: busheye --dump-tokens [filename]");
+    println!("To print the syntax tree of a file: busheye --dump-ast[=dot] [filename]");
+    println!("To check a file for errors without running it: busheye --check [filename]");
+    println!("To run a file again whenever it changes: busheye --watch [filename]");
+    println!("To run a file in the debugger: busheye --debug [filename]");
+    #[cfg(feature = "dap")]
+    println!("To debug files from an editor over the Debug Adapter Protocol: busheye dap");
+    println!("To format files: busheye fmt [--check] [filenames]");
+    println!("To print the documentation of a file: busheye doc [--html] [filename]");
+    println!("To print the bytecode of a file: busheye disasm [filename]");
+    println!("To run the tests in files: busheye test [--coverage] [filenames]");
+    println!(
+        "To lint files: busheye lint [--config path] [--enable rule] [--disable rule] [filenames]"
+    );
 }
 
-fn main() {
-    let mut args = env::args().skip(1);
+fn main() -> ExitCode {
+    let arguments: Vec<String> = env::args().skip(1).collect();
+    if arguments.first().is_some_and(|argument| argument == "fmt") {
+        return run_fmt(&arguments[1..]);
+    }
+    if arguments.first().is_some_and(|argument| argument == "lint") {
+        return run_lint(&arguments[1..]);
+    }
+    if arguments.first().is_some_and(|argument| argument == "doc") {
+        return run_doc(&arguments[1..]);
+    }
+    if arguments
+        .first()
+        .is_some_and(|argument| argument == "disasm")
+    {
+        return run_disasm(&arguments[1..]);
+    }
+    if arguments.first().is_some_and(|argument| argument == "test") {
+        return run_test(&arguments[1..]);
+    }
+    #[cfg(feature = "dap")]
+    if arguments.first().is_some_and(|argument| argument == "dap") {
+        if arguments.len() > 1 {
+            print_usage();
+            return ExitCode::from(EXIT_USAGE);
+        }
+        return match decibelle::dap::serve(Box::new(io::stdin().lock()), Box::new(io::stdout())) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("Debug adapter error: {error}");
+                ExitCode::from(EXIT_IO_ERROR)
+            }
+        };
+    }
 
-    if args.len() > 1 {
-        println!("Incorrect usage.");
-        println!("To run REPL: busheye");
-        println!("To run a file: busheye [filename]");
-        return;
+    let mut options = Options::default();
+    let mut filename = None;
+    for (index, arg) in arguments.iter().enumerate() {
+        match arg.as_str() {
+            "--profile" => options.profile = true,
+            "--stats" => options.stats = true,
+            "--dump-tokens" => options.dump_tokens = true,
+            "--dump-ast" => options.dump_ast = Some(AstFormat::Text),
+            "--dump-ast=dot" => options.dump_ast = Some(AstFormat::Dot),
+            "--check" => options.check = true,
+            "--watch" => options.watch = true,
+            "--coverage" => options.coverage = true,
+            "--trace" => options.trace = true,
+            "--debug" => options.debug = true,
+            "--time" => options.time = true,
+            "--no-prelude" => options.no_prelude = true,
+            "--keep-going" => options.keep_going = true,
+            #[cfg(feature = "jit")]
+            "--jit" => options.backend = Backend::Jit,
+            _ if arg.starts_with("--record=") => {
+                options.record = Some(arg["--record=".len()..].into())
+            }
+            _ if arg.starts_with("--replay=") => {
+                options.replay = Some(arg["--replay=".len()..].into())
+            }
+            _ if arg.starts_with("--max-depth=") => match arg["--max-depth=".len()..].parse() {
+                Ok(max_depth) => options.value_format.max_depth = Some(max_depth),
+                Err(_) => {
+                    print_usage();
+                    return ExitCode::from(EXIT_USAGE);
+                }
+            },
+            _ if arg.starts_with("--max-width=") => match arg["--max-width=".len()..].parse() {
+                Ok(max_width) => options.value_format.max_width = Some(max_width),
+                Err(_) => {
+                    print_usage();
+                    return ExitCode::from(EXIT_USAGE);
+                }
+            },
+            _ if arg.starts_with("--") => {
+                print_usage();
+                return ExitCode::from(EXIT_USAGE);
+            }
+            _ => {
+                filename = Some(arg.as_str());
+                options.arguments = arguments[index + 1..].to_vec();
+                break;
+            }
+        }
     }
 
-    match args.next() {
-        None => run_repl(),
-        Some(filename) => run_file(&filename),
+    match filename {
+        Some("-") if options.watch || options.debug => {
+            print_usage();
+            ExitCode::from(EXIT_USAGE)
+        }
+        Some("-") => run_stdin(&options),
+        Some(filename) if options.watch => watch_file(filename, &options),
+        Some(filename) => run_file(filename, &options, &mut vec![]),
+        None if !io::stdin().is_terminal() => run_stdin(&options),
+        None if options.dump_tokens
+            || options.dump_ast.is_some()
+            || options.check
+            || options.watch
+            || options.debug =>
+        {
+            print_usage();
+            ExitCode::from(EXIT_USAGE)
+        }
+        None => run_repl(&options),
     }
 }
@@ -0,0 +1,279 @@
+use std::{collections::HashMap, error::Error, fmt::Display, sync::RwLock};
+
+/// The English text of an error or a warning, with a code which stays the same between versions, so hosts can
+/// show their own text for it. {0}, {1}, ... in the text are replaced by the values the message is about,
+/// and {{ and }} by braces.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Message {
+    pub code: &'static str,
+    pub text: &'static str,
+}
+
+macro_rules! messages {
+    ($($name:ident = $code:literal: $text:literal,)*) => {
+        $(pub static $name: Message = Message { code: $code, text: $text };)*
+
+        /// Every message, in the order of their codes.
+        pub static MESSAGES: &[&Message] = &[$(&$name),*];
+    };
+}
+
+// Codes are E01xx for the tokenizer, E02xx for the parser, E03xx for the checker, E04xx at runtime and W01xx
+// for lints. New messages get the next code in their group, and codes of removed messages aren't reused.
+messages! {
+    // The tokenizer's errors.
+    READ_FAILED = "E0101": "Tokenizer error: Couldn't read the program: {0}.",
+    INVALID_TOKEN = "E0102": "Tokenizer error: Invalid token {0} at line {1}.",
+    UNCLOSED_STRING = "E0103": "Tokenizer error: \" at line {0} doesn't have a closing \".",
+    UNCLOSED_INTERPOLATION = "E0104": "Tokenizer error: ${{ at line {0} doesn't have a closing }}.",
+    // The parser's errors.
+    EXPECTED_RANGE_END = "E0201": "Expected a number after {0}.. in the pattern.",
+    EXPECTED_NUMBER_AFTER_MINUS = "E0202": "Expected a number after - in the pattern.",
+    EXPECTED_PATTERN_PARENTHESIS = "E0203": "Expected ( after {0} in the pattern.",
+    PATTERN_TAKES_ONE_PATTERN = "E0204": "{0} takes one pattern.",
+    EXPECTED_TUPLE_PATTERN_SEPARATOR = "E0205": "Expected , or ) in the tuple pattern.",
+    EXPECTED_CASE_PATTERN = "E0206": "Expected a pattern after case.",
+    UNMATCHED_LEFT_BRACE = "E0207": "Unmatched left brace.",
+    EXPECTED_LOOP_BLOCK = "E0208": "Expected block statement for loop.",
+    EXPECTED_IF_BLOCK = "E0209": "Expected a block after the if condition.",
+    EXPECTED_ELSE_BRANCH = "E0210": "Expected a block or if after else.",
+    IF_VALUE_NEEDS_ELSE = "E0211": "An if used as a value needs an else branch.",
+    EXPECTED_BLOCK_VALUE = "E0212": "Expected a value at the end of the block.",
+    EXPECTED_LET_AFTER_STATIC = "E0213": "Expected let after static.",
+    EXPECTED_LET_VARIABLE = "E0214": "Expected variable after let.",
+    EXPECTED_LET_EQUAL = "E0215": "Expected equal symbol in let statement.",
+    EXPECTED_LET_SEMICOLON = "E0216": "Expected semicolon after let statement.",
+    EXPECTED_IF_CONDITION = "E0217": "Expected condition after if.",
+    EXPECTED_THEN_BLOCK = "E0218": "Expected then block after condition.",
+    EXPECTED_ELSE_BLOCK = "E0219": "Expected else block after else.",
+    EXPECTED_WHILE_CONDITION = "E0220": "Expected expression after while.",
+    EXPECTED_WHILE_BLOCK = "E0221": "Expected block statement for while.",
+    EXPECTED_FOR_VARIABLE = "E0222": "Expected a variable name after for.",
+    EXPECTED_FOR_IN = "E0223": "Expected in after for {0}.",
+    EXPECTED_FOR_SEQUENCE = "E0224": "Expected expression after in.",
+    EXPECTED_FOR_BLOCK = "E0225": "Expected block statement for the for loop.",
+    EXPECTED_TRY_BLOCK = "E0226": "Expected block statement after try.",
+    EXPECTED_CATCH = "E0227": "Expected catch after the try block.",
+    EXPECTED_CATCH_VARIABLE = "E0228": "Expected a variable name after catch.",
+    EXPECTED_CATCH_BLOCK = "E0229": "Expected block statement after catch {0}.",
+    EXPECTED_MATCH_VALUE = "E0230": "Expected expression after match.",
+    EXPECTED_MATCH_BRACE = "E0231": "Expected {{ after match {0}.",
+    EXPECTED_GUARD_CONDITION = "E0232": "Expected a condition after case {0} if.",
+    EXPECTED_CASE_ARROW = "E0233": "Expected => after case {0}.",
+    EXPECTED_CASE_BLOCK = "E0234": "Expected block statement after case {0} =>.",
+    EXPECTED_CASE_OR_BRACE = "E0235": "Expected case or }} in the match statement.",
+    EXPECTED_FUNCTION_NAME = "E0236": "Function name required.",
+    EXPECTED_PARAMETERS = "E0237": "Left parenthesis required after function name.",
+    EXPECTED_PARAMETER_NAME = "E0238": "Parameter name expected.",
+    EXPECTED_PARAMETER_COLON = "E0239": "Colon expected after parameter.",
+    EXPECTED_PARAMETER_COMMA = "E0240": "Comma expected after parameter",
+    EXPECTED_FUNCTION_BLOCK = "E0241": "Expected block statement for function.",
+    EXPECTED_TEST_BLOCK = "E0242": "Expected block statement for test.",
+    EXPECTED_SEMICOLON = "E0243": "Expected semicolon at the end of the statement.",
+    EXPECTED_RAISED_ERROR = "E0244": "Expected the error to raise after raise.",
+    INVALID_INCREMENT_TARGET = "E0245": "Only variables, tuple fields and dereferenced references can be used with {0}.",
+    DOC_COMMENT_WITHOUT_DECLARATION = "E0246": "Doc comments must be followed by a let or fn declaration.",
+    INVALID_TYPE = "E0247": "Invalid type.",
+    PARTLY_NAMED_TUPLE_TYPE = "E0248": "Either all or none of the fields of a tuple type must be named.",
+    TUPLE_TYPE_TOO_SHORT = "E0249": "Tuple type must have at least two elements.",
+    EXPECTED_RESULT_TYPE = "E0250": "Expected result(value type, error type).",
+    EXPECTED_OPTION_TYPE = "E0251": "Expected option(value type).",
+    EXPECTED_ITERATOR_TYPE = "E0252": "Expected iterator(item type).",
+    EXPECTED_CHANNEL_TYPE = "E0253": "Expected channel(message type).",
+    EXPECTED_TASK_TYPE = "E0254": "Expected task(result type) or task(result type, message type).",
+    EXPECTED_FUNCTION_TYPE_PARAMETERS = "E0255": "Expected ( after fn",
+    TYPE_ENDS_EARLY = "E0256": "EOF while parsing type",
+    EXPECTED_INDEX = "E0257": "Expected nonnegative integer index.",
+    EXPECTED_FIELD = "E0258": "Expected index or field name after dot.",
+    EXPECTED_RECORD_FIELD = "E0259": "Expected a field name and : in record.",
+    REPEATED_RECORD_FIELD = "E0260": "The field {0} is repeated in the record.",
+    EXPECTED_RECORD_SEPARATOR = "E0261": "Expected , or ) in record.",
+    NAMED_ARGUMENT = "E0262": "Function arguments can't be named.",
+    PROGRAM_ENDS_EARLY = "E0263": "No tokens available to parse (EOF)",
+    EXPECTED_VALUE_PARENTHESIS = "E0264": "Expected ( after {0}.",
+    TAKES_ONE_VALUE = "E0265": "{0} takes one value.",
+    EXPECTED_INTERPOLATED_VALUE = "E0266": "Expected a value after ${{.",
+    EXPECTED_INTERPOLATION_END = "E0267": "Expected }} after the interpolated value.",
+    EXPECTED_TUPLE_COMMA = "E0268": "Expected , in tuple.",
+    EXPECTED_PRIMARY_EXPRESSION = "E0269": "Expected primary expression, got {0} instead.",
+    INVALID_FORMAT_SPEC = "E0270": "Invalid format spec {0}. Expected [[fill]align][0][width][.precision][x|X|b|o|e].",
+    OMITTED_SEMICOLON = "E0271": "Expected semicolon at the end of the statement, as semicolons are required.",
+    NESTED_TOO_DEEPLY = "E0272": "The program nests more than {0} levels deep.",
+    EXPECTED_ERROR_FIELDS = "E0273": "Expected , or ) after the message of Error.",
+    UNKNOWN_ERROR_FIELD = "E0274": "Errors have a message and a code, so Error can't take {0}.",
+    // The type checker's errors, and errors importing native modules.
+    IF_CONDITION_NOT_BOOLEAN = "E0301": "Boolean expression expected for if condition.",
+    LET_TYPE_MISMATCH = "E0302": "Expression of type {0} can't be assigned to a variable of type {1}.",
+    STATIC_OUTSIDE_FUNCTION = "E0303": "Static variables can only be declared in functions and blocks.",
+    WHILE_CONDITION_NOT_BOOLEAN = "E0304": "Boolean expression expected for while condition.",
+    FOR_NOT_SEQUENCE = "E0305": "for can only loop over strings and iterators, but {0} is of type {1}.",
+    GUARD_NOT_BOOLEAN = "E0306": "Boolean expression expected for the condition of case {0}.",
+    TEST_NOT_AT_TOP_LEVEL = "E0307": "Tests must be at the top level of a program.",
+    IMPORT_NOT_AT_TOP_LEVEL = "E0308": "Native modules must be imported at the top level of a program.",
+    FUNCTION_MAY_NOT_RETURN = "E0309": "Function body may not return",
+    RETURN_TYPE_MISMATCH = "E0310": "Expected {0} return, got {1}.",
+    RETURN_IN_BLOCK_EXPRESSION = "E0311": "Return can't be used in a block or loop expression.",
+    RAISED_VALUE_NOT_ERROR = "E0312": "Only errors of type {0} can be raised, but {1} is of type {2}.",
+    BREAK_VALUE_IN_LOOP_STATEMENT = "E0313": "A break out of a while or for loop can't have a value.",
+    BREAK_TYPE_MISMATCH = "E0314": "The breaks out of the loop have different types, {0} and {1}.",
+    BREAK_OUTSIDE_LOOP = "E0315": "Break can't be used outside a loop or to leave a function or block expression.",
+    NO_SUCH_FIELD = "E0316": "{0} has no field called {1}.\nExpression type: {2}",
+    TUPLE_FIELD_NAME = "E0317": "Only records have named fields, but {0} is a tuple, so it has no field called {1}.",
+    TUPLE_REQUIRED = "E0318": "Tuple required for tuple access",
+    INDEX_TOO_LARGE = "E0319": "Index too large",
+    IF_BRANCH_TYPE_MISMATCH = "E0320": "The branches of the if have different types, {0} and {1}.",
+    PROPAGATE_NOT_RESULT = "E0321": "? can only be used on results, but {0} is of type {1}.",
+    PROPAGATE_ERROR_TYPE_MISMATCH = "E0322": "? returns the error of {0}, but the function returns {1}, not {2}.",
+    PROPAGATE_IN_BLOCK_EXPRESSION = "E0323": "? can't be used in a block or loop expression.",
+    NO_SUCH_VARIABLE = "E0324": "No variable called {0} exists.",
+    NOT_A_FUNCTION = "E0325": "{0} is not a function",
+    INVALID_ARGUMENTS = "E0326": "Invalid function calls.\nFunction parameters: {0}\nArguments passed: {1}",
+    UNWRAP_OR_TYPE_MISMATCH = "E0327": "unwrap_or needs an option and a default value of the same type, not {0} and {1}.",
+    NOT_A_CHANNEL = "E0328": "{0} needs a channel or a task with a mailbox, not a value of type {1}.",
+    CHANNEL_MESSAGE_TYPE_MISMATCH = "E0329": "{0} can't be sent over a channel of {1}, as it's of type {2}.",
+    MAILBOX_MESSAGE_TYPE_MISMATCH = "E0330": "{0} can't be sent over a mailbox of {1}, as it's of type {2}.",
+    INVALID_SPAWN = "E0331": "spawn needs a function without parameters, or with a channel as its only parameter, which only uses values that can be sent to another thread, not {0}.",
+    JOIN_NOT_TASK = "E0332": "join needs a task, not a value of type {0}.",
+    PATTERN_VARIABLE_REPEATED = "E0333": "{0} is set more than once in the pattern.",
+    EMPTY_RANGE_PATTERN = "E0334": "The range {0}..{1} doesn't match any number, as it doesn't start before its end.",
+    ALTERNATIVE_VARIABLES_DIFFER = "E0335": "Each pattern in {0} must set the same variables, with the same types.",
+    PATTERN_TYPE_MISMATCH = "E0336": "The pattern {0} can't match values of type {1}.",
+    REFERENCE_NOT_VARIABLE = "E0337": "Only variables can be referenced, so &{0} isn't allowed.",
+    INVALID_UNARY_OPERAND = "E0338": "Can't apply {0} on {1}.\nExpression type: {2}",
+    INVALID_ASSIGNMENT_TARGET = "E0339": "Can only assign to variables, tuple elements or dereferenced references.",
+    INVALID_BINARY_OPERANDS = "E0340": "Can't apply {0} on {1} and {2}.\nLeft type: {3}\nRight type: {4}",
+    NATIVE_MODULES_UNSUPPORTED = "E0341": "Couldn't import {0}, as this build of decibelle can't load native modules.",
+    FORMAT_SPEC_TYPE_MISMATCH = "E0342": "The format spec {0} can't be used with a value of type {1}.",
+    NATIVE_MODULE_NOT_LOADED = "E0343": "Couldn't load the native module {0}: {1}",
+    NOT_A_NATIVE_MODULE = "E0344": "{0} isn't a native module, as it doesn't use native_module!.",
+    NATIVE_MODULE_VERSION_MISMATCH = "E0345": "The native module {0} was built for decibelle {1}, not {2}.",
+    INCLUDES_NOT_ALLOWED = "E0346": "{0} can't be included, as the host hasn't allowed including files.",
+    INCLUDE_OUTSIDE_DIRECTORY = "E0347": "{0} can't be included, as it isn't in the directory files are included from.",
+    INCLUDE_READ_FAILED = "E0348": "Couldn't read {0}: {1}",
+    INCLUDE_CYCLE = "E0349": "{0} includes itself.",
+    INCLUDE_NOT_AT_TOP_LEVEL = "E0350": "include can only be used as a statement at the top level of a program, with a string path.",
+    // Runtime errors.
+    CALL_DEPTH_EXCEEDED = "E0401": "Maximum call depth of {0} exceeded.",
+    STOPPED_BY_DEBUGGER = "E0402": "Stopped by the debugger.",
+    UNCAUGHT_ERROR = "E0403": "Uncaught error with code {0}: {1}",
+    EXITED = "E0404": "Exited with code {0}.",
+    ASSERTION_FAILED = "E0405": "Assertion failed.",
+    LEN_NEEDS_SEQUENCE = "E0406": "len needs a string, tuple or record, not a value of type {0}.",
+    SUBSTRING_OUT_OF_RANGE = "E0407": "{0}({1}, {2}) is out of range for a string of length {3}.",
+    INVALID_BASE = "E0408": "parse_int can't use base {0}. Bases are whole numbers from 2 to 36.",
+    PARSE_INT_FAILED = "E0409": "parse_int couldn't parse \"{0}\" as a whole number in base {1}.",
+    NOT_A_64_BIT_INTEGER = "E0410": "{0} needs a whole number which fits in 64 bits, but got {1}.",
+    IS_SOME_NEEDS_OPTION = "E0411": "is_some needs an option, not a value of type {0}.",
+    NOT_A_CHANNEL_OR_TASK = "E0412": "{0} needs a channel or a task, not a value of type {1}.",
+    SPAWN_NEEDS_FUNCTION = "E0413": "spawn needs a function, not a value of type {0}.",
+    RUST_FUNCTION_SPAWNED = "E0414": "Functions written in Rust can't be spawned.",
+    INVALID_SPAWN_PARAMETERS = "E0415": "spawn needs a function without parameters or with a channel as its only parameter, not fn({0}).",
+    NO_MAILBOX = "E0416": "The task has no mailbox.",
+    TASK_FINISHED = "E0417": "The task finished, so it can't receive messages.",
+    TASK_JOINED = "E0418": "The task was joined, so it can't receive messages.",
+    TASK_PANICKED = "E0419": "The task panicked.",
+    TASK_EXITED = "E0420": "The task exited with code {0}.",
+    FUNCTION_NOT_SENDABLE = "E0421": "Functions can't be sent to another thread.",
+    REFERENCE_NOT_SENDABLE = "E0422": "References can't be sent to another thread.",
+    ITERATOR_NOT_SENDABLE = "E0423": "Iterators can't be sent to another thread.",
+    CHANNEL_NOT_SENDABLE = "E0424": "Channels can't be sent to another thread.",
+    TASK_NOT_SENDABLE = "E0425": "Tasks can't be sent to another thread.",
+    CHANNEL_CLOSED = "E0426": "The other end of the channel was closed.",
+    REPLAY_LOG_ENDED = "E0427": "The replay log ended before {0} was called.",
+    REPLAY_MISMATCH = "E0428": "The replay log's next result is for {0}, but {1} was called.",
+    INVALID_REPLAYED_RESULT = "E0429": "The replay log's result {0} isn't one {1} returns.",
+    STEP_LIMIT_EXCEEDED = "E0430": "The program ran more than {0} statements.",
+    CANCELLED = "E0431": "The program was cancelled.",
+    PROGRAM_PANICKED = "E0432": "The interpreter panicked while running the program.",
+    HOST_FUNCTION_FAILED = "E0433": "{0} failed: {1}.",
+    // The linter's warnings.
+    SHADOWED_VARIABLE = "W0101": "{0} shadows the variable declared at line {1}.",
+    UNUSED_VARIABLE = "W0102": "{0} is never used.",
+    UNREACHABLE_CODE = "W0103": "This code is after a return, break or raise, so it never runs.",
+    UNREACHABLE_CASE = "W0104": "The case {0} never runs, as the cases before it match every value it matches.",
+    UNREACHABLE_ALTERNATIVE = "W0105": "The pattern {0} in case {1} never matches, as the patterns before it match every value it matches.",
+    NON_EXHAUSTIVE_MATCH = "W0106": "The cases don't match every value, like {0}.",
+    ASSIGNMENT_CONDITION = "W0107": "The condition is an assignment. Did you mean == ?",
+    CONSTANT_CONDITION = "W0108": "The condition is always the same.",
+    MISSING_SEMICOLON = "W0109": "The statement doesn't end with a semicolon.",
+}
+
+/// An error or a warning: a message and the values filled into its text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: &'static Message,
+    pub arguments: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &'static Message, arguments: Vec<String>) -> Diagnostic {
+        Diagnostic { message, arguments }
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.message.code
+    }
+}
+
+/// Writes the message's translation, or its English text if it has none, with the arguments filled in.
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let translations = TRANSLATIONS.read().unwrap();
+        let text = translations
+            .as_ref()
+            .and_then(|translations| translations.get(self.message.code))
+            .map_or(self.message.text, String::as_str);
+        let mut rest = text;
+        while let Some(index) = rest.find(['{', '}']) {
+            f.write_str(&rest[..index])?;
+            rest = &rest[index..];
+            if rest.starts_with("{{") || rest.starts_with("}}") {
+                f.write_str(&rest[..1])?;
+                rest = &rest[2..];
+                continue;
+            }
+            // Placeholders without an argument, and lone braces, are written as they are.
+            let argument = rest[1..]
+                .split_once('}')
+                .filter(|_| rest.starts_with('{'))
+                .and_then(|(index, _)| {
+                    let argument = self.arguments.get(index.parse::<usize>().ok()?)?;
+                    Some((argument, index.len() + 2))
+                });
+            match argument {
+                Some((argument, length)) => {
+                    f.write_str(argument)?;
+                    rest = &rest[length..];
+                }
+                None => {
+                    f.write_str(&rest[..1])?;
+                    rest = &rest[1..];
+                }
+            }
+        }
+        f.write_str(rest)
+    }
+}
+
+impl Error for Diagnostic {}
+
+static TRANSLATIONS: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// Replaces the text of messages with the translations, keyed by message code, in every interpreter of the process.
+/// Translations use the same {0}, {1}, ... as the English text. Messages without a translation keep their
+/// English text, and None brings back the English text of every message.
+pub fn set_translations(translations: Option<HashMap<String, String>>) {
+    *TRANSLATIONS.write().unwrap() = translations;
+}
+
+/// Makes the diagnostic for the message in this module with the arguments, which are converted with to_string.
+macro_rules! diagnostic {
+    ($message:ident $(, $argument:expr)* $(,)?) => {
+        $crate::messages::Diagnostic::new(
+            &$crate::messages::$message,
+            vec![$($argument.to_string()),*],
+        )
+    };
+}
+
+pub(crate) use diagnostic;
@@ -0,0 +1,108 @@
+#[cfg(all(feature = "native", not(target_family = "wasm")))]
+use crate::messages::{diagnostic, Diagnostic};
+use crate::tokenizer::{Type, Value};
+
+/// The version of decibelle native modules are built against. Modules share Rust types with the interpreter,
+/// so a module can only be imported by the version of decibelle it was built with, compiled by the same compiler.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A function a native module declares as a global.
+pub struct NativeFunction {
+    pub name: String,
+    pub parameters: Vec<Type>,
+    pub return_type: Type,
+    pub function: Box<dyn Fn(Vec<Value>) -> Value>,
+}
+
+/// The functions a native module registers when it's imported.
+#[derive(Default)]
+pub struct NativeModule {
+    pub functions: Vec<NativeFunction>,
+}
+
+impl NativeModule {
+    /// Adds a global function, like Interpreter::register_function.
+    /// The arguments passed to the function are checked against the parameter types,
+    /// and the function must return a value of the return type.
+    pub fn register_function(
+        &mut self,
+        name: &str,
+        parameters: Vec<Type>,
+        return_type: Type,
+        function: impl Fn(Vec<Value>) -> Value + 'static,
+    ) {
+        self.functions.push(NativeFunction {
+            name: name.into(),
+            parameters,
+            return_type,
+            function: Box::new(function),
+        });
+    }
+}
+
+/// Exports the function which registers a native module's functions, so the cdylib it's in can be imported with
+/// `import native "name";`. The function is passed a NativeModule to register its functions with:
+///
+/// ```ignore
+/// fn register(module: &mut decibelle::native::NativeModule) {
+///     module.register_function("double", vec![Type::Number], Type::Number, |values| match values[0] {
+///         Value::Number(number) => Value::Number(number * 2.0),
+///         _ => unreachable!(),
+///     });
+/// }
+///
+/// decibelle::native_module!(register);
+/// ```
+#[macro_export]
+macro_rules! native_module {
+    ($register:path) => {
+        #[no_mangle]
+        pub fn decibelle_native_version() -> &'static str {
+            $crate::native::VERSION
+        }
+
+        #[no_mangle]
+        pub fn decibelle_native_register(module: &mut $crate::native::NativeModule) {
+            $register(module)
+        }
+    };
+}
+
+/// Loads the native module and returns the functions it registers. A name with a / is the path to the library,
+/// and other names are turned into the platform's filename for the library, like libname.so, which is searched
+/// for where the platform looks for libraries. The library stays loaded until the process exits, as the functions'
+/// code is in it.
+#[cfg(all(feature = "native", not(target_family = "wasm")))]
+pub fn load(name: &str) -> Result<NativeModule, Diagnostic> {
+    let filename = if name.contains(['/', '\\']) {
+        name.into()
+    } else {
+        libloading::library_filename(name)
+    };
+    // Loading a library runs its initialization code, which we trust like the module's functions.
+    let library = unsafe { libloading::Library::new(&filename) }
+        .map_err(|error| diagnostic!(NATIVE_MODULE_NOT_LOADED, name, error))?;
+    // The symbols have these types if the module was exported with native_module!,
+    // and the version check makes sure its NativeModule is the same as ours.
+    let module = unsafe {
+        let version = library
+            .get::<fn() -> &'static str>(b"decibelle_native_version")
+            .map_err(|_| diagnostic!(NOT_A_NATIVE_MODULE, name))?;
+        if version() != VERSION {
+            return Err(diagnostic!(
+                NATIVE_MODULE_VERSION_MISMATCH,
+                name,
+                version(),
+                VERSION
+            ));
+        }
+        let register = library
+            .get::<fn(&mut NativeModule)>(b"decibelle_native_register")
+            .map_err(|_| diagnostic!(NOT_A_NATIVE_MODULE, name))?;
+        let mut module = NativeModule::default();
+        register(&mut module);
+        module
+    };
+    std::mem::forget(library);
+    Ok(module)
+}
@@ -0,0 +1,52 @@
+use std::rc::Rc;
+
+use crate::{
+    compiler::Function, constant_folding::fold_constants,
+    dead_code_elimination::eliminate_dead_code, parser::Statement, peephole::optimize_peephole,
+};
+
+/// How much to optimize programs before running them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// Run programs as written.
+    None,
+    /// Only fold constants.
+    Basic,
+    /// Run every pass, including the peephole pass over bytecode.
+    #[default]
+    Full,
+}
+
+/// An optimization pass over type checked statements.
+struct Pass {
+    level: OptLevel, // The lowest level the pass runs at.
+    run: fn(&mut Vec<Statement>),
+}
+
+/// Passes in the order they run. Later passes can rely on the earlier ones.
+const PASSES: &[Pass] = &[
+    Pass {
+        level: OptLevel::Basic,
+        run: |statements| fold_constants(statements),
+    },
+    Pass {
+        level: OptLevel::Full,
+        run: eliminate_dead_code,
+    },
+];
+
+/// Runs the passes enabled at the given level.
+pub fn optimize(statements: &mut Vec<Statement>, level: OptLevel) {
+    for pass in PASSES {
+        if pass.level <= level {
+            (pass.run)(statements);
+        }
+    }
+}
+
+/// Runs the bytecode passes enabled at the given level on a compiled function.
+pub fn optimize_bytecode(function: &mut Rc<Function>, level: OptLevel) {
+    if level == OptLevel::Full {
+        optimize_peephole(function);
+    }
+}
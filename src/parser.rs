@@ -1,11 +1,24 @@
-use std::fmt::{Debug, Display};
-
-use crate::tokenizer::{Token, TokenType, Type, Value};
+use std::{
+    cell::Cell,
+    fmt::{Debug, Display, Write},
+    rc::Rc,
+    str::FromStr,
+};
+
+use crate::{
+    format::FormatSpec,
+    messages::{diagnostic, Diagnostic, NESTED_TOO_DEEPLY, OMITTED_SEMICOLON},
+    symbol::Symbol,
+    tokenizer::{quote, tokenize, Token, TokenType, Type, Value},
+};
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum UnaryOperation {
     Minus,
     Not,
+    BitwiseNot,
+    Reference,
+    Dereference,
 }
 
 impl Debug for UnaryOperation {
@@ -15,6 +28,9 @@ impl Debug for UnaryOperation {
         let operator = match self {
             Minus => "-",
             Not => "!",
+            BitwiseNot => "~",
+            Reference => "&",
+            Dereference => "*",
         };
 
         write!(f, "{operator}")
@@ -35,6 +51,11 @@ pub enum BinaryOperation {
     GreaterEqual,
     And,
     Or,
+    BitwiseAnd,
+    BitwiseOr,
+    BitwiseXor,
+    ShiftLeft,
+    ShiftRight,
     Assignment,
 }
 
@@ -55,6 +76,11 @@ impl Debug for BinaryOperation {
             GreaterEqual => ">=",
             Or => "||",
             And => "&&",
+            BitwiseAnd => "&",
+            BitwiseOr => "|",
+            BitwiseXor => "^",
+            ShiftLeft => "<<",
+            ShiftRight => ">>",
             Assignment => "=",
         };
 
@@ -75,20 +101,75 @@ pub enum ExpressionType {
     },
     Literal(Value),
     Variable {
-        name: String,
+        name: Symbol,
         shadow_id: Option<usize>,
         parent_height: Option<usize>,
+        slot: Option<usize>,
     },
     Grouping(Box<Expression>),
     Tuple(Vec<Expression>),
+    Record(Vec<(Symbol, Expression)>), // A tuple with named fields, like (x: 1, y: 2).
     TupleAccess {
         expression: Box<Expression>,
-        index: usize,
+        field: Field,
     },
     FunctionCall {
         function: Box<Expression>,
         arguments: Vec<Expression>,
     },
+    // A string with ${value:spec} in it, which formats each value with its spec.
+    Interpolation {
+        strings: Vec<String>, // The text around the values, one more than there are values.
+        values: Vec<Expression>,
+        specs: Vec<FormatSpec>,
+    },
+    // A block like { let t = f(); t * 2 }, whose value is the expression at its end.
+    Block {
+        statements: Vec<Statement>,
+        value: Box<Expression>,
+    },
+    Loop(Box<Statement>), // A loop { ... } which runs its block until a break, whose value is the loop's value.
+    // An if with a value. The branches are block expressions, except for an if after else.
+    If {
+        condition: Box<Expression>,
+        then_expression: Box<Expression>,
+        else_expression: Box<Expression>,
+    },
+    Ok(Box<Expression>),
+    Err(Box<Expression>),
+    Propagate(Box<Expression>), // A result followed by ?, which is its value if it's Ok, and is returned if it's Err.
+    Some(Box<Expression>),      // None is a literal.
+}
+
+/// A field of a tuple, accessed by its position or, in a record, by its name.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Field {
+    Index(usize),
+    Name {
+        name: Symbol,
+        index: Option<usize>, // Position of the field, which is found during type checking.
+    },
+}
+
+impl Field {
+    pub fn index(&self) -> usize {
+        match self {
+            Field::Index(index)
+            | Field::Name {
+                index: Some(index), ..
+            } => *index,
+            Field::Name { index: None, .. } => unreachable!(),
+        }
+    }
+}
+
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::Index(index) => write!(f, "{index}"),
+            Field::Name { name, .. } => write!(f, "{name}"),
+        }
+    }
 }
 
 #[derive(PartialEq, Clone)]
@@ -121,6 +202,7 @@ impl Debug for Expression {
                 name,
                 shadow_id,
                 parent_height,
+                ..
             } => {
                 write!(f, "{name}({shadow_id:?},{parent_height:?})")
             }
@@ -142,8 +224,18 @@ impl Debug for Expression {
                 write!(f, ")")?;
                 Ok(())
             }
-            ExpressionType::TupleAccess { expression, index } => {
-                write!(f, ". {expression:?} {index}")
+            ExpressionType::Record(fields) => {
+                write!(f, "(")?;
+                for (index, (name, expression)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{name}:{expression:?}")?;
+                }
+                write!(f, ")")
+            }
+            ExpressionType::TupleAccess { expression, field } => {
+                write!(f, ". {expression:?} {field}")
             }
             ExpressionType::FunctionCall {
                 function,
@@ -159,6 +251,36 @@ impl Debug for Expression {
                 }
                 write!(f, ")")
             }
+            ExpressionType::Interpolation { values, specs, .. } => {
+                write!(f, "interpolate (")?;
+                for (index, (value, spec)) in values.iter().zip(specs).enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value:?}:{spec}")?;
+                }
+                write!(f, ")")
+            }
+            ExpressionType::Block { statements, value } => {
+                write!(f, "block (")?;
+                for statement in statements {
+                    write!(f, "{statement:?}, ")?;
+                }
+                write!(f, "{value:?})")
+            }
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => write!(
+                f,
+                "if ({condition:?}) ({then_expression:?}) ({else_expression:?})"
+            ),
+            ExpressionType::Loop(body) => write!(f, "loop ({body:?})"),
+            ExpressionType::Ok(expression) => write!(f, "Ok ({expression:?})"),
+            ExpressionType::Err(expression) => write!(f, "Err ({expression:?})"),
+            ExpressionType::Propagate(expression) => write!(f, "? ({expression:?})"),
+            ExpressionType::Some(expression) => write!(f, "Some ({expression:?})"),
         }
     }
 }
@@ -170,7 +292,13 @@ impl Display for Expression {
                 operation,
                 expression,
             } => {
-                write!(f, "{operation:?}{expression}")
+                // A space keeps - -x from being read back as the decrement --x.
+                let operand = expression.to_string();
+                if matches!(operation, UnaryOperation::Minus) && operand.starts_with('-') {
+                    write!(f, "{operation:?} {operand}")
+                } else {
+                    write!(f, "{operation:?}{operand}")
+                }
             }
             ExpressionType::Binary {
                 operation,
@@ -179,8 +307,8 @@ impl Display for Expression {
             } => {
                 write!(f, "{left_expression} {operation:?} {right_expression}")
             }
-            ExpressionType::TupleAccess { expression, index } => {
-                write!(f, "{expression}.{index}")
+            ExpressionType::TupleAccess { expression, field } => {
+                write!(f, "{expression}.{field}")
             }
             ExpressionType::FunctionCall {
                 function,
@@ -196,9 +324,71 @@ impl Display for Expression {
                 }
                 write!(f, ")")
             }
-            _ => {
-                write!(f, "{self:?}")
+            ExpressionType::Literal(value) => write!(f, "{}", literal_source(value)),
+            ExpressionType::Interpolation {
+                strings,
+                values,
+                specs,
+            } => {
+                write!(f, "\"{}", strings[0])?;
+                for ((value, spec), string) in values.iter().zip(specs).zip(&strings[1..]) {
+                    if *spec == FormatSpec::default() {
+                        write!(f, "${{{value}}}{string}")?;
+                    } else {
+                        write!(f, "${{{value}:{spec}}}{string}")?;
+                    }
+                }
+                write!(f, "\"")
+            }
+            ExpressionType::Variable { name, .. } => write!(f, "{name}"),
+            ExpressionType::Grouping(expression) => write!(f, "({expression})"),
+            ExpressionType::Tuple(expressions) => {
+                write!(f, "(")?;
+                let mut expressions = expressions.iter();
+                if let Some(expression) = expressions.next() {
+                    write!(f, "{expression}")?;
+                    for expression in expressions {
+                        write!(f, ", {expression}")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            // Error(message, code: n) makes the same record, and is how errors are usually written.
+            ExpressionType::Record(fields)
+                if fields
+                    .iter()
+                    .map(|(name, _)| name.as_str())
+                    .eq(["message", "code"]) =>
+            {
+                write!(f, "Error({}, code: {})", fields[0].1, fields[1].1)
+            }
+            ExpressionType::Record(fields) => {
+                write!(f, "(")?;
+                for (index, (name, expression)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{name}: {expression}")?;
+                }
+                write!(f, ")")
+            }
+            ExpressionType::Block { statements, value } => {
+                write!(f, "{{ ")?;
+                for statement in statements {
+                    write!(f, "{statement} ")?;
+                }
+                write!(f, "{value} }}")
             }
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => write!(f, "if {condition} {then_expression} else {else_expression}"),
+            ExpressionType::Loop(body) => write!(f, "loop {body}"),
+            ExpressionType::Ok(expression) => write!(f, "Ok({expression})"),
+            ExpressionType::Err(expression) => write!(f, "Err({expression})"),
+            ExpressionType::Propagate(expression) => write!(f, "{expression}?"),
+            ExpressionType::Some(expression) => write!(f, "Some({expression})"),
         }
     }
 }
@@ -206,17 +396,22 @@ impl Display for Expression {
 #[derive(Debug, PartialEq, Clone)]
 pub enum StatementType {
     VariableDeclaration {
-        variable: String,
+        doc: Option<String>, // The lines of the doc comments before the declaration, after their ///.
+        variable: Symbol,
         variable_type: Option<Type>,
         value: Expression,
+        is_static: bool, // Static variables are globals which are only set the first time their declaration runs.
         shadow_id: Option<usize>,
+        slot: Option<usize>, // Index of the variable in its environment, or the global slot of a static variable.
     },
     FunctionDeclaration {
-        name: String,
-        parameters: Vec<(String, Option<usize>, Type)>, // Option<usize> is the shadow_id.
+        doc: Option<String>,
+        name: Symbol,
+        parameters: Vec<(Symbol, Option<usize>, Type)>, // Option<usize> is the shadow_id, the slot is the index.
         return_type: Type,
-        body: Box<Statement>,
+        body: Rc<Statement>,
         shadow_id: Option<usize>,
+        slot: Option<usize>,
     },
     Return(Option<Expression>),
     Expression(Expression),
@@ -230,6 +425,139 @@ pub enum StatementType {
         expression: Expression,
         statement: Box<Statement>,
     },
+    // Runs the statement with the variable set to each grapheme cluster of the string, in its own scope each time.
+    For {
+        variable: Symbol,
+        shadow_id: Option<usize>, // The variable is in slot 0.
+        expression: Expression,
+        statement: Box<Statement>,
+    },
+    Break(Option<Expression>), // The value is the value of the loop expression it breaks out of.
+    Raise(Expression), // Raises an error, which is a (message: string, code: number) record.
+    // Runs the statement, and runs the catch statement with the variable set to the error if it raises one.
+    Try {
+        statement: Box<Statement>,
+        variable: Symbol,
+        shadow_id: Option<usize>, // The variable is in slot 0.
+        catch_statement: Box<Statement>,
+    },
+    // Runs the statement of the first arm whose pattern matches the value, if one does.
+    Match {
+        expression: Expression,
+        arms: Vec<MatchArm>,
+    },
+    // A test block, which only runs under the test runner. Tests are removed from programs before they're checked.
+    Test {
+        name: String,
+        body: Box<Statement>,
+    },
+    // import native "name"; which loads a library of builtins. Imports are loaded and removed before checking.
+    ImportNative(String),
+}
+
+/// An arm of a match statement, whose statement runs with the variables of the pattern set.
+#[derive(Debug, PartialEq, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expression>, // The condition after if, which must also be true for the arm to run.
+    pub statement: Statement,
+    pub lines: (usize, usize),
+}
+
+impl MatchArm {
+    /// Whether the arm runs for every value of the type its pattern is checked against.
+    pub fn matches_everything(&self) -> bool {
+        self.guard.is_none() && self.pattern.matches_everything()
+    }
+}
+
+impl Display for MatchArm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "case {}", self.pattern)?;
+        if let Some(guard) = &self.guard {
+            write!(f, " if {guard}")?;
+        }
+        write!(f, " =>")
+    }
+}
+
+/// What a match statement compares its value with.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Pattern {
+    Wildcard, // _, which matches any value without setting a variable.
+    // Matches any value and sets the variable to it. The variables of a pattern are in slots in the order they're written.
+    Variable {
+        name: Symbol,
+        shadow_id: Option<usize>,
+    },
+    Literal(Value), // Matches values equal to the literal, including None.
+    Tuple(Vec<Pattern>),
+    Some(Box<Pattern>),
+    Ok(Box<Pattern>),
+    Err(Box<Pattern>),
+    Or(Vec<Pattern>), // Matches values any of the patterns match. Each pattern sets the same variables.
+    Range {
+        start: f64,
+        end: f64, // Numbers from the start up to but not including the end match.
+    },
+}
+
+impl Pattern {
+    /// Whether the pattern matches every value of the type it's checked against.
+    pub fn matches_everything(&self) -> bool {
+        match self {
+            Pattern::Wildcard | Pattern::Variable { .. } => true,
+            Pattern::Tuple(patterns) => patterns.iter().all(Pattern::matches_everything),
+            Pattern::Or(patterns) => patterns.iter().any(Pattern::matches_everything),
+            _ => false,
+        }
+    }
+
+    /// The names and shadow_ids of the variables the pattern sets, in the order of their slots.
+    pub fn variables(&self) -> Vec<(Symbol, Option<usize>)> {
+        match self {
+            Pattern::Wildcard | Pattern::Literal(_) | Pattern::Range { .. } => vec![],
+            Pattern::Variable { name, shadow_id } => vec![(*name, *shadow_id)],
+            Pattern::Tuple(patterns) => patterns.iter().flat_map(Pattern::variables).collect(),
+            Pattern::Some(pattern) | Pattern::Ok(pattern) | Pattern::Err(pattern) => {
+                pattern.variables()
+            }
+            Pattern::Or(patterns) => patterns[0].variables(),
+        }
+    }
+}
+
+impl Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Variable { name, .. } => write!(f, "{name}"),
+            Pattern::Literal(Value::String(string)) => write!(f, "\"{string}\""),
+            Pattern::Literal(value) => write!(f, "{value}"),
+            Pattern::Tuple(patterns) => {
+                let patterns = patterns
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "({patterns})")
+            }
+            Pattern::Some(pattern) => write!(f, "Some({pattern})"),
+            Pattern::Ok(pattern) => write!(f, "Ok({pattern})"),
+            Pattern::Err(pattern) => write!(f, "Err({pattern})"),
+            Pattern::Or(patterns) => {
+                let patterns = patterns
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" | ");
+                write!(f, "{patterns}")
+            }
+            Pattern::Range { start, end } => {
+                write!(f, "{}..{}", Value::Number(*start), Value::Number(*end))
+            }
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -238,13 +566,486 @@ pub struct Statement {
     pub lines: (usize, usize),
 }
 
+/// Writes a literal value the way it's written in a program. Constant folding can make literals of tuples, records,
+/// results and options, and numbers which can only be written as expressions.
+fn literal_source(value: &Value) -> String {
+    let join = |values: &mut dyn Iterator<Item = String>| values.collect::<Vec<_>>().join(", ");
+    match value {
+        Value::String(string) => quote(string),
+        Value::Number(number) if number.is_nan() => "(0 / 0)".into(),
+        Value::Number(number) if number.is_infinite() && *number > 0.0 => "(1 / 0)".into(),
+        Value::Number(number) if number.is_infinite() => "(-1 / 0)".into(),
+        Value::Tuple(values) => format!("({})", join(&mut values.iter().map(literal_source))),
+        Value::Record(fields) => format!(
+            "({})",
+            join(
+                &mut fields
+                    .iter()
+                    .map(|(name, value)| format!("{name}: {}", literal_source(value)))
+            )
+        ),
+        Value::Result(result) => match &**result {
+            Ok(value) => format!("Ok({})", literal_source(value)),
+            Err(error) => format!("Err({})", literal_source(error)),
+        },
+        Value::Option(Some(value)) => format!("Some({})", literal_source(value)),
+        value => value.to_string(),
+    }
+}
+
+/// Writes the statement on one line, with blocks written like { a; b; }.
+impl Display for Statement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.statement {
+            StatementType::VariableDeclaration {
+                variable,
+                variable_type,
+                value,
+                is_static,
+                ..
+            } => {
+                if *is_static {
+                    write!(f, "static ")?;
+                }
+                write!(f, "let {variable}")?;
+                if let Some(variable_type) = variable_type {
+                    write!(f, ": {variable_type}")?;
+                }
+                write!(f, " = {value};")
+            }
+            StatementType::FunctionDeclaration {
+                name,
+                parameters,
+                return_type,
+                body,
+                ..
+            } => {
+                let parameters = parameters
+                    .iter()
+                    .map(|(name, _, parameter_type)| format!("{name}: {parameter_type}"))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "fn {name}({parameters})")?;
+                if *return_type != Type::Void {
+                    write!(f, " -> {return_type}")?;
+                }
+                write!(f, " {body}")
+            }
+            StatementType::Return(Some(expression)) => write!(f, "return {expression};"),
+            StatementType::Return(None) => write!(f, "return;"),
+            StatementType::Break(Some(expression)) => write!(f, "break {expression};"),
+            StatementType::Break(None) => write!(f, "break;"),
+            StatementType::Raise(expression) => write!(f, "raise {expression};"),
+            StatementType::Expression(expression) => write!(f, "{expression};"),
+            StatementType::Block(statements) if statements.is_empty() => write!(f, "{{}}"),
+            StatementType::Block(statements) => {
+                write!(f, "{{")?;
+                for statement in statements {
+                    write!(f, " {statement}")?;
+                }
+                write!(f, " }}")
+            }
+            StatementType::If {
+                expression,
+                then_statement,
+                else_statement,
+            } => {
+                write!(f, "if {expression} {then_statement}")?;
+                if let Some(else_statement) = else_statement {
+                    write!(f, " else {else_statement}")?;
+                }
+                Ok(())
+            }
+            StatementType::While {
+                expression,
+                statement,
+            } => write!(f, "while {expression} {statement}"),
+            StatementType::For {
+                variable,
+                expression,
+                statement,
+                ..
+            } => write!(f, "for {variable} in {expression} {statement}"),
+            StatementType::Try {
+                statement,
+                variable,
+                catch_statement,
+                ..
+            } => write!(f, "try {statement} catch {variable} {catch_statement}"),
+            StatementType::Match { expression, arms } => {
+                write!(f, "match {expression} {{")?;
+                for arm in arms {
+                    write!(f, " {arm} {}", arm.statement)?;
+                }
+                write!(f, " }}")
+            }
+            StatementType::Test { name, body } => write!(f, "test \"{name}\" {body}"),
+            StatementType::ImportNative(name) => write!(f, "import native \"{name}\";"),
+        }
+    }
+}
+
+/// A node of the syntax tree as it's printed, with its label, lines and children.
+struct TreeNode {
+    label: String,
+    lines: (usize, usize),
+    children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    fn new(label: impl Into<String>, lines: (usize, usize), children: Vec<TreeNode>) -> TreeNode {
+        TreeNode {
+            label: label.into(),
+            lines,
+            children,
+        }
+    }
+
+    fn format_lines(&self) -> String {
+        if self.lines.0 == self.lines.1 {
+            format!("line {}", self.lines.0)
+        } else {
+            format!("lines {}-{}", self.lines.0, self.lines.1)
+        }
+    }
+}
+
+/// Formats the statements as an indented tree, showing the lines of each statement and expression.
+pub fn format_tree(statements: &[Statement]) -> String {
+    let mut tree = String::new();
+    for statement in statements {
+        write_tree(&mut tree, &statement_node(statement), 0);
+    }
+    tree
+}
+
+fn write_tree(tree: &mut String, node: &TreeNode, depth: usize) {
+    writeln!(
+        tree,
+        "{}{} ({})",
+        "  ".repeat(depth),
+        node.label,
+        node.format_lines()
+    )
+    .unwrap();
+    for child in &node.children {
+        write_tree(tree, child, depth + 1);
+    }
+}
+
+/// Formats the statements as a Graphviz DOT graph, with a node for each statement and expression
+/// pointing to its parts. The statements hang off a Program node.
+pub fn format_dot(statements: &[Statement]) -> String {
+    let mut dot =
+        String::from("digraph ast {\n    node [shape=box];\n    n0 [label=\"Program\"];\n");
+    let mut next_id = 1;
+    for statement in statements {
+        write_dot(&mut dot, &statement_node(statement), 0, &mut next_id);
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+fn write_dot(dot: &mut String, node: &TreeNode, parent: usize, next_id: &mut usize) {
+    let id = *next_id;
+    *next_id += 1;
+    let label = node.label.replace('\\', "\\\\").replace('"', "\\\"");
+    writeln!(
+        dot,
+        "    n{id} [label=\"{label}\\n{}\"];",
+        node.format_lines()
+    )
+    .unwrap();
+    writeln!(dot, "    n{parent} -> n{id};").unwrap();
+    for child in &node.children {
+        write_dot(dot, child, id, next_id);
+    }
+}
+
+fn statement_node(statement: &Statement) -> TreeNode {
+    let lines = statement.lines;
+    match &statement.statement {
+        StatementType::VariableDeclaration {
+            variable,
+            variable_type,
+            value,
+            is_static,
+            ..
+        } => {
+            let keyword = if *is_static { "Static" } else { "Let" };
+            let label = match variable_type {
+                Some(variable_type) => format!("{keyword} {variable}: {variable_type:?}"),
+                None => format!("{keyword} {variable}"),
+            };
+            TreeNode::new(label, lines, vec![expression_node(value)])
+        }
+        StatementType::FunctionDeclaration {
+            name,
+            parameters,
+            return_type,
+            body,
+            ..
+        } => {
+            let parameters = parameters
+                .iter()
+                .map(|(parameter, _, parameter_type)| format!("{parameter}: {parameter_type:?}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let label = format!("Fn {name}({parameters}) -> {return_type:?}");
+            TreeNode::new(label, lines, vec![statement_node(body)])
+        }
+        StatementType::Return(expression) => TreeNode::new(
+            "Return",
+            lines,
+            expression.iter().map(expression_node).collect(),
+        ),
+        StatementType::Break(expression) => TreeNode::new(
+            "Break",
+            lines,
+            expression.iter().map(expression_node).collect(),
+        ),
+        StatementType::Raise(expression) => {
+            TreeNode::new("Raise", lines, vec![expression_node(expression)])
+        }
+        StatementType::Expression(expression) => {
+            TreeNode::new("Expression", lines, vec![expression_node(expression)])
+        }
+        StatementType::Block(statements) => TreeNode::new(
+            "Block",
+            lines,
+            statements.iter().map(statement_node).collect(),
+        ),
+        StatementType::If {
+            expression,
+            then_statement,
+            else_statement,
+        } => {
+            let mut children = vec![expression_node(expression), statement_node(then_statement)];
+            if let Some(else_statement) = else_statement {
+                children.push(TreeNode::new(
+                    "Else",
+                    else_statement.lines,
+                    vec![statement_node(else_statement)],
+                ));
+            }
+            TreeNode::new("If", lines, children)
+        }
+        StatementType::While {
+            expression,
+            statement: body,
+        } => TreeNode::new(
+            "While",
+            lines,
+            vec![expression_node(expression), statement_node(body)],
+        ),
+        StatementType::For {
+            variable,
+            expression,
+            statement: body,
+            ..
+        } => TreeNode::new(
+            format!("For {variable}"),
+            lines,
+            vec![expression_node(expression), statement_node(body)],
+        ),
+        StatementType::Try {
+            statement: body,
+            variable,
+            catch_statement,
+            ..
+        } => TreeNode::new(
+            "Try",
+            lines,
+            vec![
+                statement_node(body),
+                TreeNode::new(
+                    format!("Catch {variable}"),
+                    catch_statement.lines,
+                    vec![statement_node(catch_statement)],
+                ),
+            ],
+        ),
+        StatementType::Match { expression, arms } => {
+            let mut children = vec![expression_node(expression)];
+            children.extend(arms.iter().map(|arm| {
+                let mut arm_children: Vec<_> = arm.guard.iter().map(expression_node).collect();
+                arm_children.push(statement_node(&arm.statement));
+                let label = match &arm.guard {
+                    Some(_) => format!("Case {} if", arm.pattern),
+                    None => format!("Case {}", arm.pattern),
+                };
+                TreeNode::new(label, arm.lines, arm_children)
+            }));
+            TreeNode::new("Match", lines, children)
+        }
+        StatementType::Test { name, body } => TreeNode::new(
+            format!("Test \"{name}\""),
+            lines,
+            vec![statement_node(body)],
+        ),
+        StatementType::ImportNative(name) => {
+            TreeNode::new(format!("ImportNative \"{name}\""), lines, vec![])
+        }
+    }
+}
+
+fn expression_node(expression: &Expression) -> TreeNode {
+    let lines = expression.lines;
+    match &expression.expression_type {
+        ExpressionType::Unary {
+            operation,
+            expression: operand,
+        } => TreeNode::new(
+            format!("Unary {operation:?}"),
+            lines,
+            vec![expression_node(operand)],
+        ),
+        ExpressionType::Binary {
+            operation,
+            left_expression,
+            right_expression,
+        } => TreeNode::new(
+            format!("Binary {operation:?}"),
+            lines,
+            vec![
+                expression_node(left_expression),
+                expression_node(right_expression),
+            ],
+        ),
+        ExpressionType::Literal(Value::String(string)) => {
+            TreeNode::new(format!("Literal {string:?}"), lines, vec![])
+        }
+        ExpressionType::Literal(value) => TreeNode::new(format!("Literal {value}"), lines, vec![]),
+        ExpressionType::Variable { name, .. } => {
+            TreeNode::new(format!("Variable {name}"), lines, vec![])
+        }
+        ExpressionType::Grouping(inner_expression) => {
+            TreeNode::new("Grouping", lines, vec![expression_node(inner_expression)])
+        }
+        ExpressionType::Ok(inner_expression) => {
+            TreeNode::new("Ok", lines, vec![expression_node(inner_expression)])
+        }
+        ExpressionType::Err(inner_expression) => {
+            TreeNode::new("Err", lines, vec![expression_node(inner_expression)])
+        }
+        ExpressionType::Some(inner_expression) => {
+            TreeNode::new("Some", lines, vec![expression_node(inner_expression)])
+        }
+        ExpressionType::Propagate(inner_expression) => TreeNode::new(
+            "Propagate ?",
+            lines,
+            vec![expression_node(inner_expression)],
+        ),
+        ExpressionType::Tuple(expressions) => TreeNode::new(
+            "Tuple",
+            lines,
+            expressions.iter().map(expression_node).collect(),
+        ),
+        ExpressionType::Record(fields) => TreeNode::new(
+            "Record",
+            lines,
+            fields
+                .iter()
+                .map(|(name, expression)| {
+                    TreeNode::new(
+                        format!("Field {name}"),
+                        expression.lines,
+                        vec![expression_node(expression)],
+                    )
+                })
+                .collect(),
+        ),
+        ExpressionType::TupleAccess {
+            expression: tuple,
+            field,
+        } => TreeNode::new(
+            format!("TupleAccess .{field}"),
+            lines,
+            vec![expression_node(tuple)],
+        ),
+        ExpressionType::FunctionCall {
+            function,
+            arguments,
+        } => TreeNode::new(
+            "Call",
+            lines,
+            std::iter::once(function.as_ref())
+                .chain(arguments)
+                .map(expression_node)
+                .collect(),
+        ),
+        ExpressionType::Interpolation { values, specs, .. } => TreeNode::new(
+            "Interpolation",
+            lines,
+            values
+                .iter()
+                .zip(specs)
+                .map(|(value, spec)| {
+                    // Values with a spec are shown under it.
+                    if *spec == FormatSpec::default() {
+                        expression_node(value)
+                    } else {
+                        TreeNode::new(
+                            format!("Format :{spec}"),
+                            value.lines,
+                            vec![expression_node(value)],
+                        )
+                    }
+                })
+                .collect(),
+        ),
+        ExpressionType::Block { statements, value } => TreeNode::new(
+            "Block",
+            lines,
+            statements
+                .iter()
+                .map(statement_node)
+                .chain(std::iter::once(expression_node(value)))
+                .collect(),
+        ),
+        ExpressionType::Loop(body) => TreeNode::new("Loop", lines, vec![statement_node(body)]),
+        ExpressionType::If {
+            condition,
+            then_expression,
+            else_expression,
+        } => TreeNode::new(
+            "If",
+            lines,
+            vec![
+                expression_node(condition),
+                expression_node(then_expression),
+                TreeNode::new(
+                    "Else",
+                    else_expression.lines,
+                    vec![expression_node(else_expression)],
+                ),
+            ],
+        ),
+    }
+}
+
 #[derive(Debug)]
 pub struct CompilerError {
     pub lines: (usize, usize),
-    pub error: String,
+    pub error: Diagnostic,
 }
 
+/// Parses the tokens of a program. A statement's semicolon can be left out at the end of its line or block.
 pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Statement>, Vec<CompilerError>> {
+    parse_with_semicolons(tokens, false)
+}
+
+/// Like parse, but every statement must end with a semicolon.
+pub fn parse_requiring_semicolons(
+    tokens: &Vec<Token>,
+) -> Result<Vec<Statement>, Vec<CompilerError>> {
+    parse_with_semicolons(tokens, true)
+}
+
+fn parse_with_semicolons(
+    tokens: &Vec<Token>,
+    require_semicolons: bool,
+) -> Result<Vec<Statement>, Vec<CompilerError>> {
     let mut current_token = 0;
     let mut errors = vec![];
     let mut statements = vec![];
@@ -256,6 +1057,11 @@ pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Statement>, Vec<CompilerError>>
         statements.push(statement);
     }
 
+    drop_errors_after_nesting(&mut errors);
+    // Omitted semicolons are recorded as errors while parsing, so they're only reported when they're required.
+    if !require_semicolons {
+        errors.retain(|error| error.error.message != &OMITTED_SEMICOLON);
+    }
     if errors.is_empty() {
         Ok(statements)
     } else {
@@ -263,59 +1069,306 @@ pub fn parse(tokens: &Vec<Token>) -> Result<Vec<Statement>, Vec<CompilerError>>
     }
 }
 
-fn panic_forward(tokens: &Vec<Token>, current_token: &mut usize) {
-    while *current_token < tokens.len() {
-        if matches!(
-            tokens[*current_token].token_type,
-            TokenType::If
-                | TokenType::While
-                | TokenType::Let
-                | TokenType::LeftBrace
-                | TokenType::RightBrace
-        ) {
-            break;
+/// How deeply statements, expressions, patterns and types can nest. The passes after parsing recurse
+/// through the tree, so deeper programs would overflow the stack.
+const MAX_NESTING: usize = 64;
+/// How many binary operators in a chain like 1 + 2 + 3 count as one level of nesting. Each operator nests the tree a
+/// level deeper on its left, but the passes after parsing use much less stack for it than for a block or a grouping.
+const OPERATORS_PER_LEVEL: usize = 8;
+
+thread_local! {
+    static NESTING: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Drops the errors reported after the program nested too deeply, which come from the enclosing statements that were
+/// cut short when the rest of the tokens were skipped.
+pub(crate) fn drop_errors_after_nesting(errors: &mut Vec<CompilerError>) {
+    if let Some(index) = errors
+        .iter()
+        .position(|error| error.error.message == &NESTED_TOO_DEEPLY)
+    {
+        errors.truncate(index + 1);
+    }
+}
+
+/// Nesting which is left when it's dropped, counted in binary operators.
+struct Nesting(usize);
+
+impl Nesting {
+    /// Enters a level of nesting at the current token. If the program nests too deeply, the error is reported and the
+    /// rest of the tokens are skipped, as there's no good place to carry on parsing from.
+    fn enter(
+        tokens: &[Token],
+        current_token: &mut usize,
+        errors: &mut Vec<CompilerError>,
+    ) -> Option<Nesting> {
+        Nesting::enter_by(OPERATORS_PER_LEVEL, tokens, current_token, errors)
+    }
+
+    /// Enters the nesting of a binary operator in a chain.
+    fn enter_operator(
+        tokens: &[Token],
+        current_token: &mut usize,
+        errors: &mut Vec<CompilerError>,
+    ) -> Option<Nesting> {
+        Nesting::enter_by(1, tokens, current_token, errors)
+    }
+
+    fn enter_by(
+        operators: usize,
+        tokens: &[Token],
+        current_token: &mut usize,
+        errors: &mut Vec<CompilerError>,
+    ) -> Option<Nesting> {
+        let depth = NESTING.with(|nesting| nesting.get()) + operators;
+        if depth > MAX_NESTING * OPERATORS_PER_LEVEL {
+            errors.push(CompilerError {
+                lines: tokens[(*current_token).min(tokens.len() - 1)].lines,
+                error: diagnostic!(NESTED_TOO_DEEPLY, MAX_NESTING),
+            });
+            *current_token = tokens.len();
+            return None;
         }
+        NESTING.with(|nesting| nesting.set(depth));
+        Some(Nesting(operators))
+    }
+}
 
+impl Drop for Nesting {
+    fn drop(&mut self) {
+        NESTING.with(|nesting| nesting.set(nesting.get() - self.0));
+    }
+}
+
+/// Parses the pattern of a match arm which starts on the line.
+fn parse_pattern(
+    tokens: &[Token],
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+    line: usize,
+) -> Option<Pattern> {
+    let mut patterns = vec![parse_single_pattern(tokens, current_token, errors, line)?];
+    while tokens.get(*current_token).map(|token| &token.token_type) == Some(&TokenType::Pipe) {
         *current_token += 1;
+        patterns.push(parse_single_pattern(tokens, current_token, errors, line)?);
+    }
+    if patterns.len() == 1 {
+        patterns.pop()
+    } else {
+        Some(Pattern::Or(patterns))
     }
 }
 
-/// Parses a block statement.
-pub fn parse_block_statement(
-    tokens: &Vec<Token>,
+/// Parses a pattern which isn't an or-pattern.
+fn parse_single_pattern(
+    tokens: &[Token],
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
-) -> Option<Statement> {
-    let start_line = match tokens
-        .get(*current_token)
-        .map(|token| (&token.token_type, token))
-    {
-        Some((TokenType::LeftBrace, token)) => token.lines.0,
-        _ => {
-            panic_forward(tokens, current_token);
-            return None;
+    line: usize,
+) -> Option<Pattern> {
+    let _nesting = Nesting::enter(tokens, current_token, errors)?;
+    if let Some(start) = parse_pattern_number(tokens, current_token) {
+        if tokens.get(*current_token).map(|token| &token.token_type) != Some(&TokenType::DotDot) {
+            return Some(Pattern::Literal(Value::Number(start)));
         }
-    };
+        *current_token += 1;
+        let Some(end) = parse_pattern_number(tokens, current_token) else {
+            errors.push(CompilerError {
+                lines: (line, line),
+                error: diagnostic!(EXPECTED_RANGE_END, Value::Number(start)),
+            });
+            return None;
+        };
+        return Some(Pattern::Range { start, end });
+    }
+    let token_type = tokens.get(*current_token).map(|token| &token.token_type);
     *current_token += 1;
-
-    let mut statements = vec![];
-    let mut statement_error_occured = false;
-    let mut end_line = start_line;
-    loop {
-        match tokens
-            .get(*current_token)
-            .map(|token| (&token.token_type, token))
-        {
-            Some((&TokenType::RightBrace, token)) => {
-                end_line = token.lines.1;
-                *current_token += 1;
-                break;
-            }
-            None => {
-                errors.push(CompilerError {
-                    lines: (start_line, end_line),
-                    error: "Unmatched left brace.".into(),
-                });
+    match token_type {
+        Some(TokenType::Variable(name)) if name.as_str() == "_" => Some(Pattern::Wildcard),
+        Some(TokenType::Variable(name)) => Some(Pattern::Variable {
+            name: *name,
+            shadow_id: None,
+        }),
+        Some(TokenType::Literal(value)) => Some(Pattern::Literal(value.clone())),
+        Some(TokenType::None) => Some(Pattern::Literal(Value::Option(None))),
+        Some(TokenType::Minus) => {
+            errors.push(CompilerError {
+                lines: (line, line),
+                error: diagnostic!(EXPECTED_NUMBER_AFTER_MINUS),
+            });
+            None
+        }
+        Some(token_type @ (TokenType::Some | TokenType::Ok | TokenType::Err)) => {
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::LeftParenthesis)
+            {
+                errors.push(CompilerError {
+                    lines: (line, line),
+                    error: diagnostic!(EXPECTED_PATTERN_PARENTHESIS, token_type),
+                });
+                return None;
+            }
+            *current_token += 1;
+            let pattern = parse_pattern(tokens, current_token, errors, line)?.into();
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::RightParenthesis)
+            {
+                errors.push(CompilerError {
+                    lines: (line, line),
+                    error: diagnostic!(PATTERN_TAKES_ONE_PATTERN, token_type),
+                });
+                return None;
+            }
+            *current_token += 1;
+            Some(match token_type {
+                TokenType::Some => Pattern::Some(pattern),
+                TokenType::Ok => Pattern::Ok(pattern),
+                _ => Pattern::Err(pattern),
+            })
+        }
+        Some(TokenType::LeftParenthesis) => {
+            let mut patterns = vec![parse_pattern(tokens, current_token, errors, line)?];
+            loop {
+                match tokens.get(*current_token).map(|token| &token.token_type) {
+                    Some(TokenType::Comma) => {
+                        *current_token += 1;
+                        patterns.push(parse_pattern(tokens, current_token, errors, line)?);
+                    }
+                    Some(TokenType::RightParenthesis) => {
+                        *current_token += 1;
+                        break;
+                    }
+                    _ => {
+                        errors.push(CompilerError {
+                            lines: (line, line),
+                            error: diagnostic!(EXPECTED_TUPLE_PATTERN_SEPARATOR),
+                        });
+                        return None;
+                    }
+                }
+            }
+            // Like in expressions, a pattern in parentheses is only a tuple if it has a comma.
+            if patterns.len() == 1 {
+                patterns.pop()
+            } else {
+                Some(Pattern::Tuple(patterns))
+            }
+        }
+        _ => {
+            errors.push(CompilerError {
+                lines: (line, line),
+                error: diagnostic!(EXPECTED_CASE_PATTERN),
+            });
+            None
+        }
+    }
+}
+
+/// Parses a number in a pattern, which can be negative.
+fn parse_pattern_number(tokens: &[Token], current_token: &mut usize) -> Option<f64> {
+    match tokens.get(*current_token).map(|token| &token.token_type) {
+        Some(TokenType::Literal(Value::Number(number))) => {
+            *current_token += 1;
+            Some(*number)
+        }
+        Some(TokenType::Minus) => match tokens
+            .get(*current_token + 1)
+            .map(|token| &token.token_type)
+        {
+            Some(TokenType::Literal(Value::Number(number))) => {
+                *current_token += 2;
+                Some(-number)
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn panic_forward(tokens: &[Token], current_token: &mut usize) {
+    while *current_token < tokens.len() {
+        if matches!(
+            tokens[*current_token].token_type,
+            TokenType::If
+                | TokenType::While
+                | TokenType::Loop
+                | TokenType::For
+                | TokenType::Try
+                | TokenType::Match
+                | TokenType::Let
+                | TokenType::Static
+                | TokenType::LeftBrace
+                | TokenType::RightBrace
+        ) {
+            break;
+        }
+
+        *current_token += 1;
+    }
+}
+
+/// Consumes the semicolon after a statement whose last token is on the end line, and returns the line the statement
+/// ends on. The semicolon can be left out if the next token is on a later line, or ends the block or the program.
+fn parse_statement_end(
+    tokens: &[Token],
+    current_token: &mut usize,
+    end_line: usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<usize> {
+    match tokens.get(*current_token) {
+        Some(token) if token.token_type == TokenType::Semicolon => {
+            *current_token += 1;
+            Some(token.lines.1)
+        }
+        Some(token) if token.token_type != TokenType::RightBrace && token.lines.0 <= end_line => {
+            None
+        }
+        _ => {
+            errors.push(CompilerError {
+                lines: (end_line, end_line),
+                error: diagnostic!(OMITTED_SEMICOLON),
+            });
+            Some(end_line)
+        }
+    }
+}
+
+/// Parses a block statement.
+pub fn parse_block_statement(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Statement> {
+    let start_line = match tokens
+        .get(*current_token)
+        .map(|token| (&token.token_type, token))
+    {
+        Some((TokenType::LeftBrace, token)) => token.lines.0,
+        _ => {
+            panic_forward(tokens, current_token);
+            return None;
+        }
+    };
+    *current_token += 1;
+
+    let mut statements = vec![];
+    let mut statement_error_occured = false;
+    let mut end_line = start_line;
+    loop {
+        match tokens
+            .get(*current_token)
+            .map(|token| (&token.token_type, token))
+        {
+            Some((&TokenType::RightBrace, token)) => {
+                end_line = token.lines.1;
+                *current_token += 1;
+                break;
+            }
+            None => {
+                errors.push(CompilerError {
+                    lines: (start_line, end_line),
+                    error: diagnostic!(UNMATCHED_LEFT_BRACE),
+                });
                 return None;
             }
             _ => {
@@ -343,28 +1396,193 @@ pub fn parse_block_statement(
     })
 }
 
+fn parse_loop_expression(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let loop_line = tokens[*current_token].lines.0;
+    *current_token += 1;
+
+    let Some(body) = parse_block_statement(tokens, current_token, errors) else {
+        errors.push(CompilerError {
+            lines: (loop_line, loop_line),
+            error: diagnostic!(EXPECTED_LOOP_BLOCK),
+        });
+        return None;
+    };
+    Some(Expression {
+        lines: (loop_line, body.lines.1),
+        expression_type: ExpressionType::Loop(body.into()),
+    })
+}
+
+/// Parses an if with a value, which must have an else branch.
+fn parse_if_expression(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let if_line = tokens[*current_token].lines.0;
+    *current_token += 1;
+
+    let condition = parse_expression(tokens, current_token, errors)?;
+    let then_expression = match tokens.get(*current_token) {
+        Some(token) if token.token_type == TokenType::LeftBrace => {
+            parse_block_expression(tokens, current_token, errors)?
+        }
+        _ => {
+            errors.push(CompilerError {
+                lines: (if_line, condition.lines.1),
+                error: diagnostic!(EXPECTED_IF_BLOCK),
+            });
+            return None;
+        }
+    };
+    let else_expression = match tokens.get(*current_token).map(|token| &token.token_type) {
+        Some(TokenType::Else) => {
+            *current_token += 1;
+            match tokens.get(*current_token).map(|token| &token.token_type) {
+                Some(TokenType::If) => {
+                    let _nesting = Nesting::enter(tokens, current_token, errors)?;
+                    parse_if_expression(tokens, current_token, errors)?
+                }
+                Some(TokenType::LeftBrace) => {
+                    parse_block_expression(tokens, current_token, errors)?
+                }
+                _ => {
+                    errors.push(CompilerError {
+                        lines: (if_line, then_expression.lines.1),
+                        error: diagnostic!(EXPECTED_ELSE_BRANCH),
+                    });
+                    return None;
+                }
+            }
+        }
+        _ => {
+            errors.push(CompilerError {
+                lines: (if_line, then_expression.lines.1),
+                error: diagnostic!(IF_VALUE_NEEDS_ELSE),
+            });
+            return None;
+        }
+    };
+
+    Some(Expression {
+        lines: (if_line, else_expression.lines.1),
+        expression_type: ExpressionType::If {
+            condition: Box::new(condition),
+            then_expression: Box::new(then_expression),
+            else_expression: Box::new(else_expression),
+        },
+    })
+}
+
+/// Parses a block whose last expression, without a semicolon after it, is the value of the block.
+fn parse_block_expression(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let start_line = tokens[*current_token].lines.0;
+    *current_token += 1;
+
+    let mut statements = vec![];
+    loop {
+        match tokens
+            .get(*current_token)
+            .map(|token| (&token.token_type, token))
+        {
+            Some((TokenType::RightBrace, token)) => {
+                errors.push(CompilerError {
+                    lines: (start_line, token.lines.1),
+                    error: diagnostic!(EXPECTED_BLOCK_VALUE),
+                });
+                *current_token += 1;
+                return None;
+            }
+            None => {
+                errors.push(CompilerError {
+                    lines: (start_line, start_line),
+                    error: diagnostic!(UNMATCHED_LEFT_BRACE),
+                });
+                return None;
+            }
+            Some((
+                TokenType::Let
+                | TokenType::Static
+                | TokenType::While
+                | TokenType::Fn
+                | TokenType::Return
+                | TokenType::Break
+                | TokenType::DocComment(_),
+                _,
+            )) => statements.push(parse_statement(tokens, current_token, errors)?),
+            _ => {
+                // An expression followed by } is the value. Otherwise it's parsed again as a statement,
+                // which may be a block or if statement.
+                let start = *current_token;
+                if let Some(value) = parse_expression(tokens, current_token, &mut vec![]) {
+                    if let Some((TokenType::RightBrace, token)) = tokens
+                        .get(*current_token)
+                        .map(|token| (&token.token_type, token))
+                    {
+                        *current_token += 1;
+                        return Some(Expression {
+                            expression_type: ExpressionType::Block {
+                                statements,
+                                value: Box::new(value),
+                            },
+                            lines: (start_line, token.lines.1),
+                        });
+                    }
+                }
+                *current_token = start;
+                statements.push(parse_statement(tokens, current_token, errors)?);
+            }
+        }
+    }
+}
+
 pub fn parse_statement(
     tokens: &Vec<Token>,
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Statement> {
+    let _nesting = Nesting::enter(tokens, current_token, errors)?;
+    let doc = parse_doc_comments(tokens, current_token, errors)?;
+
     match tokens
         .get(*current_token)
         .map(|token| (&token.token_type, token))
     {
-        Some((&TokenType::Let, token)) => {
+        Some((&(TokenType::Let | TokenType::Static), token)) => {
             let line_start = token.lines.0;
+            let is_static = token.token_type == TokenType::Static;
             *current_token += 1;
+            if is_static {
+                match tokens.get(*current_token) {
+                    Some(token) if token.token_type == TokenType::Let => *current_token += 1,
+                    _ => {
+                        errors.push(CompilerError {
+                            lines: token.lines,
+                            error: diagnostic!(EXPECTED_LET_AFTER_STATIC),
+                        });
+                        panic_forward(tokens, current_token);
+                        return None;
+                    }
+                }
+            }
 
             let (variable, variable_end) = match tokens
                 .get(*current_token)
                 .map(|token| (&token.token_type, token))
             {
-                Some((TokenType::Variable(variable), token)) => (variable.clone(), token.lines.1),
+                Some((TokenType::Variable(variable), token)) => (*variable, token.lines.1),
                 _ => {
                     errors.push(CompilerError {
                         lines: (line_start, token.lines.1),
-                        error: "Expected variable after let.".into(),
+                        error: diagnostic!(EXPECTED_LET_VARIABLE),
                     });
                     panic_forward(tokens, current_token);
                     return None;
@@ -385,14 +1603,17 @@ pub fn parse_statement(
             };
 
             let Some(equal_token) = tokens.get(*current_token) else {
-                errors.push(CompilerError { lines: (line_start, variable_end), error: "Expected equal symbol in let statement.".into() });
+                errors.push(CompilerError {
+                    lines: (line_start, variable_end),
+                    error: diagnostic!(EXPECTED_LET_EQUAL),
+                });
                 panic_forward(tokens, current_token);
                 return None;
             };
             if equal_token.token_type != TokenType::Equal {
                 errors.push(CompilerError {
                     lines: (line_start, equal_token.lines.1),
-                    error: "Expected equal symbol in let statement.".into(),
+                    error: diagnostic!(EXPECTED_LET_EQUAL),
                 });
                 panic_forward(tokens, current_token);
                 return None;
@@ -404,27 +1625,26 @@ pub fn parse_statement(
                 return None;
             };
 
-            let semicolon_line = if let Some((&TokenType::Semicolon, token)) = tokens
-                .get(*current_token)
-                .map(|token| (&token.token_type, token))
-            {
-                token.lines.1
-            } else {
+            let Some(semicolon_line) =
+                parse_statement_end(tokens, current_token, expression.lines.1, errors)
+            else {
                 errors.push(CompilerError {
                     lines: (line_start, expression.lines.1),
-                    error: "Expected semicolon after let statement.".into(),
+                    error: diagnostic!(EXPECTED_LET_SEMICOLON),
                 });
                 panic_forward(tokens, current_token);
                 return None;
             };
-            *current_token += 1;
 
             Some(Statement {
                 statement: StatementType::VariableDeclaration {
+                    doc,
                     variable,
                     variable_type,
                     value: expression,
+                    is_static,
                     shadow_id: None,
+                    slot: None,
                 },
                 lines: (line_start, semicolon_line),
             })
@@ -435,13 +1655,22 @@ pub fn parse_statement(
             *current_token += 1;
 
             let Some(expression) = parse_expression(tokens, current_token, errors) else {
-                errors.push(CompilerError { lines: (if_line, if_line), error: "Expected condition after if.".into() });
+                errors.push(CompilerError {
+                    lines: (if_line, if_line),
+                    error: diagnostic!(EXPECTED_IF_CONDITION),
+                });
                 panic_forward(tokens, current_token);
                 return None;
             };
 
-            let Some(then_statement): Option<Box<Statement>> = parse_block_statement(tokens, current_token, errors).map(|statement| statement.into()) else {
-                errors.push(CompilerError { lines: (if_line, expression.lines.1), error: "Expected then block after condition.".into() });
+            let Some(then_statement): Option<Box<Statement>> =
+                parse_block_statement(tokens, current_token, errors)
+                    .map(|statement| statement.into())
+            else {
+                errors.push(CompilerError {
+                    lines: (if_line, expression.lines.1),
+                    error: diagnostic!(EXPECTED_THEN_BLOCK),
+                });
                 return None;
             };
 
@@ -450,8 +1679,12 @@ pub fn parse_statement(
                 .map(|token| (&token.token_type, token))
             {
                 *current_token += 1;
-                let Some(else_statement) = parse_block_statement(tokens, current_token, errors) else {
-                    errors.push(CompilerError { lines: (if_line, else_token.lines.1), error: "Expected else block after else.".into() });
+                let Some(else_statement) = parse_block_statement(tokens, current_token, errors)
+                else {
+                    errors.push(CompilerError {
+                        lines: (if_line, else_token.lines.1),
+                        error: diagnostic!(EXPECTED_ELSE_BLOCK),
+                    });
                     return None;
                 };
                 let end_line = else_statement.lines.1;
@@ -474,13 +1707,21 @@ pub fn parse_statement(
             *current_token += 1;
 
             let Some(expression) = parse_expression(tokens, current_token, errors) else {
-                errors.push(CompilerError { lines: (while_start, while_start), error: "Expected expression after while.".into() });
+                errors.push(CompilerError {
+                    lines: (while_start, while_start),
+                    error: diagnostic!(EXPECTED_WHILE_CONDITION),
+                });
                 panic_forward(tokens, current_token);
                 return None;
             };
 
-            let Some(statement) = parse_block_statement(tokens, current_token, errors).map(|statement| statement.into()) else {
-                errors.push(CompilerError { lines: (while_start, expression.lines.1), error: "Expected block statement for while.".into() });
+            let Some(statement) = parse_block_statement(tokens, current_token, errors)
+                .map(|statement| statement.into())
+            else {
+                errors.push(CompilerError {
+                    lines: (while_start, expression.lines.1),
+                    error: diagnostic!(EXPECTED_WHILE_BLOCK),
+                });
                 return None;
             };
 
@@ -492,6 +1733,217 @@ pub fn parse_statement(
                 },
             })
         }
+        Some((TokenType::For, token)) => {
+            let for_start = token.lines.0;
+            *current_token += 1;
+
+            let Some(TokenType::Variable(variable)) =
+                tokens.get(*current_token).map(|token| &token.token_type)
+            else {
+                errors.push(CompilerError {
+                    lines: (for_start, for_start),
+                    error: diagnostic!(EXPECTED_FOR_VARIABLE),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+            *current_token += 1;
+
+            if tokens.get(*current_token).map(|token| &token.token_type) != Some(&TokenType::In) {
+                errors.push(CompilerError {
+                    lines: (for_start, for_start),
+                    error: diagnostic!(EXPECTED_FOR_IN, variable),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            }
+            *current_token += 1;
+
+            let Some(expression) = parse_expression(tokens, current_token, errors) else {
+                errors.push(CompilerError {
+                    lines: (for_start, for_start),
+                    error: diagnostic!(EXPECTED_FOR_SEQUENCE),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+
+            let Some(statement) = parse_block_statement(tokens, current_token, errors)
+                .map(|statement| statement.into())
+            else {
+                errors.push(CompilerError {
+                    lines: (for_start, expression.lines.1),
+                    error: diagnostic!(EXPECTED_FOR_BLOCK),
+                });
+                return None;
+            };
+
+            Some(Statement {
+                lines: (for_start, expression.lines.1),
+                statement: StatementType::For {
+                    variable: *variable,
+                    shadow_id: None,
+                    expression,
+                    statement,
+                },
+            })
+        }
+        Some((TokenType::Try, token)) => {
+            let try_start = token.lines.0;
+            *current_token += 1;
+
+            let Some(statement): Option<Box<Statement>> =
+                parse_block_statement(tokens, current_token, errors)
+                    .map(|statement| statement.into())
+            else {
+                errors.push(CompilerError {
+                    lines: (try_start, try_start),
+                    error: diagnostic!(EXPECTED_TRY_BLOCK),
+                });
+                return None;
+            };
+
+            let Some((&TokenType::Catch, catch_token)) = tokens
+                .get(*current_token)
+                .map(|token| (&token.token_type, token))
+            else {
+                errors.push(CompilerError {
+                    lines: (try_start, statement.lines.1),
+                    error: diagnostic!(EXPECTED_CATCH),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+            let catch_line = catch_token.lines.0;
+            *current_token += 1;
+
+            let Some(TokenType::Variable(variable)) =
+                tokens.get(*current_token).map(|token| &token.token_type)
+            else {
+                errors.push(CompilerError {
+                    lines: (catch_line, catch_line),
+                    error: diagnostic!(EXPECTED_CATCH_VARIABLE),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+            *current_token += 1;
+
+            let Some(catch_statement) = parse_block_statement(tokens, current_token, errors) else {
+                errors.push(CompilerError {
+                    lines: (catch_line, catch_line),
+                    error: diagnostic!(EXPECTED_CATCH_BLOCK, variable),
+                });
+                return None;
+            };
+
+            Some(Statement {
+                lines: (try_start, catch_statement.lines.1),
+                statement: StatementType::Try {
+                    statement,
+                    variable: *variable,
+                    shadow_id: None,
+                    catch_statement: catch_statement.into(),
+                },
+            })
+        }
+        Some((TokenType::Match, token)) => {
+            let match_start = token.lines.0;
+            *current_token += 1;
+
+            let Some(expression) = parse_expression(tokens, current_token, errors) else {
+                errors.push(CompilerError {
+                    lines: (match_start, match_start),
+                    error: diagnostic!(EXPECTED_MATCH_VALUE),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::LeftBrace)
+            {
+                errors.push(CompilerError {
+                    lines: (match_start, expression.lines.1),
+                    error: diagnostic!(EXPECTED_MATCH_BRACE, expression),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            }
+            *current_token += 1;
+
+            let mut arms = vec![];
+            loop {
+                match tokens
+                    .get(*current_token)
+                    .map(|token| (&token.token_type, token))
+                {
+                    Some((TokenType::RightBrace, token)) => {
+                        *current_token += 1;
+                        return Some(Statement {
+                            lines: (match_start, token.lines.1),
+                            statement: StatementType::Match { expression, arms },
+                        });
+                    }
+                    Some((TokenType::Case, token)) => {
+                        let case_line = token.lines.0;
+                        *current_token += 1;
+                        let pattern = parse_pattern(tokens, current_token, errors, case_line)?;
+                        let guard = if tokens.get(*current_token).map(|token| &token.token_type)
+                            == Some(&TokenType::If)
+                        {
+                            *current_token += 1;
+                            let Some(guard) = parse_expression(tokens, current_token, errors)
+                            else {
+                                errors.push(CompilerError {
+                                    lines: (case_line, case_line),
+                                    error: diagnostic!(EXPECTED_GUARD_CONDITION, pattern),
+                                });
+                                panic_forward(tokens, current_token);
+                                return None;
+                            };
+                            Some(guard)
+                        } else {
+                            None
+                        };
+                        if tokens.get(*current_token).map(|token| &token.token_type)
+                            != Some(&TokenType::FatArrow)
+                        {
+                            errors.push(CompilerError {
+                                lines: (case_line, case_line),
+                                error: diagnostic!(EXPECTED_CASE_ARROW, pattern),
+                            });
+                            panic_forward(tokens, current_token);
+                            return None;
+                        }
+                        *current_token += 1;
+                        let Some(statement) = parse_block_statement(tokens, current_token, errors)
+                        else {
+                            errors.push(CompilerError {
+                                lines: (case_line, case_line),
+                                error: diagnostic!(EXPECTED_CASE_BLOCK, pattern),
+                            });
+                            return None;
+                        };
+                        arms.push(MatchArm {
+                            pattern,
+                            guard,
+                            lines: (case_line, statement.lines.1),
+                            statement,
+                        });
+                    }
+                    token => {
+                        let line = token.map_or(match_start, |(_, token)| token.lines.0);
+                        errors.push(CompilerError {
+                            lines: (line, line),
+                            error: diagnostic!(EXPECTED_CASE_OR_BRACE),
+                        });
+                        panic_forward(tokens, current_token);
+                        return None;
+                    }
+                }
+            }
+        }
         Some((TokenType::Fn, token)) => {
             let function_start = token.lines.0;
             *current_token += 1;
@@ -503,7 +1955,7 @@ pub fn parse_statement(
             } else {
                 errors.push(CompilerError {
                     lines: (function_start, token.lines.1),
-                    error: "Function name required.".into(),
+                    error: diagnostic!(EXPECTED_FUNCTION_NAME),
                 });
                 return None;
             };
@@ -514,7 +1966,7 @@ pub fn parse_statement(
             {
                 errors.push(CompilerError {
                     lines: (function_start, token.lines.1),
-                    error: "Left parenthesis required after function name.".into(),
+                    error: diagnostic!(EXPECTED_PARAMETERS),
                 });
                 return None;
             }
@@ -541,7 +1993,7 @@ pub fn parse_statement(
                     } else {
                         errors.push(CompilerError {
                             lines: (function_start, function_start),
-                            error: "Parameter name expected.".into(),
+                            error: diagnostic!(EXPECTED_PARAMETER_NAME),
                         });
                         return None;
                     };
@@ -555,14 +2007,14 @@ pub fn parse_statement(
                 } else {
                     errors.push(CompilerError {
                         lines: (function_start, parameter_name_line_end),
-                        error: "Colon expected after parameter.".into(),
+                        error: diagnostic!(EXPECTED_PARAMETER_COLON),
                     });
                     return None;
                 };
                 *current_token += 1;
 
                 let parameter_type = parse_type(tokens, current_token, errors)?;
-                parameters.push((parameter_name.clone(), None, parameter_type));
+                parameters.push((*parameter_name, None, parameter_type));
 
                 match tokens
                     .get(*current_token)
@@ -579,14 +2031,14 @@ pub fn parse_statement(
                     Some((_, token)) => {
                         errors.push(CompilerError {
                             lines: (function_start, token.lines.1),
-                            error: "Comma expected after parameter".into(),
+                            error: diagnostic!(EXPECTED_PARAMETER_COMMA),
                         });
                         return None;
                     }
                     None => {
                         errors.push(CompilerError {
                             lines: (function_start, colon_line_end),
-                            error: "Comma expected after parameter".into(),
+                            error: diagnostic!(EXPECTED_PARAMETER_COMMA),
                         });
                         return None;
                     }
@@ -603,61 +2055,191 @@ pub fn parse_statement(
             };
 
             let Some(body) = parse_block_statement(tokens, current_token, errors) else {
-                errors.push(CompilerError { lines: (function_start, right_parenthesis_end_line), error: "Expected block statement for function.".into() });
+                errors.push(CompilerError {
+                    lines: (function_start, right_parenthesis_end_line),
+                    error: diagnostic!(EXPECTED_FUNCTION_BLOCK),
+                });
                 return None;
             };
 
             Some(Statement {
                 lines: (function_start, body.lines.1),
                 statement: StatementType::FunctionDeclaration {
-                    name: name.clone(),
+                    doc,
+                    name: *name,
                     parameters,
                     return_type,
                     body: body.into(),
                     shadow_id: None,
+                    slot: None,
                 },
             })
         }
-        Some((TokenType::Return, token)) => {
-            let return_start = token.lines.0;
-            *current_token += 1;
+        // test isn't a keyword, so it's only a test block when followed by its name.
+        Some((TokenType::Variable(name), token))
+            if name.as_str() == "test"
+                && matches!(
+                    tokens
+                        .get(*current_token + 1)
+                        .map(|token| &token.token_type),
+                    Some(TokenType::Literal(Value::String(_)))
+                ) =>
+        {
+            let test_start = token.lines.0;
+            let Some(TokenType::Literal(Value::String(name))) = tokens
+                .get(*current_token + 1)
+                .map(|token| &token.token_type)
+            else {
+                unreachable!()
+            };
+            *current_token += 2;
 
-            let (expression, semicolon_line) = if let Some((&TokenType::Semicolon, token)) = tokens
-                .get(*current_token)
-                .map(|token| (&token.token_type, token))
+            let Some(body) = parse_block_statement(tokens, current_token, errors) else {
+                errors.push(CompilerError {
+                    lines: (test_start, test_start),
+                    error: diagnostic!(EXPECTED_TEST_BLOCK),
+                });
+                return None;
+            };
+
+            Some(Statement {
+                lines: (test_start, body.lines.1),
+                statement: StatementType::Test {
+                    name: name.to_string(),
+                    body: body.into(),
+                },
+            })
+        }
+        // Neither are import and native, so it's only an import when they're followed by the module's name.
+        Some((TokenType::Variable(name), token))
+            if name.as_str() == "import"
+                && matches!(
+                    tokens
+                        .get(*current_token + 1)
+                        .map(|token| &token.token_type),
+                    Some(TokenType::Variable(native)) if native.as_str() == "native"
+                )
+                && matches!(
+                    tokens
+                        .get(*current_token + 2)
+                        .map(|token| &token.token_type),
+                    Some(TokenType::Literal(Value::String(_)))
+                ) =>
+        {
+            let import_start = token.lines.0;
+            let Some(TokenType::Literal(Value::String(name))) = tokens
+                .get(*current_token + 2)
+                .map(|token| &token.token_type)
+            else {
+                unreachable!()
+            };
+            *current_token += 3;
+
+            let name_line = tokens[*current_token - 1].lines.1;
+            let Some(semicolon_line) =
+                parse_statement_end(tokens, current_token, name_line, errors)
+            else {
+                errors.push(CompilerError {
+                    lines: (import_start, import_start),
+                    error: diagnostic!(EXPECTED_SEMICOLON),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+
+            Some(Statement {
+                lines: (import_start, semicolon_line),
+                statement: StatementType::ImportNative(name.to_string()),
+            })
+        }
+        // A loop statement doesn't need a semicolon after it.
+        Some((TokenType::Loop, _)) => {
+            let expression = parse_loop_expression(tokens, current_token, errors)?;
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                == Some(&TokenType::Semicolon)
             {
                 *current_token += 1;
-                (None, token.lines.1)
-            } else {
-                let Some(expression) = parse_expression(tokens, current_token, errors) else {
-                    panic_forward(tokens, current_token);
-                    return None;
-                };
-                let semicolon_line = if let Some((&TokenType::Semicolon, token)) = tokens
-                    .get(*current_token)
-                    .map(|token| (&token.token_type, token))
+            }
+            Some(Statement {
+                lines: expression.lines,
+                statement: StatementType::Expression(expression),
+            })
+        }
+        Some((token_type @ (TokenType::Return | TokenType::Break), token)) => {
+            let return_start = token.lines.0;
+            *current_token += 1;
+
+            // A return or break without a value can only leave out its semicolon at the end of the block or program,
+            // as the value may be on the next line.
+            let (expression, semicolon_line) = match tokens.get(*current_token) {
+                Some(token)
+                    if !matches!(
+                        token.token_type,
+                        TokenType::Semicolon | TokenType::RightBrace
+                    ) =>
                 {
-                    token.lines.1
-                } else {
-                    errors.push(CompilerError {
-                        lines: expression.lines,
-                        error: "Expected semicolon at the end of the statement.".into(),
-                    });
-                    panic_forward(tokens, current_token);
-                    return None;
-                };
-                *current_token += 1;
-                (Some(expression), semicolon_line)
+                    let Some(expression) = parse_expression(tokens, current_token, errors) else {
+                        panic_forward(tokens, current_token);
+                        return None;
+                    };
+                    let Some(semicolon_line) =
+                        parse_statement_end(tokens, current_token, expression.lines.1, errors)
+                    else {
+                        errors.push(CompilerError {
+                            lines: expression.lines,
+                            error: diagnostic!(EXPECTED_SEMICOLON),
+                        });
+                        panic_forward(tokens, current_token);
+                        return None;
+                    };
+                    (Some(expression), semicolon_line)
+                }
+                _ => (
+                    None,
+                    parse_statement_end(tokens, current_token, return_start, errors).unwrap(),
+                ),
             };
 
             Some(Statement {
                 lines: (return_start, semicolon_line),
-                statement: StatementType::Return(expression),
+                statement: match token_type {
+                    TokenType::Return => StatementType::Return(expression),
+                    _ => StatementType::Break(expression),
+                },
+            })
+        }
+        Some((TokenType::Raise, token)) => {
+            let raise_start = token.lines.0;
+            *current_token += 1;
+
+            let Some(expression) = parse_expression(tokens, current_token, errors) else {
+                errors.push(CompilerError {
+                    lines: (raise_start, raise_start),
+                    error: diagnostic!(EXPECTED_RAISED_ERROR),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+            let Some(semicolon_line) =
+                parse_statement_end(tokens, current_token, expression.lines.1, errors)
+            else {
+                errors.push(CompilerError {
+                    lines: expression.lines,
+                    error: diagnostic!(EXPECTED_SEMICOLON),
+                });
+                panic_forward(tokens, current_token);
+                return None;
+            };
+
+            Some(Statement {
+                lines: (raise_start, semicolon_line),
+                statement: StatementType::Raise(expression),
             })
         }
         _ => {
             let initial_position = *current_token;
-            let Some(expression) = parse_expression(tokens, current_token, errors) else {
+            let prefix = parse_increment(tokens, current_token);
+            let Some(mut expression) = parse_expression(tokens, current_token, errors) else {
                 if initial_position == *current_token {
                     // Make sure we keep progressing forward.
                     *current_token += 1;
@@ -665,20 +2247,33 @@ pub fn parse_statement(
                 panic_forward(tokens, current_token);
                 return None;
             };
-            let semicolon_line = if let Some((&TokenType::Semicolon, token)) = tokens
-                .get(*current_token)
-                .map(|token| (&token.token_type, token))
+            if let Some((operation, token)) =
+                prefix.or_else(|| parse_increment(tokens, current_token))
             {
-                token.lines.1
-            } else {
+                let lines = (
+                    expression.lines.0.min(token.lines.0),
+                    expression.lines.1.max(token.lines.1),
+                );
+                let Some(increment) = desugar_increment(expression, operation, lines) else {
+                    errors.push(CompilerError {
+                        lines,
+                        error: diagnostic!(INVALID_INCREMENT_TARGET, token.token_type),
+                    });
+                    panic_forward(tokens, current_token);
+                    return None;
+                };
+                expression = increment;
+            }
+            let Some(semicolon_line) =
+                parse_statement_end(tokens, current_token, expression.lines.1, errors)
+            else {
                 errors.push(CompilerError {
                     lines: expression.lines,
-                    error: "Expected semicolon at the end of the statement.".into(),
+                    error: diagnostic!(EXPECTED_SEMICOLON),
                 });
                 panic_forward(tokens, current_token);
                 return None;
             };
-            *current_token += 1;
             Some(Statement {
                 lines: (expression.lines.0, semicolon_line),
                 statement: StatementType::Expression(expression),
@@ -687,11 +2282,192 @@ pub fn parse_statement(
     }
 }
 
+/// Splits an assignment target like p.x.0 or (*r).x into the indices of its fields, outermost first,
+/// and the expression whose fields they are.
+pub fn field_path(mut expression: &Expression) -> (Vec<usize>, &Expression) {
+    let mut indices = vec![];
+    loop {
+        match &expression.expression_type {
+            ExpressionType::TupleAccess {
+                expression: tuple,
+                field,
+            } => {
+                indices.push(field.index());
+                expression = tuple;
+            }
+            ExpressionType::Grouping(inner_expression) => expression = inner_expression,
+            _ => {
+                indices.reverse();
+                return (indices, expression);
+            }
+        }
+    }
+}
+
+/// The statements of the block expressions in the expression, for passes which visit every statement.
+/// Block expressions inside those statements aren't included.
+pub fn block_statements(expression: &Expression) -> Vec<&Statement> {
+    let mut statements = vec![];
+    let mut expressions = vec![expression];
+    while let Some(expression) = expressions.pop() {
+        match &expression.expression_type {
+            ExpressionType::Block {
+                statements: block_statements,
+                value,
+            } => {
+                statements.extend(block_statements);
+                expressions.push(value);
+            }
+            ExpressionType::Loop(body) => statements.push(&**body),
+            ExpressionType::If {
+                condition,
+                then_expression,
+                else_expression,
+            } => {
+                expressions.push(condition);
+                expressions.push(then_expression);
+                expressions.push(else_expression);
+            }
+            ExpressionType::Unary { expression, .. }
+            | ExpressionType::Grouping(expression)
+            | ExpressionType::TupleAccess { expression, .. }
+            | ExpressionType::Ok(expression)
+            | ExpressionType::Err(expression)
+            | ExpressionType::Propagate(expression)
+            | ExpressionType::Some(expression) => expressions.push(expression),
+            ExpressionType::Binary {
+                left_expression,
+                right_expression,
+                ..
+            } => {
+                expressions.push(left_expression);
+                expressions.push(right_expression);
+            }
+            ExpressionType::Tuple(values) | ExpressionType::Interpolation { values, .. } => {
+                expressions.extend(values)
+            }
+            ExpressionType::Record(fields) => {
+                expressions.extend(fields.iter().map(|(_, expression)| expression))
+            }
+            ExpressionType::FunctionCall {
+                function,
+                arguments,
+            } => {
+                expressions.push(function);
+                expressions.extend(arguments);
+            }
+            ExpressionType::Literal(_) | ExpressionType::Variable { .. } => {}
+        }
+    }
+    statements
+}
+
+/// Reads a ++ or -- token, returning the operation it applies and the token.
+fn parse_increment<'a>(
+    tokens: &'a [Token],
+    current_token: &mut usize,
+) -> Option<(BinaryOperation, &'a Token)> {
+    let token = tokens.get(*current_token)?;
+    let operation = match token.token_type {
+        TokenType::PlusPlus => BinaryOperation::Add,
+        TokenType::MinusMinus => BinaryOperation::Subtract,
+        _ => return None,
+    };
+    *current_token += 1;
+    Some((operation, token))
+}
+
+/// Turns x++ and x-- (or ++x and --x) into x = x + 1 and x = x - 1, if x is a variable, tuple field or dereferenced reference.
+/// They're only statements, so it doesn't matter whether they come before or after.
+// TODO: Allow increments in expressions, evaluating to the old value after and the new value before.
+fn desugar_increment(
+    target: Expression,
+    operation: BinaryOperation,
+    lines: (usize, usize),
+) -> Option<Expression> {
+    let mut field = &target;
+    while let ExpressionType::TupleAccess { expression, .. }
+    | ExpressionType::Grouping(expression) = &field.expression_type
+    {
+        field = expression;
+    }
+    if !matches!(
+        field.expression_type,
+        ExpressionType::Variable { .. }
+            | ExpressionType::Unary {
+                operation: UnaryOperation::Dereference,
+                ..
+            }
+    ) {
+        return None;
+    }
+
+    let one = Expression {
+        expression_type: ExpressionType::Literal(Value::Number(1.0)),
+        lines,
+    };
+    Some(Expression {
+        expression_type: ExpressionType::Binary {
+            operation: BinaryOperation::Assignment,
+            left_expression: target.clone().into(),
+            right_expression: Expression {
+                expression_type: ExpressionType::Binary {
+                    operation,
+                    left_expression: target.into(),
+                    right_expression: one.into(),
+                },
+                lines,
+            }
+            .into(),
+        },
+        lines,
+    })
+}
+
+/// Reads the doc comments before a statement, which have to be followed by a declaration.
+/// Returns None if they aren't, or Some(None) if there aren't any.
+fn parse_doc_comments(
+    tokens: &[Token],
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Option<String>> {
+    let mut doc_lines = vec![];
+    let mut lines = (0, 0);
+    while let Some((TokenType::DocComment(text), token)) = tokens
+        .get(*current_token)
+        .map(|token| (&token.token_type, token))
+    {
+        if doc_lines.is_empty() {
+            lines.0 = token.lines.0;
+        }
+        lines.1 = token.lines.1;
+        doc_lines.push(text.to_string());
+        *current_token += 1;
+    }
+    if doc_lines.is_empty() {
+        return Some(None);
+    }
+
+    match tokens.get(*current_token).map(|token| &token.token_type) {
+        Some(TokenType::Let | TokenType::Static | TokenType::Fn) => {
+            Some(Some(doc_lines.join("\n")))
+        }
+        _ => {
+            errors.push(CompilerError {
+                lines,
+                error: diagnostic!(DOC_COMMENT_WITHOUT_DECLARATION),
+            });
+            None
+        }
+    }
+}
+
 fn parse_type(
     tokens: &Vec<Token>,
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Type> {
+    let _nesting = Nesting::enter(tokens, current_token, errors)?;
     match tokens
         .get(*current_token)
         .map(|token| (&token.token_type, token))
@@ -708,12 +2484,45 @@ fn parse_type(
             *current_token += 1;
             Some(Type::Boolean)
         }
+        Some((TokenType::Ampersand, _)) => {
+            *current_token += 1;
+            let value_type = parse_type(tokens, current_token, errors)?;
+            Some(Type::Reference(value_type.into()))
+        }
+        // A reference to a reference, as && is read as one token.
+        Some((TokenType::DoubleAnd, _)) => {
+            *current_token += 1;
+            let value_type = parse_type(tokens, current_token, errors)?;
+            Some(Type::Reference(Type::Reference(value_type.into()).into()))
+        }
         Some((TokenType::LeftParenthesis, token)) => {
             let start_line = token.lines.0;
             *current_token += 1;
 
             let mut types = vec![];
+            let mut names = vec![];
             loop {
+                // Records' types name their fields, like (x: number, y: number).
+                if let Some(
+                    [Token {
+                        token_type: TokenType::Variable(name),
+                        lines,
+                    }, Token {
+                        token_type: TokenType::Colon,
+                        ..
+                    }],
+                ) = tokens.get(*current_token..*current_token + 2)
+                {
+                    if names.len() != types.len() || names.contains(name) {
+                        errors.push(CompilerError {
+                            lines: (start_line, lines.1),
+                            error: diagnostic!(INVALID_TYPE),
+                        });
+                        return None;
+                    }
+                    names.push(*name);
+                    *current_token += 2;
+                }
                 let tuple_type = parse_type(tokens, current_token, errors)?;
                 types.push(tuple_type);
 
@@ -723,10 +2532,20 @@ fn parse_type(
                 {
                     Some((TokenType::RightParenthesis, token)) => {
                         *current_token += 1;
+                        if !names.is_empty() {
+                            if names.len() != types.len() {
+                                errors.push(CompilerError {
+                                    lines: (start_line, token.lines.1),
+                                    error: diagnostic!(PARTLY_NAMED_TUPLE_TYPE),
+                                });
+                                return None;
+                            }
+                            return Some(Type::Record(names.into_iter().zip(types).collect()));
+                        }
                         if types.len() == 1 {
                             errors.push(CompilerError {
                                 lines: (start_line, token.lines.1),
-                                error: "Tuple type must have at least two elements.".into(),
+                                error: diagnostic!(TUPLE_TYPE_TOO_SHORT),
                             });
                             return None;
                         }
@@ -738,13 +2557,114 @@ fn parse_type(
                     _ => {
                         errors.push(CompilerError {
                             lines: (start_line, token.lines.1),
-                            error: "Invalid type.".into(),
+                            error: diagnostic!(INVALID_TYPE),
                         });
                         return None;
                     }
                 }
             }
         }
+        // The type of Ok(value) and Err(error), like result(number, string).
+        Some((TokenType::Result, token)) => {
+            *current_token += 1;
+            let mut types = vec![];
+            for expected in [TokenType::LeftParenthesis, TokenType::Comma] {
+                if tokens.get(*current_token).map(|token| &token.token_type) != Some(&expected) {
+                    errors.push(CompilerError {
+                        lines: token.lines,
+                        error: diagnostic!(EXPECTED_RESULT_TYPE),
+                    });
+                    return None;
+                }
+                *current_token += 1;
+                types.push(parse_type(tokens, current_token, errors)?);
+            }
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::RightParenthesis)
+            {
+                errors.push(CompilerError {
+                    lines: token.lines,
+                    error: diagnostic!(EXPECTED_RESULT_TYPE),
+                });
+                return None;
+            }
+            *current_token += 1;
+            let error = types.pop().unwrap();
+            let value = types.pop().unwrap();
+            Some(Type::Result {
+                value: value.into(),
+                error: error.into(),
+            })
+        }
+        // The type of Some(value) and None, like option(number).
+        Some((TokenType::Option, token)) => {
+            *current_token += 1;
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::LeftParenthesis)
+            {
+                errors.push(CompilerError {
+                    lines: token.lines,
+                    error: diagnostic!(EXPECTED_OPTION_TYPE),
+                });
+                return None;
+            }
+            *current_token += 1;
+            let value = parse_type(tokens, current_token, errors)?;
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::RightParenthesis)
+            {
+                errors.push(CompilerError {
+                    lines: token.lines,
+                    error: diagnostic!(EXPECTED_OPTION_TYPE),
+                });
+                return None;
+            }
+            *current_token += 1;
+            Some(Type::Option(value.into()))
+        }
+        // The types of host iterators, channels and tasks, like iterator(string), channel(number) and task(bool).
+        // iterator, channel and task aren't keywords, so they can still name variables.
+        Some((TokenType::Variable(name), token))
+            if ["iterator", "channel", "task"].contains(&name.as_str())
+                && tokens
+                    .get(*current_token + 1)
+                    .map(|token| &token.token_type)
+                    == Some(&TokenType::LeftParenthesis) =>
+        {
+            *current_token += 2;
+            let value_type = parse_type(tokens, current_token, errors)?;
+            // Tasks with a mailbox also have a message type, like task(number, string).
+            let mut message_type = None;
+            if name.as_str() == "task"
+                && tokens.get(*current_token).map(|token| &token.token_type)
+                    == Some(&TokenType::Comma)
+            {
+                *current_token += 1;
+                message_type = Some(parse_type(tokens, current_token, errors)?);
+            }
+            if tokens.get(*current_token).map(|token| &token.token_type)
+                != Some(&TokenType::RightParenthesis)
+            {
+                errors.push(CompilerError {
+                    lines: token.lines,
+                    error: match name.as_str() {
+                        "iterator" => diagnostic!(EXPECTED_ITERATOR_TYPE),
+                        "channel" => diagnostic!(EXPECTED_CHANNEL_TYPE),
+                        _ => diagnostic!(EXPECTED_TASK_TYPE),
+                    },
+                });
+                return None;
+            }
+            *current_token += 1;
+            Some(match name.as_str() {
+                "iterator" => Type::Iterator(value_type.into()),
+                "channel" => Type::Channel(value_type.into()),
+                _ => Type::Task {
+                    result: value_type.into(),
+                    message: message_type.map(Box::new),
+                },
+            })
+        }
         Some((TokenType::Fn, token)) => {
             let start_line = token.lines.0;
             *current_token += 1;
@@ -754,7 +2674,7 @@ fn parse_type(
             {
                 errors.push(CompilerError {
                     lines: token.lines,
-                    error: "Expected ( after fn".into(),
+                    error: diagnostic!(EXPECTED_FUNCTION_TYPE_PARAMETERS),
                 });
                 return None;
             }
@@ -781,7 +2701,7 @@ fn parse_type(
                         _ => {
                             errors.push(CompilerError {
                                 lines: (start_line, token.lines.1),
-                                error: "Invalid type.".into(),
+                                error: diagnostic!(INVALID_TYPE),
                             });
                             return None;
                         }
@@ -806,17 +2726,110 @@ fn parse_type(
         Some((_, token)) => {
             errors.push(CompilerError {
                 lines: (token.lines.1, token.lines.1),
-                error: "Invalid type.".into(),
+                error: diagnostic!(INVALID_TYPE),
+            });
+            None
+        }
+        _ => {
+            // TODO: Fix the lines shown.
+            errors.push(CompilerError {
+                lines: (1, 1),
+                error: diagnostic!(TYPE_ENDS_EARLY),
             });
             None
         }
-        _ => {
-            // TODO: Fix the lines shown.
-            errors.push(CompilerError {
-                lines: (1, 1),
-                error: "EOF while parsing type".into(),
-            });
-            None
+    }
+}
+
+/// Writes the type the same way as in a program.
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Void => write!(f, "void"),
+            Type::Any => write!(f, "any"),
+            Type::Number => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::Boolean => write!(f, "bool"),
+            Type::Tuple(types) => {
+                write!(f, "(")?;
+                let mut types = types.iter();
+                if let Some(first_type) = types.next() {
+                    write!(f, "{first_type}")?;
+                    for tuple_type in types {
+                        write!(f, ", {tuple_type}")?;
+                    }
+                }
+                write!(f, ")")
+            }
+            Type::Record(fields) => {
+                write!(f, "(")?;
+                for (index, (name, field_type)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{name}: {field_type}")?;
+                }
+                write!(f, ")")
+            }
+            Type::Reference(value_type) => write!(f, "&{value_type}"),
+            Type::Function {
+                parameters,
+                return_type,
+            } => {
+                write!(f, "fn(")?;
+                let mut parameters = parameters.iter();
+                if let Some(parameter) = parameters.next() {
+                    write!(f, "{parameter}")?;
+                    for parameter in parameters {
+                        write!(f, ", {parameter}")?;
+                    }
+                }
+                write!(f, ")")?;
+                if **return_type != Type::Void {
+                    write!(f, " -> {return_type}")?;
+                }
+                Ok(())
+            }
+            Type::Result { value, error } => write!(f, "result({value}, {error})"),
+            Type::Option(value) => write!(f, "option({value})"),
+            Type::Iterator(item) => write!(f, "iterator({item})"),
+            Type::Channel(message) => write!(f, "channel({message})"),
+            Type::Task {
+                result,
+                message: None,
+            } => write!(f, "task({result})"),
+            Type::Task {
+                result,
+                message: Some(message),
+            } => write!(f, "task({result}, {message})"),
+            Type::Never => write!(f, "never"),
+        }
+    }
+}
+
+impl FromStr for Type {
+    type Err = String;
+
+    /// Parses a type written the same way as in a program, for example `fn(number) -> string`.
+    fn from_str(source: &str) -> Result<Type, String> {
+        let tokens = tokenize(source).map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })?;
+
+        let mut current_token = 0;
+        let mut errors = vec![];
+        match parse_type(&tokens, &mut current_token, &mut errors) {
+            Some(parsed_type) if current_token == tokens.len() => Ok(parsed_type),
+            Some(_) => Err("Unexpected tokens after type.".into()),
+            None => Err(errors
+                .into_iter()
+                .map(|error| error.error.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")),
         }
     }
 }
@@ -830,6 +2843,7 @@ fn parse_expression(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
+    let _nesting = Nesting::enter(tokens, current_token, errors)?;
     parse_assignment(tokens, current_token, errors)
 }
 
@@ -838,18 +2852,15 @@ fn parse_assignment(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(left_expression) = parse_or(tokens, current_token, errors) else {
-        return None;
-    };
+    let left_expression = parse_or(tokens, current_token, errors)?;
 
     if tokens.get(*current_token).map(|token| &token.token_type) != Some(&TokenType::Equal) {
         return Some(left_expression);
     }
     *current_token += 1;
 
-    let Some(right_expression) = parse_assignment(tokens, current_token, errors) else {
-        return None;
-    };
+    let _nesting = Nesting::enter(tokens, current_token, errors)?;
+    let right_expression = parse_assignment(tokens, current_token, errors)?;
 
     Some(Expression {
         lines: (left_expression.lines.0, right_expression.lines.1),
@@ -866,10 +2877,9 @@ fn parse_or(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(mut expression) = parse_and(tokens, current_token, errors) else {
-        return None;
-    };
+    let mut expression = parse_and(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
         let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
             Some(TokenType::DoubleOr) => BinaryOperation::Or,
@@ -878,9 +2888,8 @@ fn parse_or(
 
         *current_token += 1;
 
-        let Some(right_expression) = parse_and(tokens, current_token, errors) else {
-            return None;
-        };
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_and(tokens, current_token, errors)?;
 
         expression = Expression {
             lines: (expression.lines.0, right_expression.lines.1),
@@ -898,10 +2907,9 @@ fn parse_and(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(mut expression) = parse_comparison(tokens, current_token, errors) else {
-        return None;
-    };
+    let mut expression = parse_comparison(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
         let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
             Some(TokenType::DoubleAnd) => BinaryOperation::And,
@@ -910,9 +2918,8 @@ fn parse_and(
 
         *current_token += 1;
 
-        let Some(right_expression) = parse_comparison(tokens, current_token, errors) else {
-            return None;
-        };
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_comparison(tokens, current_token, errors)?;
 
         expression = Expression {
             lines: (expression.lines.0, right_expression.lines.1),
@@ -930,10 +2937,9 @@ fn parse_comparison(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(mut expression) = parse_term(tokens, current_token, errors) else {
-        return None;
-    };
+    let mut expression = parse_bitwise_or(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
         let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
             Some(TokenType::DoubleEqual) => BinaryOperation::Equal,
@@ -947,10 +2953,131 @@ fn parse_comparison(
 
         *current_token += 1;
 
-        let Some(right_expression) = parse_term(tokens, current_token, errors) else {
-            return None;
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_bitwise_or(tokens, current_token, errors)?;
+
+        expression = Expression {
+            lines: (expression.lines.0, right_expression.lines.1),
+            expression_type: ExpressionType::Binary {
+                operation,
+                left_expression: expression.into(),
+                right_expression: right_expression.into(),
+            },
+        };
+    }
+}
+
+// Bitwise operators bind tighter than comparisons, so flags & MASK == 0 means (flags & MASK) == 0.
+fn parse_bitwise_or(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let mut expression = parse_bitwise_xor(tokens, current_token, errors)?;
+
+    let mut nesting = vec![];
+    loop {
+        let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
+            Some(TokenType::Pipe) => BinaryOperation::BitwiseOr,
+            _ => return Some(expression),
+        };
+
+        *current_token += 1;
+
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_bitwise_xor(tokens, current_token, errors)?;
+
+        expression = Expression {
+            lines: (expression.lines.0, right_expression.lines.1),
+            expression_type: ExpressionType::Binary {
+                operation,
+                left_expression: expression.into(),
+                right_expression: right_expression.into(),
+            },
+        };
+    }
+}
+
+fn parse_bitwise_xor(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let mut expression = parse_bitwise_and(tokens, current_token, errors)?;
+
+    let mut nesting = vec![];
+    loop {
+        let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
+            Some(TokenType::Caret) => BinaryOperation::BitwiseXor,
+            _ => return Some(expression),
+        };
+
+        *current_token += 1;
+
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_bitwise_and(tokens, current_token, errors)?;
+
+        expression = Expression {
+            lines: (expression.lines.0, right_expression.lines.1),
+            expression_type: ExpressionType::Binary {
+                operation,
+                left_expression: expression.into(),
+                right_expression: right_expression.into(),
+            },
+        };
+    }
+}
+
+fn parse_bitwise_and(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let mut expression = parse_shift(tokens, current_token, errors)?;
+
+    let mut nesting = vec![];
+    loop {
+        let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
+            Some(TokenType::Ampersand) => BinaryOperation::BitwiseAnd,
+            _ => return Some(expression),
+        };
+
+        *current_token += 1;
+
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_shift(tokens, current_token, errors)?;
+
+        expression = Expression {
+            lines: (expression.lines.0, right_expression.lines.1),
+            expression_type: ExpressionType::Binary {
+                operation,
+                left_expression: expression.into(),
+                right_expression: right_expression.into(),
+            },
+        };
+    }
+}
+
+fn parse_shift(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let mut expression = parse_term(tokens, current_token, errors)?;
+
+    let mut nesting = vec![];
+    loop {
+        let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
+            Some(TokenType::DoubleLess) => BinaryOperation::ShiftLeft,
+            Some(TokenType::DoubleGreater) => BinaryOperation::ShiftRight,
+            _ => return Some(expression),
         };
 
+        *current_token += 1;
+
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_term(tokens, current_token, errors)?;
+
         expression = Expression {
             lines: (expression.lines.0, right_expression.lines.1),
             expression_type: ExpressionType::Binary {
@@ -967,10 +3094,9 @@ fn parse_term(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(mut expression) = parse_factor(tokens, current_token, errors) else {
-        return None;
-    };
+    let mut expression = parse_factor(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
         let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
             Some(TokenType::Plus) => BinaryOperation::Add,
@@ -980,9 +3106,8 @@ fn parse_term(
 
         *current_token += 1;
 
-        let Some(right_expression) = parse_factor(tokens, current_token, errors) else {
-            return None;
-        };
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_factor(tokens, current_token, errors)?;
 
         expression = Expression {
             lines: (expression.lines.0, right_expression.lines.1),
@@ -1000,10 +3125,9 @@ fn parse_factor(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(mut expression) = parse_unary(tokens, current_token, errors) else {
-        return None;
-    };
+    let mut expression = parse_unary(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
         let operation = match tokens.get(*current_token).map(|token| &token.token_type) {
             Some(TokenType::Star) => BinaryOperation::Multiply,
@@ -1013,9 +3137,8 @@ fn parse_factor(
 
         *current_token += 1;
 
-        let Some(right_expression) = parse_unary(tokens, current_token, errors) else {
-            return None;
-        };
+        nesting.push(Nesting::enter_operator(tokens, current_token, errors)?);
+        let right_expression = parse_unary(tokens, current_token, errors)?;
 
         expression = Expression {
             lines: (expression.lines.0, right_expression.lines.1),
@@ -1037,14 +3160,16 @@ fn parse_unary(
     let (operation, start_line) = match operation_token.map(|token| (&token.token_type, token)) {
         Some((TokenType::Minus, token)) => (UnaryOperation::Minus, token.lines.0),
         Some((TokenType::Exclamation, token)) => (UnaryOperation::Not, token.lines.0),
+        Some((TokenType::Tilde, token)) => (UnaryOperation::BitwiseNot, token.lines.0),
+        Some((TokenType::Ampersand, token)) => (UnaryOperation::Reference, token.lines.0),
+        Some((TokenType::Star, token)) => (UnaryOperation::Dereference, token.lines.0),
         _ => return parse_tuple_access(tokens, current_token, errors),
     };
 
     *current_token += 1;
 
-    let Some(expression) = parse_unary(tokens, current_token, errors) else {
-        return None;
-    };
+    let _nesting = Nesting::enter(tokens, current_token, errors)?;
+    let expression = parse_unary(tokens, current_token, errors)?;
 
     Some(Expression {
         lines: (start_line, expression.lines.1),
@@ -1060,11 +3185,23 @@ fn parse_tuple_access(
     current_token: &mut usize,
     errors: &mut Vec<CompilerError>,
 ) -> Option<Expression> {
-    let Some(mut expression) = parse_function_call(tokens, current_token, errors) else {
-        return None;
-    };
+    let mut expression = parse_function_call(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
+        if let Some((&TokenType::Question, token)) = tokens
+            .get(*current_token)
+            .map(|token| (&token.token_type, token))
+        {
+            nesting.push(Nesting::enter(tokens, current_token, errors)?);
+            *current_token += 1;
+            expression = Expression {
+                lines: (expression.lines.0, token.lines.1),
+                expression_type: ExpressionType::Propagate(expression.into()),
+            };
+            continue;
+        }
+
         let dot_line = if let Some((&TokenType::Dot, token)) = tokens
             .get(*current_token)
             .map(|token| (&token.token_type, token))
@@ -1074,31 +3211,36 @@ fn parse_tuple_access(
             return Some(expression);
         };
 
+        nesting.push(Nesting::enter(tokens, current_token, errors)?);
         *current_token += 1;
 
         *current_token += 1;
-        let (index, end_line) = if let Some(token) = tokens.get(*current_token - 1) {
+        let (field, end_line) = if let Some(token) = tokens.get(*current_token - 1) {
             if let TokenType::Literal(Value::Number(number)) = token.token_type {
                 if number.floor() != number {
                     errors.push(CompilerError {
                         lines: (expression.lines.0, token.lines.1),
-                        error: "Expected nonnegative integer index.".into(),
+                        error: diagnostic!(EXPECTED_INDEX),
                     });
                     return None;
                 }
 
-                (number.floor() as usize, token.lines.1) // TODO: Consider overflow
+                (Field::Index(number.floor() as usize), token.lines.1) // TODO: Consider overflow
+            } else if let TokenType::Variable(name) = token.token_type {
+                (Field::Name { name, index: None }, token.lines.1)
             } else {
                 errors.push(CompilerError {
                     lines: (expression.lines.0, token.lines.1),
-                    error: "Expected index after dot.".into(),
+                    error: diagnostic!(EXPECTED_FIELD),
                 });
                 return None;
             }
         } else {
+            // There's no token after the dot, so don't move past the end of the tokens.
+            *current_token -= 1;
             errors.push(CompilerError {
                 lines: (expression.lines.0, dot_line),
-                error: "Expected index after dot.".into(),
+                error: diagnostic!(EXPECTED_FIELD),
             });
             return None;
         };
@@ -1107,12 +3249,142 @@ fn parse_tuple_access(
             lines: (expression.lines.0, end_line),
             expression_type: ExpressionType::TupleAccess {
                 expression: expression.into(),
-                index,
+                field,
             },
         };
     }
 }
 
+/// Parses the fields of a record after its (, like x: 1, y: 2).
+fn parse_record(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+    start_line: usize,
+) -> Option<Expression> {
+    let mut fields: Vec<(Symbol, Expression)> = vec![];
+    loop {
+        let Some(
+            [Token {
+                token_type: TokenType::Variable(name),
+                lines,
+            }, Token {
+                token_type: TokenType::Colon,
+                ..
+            }],
+        ) = tokens.get(*current_token..*current_token + 2)
+        else {
+            errors.push(CompilerError {
+                lines: (
+                    start_line,
+                    tokens[(*current_token).min(tokens.len() - 1)].lines.1,
+                ),
+                error: diagnostic!(EXPECTED_RECORD_FIELD),
+            });
+            return None;
+        };
+        if fields.iter().any(|(field, _)| field == name) {
+            errors.push(CompilerError {
+                lines: *lines,
+                error: diagnostic!(REPEATED_RECORD_FIELD, name),
+            });
+            return None;
+        }
+        *current_token += 2;
+
+        let expression = parse_expression(tokens, current_token, errors)?;
+        fields.push((*name, expression));
+
+        match tokens.get(*current_token) {
+            Some(Token {
+                token_type: TokenType::RightParenthesis,
+                lines,
+            }) => {
+                *current_token += 1;
+                return Some(Expression {
+                    expression_type: ExpressionType::Record(fields),
+                    lines: (start_line, lines.1),
+                });
+            }
+            Some(Token {
+                token_type: TokenType::Comma,
+                ..
+            }) => {
+                *current_token += 1;
+            }
+            _ => {
+                errors.push(CompilerError {
+                    lines: (start_line, fields.last().unwrap().1.lines.1),
+                    error: diagnostic!(EXPECTED_RECORD_SEPARATOR),
+                });
+                return None;
+            }
+        }
+    }
+}
+
+/// Parses Error(message) or Error(message, code: 4), which make records of the error type.
+/// The code is 0 if it's left out.
+fn parse_error(
+    tokens: &Vec<Token>,
+    current_token: &mut usize,
+    errors: &mut Vec<CompilerError>,
+) -> Option<Expression> {
+    let start_line = tokens[*current_token].lines.0;
+    *current_token += 2;
+    let message = parse_expression(tokens, current_token, errors)?;
+    let (mut fields, end_line) = match tokens.get(*current_token) {
+        Some(Token {
+            token_type: TokenType::RightParenthesis,
+            lines,
+        }) => {
+            *current_token += 1;
+            (vec![], lines.1)
+        }
+        Some(Token {
+            token_type: TokenType::Comma,
+            ..
+        }) => {
+            *current_token += 1;
+            let record = parse_record(tokens, current_token, errors, start_line)?;
+            let ExpressionType::Record(fields) = record.expression_type else {
+                unreachable!()
+            };
+            (fields, record.lines.1)
+        }
+        _ => {
+            errors.push(CompilerError {
+                lines: (start_line, message.lines.1),
+                error: diagnostic!(EXPECTED_ERROR_FIELDS),
+            });
+            return None;
+        }
+    };
+
+    let code = Symbol::intern("code");
+    if let Some((name, expression)) = fields.iter().find(|(name, _)| *name != code) {
+        errors.push(CompilerError {
+            lines: expression.lines,
+            error: diagnostic!(UNKNOWN_ERROR_FIELD, name),
+        });
+        return None;
+    }
+    let code_value = fields.pop().map_or_else(
+        || Expression {
+            expression_type: ExpressionType::Literal(Value::Number(0.0)),
+            lines: (end_line, end_line),
+        },
+        |(_, expression)| expression,
+    );
+    Some(Expression {
+        expression_type: ExpressionType::Record(vec![
+            (Symbol::intern("message"), message),
+            (code, code_value),
+        ]),
+        lines: (start_line, end_line),
+    })
+}
+
 fn parse_function_call(
     tokens: &Vec<Token>,
     current_token: &mut usize,
@@ -1120,13 +3392,18 @@ fn parse_function_call(
 ) -> Option<Expression> {
     let mut expression = parse_primary(tokens, current_token, errors)?;
 
+    let mut nesting = vec![];
     loop {
-        if tokens.get(*current_token).map(|token| &token.token_type)
-            != Some(&TokenType::LeftParenthesis)
-        {
-            return Some(expression);
+        // A ( on a later line starts the next statement, so leaving out the semicolon before a line like (a, b)
+        // doesn't call the previous line's value.
+        match tokens.get(*current_token) {
+            Some(token)
+                if token.token_type == TokenType::LeftParenthesis
+                    && token.lines.0 <= expression.lines.1 => {}
+            _ => return Some(expression),
         }
 
+        nesting.push(Nesting::enter(tokens, current_token, errors)?);
         let (end_line, arguments) = if let Some((&TokenType::RightParenthesis, token)) = tokens
             .get(*current_token + 1)
             .map(|token| (&token.token_type, token))
@@ -1140,6 +3417,13 @@ fn parse_function_call(
             match arguments.expression_type {
                 ExpressionType::Grouping(expression) => (arguments.lines.1, vec![*expression]),
                 ExpressionType::Tuple(expressions) => (arguments.lines.1, expressions),
+                ExpressionType::Record(_) => {
+                    errors.push(CompilerError {
+                        lines: arguments.lines,
+                        error: diagnostic!(NAMED_ARGUMENT),
+                    });
+                    return None;
+                }
                 _ => unreachable!("parse_primary can only give a Grouping or Tuple with a starting left parenthesis"),
             }
         };
@@ -1164,11 +3448,45 @@ fn parse_primary(
             // TODO: Fix the lines
             errors.push(CompilerError {
                 lines: (1, 1),
-                error: "No tokens available to parse (EOF)".into(),
+                error: diagnostic!(PROGRAM_ENDS_EARLY),
             });
             None
         }
         Some(token) => match &token.token_type {
+            TokenType::LeftBrace => parse_block_expression(tokens, current_token, errors),
+            TokenType::If => parse_if_expression(tokens, current_token, errors),
+            TokenType::Loop => parse_loop_expression(tokens, current_token, errors),
+            TokenType::Ok | TokenType::Err | TokenType::Some => {
+                let start_line = token.lines.0;
+                *current_token += 1;
+                let value = match tokens.get(*current_token).map(|token| &token.token_type) {
+                    Some(TokenType::LeftParenthesis) => {
+                        parse_primary(tokens, current_token, errors)?
+                    }
+                    _ => {
+                        errors.push(CompilerError {
+                            lines: token.lines,
+                            error: diagnostic!(EXPECTED_VALUE_PARENTHESIS, token.token_type),
+                        });
+                        return None;
+                    }
+                };
+                let ExpressionType::Grouping(inner_expression) = value.expression_type else {
+                    errors.push(CompilerError {
+                        lines: value.lines,
+                        error: diagnostic!(TAKES_ONE_VALUE, token.token_type),
+                    });
+                    return None;
+                };
+                Some(Expression {
+                    lines: (start_line, value.lines.1),
+                    expression_type: match token.token_type {
+                        TokenType::Ok => ExpressionType::Ok(inner_expression),
+                        TokenType::Err => ExpressionType::Err(inner_expression),
+                        _ => ExpressionType::Some(inner_expression),
+                    },
+                })
+            }
             TokenType::Literal(value) => {
                 *current_token += 1;
                 Some(Expression {
@@ -1176,13 +3494,69 @@ fn parse_primary(
                     lines: token.lines,
                 })
             }
+            TokenType::None => {
+                *current_token += 1;
+                Some(Expression {
+                    expression_type: ExpressionType::Literal(Value::Option(None)),
+                    lines: token.lines,
+                })
+            }
+            TokenType::InterpolatedString(string) => {
+                *current_token += 1;
+                let mut values = vec![];
+                let mut specs = vec![];
+                for (value_tokens, spec) in &string.values {
+                    if value_tokens.is_empty() {
+                        errors.push(CompilerError {
+                            lines: token.lines,
+                            error: diagnostic!(EXPECTED_INTERPOLATED_VALUE),
+                        });
+                        return None;
+                    }
+                    let mut value_token = 0;
+                    let value = parse_expression(value_tokens, &mut value_token, errors)?;
+                    if value_token < value_tokens.len() {
+                        errors.push(CompilerError {
+                            lines: value.lines,
+                            error: diagnostic!(EXPECTED_INTERPOLATION_END),
+                        });
+                        return None;
+                    }
+                    let spec = spec.parse().map_err(|error| {
+                        errors.push(CompilerError {
+                            lines: value.lines,
+                            error,
+                        })
+                    });
+                    values.push(value);
+                    specs.push(spec.ok()?);
+                }
+                Some(Expression {
+                    expression_type: ExpressionType::Interpolation {
+                        strings: string.strings.clone(),
+                        values,
+                        specs,
+                    },
+                    lines: token.lines,
+                })
+            }
+            TokenType::Variable(variable)
+                if variable.as_str() == "Error"
+                    && tokens
+                        .get(*current_token + 1)
+                        .map(|token| &token.token_type)
+                        == Some(&TokenType::LeftParenthesis) =>
+            {
+                parse_error(tokens, current_token, errors)
+            }
             TokenType::Variable(variable) => {
                 *current_token += 1;
                 Some(Expression {
                     expression_type: ExpressionType::Variable {
-                        name: variable.clone(),
+                        name: *variable,
                         shadow_id: None, // Shadow id's will be set during variable and type checking.
                         parent_height: None,
+                        slot: None,
                     },
                     lines: token.lines,
                 })
@@ -1191,7 +3565,18 @@ fn parse_primary(
                 let start_line = token.lines.0;
                 *current_token += 1;
 
-                let Some(expression) = parse_expression(tokens, current_token, errors) else { return None };
+                if let [Token {
+                    token_type: TokenType::Variable(_),
+                    ..
+                }, Token {
+                    token_type: TokenType::Colon,
+                    ..
+                }, ..] = &tokens[*current_token..]
+                {
+                    return parse_record(tokens, current_token, errors, start_line);
+                }
+
+                let expression = parse_expression(tokens, current_token, errors)?;
 
                 if let Some(token) = tokens.get(*current_token) {
                     if token.token_type == TokenType::RightParenthesis {
@@ -1213,13 +3598,13 @@ fn parse_primary(
                                 expressions.first().unwrap().lines.0,
                                 expressions.last().unwrap().lines.1,
                             ),
-                            error: "Expected , in tuple.".into(),
+                            error: diagnostic!(EXPECTED_TUPLE_COMMA),
                         });
                         return None;
                     }
                     *current_token += 1;
 
-                    let Some(expression) = parse_expression(tokens, current_token, errors) else { return None };
+                    let expression = parse_expression(tokens, current_token, errors)?;
                     let expression_end = expression.lines.1;
                     expressions.push(expression);
 
@@ -1237,9 +3622,9 @@ fn parse_primary(
             _ => {
                 errors.push(CompilerError {
                     lines: token.lines,
-                    error: format!(
-                        "Expected primary expression, got {:?} instead.",
-                        token.token_type
+                    error: diagnostic!(
+                        EXPECTED_PRIMARY_EXPRESSION,
+                        format!("{:?}", token.token_type)
                     ),
                 });
                 None
@@ -0,0 +1,265 @@
+use std::rc::Rc;
+
+use crate::{
+    compiler::{Chunk, Comparison, Function, Instruction},
+    tokenizer::Value,
+};
+
+/// Rewrites short instruction sequences of a compiled function and the functions inside it,
+/// so the VM executes fewer instructions:
+/// jumps to jumps go straight to the final target, values which are pushed and immediately popped
+/// aren't pushed, comparisons followed by a conditional jump become a single instruction
+/// and conditional jumps on a constant become unconditional.
+pub fn optimize_peephole(function: &mut Rc<Function>) {
+    // Functions aren't shared until they run.
+    let function = Rc::get_mut(function).unwrap();
+    for function in &mut function.chunk.functions {
+        optimize_peephole(function);
+    }
+
+    let chunk = &mut function.chunk;
+    thread_jumps(chunk);
+    while combine_instructions(chunk) {
+        thread_jumps(chunk);
+    }
+}
+
+fn jump_target(instruction: &mut Instruction) -> Option<&mut usize> {
+    match instruction {
+        Instruction::Jump(target)
+        | Instruction::JumpIfFalse(target)
+        | Instruction::JumpIfFalseOrPop(target)
+        | Instruction::JumpIfTrueOrPop(target)
+        | Instruction::Next(target)
+        | Instruction::JumpIfOk(target)
+        | Instruction::Match { target, .. }
+        | Instruction::Try(target)
+        | Instruction::JumpUnless { target, .. }
+        | Instruction::JumpIfInitialized { target, .. } => Some(target),
+        _ => None,
+    }
+}
+
+/// Makes jumps which land on an unconditional jump go to its target instead.
+fn thread_jumps(chunk: &mut Chunk) {
+    for index in 0..chunk.instructions.len() {
+        // Following at most as many jumps as there are instructions stops us from looping forever on cycles of jumps.
+        for _ in 0..chunk.instructions.len() {
+            let mut instruction = chunk.instructions[index];
+            let Some(target) = jump_target(&mut instruction) else {
+                break;
+            };
+            match chunk.instructions[*target] {
+                Instruction::Jump(next_target) if next_target != *target => *target = next_target,
+                // The value is false when JumpIfFalse is reached this way, so it pops it and jumps.
+                // When the value is true, both instructions pop it and continue.
+                Instruction::JumpIfFalse(next_target)
+                    if matches!(instruction, Instruction::JumpIfFalseOrPop(_)) =>
+                {
+                    instruction = Instruction::JumpIfFalse(next_target)
+                }
+                _ => break,
+            }
+            chunk.instructions[index] = instruction;
+        }
+    }
+}
+
+/// Replaces pairs of instructions with at most one instruction.
+/// Returns whether any instructions were replaced.
+fn combine_instructions(chunk: &mut Chunk) -> bool {
+    let mut is_jump_target = vec![false; chunk.instructions.len() + 1];
+    for instruction in chunk.instructions.iter_mut() {
+        if let Some(target) = jump_target(instruction) {
+            is_jump_target[*target] = true;
+        }
+    }
+
+    let mut removed = vec![false; chunk.instructions.len()];
+    let mut index = 0;
+    while index + 1 < chunk.instructions.len() {
+        // The second instruction can't be removed if something jumps to it.
+        if is_jump_target[index + 1] {
+            index += 1;
+            continue;
+        }
+
+        let replacement = match (chunk.instructions[index], chunk.instructions[index + 1]) {
+            (Instruction::SetGlobal(slot), Instruction::Pop) => {
+                Some(Instruction::StoreGlobal(slot))
+            }
+            (Instruction::SetLocal { depth, slot }, Instruction::Pop) => {
+                Some(Instruction::StoreLocal { depth, slot })
+            }
+            (
+                Instruction::Constant(_) | Instruction::GetGlobal(_) | Instruction::GetLocal { .. },
+                Instruction::Pop,
+            ) => None,
+            (Instruction::Constant(constant), Instruction::JumpIfFalse(target)) => {
+                match chunk.constants[constant] {
+                    Value::Boolean(true) => None,
+                    Value::Boolean(false) => Some(Instruction::Jump(target)),
+                    _ => unreachable!(),
+                }
+            }
+            (instruction, Instruction::JumpIfFalse(target)) => {
+                match Comparison::from_instruction(instruction) {
+                    Some(comparison) => Some(Instruction::JumpUnless { comparison, target }),
+                    None => {
+                        index += 1;
+                        continue;
+                    }
+                }
+            }
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+
+        match replacement {
+            Some(instruction) => chunk.instructions[index] = instruction,
+            None => removed[index] = true,
+        }
+        removed[index + 1] = true;
+        index += 2;
+    }
+
+    if !removed.contains(&true) {
+        return false;
+    }
+
+    // New index of each instruction. Removed instructions map to the next instruction which is kept.
+    let mut new_indices = Vec::with_capacity(chunk.instructions.len() + 1);
+    let mut kept = 0;
+    for is_removed in &removed {
+        new_indices.push(kept);
+        if !is_removed {
+            kept += 1;
+        }
+    }
+    new_indices.push(kept);
+
+    let mut instructions = Vec::with_capacity(kept);
+    let mut lines = Vec::with_capacity(kept);
+    for (index, mut instruction) in chunk.instructions.iter().copied().enumerate() {
+        if removed[index] {
+            continue;
+        }
+        if let Some(target) = jump_target(&mut instruction) {
+            *target = new_indices[*target];
+        }
+        instructions.push(instruction);
+        lines.push(chunk.lines[index]);
+    }
+    chunk.instructions = instructions;
+    chunk.lines = lines;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::{jump_target, optimize_peephole};
+    use crate::{
+        compiler::{Comparison, Instruction},
+        Backend, Interpreter, OptLevel,
+    };
+
+    /// Compiles the program without optimizing it, and returns its instructions before and after the peephole pass.
+    fn optimize(program: &str) -> (Vec<Instruction>, Vec<Instruction>) {
+        let mut interpreter = Interpreter::with_backend(Backend::Vm);
+        interpreter.set_opt_level(OptLevel::None);
+        let mut function = interpreter.compile(program).unwrap();
+        let before = function.chunk.instructions.clone();
+        optimize_peephole(&mut function);
+        (before, function.chunk.instructions.clone())
+    }
+
+    #[test]
+    fn stores_assignments_whose_value_is_unused() {
+        let (before, after) = optimize("let a = 1;\na = 2;");
+        assert!(before.contains(&Instruction::Pop));
+        assert!(!after.contains(&Instruction::Pop));
+        assert!(after
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::StoreGlobal(_))));
+    }
+
+    #[test]
+    fn jumps_on_comparisons_in_one_instruction() {
+        let (_, after) = optimize("let a = 0;\nwhile a < 3 { a = a + 1; }");
+        assert!(after.iter().any(|instruction| matches!(
+            instruction,
+            Instruction::JumpUnless {
+                comparison: Comparison::Less,
+                ..
+            }
+        )));
+        assert!(!after.contains(&Instruction::Less));
+    }
+
+    #[test]
+    fn removes_jumps_on_constants() {
+        let (before, after) = optimize("if true { print(1); }");
+        assert!(before
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::JumpIfFalse(_))));
+        assert!(!after.iter().any(|instruction| matches!(
+            instruction,
+            Instruction::JumpIfFalse(_) | Instruction::Jump(_)
+        )));
+
+        let (_, after) = optimize("if false { print(1); } else { print(2); }");
+        assert!(!after
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::JumpIfFalse(_))));
+    }
+
+    #[test]
+    fn threads_jumps_to_jumps() {
+        let (_, after) =
+            optimize("let a = 0;\nwhile a < 5 { if a == 1 { a = a + 1; } else { a = a + 2; } }");
+        for mut instruction in after.iter().copied() {
+            if let Some(target) = jump_target(&mut instruction) {
+                assert!(
+                    !matches!(after.get(*target), Some(Instruction::Jump(_))),
+                    "{after:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_what_programs_print() {
+        let program = "let a = 0;
+let total = 0;
+while a < 10 {
+    if a == 3 || a == 5 {
+    } else {
+        if true { total = total + a; }
+    }
+    let b = a;
+    b;
+    a = a + 1;
+}
+print(total);
+let i = 0;
+while i < 3 {
+    if i != 1 && i >= 0 { print(i); } else { print(\"skipped\"); }
+    i = i + 1;
+}";
+        let run = |opt_level| {
+            let output = Rc::new(RefCell::new(String::new()));
+            let mut interpreter = Interpreter::with_backend(Backend::Vm);
+            interpreter.set_opt_level(opt_level);
+            let sink = output.clone();
+            interpreter.set_output(move |text| sink.borrow_mut().push_str(text));
+            interpreter.eval(program).unwrap();
+            output.take()
+        };
+        assert_eq!(run(OptLevel::Full), run(OptLevel::None));
+        assert_eq!(run(OptLevel::Full), "37\n0\nskipped\n2\n");
+    }
+}
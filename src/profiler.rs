@@ -0,0 +1,87 @@
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    fmt::{Display, Write},
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SpanKind {
+    Statement,
+    Function,
+}
+
+impl Display for SpanKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SpanKind::Statement => write!(f, "statement"),
+            SpanKind::Function => write!(f, "function"),
+        }
+    }
+}
+
+/// How often a statement or function ran and how long it took in total.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpanProfile {
+    pub hits: usize,
+    pub time: Duration,
+}
+
+/// Records the time spent in and hit counts of each statement and function, by their lines.
+/// Times include the statements and calls nested inside, so they add up to more than the running time.
+/// A span which runs inside itself, like a recursive function, is timed from its outermost run, so the time of its
+/// inner runs isn't counted again.
+#[derive(Debug, Default)]
+pub struct Profiler {
+    pub spans: HashMap<(SpanKind, (usize, usize)), SpanProfile>,
+    running: HashMap<(SpanKind, (usize, usize)), usize>, // How many runs of each span haven't exited yet.
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    /// Counts a hit of the span and returns when it started.
+    pub fn enter(&mut self, kind: SpanKind, lines: (usize, usize)) -> Instant {
+        self.spans.entry((kind, lines)).or_default().hits += 1;
+        *self.running.entry((kind, lines)).or_default() += 1;
+        Instant::now()
+    }
+
+    /// Adds the time since the span started, if it's the outermost run of the span.
+    pub fn exit(&mut self, kind: SpanKind, lines: (usize, usize), start: Instant) {
+        let running = self.running.entry((kind, lines)).or_default();
+        *running -= 1;
+        if *running == 0 {
+            self.spans.entry((kind, lines)).or_default().time += start.elapsed();
+        }
+    }
+
+    /// Formats the spans from slowest to fastest, showing the first line of each span.
+    /// Spans recorded while running other programs may show the wrong line.
+    pub fn report(&self, program: &str) -> String {
+        let mut spans = self.spans.iter().collect::<Vec<_>>();
+        spans.sort_by_key(|(_, profile)| Reverse(profile.time));
+
+        let lines = program.lines().collect::<Vec<_>>();
+        let mut report = String::new();
+        writeln!(report, "{:>12} {:>10}  span", "time (ms)", "hits").unwrap();
+        for ((kind, (start_line, end_line)), profile) in spans {
+            let span = if start_line == end_line {
+                format!("{kind} at line {start_line}")
+            } else {
+                format!("{kind} at lines {start_line}-{end_line}")
+            };
+            let line = lines.get(start_line - 1).map_or("", |line| line.trim());
+            writeln!(
+                report,
+                "{:>12.3} {:>10}  {span}: {line}",
+                profile.time.as_secs_f64() * 1000.0,
+                profile.hits
+            )
+            .unwrap();
+        }
+        report
+    }
+}
@@ -0,0 +1,51 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+};
+
+use crate::{parser, parser::Statement, tokenizer, EvalError};
+
+/// Parsed programs by the hash of their source, so a program which is evaluated repeatedly is only parsed once.
+/// The statements are checked again on every eval, since the globals they refer to may have changed.
+#[derive(Debug, Default)]
+pub struct ProgramCache {
+    programs: HashMap<u64, (String, Vec<Statement>)>, // The source is kept to rule out hash collisions.
+}
+
+impl ProgramCache {
+    pub fn new() -> ProgramCache {
+        ProgramCache::default()
+    }
+
+    /// Returns the statements of the program, parsing it if it isn't cached.
+    /// Programs with errors aren't cached.
+    pub fn get_or_parse(&mut self, program: &str) -> Result<&Vec<Statement>, EvalError> {
+        let mut hasher = DefaultHasher::new();
+        program.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        if self
+            .programs
+            .get(&hash)
+            .is_none_or(|(source, _)| source != program)
+        {
+            let tokens = tokenizer::tokenize(program).map_err(EvalError::Tokenizer)?;
+            let statements = parser::parse(&tokens).map_err(EvalError::Compiler)?;
+            self.programs
+                .insert(hash, (program.to_string(), statements));
+        }
+        Ok(&self.programs[&hash].1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.programs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.programs.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.programs.clear();
+    }
+}
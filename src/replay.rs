@@ -0,0 +1,86 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
+
+use crate::messages::{diagnostic, Diagnostic};
+
+/// The results of builtins which can differ between runs, like clock, in the order they were called.
+/// A run which replays the log gets the same results, so it behaves like the run which recorded it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplayLog {
+    results: Vec<(String, String)>, // The builtin's name and its result, as text.
+}
+
+/// Whether builtins which can differ between runs record their results, replay them from a log, or neither.
+#[derive(Debug, Default)]
+pub enum Replay {
+    #[default]
+    Off,
+    Recording(ReplayLog),
+    Replaying(ReplayLog, usize), // The log, and how many of its results have been replayed.
+}
+
+impl ReplayLog {
+    pub fn new() -> ReplayLog {
+        ReplayLog::default()
+    }
+
+    /// Reads a log written with to_string, which has a line for each result with the builtin's name and the result.
+    pub fn parse(log: &str) -> Result<ReplayLog, String> {
+        let results = log
+            .lines()
+            .enumerate()
+            .map(|(index, line)| {
+                let (builtin, result) = line.split_once(' ').ok_or_else(|| {
+                    format!(
+                        "Line {} of the replay log isn't a builtin's name and result.",
+                        index + 1
+                    )
+                })?;
+                Ok((builtin.to_string(), result.to_string()))
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(ReplayLog { results })
+    }
+}
+
+impl Display for ReplayLog {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (builtin, result) in &self.results {
+            writeln!(f, "{builtin} {result}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Replay {
+    /// The builtin's result: the result of live, which is added to the log when recording,
+    /// or the log's next result when replaying. Errors if the log's next result is for another builtin.
+    pub fn result<T: Display + FromStr>(
+        &mut self,
+        builtin: &str,
+        live: impl FnOnce() -> T,
+    ) -> Result<T, Diagnostic> {
+        match self {
+            Replay::Off => Ok(live()),
+            Replay::Recording(log) => {
+                let result = live();
+                log.results.push((builtin.into(), result.to_string()));
+                Ok(result)
+            }
+            Replay::Replaying(log, replayed) => {
+                let Some((logged_builtin, result)) = log.results.get(*replayed) else {
+                    return Err(diagnostic!(REPLAY_LOG_ENDED, builtin));
+                };
+                if logged_builtin != builtin {
+                    return Err(diagnostic!(REPLAY_MISMATCH, logged_builtin, builtin));
+                }
+                *replayed += 1;
+                result
+                    .parse()
+                    .map_err(|_| diagnostic!(INVALID_REPLAYED_RESULT, result, builtin))
+            }
+        }
+    }
+}
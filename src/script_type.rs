@@ -0,0 +1,248 @@
+use std::rc::Rc;
+
+use crate::{
+    symbol::Symbol,
+    tokenizer::{Type, Value},
+    Interpreter,
+};
+
+/// A Rust type which converts to and from the values of one type in scripts.
+pub trait ScriptValue: Sized {
+    /// The type of the values in scripts.
+    fn script_type() -> Type;
+
+    fn to_value(&self) -> Value;
+
+    /// Converts a value of the script type back, or returns None if the value has another type.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+/// A Rust struct which scripts see as a record with the same fields, in the same order.
+/// Derive it with `#[derive(ScriptType)]` from decibelle-macros.
+pub trait ScriptType: ScriptValue {
+    /// Registers the methods listed in `#[script(methods(...))]` with Interpreter::register_method,
+    /// each named after the struct and the method, like point_length for Point::length.
+    fn register_methods(interpreter: &mut Interpreter);
+}
+
+/// A method taking &S, and arguments and a return value which convert to and from script values,
+/// which can be registered with Interpreter::register_method. Args is the tuple of the arguments' types.
+pub trait ScriptMethod<S, Args> {
+    /// The types of the value the method is called on and of the arguments.
+    fn parameter_types() -> Vec<Type>;
+
+    fn return_type() -> Type;
+
+    /// Calls the method with the value it's called on followed by the arguments, which must have the parameter types.
+    fn call(&self, values: Vec<Value>) -> Value;
+}
+
+macro_rules! impl_script_method {
+    ($($argument:ident),*) => {
+        impl<S, $($argument,)* R, F> ScriptMethod<S, ($($argument,)*)> for F
+        where
+            S: ScriptValue,
+            $($argument: ScriptValue,)*
+            R: ScriptValue,
+            F: Fn(&S, $($argument),*) -> R,
+        {
+            fn parameter_types() -> Vec<Type> {
+                vec![S::script_type(), $($argument::script_type()),*]
+            }
+
+            fn return_type() -> Type {
+                R::script_type()
+            }
+
+            #[allow(non_snake_case)]
+            fn call(&self, values: Vec<Value>) -> Value {
+                // The arguments were checked against the parameter types, so they convert.
+                let mut values = values.iter();
+                let this = S::from_value(values.next().unwrap()).unwrap();
+                $(let $argument = $argument::from_value(values.next().unwrap()).unwrap();)*
+                self(&this, $($argument),*).to_value()
+            }
+        }
+    };
+}
+
+impl_script_method!();
+impl_script_method!(A);
+impl_script_method!(A, B);
+impl_script_method!(A, B, C);
+impl_script_method!(A, B, C, D);
+
+/// A record value with the fields, for #[derive(ScriptType)].
+#[doc(hidden)]
+pub fn record(fields: Vec<(&str, Value)>) -> Value {
+    Value::Record(Rc::new(
+        fields
+            .into_iter()
+            .map(|(name, value)| (Symbol::intern(name), value))
+            .collect(),
+    ))
+}
+
+/// A record type with the fields, for #[derive(ScriptType)].
+#[doc(hidden)]
+pub fn record_type(fields: Vec<(&str, Type)>) -> Type {
+    Type::Record(
+        fields
+            .into_iter()
+            .map(|(name, field_type)| (Symbol::intern(name), field_type))
+            .collect(),
+    )
+}
+
+/// The fields of a record value if it has the field names, for #[derive(ScriptType)].
+#[doc(hidden)]
+pub fn record_fields<'a>(value: &'a Value, names: &[&str]) -> Option<Vec<&'a Value>> {
+    match value {
+        Value::Record(fields)
+            if fields.len() == names.len()
+                && fields
+                    .iter()
+                    .zip(names)
+                    .all(|((name, _), other)| name.as_str() == *other) =>
+        {
+            Some(fields.iter().map(|(_, value)| value).collect())
+        }
+        _ => None,
+    }
+}
+
+impl ScriptValue for () {
+    fn script_type() -> Type {
+        Type::Void
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Void
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        matches!(value, Value::Void).then_some(())
+    }
+}
+
+impl ScriptValue for f64 {
+    fn script_type() -> Type {
+        Type::Number
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Number(*self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+}
+
+impl ScriptValue for bool {
+    fn script_type() -> Type {
+        Type::Boolean
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Boolean(*self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+}
+
+impl ScriptValue for String {
+    fn script_type() -> Type {
+        Type::String
+    }
+
+    fn to_value(&self) -> Value {
+        Value::String(self.as_str().into())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(string) => Some(string.as_str().into()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ScriptValue> ScriptValue for Option<T> {
+    fn script_type() -> Type {
+        Type::Option(Box::new(T::script_type()))
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Option(self.as_ref().map(|value| Rc::new(value.to_value())))
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Option(Some(value)) => Some(Some(T::from_value(value)?)),
+            Value::Option(None) => Some(None),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ScriptValue, E: ScriptValue> ScriptValue for Result<T, E> {
+    fn script_type() -> Type {
+        Type::Result {
+            value: Box::new(T::script_type()),
+            error: Box::new(E::script_type()),
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Result(Rc::new(match self {
+            Ok(value) => Ok(value.to_value()),
+            Err(error) => Err(error.to_value()),
+        }))
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Result(result) => match &**result {
+                Ok(value) => Some(Ok(T::from_value(value)?)),
+                Err(error) => Some(Err(E::from_value(error)?)),
+            },
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_script_value_for_tuple {
+    ($($element:ident $index:tt),*) => {
+        impl<$($element: ScriptValue),*> ScriptValue for ($($element,)*) {
+            fn script_type() -> Type {
+                Type::Tuple(vec![$($element::script_type()),*])
+            }
+
+            fn to_value(&self) -> Value {
+                Value::Tuple(Rc::new(vec![$(self.$index.to_value()),*]))
+            }
+
+            fn from_value(value: &Value) -> Option<Self> {
+                match value {
+                    Value::Tuple(values) if values.len() == [$($index),*].len() => {
+                        Some(($($element::from_value(&values[$index])?,)*))
+                    }
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_script_value_for_tuple!(A 0, B 1);
+impl_script_value_for_tuple!(A 0, B 1, C 2);
+impl_script_value_for_tuple!(A 0, B 1, C 2, D 3);
@@ -0,0 +1,140 @@
+use serde_json::{json, Value as Json};
+
+use crate::{
+    tokenizer::{Type, Value},
+    Interpreter,
+};
+
+/// A REPL session which can be saved to disk and restored later: the programs entered, and the globals they declared.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Session {
+    pub history: Vec<String>,
+    pub globals: Vec<Global>,
+}
+
+/// A global saved with a session. Functions are saved as their declarations, and other values as JSON with their type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Global {
+    Value {
+        name: String,
+        value_type: Type,
+        value: Json,
+    },
+    Function {
+        name: String,
+        source: String,
+    },
+}
+
+impl Session {
+    /// Saves the history and the globals of the interpreter, apart from those which are the same in the fresh
+    /// interpreter, like the prelude's functions, and functions written in Rust, which the host declares again.
+    /// Returns the session and the names of the globals which can't be saved, like iterators and channels.
+    pub fn capture(
+        interpreter: &Interpreter,
+        fresh: &Interpreter,
+        history: Vec<String>,
+    ) -> (Session, Vec<String>) {
+        let mut globals = vec![];
+        let mut skipped = vec![];
+        for (name, value) in interpreter.globals() {
+            let fresh_value = fresh.get_global(&name);
+            match &value {
+                Value::Function(function) => {
+                    let Some(source) = function.source(&name) else {
+                        continue;
+                    };
+                    let fresh_source = match &fresh_value {
+                        Some(Value::Function(function)) => function.source(&name),
+                        _ => None,
+                    };
+                    if fresh_source.as_ref() != Some(&source) {
+                        globals.push(Global::Function { name, source });
+                    }
+                }
+                _ if fresh_value.as_ref() == Some(&value) => {}
+                _ => match value.to_json() {
+                    Ok(json) => globals.push(Global::Value {
+                        name,
+                        value_type: value.value_type(),
+                        value: json,
+                    }),
+                    Err(_) => skipped.push(name),
+                },
+            }
+        }
+        (Session { history, globals }, skipped)
+    }
+
+    /// Declares the saved globals in the interpreter, in the order they were declared.
+    /// Returns why each global which couldn't be declared failed, like a function using a variable which wasn't saved.
+    pub fn restore(&self, interpreter: &mut Interpreter) -> Vec<String> {
+        let mut errors = vec![];
+        for global in &self.globals {
+            match global {
+                Global::Value {
+                    name,
+                    value_type,
+                    value,
+                } => match Value::from_json_as(value, value_type) {
+                    Ok(value) => interpreter.declare_global(name, value),
+                    Err(error) => errors.push(format!("{name}: {error}")),
+                },
+                Global::Function { name, source } => {
+                    if let Err(error) = interpreter.eval(source) {
+                        errors.push(format!("{name}: {}", error.report(source).trim_end()));
+                    }
+                }
+            }
+        }
+        errors
+    }
+
+    pub fn to_json(&self) -> Json {
+        let globals: Vec<_> = self
+            .globals
+            .iter()
+            .map(|global| match global {
+                Global::Value {
+                    name,
+                    value_type,
+                    value,
+                } => json!({"name": name, "type": value_type.to_string(), "value": value}),
+                Global::Function { name, source } => json!({"name": name, "function": source}),
+            })
+            .collect();
+        json!({"history": self.history, "globals": globals})
+    }
+
+    /// Reads a session written by to_json.
+    pub fn from_json(json: &Json) -> Result<Session, String> {
+        let invalid = || "The session isn't one saved by decibelle.".to_string();
+        let strings = |json: &Json| json.as_str().map(String::from).ok_or_else(invalid);
+        let history = json["history"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(strings)
+            .collect::<Result<_, _>>()?;
+        let globals = json["globals"]
+            .as_array()
+            .ok_or_else(invalid)?
+            .iter()
+            .map(|global| {
+                let name = strings(&global["name"])?;
+                Ok(match &global["function"] {
+                    Json::String(source) => Global::Function {
+                        name,
+                        source: source.clone(),
+                    },
+                    _ => Global::Value {
+                        name,
+                        value_type: strings(&global["type"])?.parse()?,
+                        value: global["value"].clone(),
+                    },
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        Ok(Session { history, globals })
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    cmp::Ordering,
+    fmt::{Debug, Display},
+    hash::{Hash, Hasher},
+    ops::Deref,
+    rc::Rc,
+};
+
+/// The longest string stored inline, chosen so that a Value still fits in 16 bytes.
+pub const INLINE_CAPACITY: usize = 14;
+
+/// An immutable string. Short strings are stored inline so that creating, concatenating
+/// and comparing them doesn't allocate, longer ones are reference counted.
+#[derive(Clone)]
+pub struct SmallString(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline {
+        length: u8,
+        bytes: [u8; INLINE_CAPACITY],
+    },
+    Heap(Rc<String>),
+}
+
+impl SmallString {
+    fn inline(parts: &[&str]) -> SmallString {
+        let mut bytes = [0; INLINE_CAPACITY];
+        let mut length = 0;
+        for part in parts {
+            bytes[length..length + part.len()].copy_from_slice(part.as_bytes());
+            length += part.len();
+        }
+        SmallString(Repr::Inline {
+            length: length as u8,
+            bytes,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // The bytes are whole strs copied one after another, so they are valid UTF-8.
+            Repr::Inline { length, bytes } => unsafe {
+                std::str::from_utf8_unchecked(&bytes[..*length as usize])
+            },
+            Repr::Heap(string) => string,
+        }
+    }
+
+    /// Is the string stored without a heap allocation?
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline { .. })
+    }
+
+    pub fn concat(&self, other: &str) -> SmallString {
+        let length = self.len() + other.len();
+        if length <= INLINE_CAPACITY {
+            SmallString::inline(&[self, other])
+        } else {
+            let mut string = String::with_capacity(length);
+            string.push_str(self);
+            string.push_str(other);
+            SmallString(Repr::Heap(Rc::new(string)))
+        }
+    }
+}
+
+impl From<&str> for SmallString {
+    fn from(string: &str) -> Self {
+        if string.len() <= INLINE_CAPACITY {
+            SmallString::inline(&[string])
+        } else {
+            SmallString(Repr::Heap(Rc::new(string.to_string())))
+        }
+    }
+}
+
+impl From<String> for SmallString {
+    fn from(string: String) -> Self {
+        if string.len() <= INLINE_CAPACITY {
+            SmallString::inline(&[&string])
+        } else {
+            SmallString(Repr::Heap(Rc::new(string)))
+        }
+    }
+}
+
+impl Deref for SmallString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl PartialEq for SmallString {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for SmallString {}
+
+impl PartialOrd for SmallString {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for SmallString {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for SmallString {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl Display for SmallString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for SmallString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
@@ -0,0 +1,81 @@
+/// Where the lines of a program a host assembled from fragments came from, so errors and dbg can point at the
+/// fragment a line was written in rather than at the assembled program.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SourceMap {
+    fragments: Vec<Fragment>, // Sorted by their lines in the program, without overlaps.
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Fragment {
+    lines: (usize, usize), // The fragment's first and last line in the program.
+    source_name: String,
+    original_line: usize, // The line the fragment starts on in its source.
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    /// Maps the program's lines from lines.0 to lines.1 to the lines of the source from original_line on.
+    /// A mapping replaces the parts of earlier ones it overlaps.
+    pub fn add(&mut self, lines: (usize, usize), source_name: &str, original_line: usize) {
+        let mut fragments = vec![];
+        for fragment in self.fragments.drain(..) {
+            if fragment.lines.1 < lines.0 || fragment.lines.0 > lines.1 {
+                fragments.push(fragment);
+                continue;
+            }
+            if fragment.lines.0 < lines.0 {
+                fragments.push(Fragment {
+                    lines: (fragment.lines.0, lines.0 - 1),
+                    ..fragment.clone()
+                });
+            }
+            if fragment.lines.1 > lines.1 {
+                fragments.push(Fragment {
+                    lines: (lines.1 + 1, fragment.lines.1),
+                    original_line: fragment.original_line + lines.1 + 1 - fragment.lines.0,
+                    source_name: fragment.source_name,
+                });
+            }
+        }
+        fragments.push(Fragment {
+            lines,
+            source_name: source_name.into(),
+            original_line,
+        });
+        fragments.sort_by_key(|fragment| fragment.lines);
+        self.fragments = fragments;
+    }
+
+    /// Appends the fragment to the program on lines of its own, so code appended after it starts on a new line,
+    /// and maps them to the source, starting at its first line.
+    pub fn append(&mut self, program: &mut String, source_name: &str, fragment: &str) {
+        if !program.is_empty() && !program.ends_with('\n') {
+            program.push('\n');
+        }
+        let first_line = program.lines().count() + 1;
+        program.push_str(fragment);
+        if !fragment.ends_with('\n') {
+            program.push('\n');
+        }
+        let line_count = fragment.lines().count().max(1);
+        self.add((first_line, first_line + line_count - 1), source_name, 1);
+    }
+
+    /// The source and line the program's line came from, or None if it isn't in a mapped fragment,
+    /// like code the host added between fragments.
+    pub fn original(&self, line: usize) -> Option<(&str, usize)> {
+        let index = self
+            .fragments
+            .partition_point(|fragment| fragment.lines.1 < line);
+        let fragment = self.fragments.get(index)?;
+        (fragment.lines.0 <= line).then(|| {
+            (
+                fragment.source_name.as_str(),
+                fragment.original_line + line - fragment.lines.0,
+            )
+        })
+    }
+}
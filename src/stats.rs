@@ -0,0 +1,80 @@
+use std::{fmt::Display, time::Duration};
+
+/// Counts of what the backends did while running programs, for tracking the performance of the evaluator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub statements_executed: usize,   // Only counted by the tree walker.
+    pub instructions_executed: usize, // Only counted by the VM.
+    pub allocations: usize, // Heap allocated strings, tuples and functions created while running.
+    pub environment_pushes: usize,
+    pub garbage_collections: usize,
+    pub max_call_depth: usize,
+    pub errors_raised: usize, // Errors raised with raise, whether they were caught or not.
+}
+
+impl Stats {
+    /// The counts since the earlier stats were taken. The max call depth is the deepest since the interpreter
+    /// was created.
+    pub fn since(&self, earlier: &Stats) -> Stats {
+        Stats {
+            statements_executed: self.statements_executed - earlier.statements_executed,
+            instructions_executed: self.instructions_executed - earlier.instructions_executed,
+            allocations: self.allocations - earlier.allocations,
+            environment_pushes: self.environment_pushes - earlier.environment_pushes,
+            garbage_collections: self.garbage_collections - earlier.garbage_collections,
+            max_call_depth: self.max_call_depth,
+            errors_raised: self.errors_raised - earlier.errors_raised,
+        }
+    }
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "statements executed:   {}", self.statements_executed)?;
+        writeln!(f, "instructions executed: {}", self.instructions_executed)?;
+        writeln!(f, "allocations:           {}", self.allocations)?;
+        writeln!(f, "environment pushes:    {}", self.environment_pushes)?;
+        writeln!(f, "garbage collections:   {}", self.garbage_collections)?;
+        writeln!(f, "max call depth:        {}", self.max_call_depth)?;
+        writeln!(f, "errors raised:         {}", self.errors_raised)
+    }
+}
+
+/// The function set with Interpreter::set_metrics_sink.
+pub type MetricsSink = Box<dyn FnMut(&EvalMetrics)>;
+
+/// What a call to eval did, which the metrics sink set with Interpreter::set_metrics_sink gets when it finishes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalMetrics {
+    pub stats: Stats, // The counts for this eval. Statements executed are the fuel the step limit counts.
+    pub duration: Duration, // Measured with the interpreter's clock.
+    pub failed: bool, // Whether the eval stopped with an error, other than the program calling exit.
+    pub fuel_remaining: Option<usize>, // How many more statements the step limit allowed, if one is set.
+}
+
+/// How long each phase of running programs took, in total.
+/// Checking includes the optimizations, and executing includes compiling to bytecode for the VM.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timings {
+    pub tokenize: Duration,
+    pub parse: Duration,
+    pub check: Duration,
+    pub execute: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        self.tokenize + self.parse + self.check + self.execute
+    }
+}
+
+impl Display for Timings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        writeln!(f, "tokenize: {:>12.3} ms", milliseconds(self.tokenize))?;
+        writeln!(f, "parse:    {:>12.3} ms", milliseconds(self.parse))?;
+        writeln!(f, "check:    {:>12.3} ms", milliseconds(self.check))?;
+        writeln!(f, "execute:  {:>12.3} ms", milliseconds(self.execute))?;
+        writeln!(f, "total:    {:>12.3} ms", milliseconds(self.total()))
+    }
+}
@@ -0,0 +1,59 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{Debug, Display},
+    marker::PhantomData,
+};
+
+/// An interned identifier. Symbols are cheap to copy, compare and hash.
+/// Symbols are interned per thread, so they can't be sent to another thread.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32, PhantomData<*const ()>); // The pointer makes symbols !Send and !Sync.
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    /// Returns the symbol for a name, creating it if needed.
+    /// Names are never freed, which is fine as programs only have so many identifiers.
+    pub fn intern(name: &str) -> Symbol {
+        INTERNER.with_borrow_mut(|interner| {
+            if let Some(symbol) = interner.symbols.get(name) {
+                return *symbol;
+            }
+            let name: &'static str = Box::leak(name.into());
+            let symbol = Symbol(interner.names.len() as u32, PhantomData);
+            interner.names.push(name);
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// Returns the symbol for a name if it has been interned.
+    pub fn get(name: &str) -> Option<Symbol> {
+        INTERNER.with_borrow(|interner| interner.symbols.get(name).copied())
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.with_borrow(|interner| interner.names[self.0 as usize])
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
@@ -0,0 +1,252 @@
+use crate::{
+    parser::{parse, Statement},
+    tokenizer::{tokenize, Token, TokenType, Value},
+    EvalError,
+};
+
+/// The kinds of source text which the parser skips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+    Shebang, // A #! line at the start of the program.
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub line: usize, // The line the trivia starts on.
+}
+
+/// A token with its text as written and the trivia before it.
+#[derive(Debug)]
+pub struct SyntaxToken {
+    pub leading_trivia: Vec<Trivia>,
+    pub text: String,
+    pub token: Token,
+}
+
+/// A lossless view of a program: its tokens with the whitespace and comments around them, alongside its syntax tree.
+/// Concatenating the trivia and text of the tokens gives back the source exactly,
+/// so tools can edit the source through the tokens without losing its comments.
+#[derive(Debug)]
+pub struct SyntaxTree {
+    pub tokens: Vec<SyntaxToken>,
+    pub trailing_trivia: Vec<Trivia>, // The trivia after the last token.
+    pub statements: Vec<Statement>,
+}
+
+impl SyntaxTree {
+    pub fn parse(program: &str) -> Result<SyntaxTree, EvalError> {
+        let tokens = tokenize(program).map_err(EvalError::Tokenizer)?;
+        let statements = parse(&tokens).map_err(EvalError::Compiler)?;
+        let (tokens, trailing_trivia) = add_trivia(program, tokens);
+        Ok(SyntaxTree {
+            tokens,
+            trailing_trivia,
+            statements,
+        })
+    }
+
+    /// The source of the program, exactly as it was parsed.
+    pub fn source(&self) -> String {
+        let mut source = String::new();
+        for token in &self.tokens {
+            for trivia in &token.leading_trivia {
+                source.push_str(&trivia.text);
+            }
+            source.push_str(&token.text);
+        }
+        for trivia in &self.trailing_trivia {
+            source.push_str(&trivia.text);
+        }
+        source
+    }
+
+    /// The comments in the program, with whether each one follows a token on the same line.
+    pub fn comments(&self) -> Vec<(&Trivia, bool)> {
+        let mut comments = vec![];
+        let mut previous_token_line = None;
+        let trivia = self
+            .tokens
+            .iter()
+            .map(|token| (&token.leading_trivia, Some(token.token.lines.1)))
+            .chain([(&self.trailing_trivia, None)]);
+        for (leading_trivia, token_line) in trivia {
+            for trivia in leading_trivia {
+                if trivia.kind == TriviaKind::Comment {
+                    comments.push((trivia, previous_token_line == Some(trivia.line)));
+                }
+            }
+            previous_token_line = token_line;
+        }
+        comments
+    }
+}
+
+/// Tokenizes the program without parsing it, keeping the trivia. Returns the tokens and the trivia after the last one.
+pub fn tokenize_lossless(program: &str) -> Result<(Vec<SyntaxToken>, Vec<Trivia>), EvalError> {
+    let tokens = tokenize(program).map_err(EvalError::Tokenizer)?;
+    Ok(add_trivia(program, tokens))
+}
+
+/// Adds the text and leading trivia of each of the program's tokens.
+fn add_trivia(program: &str, tokens: Vec<Token>) -> (Vec<SyntaxToken>, Vec<Trivia>) {
+    let mut scanner = Scanner {
+        rest: program,
+        line: 1,
+    };
+    let mut syntax_tokens = Vec::with_capacity(tokens.len());
+    let mut leading_trivia = scanner.shebang().into_iter().collect::<Vec<_>>();
+    let mut previous_token_type = None;
+    for token in tokens {
+        leading_trivia.extend(scanner.trivia());
+        let text = scanner.token_text(&token.token_type, previous_token_type);
+        previous_token_type = Some(token.token_type.clone());
+        syntax_tokens.push(SyntaxToken {
+            leading_trivia: std::mem::take(&mut leading_trivia),
+            text,
+            token,
+        });
+    }
+    leading_trivia.extend(scanner.trivia());
+    (syntax_tokens, leading_trivia)
+}
+
+/// Splits the source of a program which has already been tokenized into trivia and token text.
+struct Scanner<'a> {
+    rest: &'a str,
+    line: usize,
+}
+
+impl Scanner<'_> {
+    fn take(&mut self, length: usize) -> String {
+        let (text, rest) = self.rest.split_at(length);
+        self.rest = rest;
+        self.line += text.matches('\n').count();
+        text.to_string()
+    }
+
+    fn shebang(&mut self) -> Option<Trivia> {
+        if !self.rest.starts_with("#!") {
+            return None;
+        }
+        let line = self.line;
+        let length = self.rest.find('\n').unwrap_or(self.rest.len());
+        Some(Trivia {
+            kind: TriviaKind::Shebang,
+            text: self.take(length),
+            line,
+        })
+    }
+
+    fn trivia(&mut self) -> Vec<Trivia> {
+        let mut trivia = vec![];
+        loop {
+            let line = self.line;
+            // Doc comments are tokens.
+            let is_doc_comment = self.rest.starts_with("///") && !self.rest.starts_with("////");
+            let (kind, length) = if self.rest.starts_with("//") && !is_doc_comment {
+                let length = self.rest.find('\n').unwrap_or(self.rest.len());
+                (TriviaKind::Comment, length)
+            } else {
+                let length = self
+                    .rest
+                    .find(|ch: char| !ch.is_whitespace())
+                    .unwrap_or(self.rest.len());
+                (TriviaKind::Whitespace, length)
+            };
+            if length == 0 {
+                return trivia;
+            }
+            trivia.push(Trivia {
+                kind,
+                text: self.take(length),
+                line,
+            });
+        }
+    }
+
+    fn token_text(
+        &mut self,
+        token_type: &TokenType,
+        previous_token_type: Option<TokenType>,
+    ) -> String {
+        let length = match token_type {
+            TokenType::Literal(Value::String(string)) => string.len() + 2,
+            TokenType::Literal(Value::Number(_)) => {
+                let digits = |text: &str| {
+                    text.find(|ch: char| !ch.is_ascii_digit())
+                        .unwrap_or(text.len())
+                };
+                let mut length = digits(self.rest);
+                // The tokenizer reads a.2.2 as a, dot, 2, dot, 2, so numbers after a dot don't have a fraction.
+                // Numbers before .. don't have one either.
+                if self.rest[length..].starts_with('.')
+                    && !self.rest[length..].starts_with("..")
+                    && previous_token_type != Some(TokenType::Dot)
+                {
+                    length += 1 + digits(&self.rest[length + 1..]);
+                }
+                length
+            }
+            token_type => token_type.to_string().len(),
+        };
+        self.take(length)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{SyntaxTree, TriviaKind};
+
+    #[test]
+    fn gives_back_the_source_of_every_spec() {
+        let mut specs = vec![];
+        for directory in ["tests/spec", "tests/spec/errors"] {
+            for entry in fs::read_dir(directory).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().is_some_and(|extension| extension == "db") {
+                    specs.push(path);
+                }
+            }
+        }
+        let mut parsed = 0;
+        for path in specs {
+            let source = fs::read_to_string(&path).unwrap();
+            // Specs of parse errors have no syntax tree.
+            if let Ok(syntax_tree) = SyntaxTree::parse(&source) {
+                assert_eq!(syntax_tree.source(), source, "{}", path.display());
+                parsed += 1;
+            }
+        }
+        assert!(parsed > 0);
+    }
+
+    #[test]
+    fn keeps_comments_whitespace_and_shebangs() {
+        let source = "#!/usr/bin/env busheye\n// Greets.\n\tprint(\"🦀 {1 + 2}\"); // Crab.\n\nlet t = ((1, 2), 3);\n/// Doc.\nlet a = t.0.1 + 1.50;\n// End.";
+        let syntax_tree = SyntaxTree::parse(source).unwrap();
+        assert_eq!(syntax_tree.source(), source);
+        assert_eq!(
+            syntax_tree.tokens[0].leading_trivia[0].kind,
+            TriviaKind::Shebang
+        );
+        let comments = syntax_tree
+            .comments()
+            .into_iter()
+            .map(|(comment, trailing)| (comment.text.as_str(), comment.line, trailing))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            comments,
+            [
+                ("// Greets.", 2, false),
+                ("// Crab.", 3, true),
+                ("// End.", 8, false)
+            ]
+        );
+    }
+}
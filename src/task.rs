@@ -0,0 +1,197 @@
+use std::{
+    cell::RefCell,
+    fmt::Debug,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Mutex, OnceLock,
+    },
+    thread,
+};
+
+use crate::{
+    channel::{Channel, Message},
+    messages::{diagnostic, Diagnostic},
+    tokenizer::{FunctionValue, Type, Value},
+    Backend, EvalError, Interpreter,
+};
+
+/// A function running in its own interpreter on another thread, started with spawn.
+pub struct Task {
+    pub result_type: Type,
+    pub message_type: Option<Type>, // The type of the messages sent to and from the task's mailbox, if it has one.
+    receiver: Receiver<Result<Message, String>>,
+    result: RefCell<Option<Result<Message, String>>>, // Set by the first join.
+    mailbox: RefCell<Option<Sender<Message>>>, // Dropped by join, so the task stops waiting for messages.
+    replies: Option<Receiver<Message>>,        // Messages the task sent over its mailbox.
+}
+
+impl Task {
+    /// Starts running the function in a new interpreter with the backend on a thread from the pool. The function
+    /// is sent as its source, as the values it's made of can't leave this thread, so it can only use its own
+    /// variables, the functions declared in it, the builtins and the prelude. It takes no arguments, or a channel,
+    /// its mailbox, whose other end is the task.
+    pub fn spawn(function: &FunctionValue, backend: Backend) -> Result<Task, Diagnostic> {
+        let Some(source) = function.source("task") else {
+            return Err(diagnostic!(RUST_FUNCTION_SPAWNED));
+        };
+        let message_type = match &function.parameters[..] {
+            [] => None,
+            [(_, _, Type::Channel(message_type))] => Some((**message_type).clone()),
+            parameters => {
+                let parameter_types: Vec<_> = parameters
+                    .iter()
+                    .map(|(_, _, parameter_type)| parameter_type.to_string())
+                    .collect();
+                return Err(diagnostic!(
+                    INVALID_SPAWN_PARAMETERS,
+                    parameter_types.join(", ")
+                ));
+            }
+        };
+        let (mailbox, replies, ends) = match &message_type {
+            Some(message_type) => {
+                let (mailbox, task_receiver) = mpsc::channel();
+                let (task_sender, replies) = mpsc::channel();
+                // Types name record fields with symbols, which can't leave this thread, so the type is sent as text.
+                let ends = (message_type.to_string(), task_sender, task_receiver);
+                (Some(mailbox), Some(replies), Some(ends))
+            }
+            None => (None, None, None),
+        };
+        let (sender, receiver) = mpsc::channel();
+        pool().execute(Box::new(move || {
+            let _ = sender.send(run(&source, backend, ends));
+        }));
+        Ok(Task {
+            result_type: function.return_type.clone(),
+            message_type,
+            receiver,
+            result: RefCell::new(None),
+            mailbox: RefCell::new(mailbox),
+            replies,
+        })
+    }
+
+    /// Sends the value to the task's mailbox, or returns an error if it can't be sent, the task was joined
+    /// or the task finished.
+    pub fn send(&self, value: &Value) -> Result<(), Diagnostic> {
+        let message = Message::from_value(value)?;
+        match &*self.mailbox.borrow() {
+            Some(mailbox) => mailbox
+                .send(message)
+                .map_err(|_| diagnostic!(TASK_FINISHED)),
+            None if self.message_type.is_none() => Err(diagnostic!(NO_MAILBOX)),
+            None => Err(diagnostic!(TASK_JOINED)),
+        }
+    }
+
+    /// Waits for the next value the task sends over its mailbox, or returns None once the task finished.
+    pub fn recv(&self) -> Option<Value> {
+        let replies = self.replies.as_ref()?;
+        replies.recv().ok().map(Message::into_value)
+    }
+
+    /// Closes the task's mailbox, so receiving from it returns None once the task has read every message, then waits
+    /// for the task to finish, and returns the function's result or why the task failed.
+    /// Joining a task again returns the same result.
+    pub fn join(&self) -> Result<Message, String> {
+        self.mailbox.borrow_mut().take();
+        self.result
+            .borrow_mut()
+            .get_or_insert_with(|| {
+                self.receiver
+                    .recv()
+                    .unwrap_or_else(|_| Err(diagnostic!(TASK_PANICKED).to_string()))
+            })
+            .clone()
+    }
+}
+
+/// Runs the source of a spawned function, and calls it with a channel made from the ends of its mailbox, if it has one.
+fn run(
+    source: &str,
+    backend: Backend,
+    mailbox: Option<(String, Sender<Message>, Receiver<Message>)>,
+) -> Result<Message, String> {
+    let mut interpreter = Interpreter::with_backend(backend);
+    let report = |error: EvalError| match error {
+        EvalError::Exit(code) => diagnostic!(TASK_EXITED, code).to_string(),
+        error => error.report(source).trim_end().into(),
+    };
+    interpreter.eval(source).map_err(report)?;
+    let arguments = match mailbox {
+        Some((message_type, sender, receiver)) => {
+            let message_type = message_type.parse().unwrap();
+            vec![Value::Channel(Rc::new(Channel::new(
+                message_type,
+                sender,
+                receiver,
+            )))]
+        }
+        None => vec![],
+    };
+    let value = interpreter
+        .call_function("task", arguments)
+        .unwrap()
+        .map_err(report)?;
+    Message::from_value(&value).map_err(|error| error.to_string())
+}
+
+impl Debug for Task {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.message_type {
+            Some(message_type) => write!(f, "task({:?}, {message_type:?})", self.result_type),
+            None => write!(f, "task({:?})", self.result_type),
+        }
+    }
+}
+
+/// Tasks are equal if they're the same task.
+impl PartialEq for Task {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads which run tasks. A thread is started whenever there's no idle thread for a task, so tasks which join
+/// other tasks never wait for a thread.
+struct Pool {
+    sender: Mutex<Sender<Job>>,
+    receiver: Mutex<Receiver<Job>>,
+    idle_threads: AtomicUsize, // Threads waiting for a job which no other job has claimed.
+}
+
+fn pool() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+    POOL.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel();
+        Pool {
+            sender: Mutex::new(sender),
+            receiver: Mutex::new(receiver),
+            idle_threads: AtomicUsize::new(0),
+        }
+    })
+}
+
+impl Pool {
+    fn execute(&'static self, job: Job) {
+        let claimed_idle_thread = self
+            .idle_threads
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |idle| {
+                idle.checked_sub(1)
+            })
+            .is_ok();
+        if !claimed_idle_thread {
+            thread::spawn(move || loop {
+                let job = self.receiver.lock().unwrap().recv().unwrap();
+                job();
+                self.idle_threads.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        self.sender.lock().unwrap().send(job).unwrap();
+    }
+}
@@ -0,0 +1,270 @@
+use std::{
+    cell::{Cell, RefCell},
+    fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    coverage::Coverage,
+    debugger::format_value,
+    format::ValueFormat,
+    parser::{parse, StatementType},
+    tokenizer::{tokenize, Value},
+    Backend, EvalError, Interpreter, OptLevel, EXIT_RUNTIME_ERROR,
+};
+
+/// The outcome of a test block.
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub lines: (usize, usize),
+    pub output: String,           // What the test printed.
+    pub error: Option<EvalError>, // Why the test failed, or None if it passed.
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Runs each of the program's top level `test "name" { ... }` blocks, in order.
+/// Each test runs in a new interpreter, after the rest of the program, so tests can't affect each other.
+/// A test fails if it has an error, like a failed assert, or exits with a code other than 0.
+/// If coverage is given, the statements run by every test are added to it.
+pub fn run_tests(
+    program: &str,
+    backend: Backend,
+    mut coverage: Option<&mut Coverage>,
+) -> Result<Vec<TestResult>, EvalError> {
+    let tokens = tokenize(program).map_err(EvalError::Tokenizer)?;
+    let statements = parse(&tokens).map_err(EvalError::Compiler)?;
+    let (tests, program_statements): (Vec<_>, Vec<_>) = statements
+        .into_iter()
+        .partition(|statement| matches!(statement.statement, StatementType::Test { .. }));
+
+    let mut results = vec![];
+    for test in tests {
+        let StatementType::Test { name, body } = test.statement else {
+            unreachable!()
+        };
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut interpreter = Interpreter::with_backend(backend);
+        let test_output = output.clone();
+        interpreter.set_output(move |text| test_output.borrow_mut().push_str(text));
+        if coverage.is_some() {
+            interpreter.enable_coverage();
+            interpreter.set_opt_level(OptLevel::None);
+        }
+
+        let mut statements = program_statements.clone();
+        statements.push(*body);
+        let error = match interpreter.eval_statements(statements) {
+            Ok(()) | Err(EvalError::Exit(0)) => None,
+            Err(error) => Some(error),
+        };
+        if let (Some(coverage), Some(test_coverage)) = (&mut coverage, interpreter.coverage()) {
+            coverage.merge(test_coverage);
+        }
+        results.push(TestResult {
+            name,
+            lines: test.lines,
+            output: output.take(),
+            error,
+        });
+    }
+    Ok(results)
+}
+
+/// The backends compare_backends and run_specs run programs with. The tree walker is first, as the others are compared
+/// with it.
+const BACKENDS: &[Backend] = &[
+    Backend::TreeWalker,
+    Backend::Vm,
+    #[cfg(feature = "jit")]
+    Backend::Jit,
+];
+
+/// What a program did when it ran with a backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackendRun {
+    pub backend: Backend,
+    pub output: String, // What the program printed.
+    // The value the program returned from its top level, or the report of the error it stopped with.
+    pub result: Result<String, String>,
+}
+
+/// Runs the program with each backend, in a new interpreter each time, and checks they all printed the same output
+/// and returned the same value or stopped with the same error.
+/// Returns the tree walker's run and the first run which differed from it, or None if they all agree.
+pub fn compare_backends(program: &str) -> Option<(BackendRun, BackendRun)> {
+    let run = |backend| {
+        let output = Rc::new(RefCell::new(String::new()));
+        let mut interpreter = Interpreter::with_backend(backend);
+        let program_output = output.clone();
+        interpreter.set_output(move |text| program_output.borrow_mut().push_str(text));
+        let result = match interpreter.eval(program) {
+            Ok(()) => Ok(format_value(interpreter.return_value())),
+            Err(error) => Err(error.report(program)),
+        };
+        BackendRun {
+            backend,
+            output: output.take(),
+            result,
+        }
+    };
+    let expected = run(BACKENDS[0]);
+    for &backend in &BACKENDS[1..] {
+        let actual = run(backend);
+        if actual.output != expected.output || actual.result != expected.result {
+            return Some((expected, actual));
+        }
+    }
+    None
+}
+
+/// Panics with what each backend did if they ran the program differently, for tests which keep the backends from
+/// diverging.
+pub fn assert_backends_agree(program: &str) {
+    if let Some((expected, actual)) = compare_backends(program) {
+        panic!(
+            "{:?} and {:?} ran the program differently.\n{expected:#?}\n{actual:#?}",
+            expected.backend, actual.backend
+        );
+    }
+}
+
+/// The outcome of a spec, a program whose output and exit status are pinned down by the .expected file next to it.
+#[derive(Debug)]
+pub struct SpecResult {
+    pub path: PathBuf,            // The program.
+    pub backend: Backend,         // The backend it ran with.
+    pub expected: Option<String>, // The contents of its .expected file, or None if it doesn't have one.
+    pub actual: String, // What the program printed and the status it exited with, in the same format.
+}
+
+impl SpecResult {
+    pub fn passed(&self) -> bool {
+        self.expected.as_ref() == Some(&self.actual)
+    }
+
+    /// The program's .expected file.
+    pub fn expected_path(&self) -> PathBuf {
+        self.path.with_extension("expected")
+    }
+
+    /// Makes the spec pass by writing what the program did to its .expected file.
+    pub fn bless(&self) -> io::Result<()> {
+        fs::write(self.expected_path(), &self.actual)
+    }
+}
+
+/// Runs each .db file in the directory and its subdirectories, in order of their paths, with each backend, and compares
+/// what it printed and the status it exited with to its .expected file, so the backends must all agree with it.
+/// The .expected file holds the output, including the report of the error the program stopped with, followed by
+/// a line with `exit status: <status>` if the status isn't 0.
+/// Statuses are the command line interpreter's, so a number the program returns from its top level is its status.
+/// A program whose first line is a comment with flags, like `// --keep-going --max-depth=2`, runs like it does with
+/// those flags. --keep-going continues after runtime errors, and --max-depth=n and --max-width=n limit how much of
+/// big values print and dbg write.
+pub fn run_specs(directory: &Path) -> io::Result<Vec<SpecResult>> {
+    let mut paths = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "db") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    let mut results = vec![];
+    for path in paths {
+        for &backend in BACKENDS {
+            results.push(run_spec(&path, backend)?);
+        }
+    }
+    Ok(results)
+}
+
+/// Runs the program with the backend and compares what it did to its .expected file, as run_specs does.
+pub fn run_spec(path: &Path, backend: Backend) -> io::Result<SpecResult> {
+    let program = fs::read_to_string(path)?;
+    let output = Rc::new(RefCell::new(String::new()));
+    let mut interpreter = Interpreter::with_backend(backend);
+    let program_output = output.clone();
+    interpreter.set_output(move |text| program_output.borrow_mut().push_str(text));
+    // Reports name the file without its directory, so they don't depend on where the specs are run from.
+    let source_name = path.file_name().unwrap_or_default().to_string_lossy();
+    interpreter.set_source_name(&source_name);
+    let flags = program
+        .lines()
+        .next()
+        .and_then(|line| line.strip_prefix("// "))
+        .filter(|line| line.starts_with("--"))
+        .map_or(vec![], |line| line.split_whitespace().collect());
+    let mut value_format = ValueFormat::default();
+    let mut keep_going = false;
+    for flag in flags {
+        let number = |prefix: &str| {
+            flag.strip_prefix(prefix)
+                .and_then(|number| number.parse().ok())
+        };
+        if flag == "--keep-going" {
+            keep_going = true;
+        } else if let Some(max_depth) = number("--max-depth=") {
+            value_format.max_depth = Some(max_depth);
+        } else if let Some(max_width) = number("--max-width=") {
+            value_format.max_width = Some(max_width);
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has an unknown flag {flag}.", path.display()),
+            ));
+        }
+    }
+    interpreter.set_value_format(value_format);
+    let error_reported = Rc::new(Cell::new(false));
+    if keep_going {
+        let (program, source_name, program_output, error_reported) = (
+            program.clone(),
+            source_name.to_string(),
+            output.clone(),
+            error_reported.clone(),
+        );
+        interpreter.continue_after_errors(move |error| {
+            let report = error.report_in(&source_name, &program);
+            program_output.borrow_mut().push_str(&report);
+            error_reported.set(true);
+        });
+    }
+    let result = interpreter.eval(&program);
+    let mut actual = output.take();
+    let status = match (&result, interpreter.return_value()) {
+        (Ok(()), Value::Number(code)) => *code as i32 as u8,
+        (Ok(()), _) if error_reported.get() => EXIT_RUNTIME_ERROR,
+        (Ok(()), _) => 0,
+        (Err(error), _) => {
+            actual.push_str(&error.report_in(&source_name, &program));
+            error.exit_status()
+        }
+    };
+    if status != 0 {
+        actual.push_str(&format!("exit status: {status}\n"));
+    }
+    let expected = match fs::read_to_string(path.with_extension("expected")) {
+        Ok(expected) => Some(expected),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+        Err(error) => return Err(error),
+    };
+    Ok(SpecResult {
+        path: path.to_path_buf(),
+        backend,
+        expected,
+        actual,
+    })
+}
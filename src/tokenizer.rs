@@ -1,10 +1,15 @@
 use std::{
+    cell::RefCell,
     error::Error,
     fmt::{Debug, Display},
+    io::{self, BufReader, Read},
     rc::Rc,
 };
 
-use crate::parser::Statement;
+use crate::{
+    channel::Channel, compiler::Function, format::ValueFormat, messages::diagnostic,
+    parser::Statement, small_string::SmallString, symbol::Symbol, task::Task,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Type {
@@ -14,15 +19,32 @@ pub enum Type {
     String,
     Boolean,
     Tuple(Vec<Type>),
+    Record(Vec<(Symbol, Type)>), // A tuple with named fields.
+    Reference(Box<Type>),
     Function {
         parameters: Vec<Type>,
         return_type: Box<Type>,
     },
+    Result {
+        value: Box<Type>,
+        error: Box<Type>,
+    },
+    Option(Box<Type>),
+    Iterator(Box<Type>), // A host iterator, which for loops take values of the type from.
+    Channel(Box<Type>),  // A channel to the host, which sends and receives values of the type.
+    // A spawned function, which returns a value of the result type when it's joined. Tasks spawned with a mailbox
+    // exchange messages of the message type with the program which spawned them.
+    Task {
+        result: Box<Type>,
+        message: Option<Box<Type>>,
+    },
+    Never, // The type of values which can't exist, like the error of Ok(1). It can be assigned to any type.
 }
 
 #[derive(Clone)]
 pub enum FunctionBody {
-    Statement(Box<Statement>),
+    Statement(Rc<Statement>),
+    Bytecode(Rc<Function>),
     RustClosure {
         id: usize,
         closure: Rc<dyn Fn(Vec<Value>) -> Value>,
@@ -33,6 +55,7 @@ impl Debug for FunctionBody {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             FunctionBody::Statement(statement) => write!(f, "{statement:?}"),
+            FunctionBody::Bytecode(function) => write!(f, "bytecode {}", function.name),
             FunctionBody::RustClosure { .. } => write!(f, "Rust closure"),
         }
     }
@@ -44,6 +67,9 @@ impl PartialEq for FunctionBody {
             (FunctionBody::Statement(statement1), FunctionBody::Statement(statement2)) => {
                 statement1 == statement2
             }
+            (FunctionBody::Bytecode(function1), FunctionBody::Bytecode(function2)) => {
+                Rc::ptr_eq(function1, function2)
+            }
             (
                 FunctionBody::RustClosure { id: id1, .. },
                 FunctionBody::RustClosure { id: id2, .. },
@@ -57,43 +83,147 @@ impl PartialEq for FunctionBody {
 pub enum Value {
     Void,
     Number(f64),
-    String(String),
+    // Short strings are stored inline. Longer strings, tuples, records and functions are behind
+    // thin reference counted pointers, so cloning values is cheap and a value fits in 16 bytes.
+    String(SmallString),
     Boolean(bool),
-    Tuple(Vec<Value>),
-    Function {
-        parameters: Vec<(String, Option<usize>, Type)>, // Option<usize> is the shadow_id of the parameter.
-        return_type: Type,
-        body: FunctionBody,
-        parent_environment: usize, // ID of the environment in which the function was defined.
-    },
+    Tuple(Rc<Vec<Value>>),
+    Record(Rc<Vec<(Symbol, Value)>>),
+    Reference(Rc<Reference>),
+    Function(Rc<FunctionValue>),
+    Result(Rc<Result<Value, Value>>), // Made with Ok(value) or Err(error).
+    Option(Option<Rc<Value>>),        // Made with Some(value) or None.
+    Iterator(Rc<HostIterator>),
+    Channel(Rc<Channel>),
+    Task(Rc<Task>),
+}
+
+/// A reference to a variable, made with &variable.
+/// Environments which are referenced aren't freed, so the variable lives as long as the reference.
+#[derive(Debug)]
+pub struct Reference {
+    pub environment: usize, // ID of the environment the variable is in. 0 is the global environment.
+    pub slot: usize,
+    pub value_type: Type, // Type of the variable's value when the reference was made, for value_type.
+}
+
+/// References are equal if they refer to the same variable.
+impl PartialEq for Reference {
+    fn eq(&self, other: &Self) -> bool {
+        self.environment == other.environment && self.slot == other.slot
+    }
+}
+
+const _: () = assert!(std::mem::size_of::<Value>() == 16);
+
+/// A Rust iterator which for loops take values from as they run, so the values don't all have to exist at once.
+/// Iterators are shared by the values made from them, so each value is only taken by one loop.
+pub struct HostIterator {
+    pub item_type: Type,
+    iterator: RefCell<Box<dyn Iterator<Item = Value>>>,
+    finalizer: Option<Box<dyn FnOnce()>>, // Run once the program no longer references the iterator.
+}
+
+impl HostIterator {
+    /// Wraps an iterator whose values are all of the item type.
+    pub fn new(item_type: Type, iterator: impl Iterator<Item = Value> + 'static) -> HostIterator {
+        HostIterator {
+            item_type,
+            iterator: RefCell::new(Box::new(iterator)),
+            finalizer: None,
+        }
+    }
+
+    /// Sets a function to run when the program no longer references the iterator, or when the interpreter is
+    /// dropped, like closing the file or connection it reads from. Iterators only reachable from environments
+    /// which closures no longer reach are finalized by the next garbage collection.
+    pub fn with_finalizer(mut self, finalizer: impl FnOnce() + 'static) -> HostIterator {
+        self.finalizer = Some(Box::new(finalizer));
+        self
+    }
+
+    pub fn next(&self) -> Option<Value> {
+        self.iterator.borrow_mut().next()
+    }
+}
+
+impl Drop for HostIterator {
+    fn drop(&mut self) {
+        if let Some(finalizer) = self.finalizer.take() {
+            finalizer();
+        }
+    }
+}
+
+impl Debug for HostIterator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "iterator({:?})", self.item_type)
+    }
+}
+
+/// Iterators are equal if they're the same iterator.
+impl PartialEq for HostIterator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
 }
 
+#[derive(Debug, PartialEq)]
+pub struct FunctionValue {
+    pub parameters: Vec<(Symbol, Option<usize>, Type)>, // Option<usize> is the shadow_id of the parameter.
+    pub return_type: Type,
+    pub body: FunctionBody,
+    pub parent_environment: usize, // ID of the environment in which the function was defined.
+}
+
+impl FunctionValue {
+    /// The declaration of the function with the name, like `fn name(x: number) -> number { return x * 2; }`,
+    /// or None if it's written in Rust.
+    pub fn source(&self, name: &str) -> Option<String> {
+        let body = match &self.body {
+            FunctionBody::Statement(body) => body.clone(),
+            FunctionBody::Bytecode(function) => function.body.clone().unwrap(),
+            FunctionBody::RustClosure { .. } => return None,
+        };
+        let parameters: Vec<_> = self
+            .parameters
+            .iter()
+            .map(|(parameter, _, parameter_type)| format!("{parameter}: {parameter_type}"))
+            .collect();
+        let parameters = parameters.join(", ");
+        Some(match self.return_type {
+            Type::Void => format!("fn {name}({parameters}) {body}"),
+            ref return_type => format!("fn {name}({parameters}) -> {return_type} {body}"),
+        })
+    }
+}
+
+/// Writes the value as print does.
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Value::Void => write!(f, "void"),
-            Value::Number(number) => write!(f, "{number}"),
-            Value::String(string) => write!(f, "{string}"),
-            Value::Boolean(boolean) => write!(f, "{boolean}"),
-            Value::Tuple(values) => {
-                write!(f, "(")?;
-                let mut values = values.iter();
-                if let Some(first_value) = values.next() {
-                    write!(f, "{first_value}")?;
-                    for value in values {
-                        write!(f, ", {value}")?;
-                    }
-                }
-                write!(f, ")")
-            }
-            Value::Function { .. } => {
-                write!(f, "function") // TODO: Improve this
-            }
-        }
+        write!(f, "{}", ValueFormat::default().display(self))
     }
 }
 
 impl Value {
+    /// The field at the index of a tuple or record.
+    pub fn field(&self, index: usize) -> &Value {
+        match self {
+            Value::Tuple(values) => &values[index],
+            Value::Record(fields) => &fields[index].1,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The field at the index of a tuple or record, which is copied first if it's shared with other values.
+    pub fn field_mut(&mut self, index: usize) -> &mut Value {
+        match self {
+            Value::Tuple(values) => &mut Rc::make_mut(values)[index],
+            Value::Record(fields) => &mut Rc::make_mut(fields)[index].1,
+            _ => unreachable!(),
+        }
+    }
+
     pub fn value_type(&self) -> Type {
         match self {
             Value::Void => Type::Void,
@@ -103,16 +233,38 @@ impl Value {
             Value::Tuple(values) => {
                 Type::Tuple(values.iter().map(|value| value.value_type()).collect())
             }
-            Value::Function {
-                parameters,
-                return_type,
-                ..
-            } => Type::Function {
-                parameters: parameters
+            Value::Record(fields) => Type::Record(
+                fields
+                    .iter()
+                    .map(|(name, value)| (*name, value.value_type()))
+                    .collect(),
+            ),
+            Value::Reference(reference) => Type::Reference(reference.value_type.clone().into()),
+            Value::Function(function) => Type::Function {
+                parameters: function
+                    .parameters
                     .iter()
                     .map(|(_, _, parameter_type)| parameter_type.clone())
                     .collect(),
-                return_type: return_type.clone().into(),
+                return_type: function.return_type.clone().into(),
+            },
+            Value::Result(result) => match &**result {
+                Ok(value) => Type::Result {
+                    value: value.value_type().into(),
+                    error: Type::Never.into(),
+                },
+                Err(error) => Type::Result {
+                    value: Type::Never.into(),
+                    error: error.value_type().into(),
+                },
+            },
+            Value::Option(Some(value)) => Type::Option(value.value_type().into()),
+            Value::Option(None) => Type::Option(Type::Never.into()),
+            Value::Iterator(iterator) => Type::Iterator(iterator.item_type.clone().into()),
+            Value::Channel(channel) => Type::Channel(channel.message_type.clone().into()),
+            Value::Task(task) => Type::Task {
+                result: task.result_type.clone().into(),
+                message: task.message_type.clone().map(Box::new),
             },
         }
     }
@@ -122,10 +274,18 @@ impl Value {
 pub enum TokenType {
     Plus,
     Minus,
+    PlusPlus,
+    MinusMinus,
     Star,
     Slash,
     DoubleAnd,
     DoubleOr,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
+    DoubleLess,
+    DoubleGreater,
     Equal,
     DoubleEqual,
     Exclamation,
@@ -139,44 +299,460 @@ pub enum TokenType {
     LeftBrace,
     RightBrace,
     Arrow,
+    FatArrow,
     Let,
+    Static,
     If,
     Else,
     While,
+    Loop,
+    For,
+    In,
+    Break,
+    Raise,
+    Try,
+    Catch,
+    Match,
+    Case,
     Fn,
     Return,
     Colon,
     Semicolon,
     Comma,
     Dot,
+    DotDot,
+    Question,
     Number,
     String,
     Bool,
+    Result,
+    Ok,
+    Err,
+    Option,
+    Some,
+    None,
     Literal(Value),
-    Variable(String),
+    InterpolatedString(Rc<InterpolatedString>),
+    Variable(Symbol),
+    DocComment(Rc<str>), // The text after ///.
 }
 
-#[derive(Debug)]
+/// A string with ${value} or ${value:spec} in it.
+#[derive(Debug, PartialEq)]
+pub struct InterpolatedString {
+    pub source: String,                    // The text between the quotes, as written.
+    pub strings: Vec<String>, // The text around the values, one more than there are values.
+    pub values: Vec<(Vec<Token>, String)>, // The tokens of each value and its format spec, which may be empty.
+}
+
+impl TokenType {
+    /// The name of the kind of token, without its value.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenType::Plus => "Plus",
+            TokenType::Minus => "Minus",
+            TokenType::PlusPlus => "PlusPlus",
+            TokenType::MinusMinus => "MinusMinus",
+            TokenType::Star => "Star",
+            TokenType::Slash => "Slash",
+            TokenType::DoubleAnd => "DoubleAnd",
+            TokenType::DoubleOr => "DoubleOr",
+            TokenType::Ampersand => "Ampersand",
+            TokenType::Pipe => "Pipe",
+            TokenType::Caret => "Caret",
+            TokenType::Tilde => "Tilde",
+            TokenType::DoubleLess => "DoubleLess",
+            TokenType::DoubleGreater => "DoubleGreater",
+            TokenType::Equal => "Equal",
+            TokenType::DoubleEqual => "DoubleEqual",
+            TokenType::Exclamation => "Exclamation",
+            TokenType::ExclamationEqual => "ExclamationEqual",
+            TokenType::Less => "Less",
+            TokenType::LessEqual => "LessEqual",
+            TokenType::Greater => "Greater",
+            TokenType::GreaterEqual => "GreaterEqual",
+            TokenType::LeftParenthesis => "LeftParenthesis",
+            TokenType::RightParenthesis => "RightParenthesis",
+            TokenType::LeftBrace => "LeftBrace",
+            TokenType::RightBrace => "RightBrace",
+            TokenType::Arrow => "Arrow",
+            TokenType::FatArrow => "FatArrow",
+            TokenType::Let => "Let",
+            TokenType::Static => "Static",
+            TokenType::If => "If",
+            TokenType::Else => "Else",
+            TokenType::While => "While",
+            TokenType::Loop => "Loop",
+            TokenType::For => "For",
+            TokenType::In => "In",
+            TokenType::Break => "Break",
+            TokenType::Raise => "Raise",
+            TokenType::Try => "Try",
+            TokenType::Catch => "Catch",
+            TokenType::Match => "Match",
+            TokenType::Case => "Case",
+            TokenType::Fn => "Fn",
+            TokenType::Return => "Return",
+            TokenType::Colon => "Colon",
+            TokenType::Semicolon => "Semicolon",
+            TokenType::Comma => "Comma",
+            TokenType::Dot => "Dot",
+            TokenType::DotDot => "DotDot",
+            TokenType::Question => "Question",
+            TokenType::Number => "Number",
+            TokenType::String => "String",
+            TokenType::Bool => "Bool",
+            TokenType::Result => "Result",
+            TokenType::Ok => "Ok",
+            TokenType::Err => "Err",
+            TokenType::Option => "Option",
+            TokenType::Some => "Some",
+            TokenType::None => "None",
+            TokenType::Literal(_) => "Literal",
+            TokenType::InterpolatedString(_) => "InterpolatedString",
+            TokenType::Variable(_) => "Variable",
+            TokenType::DocComment(_) => "DocComment",
+        }
+    }
+}
+
+/// Writes the string in quotes, as it's written in programs. Strings have no escapes, so its text is written as it is.
+pub fn quote(string: &str) -> String {
+    format!("\"{string}\"")
+}
+
+/// Formats the token as it's written in programs.
+impl Display for TokenType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lexeme = match self {
+            TokenType::Plus => "+",
+            TokenType::Minus => "-",
+            TokenType::PlusPlus => "++",
+            TokenType::MinusMinus => "--",
+            TokenType::Star => "*",
+            TokenType::Slash => "/",
+            TokenType::DoubleAnd => "&&",
+            TokenType::DoubleOr => "||",
+            TokenType::Ampersand => "&",
+            TokenType::Pipe => "|",
+            TokenType::Caret => "^",
+            TokenType::Tilde => "~",
+            TokenType::DoubleLess => "<<",
+            TokenType::DoubleGreater => ">>",
+            TokenType::Equal => "=",
+            TokenType::DoubleEqual => "==",
+            TokenType::Exclamation => "!",
+            TokenType::ExclamationEqual => "!=",
+            TokenType::Less => "<",
+            TokenType::LessEqual => "<=",
+            TokenType::Greater => ">",
+            TokenType::GreaterEqual => ">=",
+            TokenType::LeftParenthesis => "(",
+            TokenType::RightParenthesis => ")",
+            TokenType::LeftBrace => "{",
+            TokenType::RightBrace => "}",
+            TokenType::Arrow => "->",
+            TokenType::FatArrow => "=>",
+            TokenType::Let => "let",
+            TokenType::Static => "static",
+            TokenType::If => "if",
+            TokenType::Else => "else",
+            TokenType::While => "while",
+            TokenType::Loop => "loop",
+            TokenType::For => "for",
+            TokenType::In => "in",
+            TokenType::Break => "break",
+            TokenType::Raise => "raise",
+            TokenType::Try => "try",
+            TokenType::Catch => "catch",
+            TokenType::Match => "match",
+            TokenType::Case => "case",
+            TokenType::Fn => "fn",
+            TokenType::Return => "return",
+            TokenType::Colon => ":",
+            TokenType::Semicolon => ";",
+            TokenType::Comma => ",",
+            TokenType::Dot => ".",
+            TokenType::DotDot => "..",
+            TokenType::Question => "?",
+            TokenType::Number => "number",
+            TokenType::String => "string",
+            TokenType::Bool => "bool",
+            TokenType::Result => "result",
+            TokenType::Ok => "Ok",
+            TokenType::Err => "Err",
+            TokenType::Option => "option",
+            TokenType::Some => "Some",
+            TokenType::None => "None",
+            TokenType::Literal(Value::String(string)) => return write!(f, "{}", quote(string)),
+            TokenType::Literal(value) => return write!(f, "{value}"),
+            TokenType::InterpolatedString(string) => return write!(f, "\"{}\"", string.source),
+            TokenType::Variable(name) => return write!(f, "{name}"),
+            TokenType::DocComment(text) => return write!(f, "///{text}"),
+        };
+        write!(f, "{lexeme}")
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct Token {
     pub lines: (usize, usize),
     pub token_type: TokenType,
 }
 
+/// Formats the lines, kind and text of the token.
+impl Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines = if self.lines.0 == self.lines.1 {
+            self.lines.0.to_string()
+        } else {
+            format!("{}-{}", self.lines.0, self.lines.1)
+        };
+        write!(
+            f,
+            "{lines:<8} {:<17} {}",
+            self.token_type.name(),
+            self.token_type
+        )
+    }
+}
+
 pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
-    let mut tokens = vec![];
-    let mut errors = vec![];
+    tokenize_chars(program.chars())
+}
+
+/// Does the program stop in the middle of a string, brackets or an expression, so that it needs more lines?
+pub fn is_incomplete(program: &str) -> bool {
+    // Strings don't have escapes, so an odd number of quotes outside comments means the last string isn't closed.
+    let mut in_string = false;
     let mut chars = program.chars().peekable();
-    let mut line = 1;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => in_string = !in_string,
+            '/' if !in_string && chars.peek() == Some(&'/') => {
+                while chars.next_if(|&ch| ch != '\n').is_some() {}
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        return true;
+    }
+    let Ok(tokens) = tokenize(program) else {
+        return false;
+    };
 
+    let mut depth = 0;
+    for token in &tokens {
+        match token.token_type {
+            TokenType::LeftParenthesis | TokenType::LeftBrace => depth += 1,
+            TokenType::RightParenthesis | TokenType::RightBrace => depth -= 1,
+            _ => {}
+        }
+    }
+    if depth > 0 {
+        return true;
+    }
+
+    matches!(
+        tokens.last().map(|token| &token.token_type),
+        Some(
+            TokenType::Plus
+                | TokenType::Minus
+                | TokenType::Star
+                | TokenType::Slash
+                | TokenType::DoubleAnd
+                | TokenType::DoubleOr
+                | TokenType::Ampersand
+                | TokenType::Pipe
+                | TokenType::Caret
+                | TokenType::Tilde
+                | TokenType::DoubleLess
+                | TokenType::DoubleGreater
+                | TokenType::Equal
+                | TokenType::DoubleEqual
+                | TokenType::Exclamation
+                | TokenType::ExclamationEqual
+                | TokenType::Less
+                | TokenType::LessEqual
+                | TokenType::Greater
+                | TokenType::GreaterEqual
+                | TokenType::Arrow
+                | TokenType::Let
+                | TokenType::Static
+                | TokenType::If
+                | TokenType::Else
+                | TokenType::While
+                | TokenType::Loop
+                | TokenType::For
+                | TokenType::In
+                | TokenType::Raise
+                | TokenType::Try
+                | TokenType::Catch
+                | TokenType::Match
+                | TokenType::Case
+                | TokenType::FatArrow
+                | TokenType::Fn
+                | TokenType::Colon
+                | TokenType::Comma
+                | TokenType::Dot
+                | TokenType::DotDot
+        )
+    )
+}
+
+/// Tokenizes a program read as UTF-8 from a reader, without reading the whole program into memory first.
+pub fn tokenize_reader(reader: impl Read) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
+    let mut chars = ReaderChars {
+        bytes: BufReader::new(reader).bytes(),
+        error: None,
+    };
+    let result = tokenize_chars(&mut chars);
+    match chars.error {
+        Some(error) => {
+            let mut errors = result.err().unwrap_or_default();
+            errors.push(diagnostic!(READ_FAILED, error).into());
+            Err(errors)
+        }
+        None => result,
+    }
+}
+
+/// Reads the text of an interpolated value after its ${, up to the } which closes it, skipping the }.
+/// Braces in strings in the value don't count. Returns None if the value isn't closed.
+fn read_interpolated_value(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut value = String::new();
+    let mut depth = 0;
+    let mut in_string = false;
     loop {
-        let Some(ch) = chars.next() else { break; };
+        let ch = chars.next()?;
+        match ch {
+            '"' => in_string = !in_string,
+            '{' if !in_string => depth += 1,
+            '}' if !in_string && depth == 0 => return Some(value),
+            '}' if !in_string => depth -= 1,
+            _ => {}
+        }
+        value.push(ch);
+    }
+}
+
+/// Splits an interpolated value at its first colon outside strings, into the expression and the format spec.
+fn split_format_spec(value: &str) -> (&str, &str) {
+    let mut in_string = false;
+    for (index, ch) in value.char_indices() {
+        match ch {
+            '"' => in_string = !in_string,
+            ':' if !in_string => return (&value[..index], &value[index + 1..]),
+            _ => {}
+        }
+    }
+    (value, "")
+}
+
+/// Decodes the characters of a reader. Stops at the first read error or invalid UTF-8, storing the error.
+struct ReaderChars<R: Read> {
+    bytes: io::Bytes<BufReader<R>>,
+    error: Option<io::Error>,
+}
+
+impl<R: Read> ReaderChars<R> {
+    fn fail(&mut self, error: io::Error) -> Option<char> {
+        self.error = Some(error);
+        None
+    }
+}
+
+impl<R: Read> Iterator for ReaderChars<R> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.error.is_some() {
+            return None;
+        }
+
+        let mut buffer = [0; 4];
+        buffer[0] = match self.bytes.next()? {
+            Ok(byte) => byte,
+            Err(error) => return self.fail(error),
+        };
+        // The number of bytes in the character, from its first byte.
+        let width = match buffer[0] {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 0,
+        };
+        for byte in buffer.iter_mut().take(width).skip(1) {
+            *byte = match self.bytes.next() {
+                Some(Ok(next_byte)) => next_byte,
+                Some(Err(error)) => return self.fail(error),
+                None => break,
+            };
+        }
+
+        match std::str::from_utf8(&buffer[..width]) {
+            Ok(character) if width > 0 => character.chars().next(),
+            _ => self.fail(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the program isn't valid UTF-8",
+            )),
+        }
+    }
+}
+
+/// Tokenizes characters as they're produced.
+pub fn tokenize_chars(
+    chars: impl Iterator<Item = char>,
+) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
+    tokenize_from(chars, 1)
+}
+
+/// Tokenizes part of a program which starts at the given line.
+pub fn tokenize_from_line(
+    program: &str,
+    first_line: usize,
+) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
+    tokenize_from(program.chars(), first_line)
+}
+
+fn tokenize_from(
+    chars: impl Iterator<Item = char>,
+    first_line: usize,
+) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
+    let mut tokens = vec![];
+    let mut errors = vec![];
+    let mut chars = chars.peekable();
+    let mut line = first_line;
+
+    // Skip a #! line at the start of the program, so scripts can be run with #!/usr/bin/env decibelle.
+    if first_line == 1 && chars.peek() == Some(&'#') {
+        chars.next();
+        if chars.peek() == Some(&'!') {
+            while chars.next_if(|&ch| ch != '\n').is_some() {}
+        } else {
+            errors.push(diagnostic!(INVALID_TOKEN, "#", 1).into());
+        }
+    }
+
+    while let Some(ch) = chars.next() {
         match ch {
             ' ' | '\t' | '\r' => {}
             '\n' => line += 1,
-            '+' => tokens.push(Token {
-                lines: (line, line),
-                token_type: TokenType::Plus,
-            }),
+            '+' => match chars.peek() {
+                Some('+') => {
+                    tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::PlusPlus,
+                    });
+                    chars.next();
+                }
+                _ => tokens.push(Token {
+                    lines: (line, line),
+                    token_type: TokenType::Plus,
+                }),
+            },
             '-' => match chars.peek() {
                 Some('>') => {
                     tokens.push(Token {
@@ -185,6 +761,13 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     });
                     chars.next();
                 }
+                Some('-') => {
+                    tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::MinusMinus,
+                    });
+                    chars.next();
+                }
                 _ => {
                     tokens.push(Token {
                         lines: (line, line),
@@ -196,10 +779,29 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                 lines: (line, line),
                 token_type: TokenType::Star,
             }),
-            '/' => tokens.push(Token {
-                lines: (line, line),
-                token_type: TokenType::Slash,
-            }),
+            '/' => match chars.peek() {
+                // Comments last until the end of the line. Comments starting with exactly three slashes document
+                // the declaration after them, so they're kept.
+                Some('/') => {
+                    chars.next();
+                    let is_doc_comment =
+                        chars.next_if_eq(&'/').is_some() && chars.peek() != Some(&'/');
+                    let mut text = String::new();
+                    while let Some(ch) = chars.next_if(|&ch| ch != '\n') {
+                        text.push(ch);
+                    }
+                    if is_doc_comment {
+                        tokens.push(Token {
+                            lines: (line, line),
+                            token_type: TokenType::DocComment(text.into()),
+                        });
+                    }
+                }
+                _ => tokens.push(Token {
+                    lines: (line, line),
+                    token_type: TokenType::Slash,
+                }),
+            },
             '(' => tokens.push(Token {
                 lines: (line, line),
                 token_type: TokenType::LeftParenthesis,
@@ -228,9 +830,22 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                 lines: (line, line),
                 token_type: TokenType::Comma,
             }),
-            '.' => tokens.push(Token {
+            '.' => match chars.peek() {
+                Some('.') => {
+                    tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::DotDot,
+                    });
+                    chars.next();
+                }
+                _ => tokens.push(Token {
+                    lines: (line, line),
+                    token_type: TokenType::Dot,
+                }),
+            },
+            '?' => tokens.push(Token {
                 lines: (line, line),
-                token_type: TokenType::Dot,
+                token_type: TokenType::Question,
             }),
             '&' => match chars.peek() {
                 Some('&') => {
@@ -240,12 +855,10 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     });
                     chars.next();
                 }
-                _ => errors.push(
-                    format!(
-                        "Tokenizer error: Invalid token {ch} at line {line}. (Did you mean && ?)"
-                    )
-                    .into(),
-                ),
+                _ => tokens.push(Token {
+                    lines: (line, line),
+                    token_type: TokenType::Ampersand,
+                }),
             },
             '|' => match chars.peek() {
                 Some('|') => {
@@ -255,13 +868,19 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     });
                     chars.next();
                 }
-                _ => errors.push(
-                    format!(
-                        "Tokenizer error: Invalid token {ch} at line {line}. (Did you mean || ?)"
-                    )
-                    .into(),
-                ),
+                _ => tokens.push(Token {
+                    lines: (line, line),
+                    token_type: TokenType::Pipe,
+                }),
             },
+            '^' => tokens.push(Token {
+                lines: (line, line),
+                token_type: TokenType::Caret,
+            }),
+            '~' => tokens.push(Token {
+                lines: (line, line),
+                token_type: TokenType::Tilde,
+            }),
             '=' => match chars.peek() {
                 Some('=') => {
                     tokens.push(Token {
@@ -270,6 +889,13 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     });
                     chars.next();
                 }
+                Some('>') => {
+                    tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::FatArrow,
+                    });
+                    chars.next();
+                }
                 _ => {
                     tokens.push(Token {
                         lines: (line, line),
@@ -300,6 +926,13 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     });
                     chars.next();
                 }
+                Some('<') => {
+                    tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::DoubleLess,
+                    });
+                    chars.next();
+                }
                 _ => {
                     tokens.push(Token {
                         lines: (line, line),
@@ -315,6 +948,13 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     });
                     chars.next();
                 }
+                Some('>') => {
+                    tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::DoubleGreater,
+                    });
+                    chars.next();
+                }
                 _ => {
                     tokens.push(Token {
                         lines: (line, line),
@@ -324,34 +964,73 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
             },
             '"' => {
                 let mut string = String::new();
+                let mut source = String::new();
+                let mut interpolation = InterpolatedString {
+                    source: String::new(),
+                    strings: vec![],
+                    values: vec![],
+                };
                 let start_line = line;
                 loop {
                     match chars.peek() {
                         None => {
-                            errors.push(
-                                format!(
-                                    "Tokenizer error: \" at line {line} doesn't have a closing \"."
-                                )
-                                .into(),
-                            );
+                            errors.push(diagnostic!(UNCLOSED_STRING, line).into());
                             break;
                         }
                         Some(&ch) => {
                             chars.next();
                             match ch {
+                                '"' if interpolation.values.is_empty() => {
+                                    tokens.push(Token {
+                                        lines: (start_line, line),
+                                        token_type: TokenType::Literal(Value::String(
+                                            string.into(),
+                                        )),
+                                    });
+                                    break;
+                                }
                                 '"' => {
+                                    interpolation.source = source;
+                                    interpolation.strings.push(string);
                                     tokens.push(Token {
                                         lines: (start_line, line),
-                                        token_type: TokenType::Literal(Value::String(string)),
+                                        token_type: TokenType::InterpolatedString(
+                                            interpolation.into(),
+                                        ),
                                     });
                                     break;
                                 }
+                                '$' if chars.peek() == Some(&'{') => {
+                                    chars.next();
+                                    source.push_str("${");
+                                    let value_line = line;
+                                    let Some(value) = read_interpolated_value(&mut chars) else {
+                                        errors.push(
+                                            diagnostic!(UNCLOSED_INTERPOLATION, value_line).into(),
+                                        );
+                                        break;
+                                    };
+                                    source.push_str(&value);
+                                    source.push('}');
+                                    line += value.matches('\n').count();
+                                    // Expressions don't have colons, so the first colon outside strings starts the spec.
+                                    let (expression, spec) = split_format_spec(&value);
+                                    match tokenize_from(expression.chars(), value_line) {
+                                        Ok(value_tokens) => interpolation
+                                            .values
+                                            .push((value_tokens, spec.to_string())),
+                                        Err(value_errors) => errors.extend(value_errors),
+                                    }
+                                    interpolation.strings.push(std::mem::take(&mut string));
+                                }
                                 '\n' => {
                                     line += 1;
                                     string.push('\n');
+                                    source.push('\n');
                                 }
                                 ch => {
                                     string.push(ch);
+                                    source.push(ch);
                                 }
                             }
                         }
@@ -362,8 +1041,7 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                 let mut number = char.to_string();
 
                 // Read the part of the number before floating point.
-                loop {
-                    let Some(char) = chars.peek() else { break };
+                while let Some(char) = chars.peek() {
                     if char.is_ascii_digit() {
                         number.push(*char);
                         chars.next();
@@ -380,10 +1058,23 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     if *char == '.'
                         && tokens.last().map(|token| &token.token_type) != Some(&TokenType::Dot)
                     {
-                        number.push('.');
                         chars.next();
-                        loop {
-                            let Some(char) = chars.peek() else { break };
+                        // A second dot makes a range like 10..20, so the number ends before the first.
+                        if chars.next_if_eq(&'.').is_some() {
+                            tokens.push(Token {
+                                lines: (line, line),
+                                token_type: TokenType::Literal(Value::Number(
+                                    number.parse().unwrap(),
+                                )),
+                            });
+                            tokens.push(Token {
+                                lines: (line, line),
+                                token_type: TokenType::DotDot,
+                            });
+                            continue;
+                        }
+                        number.push('.');
+                        while let Some(char) = chars.peek() {
                             if char.is_ascii_digit() {
                                 number.push(*char);
                                 chars.next();
@@ -402,8 +1093,7 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
             }
             char if char.is_ascii_alphabetic() || char == '_' => {
                 let mut word = char.to_string();
-                loop {
-                    let Some(char) = chars.peek() else { break };
+                while let Some(char) = chars.peek() {
                     if char.is_ascii_alphanumeric() || *char == '_' {
                         word.push(*char);
                         chars.next();
@@ -429,6 +1119,10 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                         lines: (line, line),
                         token_type: TokenType::Let,
                     }),
+                    "static" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Static,
+                    }),
                     "if" => tokens.push(Token {
                         lines: (line, line),
                         token_type: TokenType::If,
@@ -441,6 +1135,42 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                         lines: (line, line),
                         token_type: TokenType::While,
                     }),
+                    "loop" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Loop,
+                    }),
+                    "for" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::For,
+                    }),
+                    "in" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::In,
+                    }),
+                    "break" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Break,
+                    }),
+                    "raise" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Raise,
+                    }),
+                    "try" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Try,
+                    }),
+                    "catch" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Catch,
+                    }),
+                    "match" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Match,
+                    }),
+                    "case" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Case,
+                    }),
                     "number" => tokens.push(Token {
                         lines: (line, line),
                         token_type: TokenType::Number,
@@ -453,6 +1183,30 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                         lines: (line, line),
                         token_type: TokenType::Bool,
                     }),
+                    "result" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Result,
+                    }),
+                    "Ok" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Ok,
+                    }),
+                    "Err" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Err,
+                    }),
+                    "option" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Option,
+                    }),
+                    "Some" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::Some,
+                    }),
+                    "None" => tokens.push(Token {
+                        lines: (line, line),
+                        token_type: TokenType::None,
+                    }),
                     "fn" => tokens.push(Token {
                         lines: (line, line),
                         token_type: TokenType::Fn,
@@ -463,11 +1217,11 @@ pub fn tokenize(program: &str) -> Result<Vec<Token>, Vec<Box<dyn Error>>> {
                     }),
                     _ => tokens.push(Token {
                         lines: (line, line),
-                        token_type: TokenType::Variable(word),
+                        token_type: TokenType::Variable(Symbol::intern(&word)),
                     }),
                 }
             }
-            _ => errors.push(format!("Tokenizer error: Invalid token {ch} at line {line}.").into()),
+            _ => errors.push(diagnostic!(INVALID_TOKEN, ch, line).into()),
         }
     }
 
@@ -0,0 +1,28 @@
+use crate::tokenizer::{quote, Value};
+
+/// Something a traced program did.
+#[derive(Debug, Clone)]
+pub enum TraceEvent {
+    /// A statement started running.
+    Statement { lines: (usize, usize) },
+    /// A variable was declared or assigned to, by the last statement which started.
+    /// The target is the variable or tuple field, like x or x.0.
+    Assignment { target: String, value: Value },
+}
+
+/// The function traced programs send their events to.
+pub type Tracer = Box<dyn FnMut(&TraceEvent)>;
+
+/// Formats the event on a line, showing the first line of the statement's source from the lines of the program.
+pub fn format_event(event: &TraceEvent, source_lines: &[String]) -> String {
+    match event {
+        TraceEvent::Statement { lines } => {
+            let source = source_lines.get(lines.0 - 1).map_or("", |line| line.trim());
+            format!("[trace] line {}: {source}", lines.0)
+        }
+        TraceEvent::Assignment { target, value } => match value {
+            Value::String(string) => format!("[trace]   {target} = {}", quote(string)),
+            value => format!("[trace]   {target} = {value}"),
+        },
+    }
+}
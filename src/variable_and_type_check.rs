@@ -1,32 +1,87 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 
 use crate::{
-    interpreter::Environment,
+    channel::can_send,
+    exhaustiveness::missing_pattern,
+    interpreter::{error_type, Variables},
+    messages::diagnostic,
     parser::{
-        BinaryOperation, CompilerError, Expression, ExpressionType, Statement, StatementType,
-        UnaryOperation,
+        BinaryOperation, CompilerError, Expression, ExpressionType, Field, Pattern, Statement,
+        StatementType, UnaryOperation,
     },
-    tokenizer::Type,
+    symbol::Symbol,
+    tokenizer::{Type, Value},
 };
 
+/// The variables declared in a scope, with the shadow_id, slot and type of each.
+#[derive(Default)]
+pub struct Scope {
+    variables: HashMap<Symbol, (usize, usize, Type)>,
+    slot_count: usize,                          // Includes shadowed variables.
+    argument_typed_slots: HashSet<usize>,       // Only used in the global scope.
+    operators: Vec<HostOperator>,               // Only used in the global scope.
+    static_variables: HashSet<(Symbol, usize)>, // Name and shadow_id of the static variables, which are stored as globals.
+    break_targets: Vec<BreakTarget>,            // Only used in the global scope.
+    return_types: Vec<Option<Type>>, // The return types of the functions being checked, or None where return can't be used. Only used in the global scope.
+}
+
+/// A binary operation the host registered for operand types the language doesn't support it on,
+/// which is a call to the global function with the name.
+#[derive(Clone)]
+pub struct HostOperator {
+    pub operation: BinaryOperation,
+    pub left_type: Type,
+    pub right_type: Type,
+    pub function: Symbol,
+}
+
+/// What a break statement would leave.
+enum BreakTarget {
+    LoopStatement,      // A while or for loop.
+    Loop(Option<Type>), // The type of the loop's value, if a break has set it.
+    None,               // Breaks can't leave functions or block expressions.
+}
+
+/// Checks types and resolves every variable to a shadow_id and a slot in its environment.
 pub fn check_types(
     statements: &mut Vec<Statement>,
-    global_variables: &Environment,
+    variables: &mut Variables,
 ) -> Result<(), Vec<CompilerError>> {
     let mut errors = vec![];
-    let mut global_types: HashMap<String, (usize, Type)> = HashMap::new();
-    for ((name, shadow_id), value) in global_variables.variables.iter() {
-        if !global_types.contains_key(name) || global_types[name].0 < *shadow_id {
-            global_types.insert(name.clone(), (*shadow_id, value.value_type()));
+    let global_slots = &variables.environments[&0].slots;
+    let mut global_scope = Scope {
+        variables: HashMap::new(),
+        slot_count: global_slots.len(),
+        argument_typed_slots: variables.argument_typed_slots.clone(),
+        operators: variables.operators.clone(),
+        static_variables: HashSet::new(),
+        break_targets: vec![],
+        return_types: vec![Some(Type::Any)],
+    };
+    for ((name, shadow_id), slot) in variables.global_slots.iter() {
+        if !global_scope.variables.contains_key(name) || global_scope.variables[name].0 < *shadow_id
+        {
+            global_scope
+                .variables
+                .insert(*name, (*shadow_id, *slot, global_slots[*slot].value_type()));
         }
     }
-    let mut stack = vec![global_types.clone()];
+    let mut stack = vec![global_scope];
 
+    // The top level of a program can return any value to stop it.
     for statement in statements {
-        check_statement_type(statement, &mut errors, &mut stack, None);
+        check_statement_type(statement, &mut errors, &mut stack, Some(&Type::Any));
     }
 
     if errors.is_empty() {
+        // Static variables may not be set by the time the program ends, so their slots are reserved
+        // to stop later programs from reusing them.
+        if stack[0].slot_count > global_slots.len() {
+            variables.reserve_globals(stack[0].slot_count);
+        }
         Ok(())
     } else {
         Err(errors)
@@ -36,14 +91,14 @@ pub fn check_types(
 fn check_statement_type(
     statement: &mut Statement,
     errors: &mut Vec<CompilerError>,
-    stack: &mut Vec<HashMap<String, (usize, Type)>>,
-    current_function_declaration_return_type: Option<&Type>, // Are we in a function definition, if so what is that function's return type?
+    stack: &mut Vec<Scope>,
+    current_function_declaration_return_type: Option<&Type>, // The return type of the function we're in, any at the top level, or None if we can't return.
 ) -> bool {
     // Will this statement necessarily return something?
     match &mut statement.statement {
         StatementType::Block(statements) => {
             let mut will_return = false;
-            stack.push(HashMap::new());
+            stack.push(Scope::default());
             for statement in statements {
                 let statement_will_return = check_statement_type(
                     statement,
@@ -56,6 +111,19 @@ fn check_statement_type(
             stack.pop();
             will_return
         }
+        StatementType::Expression(Expression {
+            expression_type: ExpressionType::Loop(body),
+            ..
+        }) => {
+            // Nothing else is on the stack around a loop statement, so it can return.
+            check_loop_type(
+                body,
+                errors,
+                stack,
+                current_function_declaration_return_type,
+            );
+            false
+        }
         StatementType::Expression(expression) => {
             check_expression_type(expression, errors, stack);
             false
@@ -88,7 +156,7 @@ fn check_statement_type(
             if expression_type != Type::Boolean {
                 errors.push(CompilerError {
                     lines: expression.lines,
-                    error: "Boolean expression expected for if condition.".into(),
+                    error: diagnostic!(IF_CONDITION_NOT_BOOLEAN),
                 });
             }
             then_will_return && else_will_return
@@ -97,36 +165,55 @@ fn check_statement_type(
             variable,
             variable_type,
             value,
+            is_static,
             shadow_id,
+            slot,
+            ..
         } => {
             let Some(value_type) = check_expression_type(value, errors, stack) else {
                 return false;
             };
 
-            if let Some(variable_type) = variable_type {
-                if *variable_type != value_type {
+            let value_type = match variable_type {
+                Some(variable_type) if !can_assign(variable_type, &value_type) => {
                     errors.push(CompilerError {
                         lines: statement.lines,
-                        error: format!("Expression of type {value_type:?} can't be assigned to a variable of type {variable_type:?}.")
+                        error: diagnostic!(LET_TYPE_MISMATCH, value_type, variable_type),
                     });
                     return false;
                 }
-            }
+                Some(variable_type) => variable_type.clone(),
+                None => value_type,
+            };
 
-            let new_shadow_id = set_type(variable, value_type, stack);
+            let (new_shadow_id, new_slot) = if *is_static {
+                if stack.len() == 1 {
+                    errors.push(CompilerError {
+                        lines: statement.lines,
+                        error: diagnostic!(STATIC_OUTSIDE_FUNCTION),
+                    });
+                    return false;
+                }
+                set_static_type(*variable, value_type, stack)
+            } else {
+                set_type(*variable, value_type, stack)
+            };
             *shadow_id = Some(new_shadow_id);
+            *slot = Some(new_slot);
             false
         }
         StatementType::While {
             expression,
             statement,
         } => {
+            stack[0].break_targets.push(BreakTarget::LoopStatement);
             check_statement_type(
                 statement,
                 errors,
                 stack,
                 current_function_declaration_return_type,
             );
+            stack[0].break_targets.pop();
 
             let Some(expression_type) = check_expression_type(expression, errors, stack) else {
                 return false;
@@ -134,20 +221,140 @@ fn check_statement_type(
             if expression_type != Type::Boolean {
                 errors.push(CompilerError {
                     lines: expression.lines,
-                    error: "Boolean expression expected for while condition.".into(),
+                    error: diagnostic!(WHILE_CONDITION_NOT_BOOLEAN),
                 })
             }
             false // TODO: Maybe allow infinite loops to return true - similar to how Rust handles infinite loops.
         }
+        StatementType::For {
+            variable,
+            shadow_id,
+            expression,
+            statement,
+        } => {
+            // Each grapheme cluster of a string, or each value of an iterator, is in the loop's scope.
+            let item_type = match check_expression_type(expression, errors, stack) {
+                Some(Type::String) | None => Type::String,
+                Some(Type::Iterator(item_type)) => *item_type,
+                Some(expression_type) => {
+                    errors.push(CompilerError {
+                        lines: expression.lines,
+                        error: diagnostic!(FOR_NOT_SEQUENCE, expression, expression_type),
+                    });
+                    Type::String
+                }
+            };
+            stack.push(Scope::default());
+            let (new_shadow_id, _) = set_type(*variable, item_type, stack);
+            *shadow_id = Some(new_shadow_id);
+            stack[0].break_targets.push(BreakTarget::LoopStatement);
+            check_statement_type(
+                statement,
+                errors,
+                stack,
+                current_function_declaration_return_type,
+            );
+            stack[0].break_targets.pop();
+            stack.pop();
+            false
+        }
+        StatementType::Try {
+            statement,
+            variable,
+            shadow_id,
+            catch_statement,
+        } => {
+            let try_will_return = check_statement_type(
+                statement,
+                errors,
+                stack,
+                current_function_declaration_return_type,
+            );
+
+            // The error is in the catch statement's scope.
+            stack.push(Scope::default());
+            let (new_shadow_id, _) = set_type(*variable, error_type(), stack);
+            *shadow_id = Some(new_shadow_id);
+            let catch_will_return = check_statement_type(
+                catch_statement,
+                errors,
+                stack,
+                current_function_declaration_return_type,
+            );
+            stack.pop();
+            try_will_return && catch_will_return
+        }
+        StatementType::Match { expression, arms } => {
+            // The arms are still checked if the value isn't, so their variables exist.
+            let value_type = check_expression_type(expression, errors, stack).unwrap_or(Type::Any);
+            let mut arms_will_return = vec![];
+            for arm in arms.iter_mut() {
+                // The variables of the pattern are in the arm's scope.
+                stack.push(Scope::default());
+                check_pattern(&mut arm.pattern, &value_type, arm.lines, errors, stack);
+                if let Some(guard) = &mut arm.guard {
+                    if check_expression_type(guard, errors, stack)
+                        .is_some_and(|guard_type| guard_type != Type::Boolean)
+                    {
+                        errors.push(CompilerError {
+                            lines: guard.lines,
+                            error: diagnostic!(GUARD_NOT_BOOLEAN, arm.pattern),
+                        });
+                    }
+                }
+                arms_will_return.push(check_statement_type(
+                    &mut arm.statement,
+                    errors,
+                    stack,
+                    current_function_declaration_return_type,
+                ));
+                stack.pop();
+            }
+            // The arms after one which matches everything never run.
+            let last_arm = arms.iter().position(|arm| arm.matches_everything());
+            // Which values exist is worked out from the patterns, which can't be done for values of type any.
+            let is_exhaustive = last_arm.is_some()
+                || !contains_any(&value_type)
+                    && missing_pattern(
+                        &arms
+                            .iter()
+                            .filter(|arm| arm.guard.is_none())
+                            .map(|arm| &arm.pattern)
+                            .collect::<Vec<_>>(),
+                    )
+                    .is_none();
+            is_exhaustive
+                && arms_will_return[..last_arm.map_or(arms.len(), |last_arm| last_arm + 1)]
+                    .iter()
+                    .all(|will_return| *will_return)
+        }
+        StatementType::Test { .. } => {
+            // Tests at the top level are removed before checking.
+            errors.push(CompilerError {
+                lines: statement.lines,
+                error: diagnostic!(TEST_NOT_AT_TOP_LEVEL),
+            });
+            false
+        }
+        StatementType::ImportNative(_) => {
+            // Imports at the top level are loaded and removed before checking.
+            errors.push(CompilerError {
+                lines: statement.lines,
+                error: diagnostic!(IMPORT_NOT_AT_TOP_LEVEL),
+            });
+            false
+        }
         StatementType::FunctionDeclaration {
             name,
             parameters,
             return_type,
             body,
             shadow_id,
+            slot,
+            ..
         } => {
-            let new_shadow_id = set_type(
-                name,
+            let (new_shadow_id, new_slot) = set_type(
+                *name,
                 Type::Function {
                     parameters: parameters
                         .iter()
@@ -158,27 +365,36 @@ fn check_statement_type(
                 stack,
             );
             *shadow_id = Some(new_shadow_id);
+            *slot = Some(new_slot);
 
-            stack.push(HashMap::new());
+            stack.push(Scope::default());
             for (parameter, shadow_id, parameter_type) in parameters.iter_mut() {
-                let new_shadow_id = set_type(parameter, parameter_type.clone(), stack);
+                let (new_shadow_id, _) = set_type(*parameter, parameter_type.clone(), stack);
                 *shadow_id = Some(new_shadow_id);
             }
+            // The body is only shared with the program cache until the program runs, so this rarely copies it.
+            let body = Rc::make_mut(body);
+            stack[0].break_targets.push(BreakTarget::None);
+            stack[0].return_types.push(Some(return_type.clone()));
             let body_will_return = check_statement_type(body, errors, stack, Some(return_type));
+            stack[0].return_types.pop();
+            stack[0].break_targets.pop();
             stack.pop();
 
             if !body_will_return && return_type != &Type::Void {
                 errors.push(CompilerError {
                     lines: statement.lines,
-                    error: "Function body may not return".into(),
+                    error: diagnostic!(FUNCTION_MAY_NOT_RETURN),
                 });
             }
             false
         }
+        // A return in a function returns even if its value has an error, so the function isn't also reported as
+        // maybe not returning.
         StatementType::Return(expression) => {
             let expression_type = if let Some(expression) = expression {
                 let Some(expression_type) = check_expression_type(expression, errors, stack) else {
-                    return false;
+                    return current_function_declaration_return_type.is_some();
                 };
                 expression_type
             } else {
@@ -186,54 +402,117 @@ fn check_statement_type(
             };
 
             if let Some(return_type) = current_function_declaration_return_type {
-                if expression_type != *return_type {
-                    // TODO: Consider Any?
+                if !can_assign(return_type, &expression_type) {
                     errors.push(CompilerError {
                         lines: statement.lines,
-                        error: format!("Expected {return_type:?} return, got {expression_type:?}."),
+                        error: diagnostic!(
+                            RETURN_TYPE_MISMATCH,
+                            format!("{return_type:?}"),
+                            format!("{expression_type:?}")
+                        ),
                     });
-                    false
-                } else {
-                    true
                 }
+                true
             } else {
                 errors.push(CompilerError {
                     lines: statement.lines,
-                    error: "Return can't be used outside a function.".into(),
+                    error: diagnostic!(RETURN_IN_BLOCK_EXPRESSION),
                 });
                 false
             }
         }
+        StatementType::Raise(expression) => {
+            let expression_type = check_expression_type(expression, errors, stack);
+            if expression_type
+                .as_ref()
+                .is_some_and(|expression_type| *expression_type != error_type())
+            {
+                errors.push(CompilerError {
+                    lines: expression.lines,
+                    error: diagnostic!(
+                        RAISED_VALUE_NOT_ERROR,
+                        error_type(),
+                        expression,
+                        expression_type.unwrap()
+                    ),
+                });
+            }
+            // Like a return, a raise leaves the function.
+            true
+        }
+        StatementType::Break(expression) => {
+            let expression_type = match expression {
+                Some(expression) => check_expression_type(expression, errors, stack),
+                None => Some(Type::Void),
+            };
+            match stack[0].break_targets.last_mut() {
+                Some(BreakTarget::LoopStatement) if expression.is_some() => {
+                    errors.push(CompilerError {
+                        lines: statement.lines,
+                        error: diagnostic!(BREAK_VALUE_IN_LOOP_STATEMENT),
+                    });
+                }
+                Some(BreakTarget::LoopStatement) => {}
+                Some(BreakTarget::Loop(loop_type)) => {
+                    let Some(expression_type) = expression_type else {
+                        return false;
+                    };
+                    match loop_type {
+                        Some(previous_type) => match common_type(previous_type, &expression_type) {
+                            Some(common_type) => *previous_type = common_type,
+                            None => {
+                                errors.push(CompilerError {
+                                    lines: statement.lines,
+                                    error: diagnostic!(
+                                        BREAK_TYPE_MISMATCH,
+                                        previous_type,
+                                        expression_type
+                                    ),
+                                });
+                            }
+                        },
+                        None => *loop_type = Some(expression_type),
+                    }
+                }
+                Some(BreakTarget::None) | None => {
+                    errors.push(CompilerError {
+                        lines: statement.lines,
+                        error: diagnostic!(BREAK_OUTSIDE_LOOP),
+                    });
+                }
+            }
+            false
+        }
     }
 }
 
+/// Checks the body of a loop expression and returns the type of its value.
+fn check_loop_type(
+    body: &mut Statement,
+    errors: &mut Vec<CompilerError>,
+    stack: &mut Vec<Scope>,
+    current_function_declaration_return_type: Option<&Type>,
+) -> Type {
+    stack[0].break_targets.push(BreakTarget::Loop(None));
+    check_statement_type(
+        body,
+        errors,
+        stack,
+        current_function_declaration_return_type,
+    );
+    let Some(BreakTarget::Loop(loop_type)) = stack[0].break_targets.pop() else {
+        unreachable!()
+    };
+    loop_type.unwrap_or(Type::Void)
+}
+
 fn check_expression_type(
     expression: &mut Expression,
     errors: &mut Vec<CompilerError>,
-    stack: &mut Vec<HashMap<String, (usize, Type)>>,
+    stack: &mut Vec<Scope>,
 ) -> Option<Type> {
     match &mut expression.expression_type {
-        ExpressionType::Binary {
-            operation,
-            left_expression,
-            right_expression,
-        } => {
-            let left_type = check_expression_type(left_expression, errors, stack);
-            let right_type = check_expression_type(right_expression, errors, stack);
-
-            match operation.result_type(
-                left_expression,
-                &left_type?,
-                right_expression,
-                &right_type?,
-            ) {
-                Ok(value_type) => Some(value_type),
-                Err(err) => {
-                    errors.push(err);
-                    None
-                }
-            }
-        }
+        ExpressionType::Binary { .. } => check_binary_expression(expression, errors, stack),
         ExpressionType::Grouping(expression) => check_expression_type(expression, errors, stack),
         ExpressionType::Literal(value) => Some(value.value_type()),
         ExpressionType::Tuple(expressions) => {
@@ -255,22 +534,181 @@ fn check_expression_type(
                 None
             }
         }
-        ExpressionType::TupleAccess { expression, index } => {
-            let Type::Tuple(tuple_type) = check_expression_type(expression, errors, stack)? else {
-                errors.push(CompilerError { lines: expression.lines, error: "Tuple required for tuple access".into() });
-                return None;
+        ExpressionType::Record(fields) => {
+            let mut field_types = vec![];
+            let mut valid_types = true;
+
+            for (name, expression) in fields {
+                if let Some(expression_type) = check_expression_type(expression, errors, stack) {
+                    field_types.push((*name, expression_type));
+                } else {
+                    valid_types = false;
+                }
+            }
+
+            valid_types.then_some(Type::Record(field_types))
+        }
+        ExpressionType::Interpolation { values, specs, .. } => {
+            let mut valid_types = true;
+            for (value, spec) in values.iter_mut().zip(specs.iter()) {
+                let Some(value_type) = check_expression_type(value, errors, stack) else {
+                    valid_types = false;
+                    continue;
+                };
+                if let Err(error) = spec.check(&value_type) {
+                    errors.push(CompilerError {
+                        lines: value.lines,
+                        error,
+                    });
+                    valid_types = false;
+                }
+            }
+            valid_types.then_some(Type::String)
+        }
+        ExpressionType::TupleAccess { expression, field } => {
+            let tuple_type = check_expression_type(expression, errors, stack)?;
+            let field_types = match (tuple_type, &mut *field) {
+                (Type::Tuple(field_types), Field::Index(_)) => field_types,
+                (Type::Record(fields), Field::Index(_)) => fields
+                    .into_iter()
+                    .map(|(_, field_type)| field_type)
+                    .collect(),
+                (Type::Record(fields), Field::Name { name, index }) => {
+                    let Some(position) = fields.iter().position(|(field, _)| field == name) else {
+                        errors.push(CompilerError {
+                            lines: expression.lines,
+                            error: diagnostic!(
+                                NO_SUCH_FIELD,
+                                expression,
+                                name,
+                                Type::Record(fields)
+                            ),
+                        });
+                        return None;
+                    };
+                    *index = Some(position);
+                    return Some(fields[position].1.clone());
+                }
+                (Type::Tuple(_), Field::Name { name, .. }) => {
+                    errors.push(CompilerError {
+                        lines: expression.lines,
+                        error: diagnostic!(TUPLE_FIELD_NAME, expression, name),
+                    });
+                    return None;
+                }
+                _ => {
+                    errors.push(CompilerError {
+                        lines: expression.lines,
+                        error: diagnostic!(TUPLE_REQUIRED),
+                    });
+                    return None;
+                }
             };
 
-            if *index < tuple_type.len() {
-                Some(tuple_type[*index].clone())
+            let index = field.index();
+            if index < field_types.len() {
+                Some(field_types[index].clone())
             } else {
                 errors.push(CompilerError {
                     lines: expression.lines,
-                    error: "Index too large".into(),
+                    error: diagnostic!(INDEX_TOO_LARGE),
                 });
                 None
             }
         }
+        ExpressionType::If {
+            condition,
+            then_expression,
+            else_expression,
+        } => {
+            let condition_type = check_expression_type(condition, errors, stack);
+            let then_type = check_expression_type(then_expression, errors, stack);
+            let else_type = check_expression_type(else_expression, errors, stack);
+            if condition_type.is_some_and(|condition_type| condition_type != Type::Boolean) {
+                errors.push(CompilerError {
+                    lines: condition.lines,
+                    error: diagnostic!(IF_CONDITION_NOT_BOOLEAN),
+                });
+                return None;
+            }
+            let (then_type, else_type) = (then_type?, else_type?);
+            let common_type = common_type(&then_type, &else_type);
+            if common_type.is_none() {
+                errors.push(CompilerError {
+                    lines: expression.lines,
+                    error: diagnostic!(IF_BRANCH_TYPE_MISMATCH, then_type, else_type),
+                });
+            }
+            common_type
+        }
+        ExpressionType::Block { statements, value } => {
+            // Returning or breaking from a block expression would leave the values around it on the stack, so it isn't allowed.
+            stack.push(Scope::default());
+            stack[0].break_targets.push(BreakTarget::None);
+            stack[0].return_types.push(None);
+            for statement in statements {
+                check_statement_type(statement, errors, stack, None);
+            }
+            let value_type = check_expression_type(value, errors, stack);
+            stack[0].return_types.pop();
+            stack[0].break_targets.pop();
+            stack.pop();
+            value_type
+        }
+        ExpressionType::Loop(body) => {
+            stack[0].return_types.push(None);
+            let loop_type = check_loop_type(body, errors, stack, None);
+            stack[0].return_types.pop();
+            Some(loop_type)
+        }
+        ExpressionType::Ok(value) => Some(Type::Result {
+            value: check_expression_type(value, errors, stack)?.into(),
+            error: Type::Never.into(),
+        }),
+        ExpressionType::Err(error) => Some(Type::Result {
+            value: Type::Never.into(),
+            error: check_expression_type(error, errors, stack)?.into(),
+        }),
+        ExpressionType::Some(value) => Some(Type::Option(
+            check_expression_type(value, errors, stack)?.into(),
+        )),
+        ExpressionType::Propagate(result) => {
+            let result_type = check_expression_type(result, errors, stack)?;
+            let Type::Result { value, error } = result_type else {
+                errors.push(CompilerError {
+                    lines: expression.lines,
+                    error: diagnostic!(PROPAGATE_NOT_RESULT, result, result_type),
+                });
+                return None;
+            };
+            // An Err is returned as it is, so the function must return a result with the same type of error.
+            let returned_type = Type::Result {
+                value: Type::Never.into(),
+                error,
+            };
+            match stack[0].return_types.last().unwrap() {
+                Some(return_type) if !can_assign(return_type, &returned_type) => {
+                    errors.push(CompilerError {
+                        lines: expression.lines,
+                        error: diagnostic!(
+                            PROPAGATE_ERROR_TYPE_MISMATCH,
+                            result,
+                            return_type,
+                            returned_type
+                        ),
+                    });
+                    None
+                }
+                Some(_) => Some(*value),
+                None => {
+                    errors.push(CompilerError {
+                        lines: expression.lines,
+                        error: diagnostic!(PROPAGATE_IN_BLOCK_EXPRESSION),
+                    });
+                    None
+                }
+            }
+        }
         ExpressionType::Unary {
             operation,
             expression,
@@ -288,16 +726,18 @@ fn check_expression_type(
             name,
             shadow_id,
             parent_height,
-        } => match get_type(name, stack) {
-            Some((current_shadow_id, variable_parent_height, variable_type)) => {
+            slot,
+        } => match get_type(*name, stack) {
+            Some((current_shadow_id, variable_parent_height, variable_slot, variable_type)) => {
                 *shadow_id = Some(current_shadow_id);
                 *parent_height = Some(variable_parent_height);
+                *slot = Some(variable_slot);
                 Some(variable_type)
             }
             None => {
                 errors.push(CompilerError {
                     lines: expression.lines,
-                    error: format!("No variable called {name} exists."),
+                    error: diagnostic!(NO_SUCH_VARIABLE, name),
                 });
                 None
             }
@@ -306,14 +746,21 @@ fn check_expression_type(
             function,
             arguments,
         } => {
-            let Type::Function { return_type, parameters } = check_expression_type(function, errors, stack)? else {
-                errors.push(CompilerError { lines: expression.lines, error: format!("{function} is not a function") });
+            let Type::Function {
+                return_type,
+                parameters,
+            } = check_expression_type(function, errors, stack)?
+            else {
+                errors.push(CompilerError {
+                    lines: expression.lines,
+                    error: diagnostic!(NOT_A_FUNCTION, function),
+                });
                 return None;
             };
 
             let mut argument_types = vec![];
             let mut valid_argument_types = true;
-            for argument in arguments {
+            for argument in arguments.iter_mut() {
                 match check_expression_type(argument, errors, stack) {
                     Some(argument_type) => {
                         argument_types.push(argument_type);
@@ -331,7 +778,11 @@ fn check_expression_type(
             if argument_types.len() != parameters.len() {
                 errors.push(CompilerError {
                     lines: expression.lines,
-                    error: format!("Invalid function calls.\nFunction parameters: {parameters:?}\nArguments passed: {argument_types:?}")
+                    error: diagnostic!(
+                        INVALID_ARGUMENTS,
+                        format!("{parameters:?}"),
+                        format!("{argument_types:?}")
+                    ),
                 });
                 return None;
             }
@@ -340,20 +791,232 @@ fn check_expression_type(
                 if !can_assign(parameter_type, argument_type) {
                     errors.push(CompilerError {
                         lines: expression.lines,
-                        error: format!("Invalid function calls.\nFunction parameters: {parameters:?}\nArguments passed: {argument_types:?}")
+                        error: diagnostic!(
+                            INVALID_ARGUMENTS,
+                            format!("{parameters:?}"),
+                            format!("{argument_types:?}")
+                        ),
                     });
                     return None;
                 }
             }
 
+            if let ExpressionType::Variable {
+                name,
+                parent_height: Some(parent_height),
+                slot: Some(slot),
+                ..
+            } = function.expression_type
+            {
+                if parent_height == stack.len() - 1 && stack[0].argument_typed_slots.contains(&slot)
+                {
+                    // The default of unwrap_or must be a value the option could have.
+                    if name.as_str() == "unwrap_or" {
+                        let default_type = argument_types.pop().unwrap();
+                        let option_type = argument_types.pop().unwrap();
+                        let value_type = match &option_type {
+                            Type::Option(value_type) => common_type(value_type, &default_type),
+                            _ => None,
+                        };
+                        if value_type.is_none() {
+                            errors.push(CompilerError {
+                                lines: expression.lines,
+                                error: diagnostic!(
+                                    UNWRAP_OR_TYPE_MISMATCH,
+                                    option_type,
+                                    default_type
+                                ),
+                            });
+                        }
+                        return value_type;
+                    }
+                    // send's value must be a message of the channel's or task's type, and recv returns an option
+                    // of one.
+                    if ["send", "recv"].contains(&name.as_str()) {
+                        let message_type = match &argument_types[0] {
+                            Type::Channel(message_type)
+                            | Type::Task {
+                                message: Some(message_type),
+                                ..
+                            } => (**message_type).clone(),
+                            channel_type => {
+                                errors.push(CompilerError {
+                                    lines: expression.lines,
+                                    error: diagnostic!(NOT_A_CHANNEL, name, channel_type),
+                                });
+                                return None;
+                            }
+                        };
+                        if name.as_str() == "recv" {
+                            return Some(Type::Option(message_type.into()));
+                        }
+                        if !can_assign(&message_type, &argument_types[1]) {
+                            errors.push(CompilerError {
+                                lines: expression.lines,
+                                error: match argument_types[0] {
+                                    Type::Channel(_) => diagnostic!(
+                                        CHANNEL_MESSAGE_TYPE_MISMATCH,
+                                        arguments[1],
+                                        message_type,
+                                        argument_types[1]
+                                    ),
+                                    _ => diagnostic!(
+                                        MAILBOX_MESSAGE_TYPE_MISMATCH,
+                                        arguments[1],
+                                        message_type,
+                                        argument_types[1]
+                                    ),
+                                },
+                            });
+                        }
+                        return Some(Type::Void);
+                    }
+                    // spawn needs a function whose result can be sent back from its thread. It takes no parameters,
+                    // or a channel as its mailbox, which the task returned by spawn sends and receives messages over.
+                    // join returns the result, or an error saying why the task failed.
+                    if name.as_str() == "spawn" {
+                        return match &argument_types[0] {
+                            Type::Function {
+                                parameters,
+                                return_type,
+                            } if can_send(return_type)
+                                && match &parameters[..] {
+                                    [] => true,
+                                    [Type::Channel(message_type)] => can_send(message_type),
+                                    _ => false,
+                                } =>
+                            {
+                                Some(Type::Task {
+                                    result: return_type.clone(),
+                                    message: match &parameters[..] {
+                                        [Type::Channel(message_type)] => Some(message_type.clone()),
+                                        _ => None,
+                                    },
+                                })
+                            }
+                            function_type => {
+                                errors.push(CompilerError {
+                                    lines: expression.lines,
+                                    error: diagnostic!(INVALID_SPAWN, function_type),
+                                });
+                                None
+                            }
+                        };
+                    }
+                    if name.as_str() == "join" {
+                        return match &argument_types[0] {
+                            Type::Task { result, .. } => Some(Type::Result {
+                                value: result.clone(),
+                                error: Type::String.into(),
+                            }),
+                            task_type => {
+                                errors.push(CompilerError {
+                                    lines: expression.lines,
+                                    error: diagnostic!(JOIN_NOT_TASK, task_type),
+                                });
+                                None
+                            }
+                        };
+                    }
+                    // Includes at the top level are replaced with the files they name before checking.
+                    if name.as_str() == "include" {
+                        errors.push(CompilerError {
+                            lines: expression.lines,
+                            error: diagnostic!(INCLUDE_NOT_AT_TOP_LEVEL),
+                        });
+                        return None;
+                    }
+                    // eval's value has whatever type the source gives it.
+                    if name.as_str() == "eval" {
+                        return Some(Type::Result {
+                            value: Type::Any.into(),
+                            error: Type::String.into(),
+                        });
+                    }
+                    // dbg is also passed the source of its argument and the line it's on, to print them.
+                    if name.as_str() == "dbg" {
+                        let lines = arguments[0].lines;
+                        let source = Value::String(arguments[0].to_string().as_str().into());
+                        for value in [source, Value::Number(lines.0 as f64)] {
+                            arguments.push(Expression {
+                                expression_type: ExpressionType::Literal(value),
+                                lines,
+                            });
+                        }
+                    }
+                    return argument_types.pop();
+                }
+            }
+
             Some(*return_type)
         }
     }
 }
 
-fn can_assign(left_type: &Type, right_type: &Type) -> bool {
+/// Checks a binary expression. Chains like 1 + 2 + 3 nest on the left, so the left operands which are binary
+/// expressions are checked here directly, as check_expression_type's stack frame is much larger.
+fn check_binary_expression(
+    expression: &mut Expression,
+    errors: &mut Vec<CompilerError>,
+    stack: &mut Vec<Scope>,
+) -> Option<Type> {
+    let ExpressionType::Binary {
+        operation,
+        left_expression,
+        right_expression,
+    } = &mut expression.expression_type
+    else {
+        unreachable!()
+    };
+    let left_type = match left_expression.expression_type {
+        ExpressionType::Binary { .. } => check_binary_expression(left_expression, errors, stack),
+        _ => check_expression_type(left_expression, errors, stack),
+    };
+    let right_type = check_expression_type(right_expression, errors, stack);
+
+    let (left_type, right_type) = (left_type?, right_type?);
+    match operation.result_type(left_expression, &left_type, right_expression, &right_type) {
+        Ok(value_type) => Some(value_type),
+        Err(err) => {
+            // Operations the host registered for the operands' types are calls to its functions.
+            let operator = stack[0].operators.iter().find(|operator| {
+                operator.operation == *operation
+                    && *operation != BinaryOperation::Assignment
+                    && can_assign(&operator.left_type, &left_type)
+                    && can_assign(&operator.right_type, &right_type)
+            });
+            let Some(operator) = operator else {
+                errors.push(err);
+                return None;
+            };
+            let (shadow_id, parent_height, slot, Type::Function { return_type, .. }) =
+                get_type(operator.function, stack).unwrap()
+            else {
+                unreachable!()
+            };
+            let function = Expression {
+                expression_type: ExpressionType::Variable {
+                    name: operator.function,
+                    shadow_id: Some(shadow_id),
+                    parent_height: Some(parent_height),
+                    slot: Some(slot),
+                },
+                lines: expression.lines,
+            };
+            let arguments = vec![(**left_expression).clone(), (**right_expression).clone()];
+            expression.expression_type = ExpressionType::FunctionCall {
+                function: function.into(),
+                arguments,
+            };
+            Some(*return_type)
+        }
+    }
+}
+
+pub fn can_assign(left_type: &Type, right_type: &Type) -> bool {
     match (left_type, right_type) {
         (Type::Any, _)
+        | (_, Type::Never)
         | (Type::Boolean, Type::Boolean)
         | (Type::Number, Type::Number)
         | (Type::String, Type::String)
@@ -371,6 +1034,16 @@ fn can_assign(left_type: &Type, right_type: &Type) -> bool {
 
             true
         }
+        (Type::Record(left_fields), Type::Record(right_fields)) => {
+            left_fields.len() == right_fields.len()
+                && left_fields.iter().zip(right_fields.iter()).all(
+                    |((left_name, left_type), (right_name, right_type))| {
+                        left_name == right_name && can_assign(left_type, right_type)
+                    },
+                )
+        }
+        // Values are assigned through references, so a &number can't be used as a &any.
+        (Type::Reference(left_type), Type::Reference(right_type)) => left_type == right_type,
         (
             Type::Function {
                 parameters: left_parameters,
@@ -399,15 +1072,253 @@ fn can_assign(left_type: &Type, right_type: &Type) -> bool {
 
             true
         }
+        (
+            Type::Result {
+                value: left_value,
+                error: left_error,
+            },
+            Type::Result {
+                value: right_value,
+                error: right_error,
+            },
+        ) => can_assign(left_value, right_value) && can_assign(left_error, right_error),
+        (Type::Option(left_value), Type::Option(right_value))
+        | (Type::Iterator(left_value), Type::Iterator(right_value)) => {
+            can_assign(left_value, right_value)
+        }
+        // Channels both send and receive values, so their message types must be assignable both ways.
+        (Type::Channel(left_message), Type::Channel(right_message)) => {
+            can_assign(left_message, right_message) && can_assign(right_message, left_message)
+        }
+        (
+            Type::Task {
+                result: left_result,
+                message: left_message,
+            },
+            Type::Task {
+                result: right_result,
+                message: right_message,
+            },
+        ) => {
+            can_assign(left_result, right_result)
+                && match (left_message, right_message) {
+                    (None, None) => true,
+                    (Some(left_message), Some(right_message)) => {
+                        can_assign(left_message, right_message)
+                            && can_assign(right_message, left_message)
+                    }
+                    _ => false,
+                }
+        }
         _ => false,
     }
 }
 
+/// Checks that the pattern can match values of the type, and declares its variables in the current scope.
+fn check_pattern(
+    pattern: &mut Pattern,
+    value_type: &Type,
+    lines: (usize, usize),
+    errors: &mut Vec<CompilerError>,
+    stack: &mut [Scope],
+) {
+    let matches_type = match (&mut *pattern, value_type) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Variable { name, shadow_id }, _) => {
+            if stack.last().unwrap().variables.contains_key(name) {
+                errors.push(CompilerError {
+                    lines,
+                    error: diagnostic!(PATTERN_VARIABLE_REPEATED, name),
+                });
+            }
+            *shadow_id = Some(set_type(*name, value_type.clone(), stack).0);
+            true
+        }
+        (Pattern::Literal(value), _) => can_check_equality(value_type, &value.value_type()),
+        (Pattern::Tuple(patterns), Type::Tuple(types)) if patterns.len() == types.len() => {
+            for (pattern, value_type) in patterns.iter_mut().zip(types) {
+                check_pattern(pattern, value_type, lines, errors, stack);
+            }
+            true
+        }
+        (Pattern::Some(pattern), Type::Option(value_type))
+        | (
+            Pattern::Ok(pattern),
+            Type::Result {
+                value: value_type, ..
+            },
+        )
+        | (
+            Pattern::Err(pattern),
+            Type::Result {
+                error: value_type, ..
+            },
+        ) => {
+            check_pattern(pattern, value_type, lines, errors, stack);
+            true
+        }
+        // The parts of values of these types have the same type.
+        (Pattern::Tuple(patterns), Type::Any | Type::Never) => {
+            for pattern in patterns {
+                check_pattern(pattern, value_type, lines, errors, stack);
+            }
+            true
+        }
+        (
+            Pattern::Some(pattern) | Pattern::Ok(pattern) | Pattern::Err(pattern),
+            Type::Any | Type::Never,
+        ) => {
+            check_pattern(pattern, value_type, lines, errors, stack);
+            true
+        }
+        (Pattern::Range { start, end }, Type::Number | Type::Any | Type::Never) => {
+            if start >= end {
+                errors.push(CompilerError {
+                    lines,
+                    error: diagnostic!(
+                        EMPTY_RANGE_PATTERN,
+                        Value::Number(*start),
+                        Value::Number(*end)
+                    ),
+                });
+            }
+            true
+        }
+        (Pattern::Or(patterns), _) => {
+            check_pattern(&mut patterns[0], value_type, lines, errors, stack);
+            for alternative in &mut patterns[1..] {
+                // Each alternative is checked in a scope of its own, then its variables are compared with the first's.
+                let scope = std::mem::take(stack.last_mut().unwrap());
+                check_pattern(alternative, value_type, lines, errors, stack);
+                let alternative_scope = std::mem::replace(stack.last_mut().unwrap(), scope);
+                let scope = stack.last_mut().unwrap();
+                let same_variables = alternative_scope.variables.len() == scope.variables.len()
+                    && alternative_scope.variables.iter().all(
+                        |(name, (_, _, alternative_type))| {
+                            scope
+                                .variables
+                                .get_mut(name)
+                                .is_some_and(|(_, _, variable_type)| {
+                                    match common_type(variable_type, alternative_type) {
+                                        Some(common_type) => {
+                                            *variable_type = common_type;
+                                            true
+                                        }
+                                        None => false,
+                                    }
+                                })
+                        },
+                    );
+                if !same_variables {
+                    errors.push(CompilerError {
+                        lines,
+                        error: diagnostic!(ALTERNATIVE_VARIABLES_DIFFER, pattern),
+                    });
+                    return;
+                }
+            }
+            true
+        }
+        _ => false,
+    };
+    if !matches_type {
+        errors.push(CompilerError {
+            lines,
+            error: diagnostic!(PATTERN_TYPE_MISMATCH, pattern, value_type),
+        });
+    }
+}
+
+/// Whether values of the type can contain values of type any.
+fn contains_any(value_type: &Type) -> bool {
+    match value_type {
+        Type::Any => true,
+        Type::Tuple(types) => types.iter().any(contains_any),
+        Type::Record(fields) => fields
+            .iter()
+            .any(|(_, field_type)| contains_any(field_type)),
+        Type::Option(value_type) | Type::Iterator(value_type) | Type::Channel(value_type) => {
+            contains_any(value_type)
+        }
+        Type::Task { result, message } => {
+            contains_any(result) || message.as_deref().is_some_and(contains_any)
+        }
+        Type::Result { value, error } => contains_any(value) || contains_any(error),
+        _ => false,
+    }
+}
+
+/// The type which values of both types can be assigned to, if they're the same type apart from never,
+/// like result(number, string) for Ok(1) and Err("x").
+fn common_type(left_type: &Type, right_type: &Type) -> Option<Type> {
+    match (left_type, right_type) {
+        (Type::Never, other_type) | (other_type, Type::Never) => Some(other_type.clone()),
+        (
+            Type::Result {
+                value: left_value,
+                error: left_error,
+            },
+            Type::Result {
+                value: right_value,
+                error: right_error,
+            },
+        ) => Some(Type::Result {
+            value: common_type(left_value, right_value)?.into(),
+            error: common_type(left_error, right_error)?.into(),
+        }),
+        (Type::Option(left_value), Type::Option(right_value)) => {
+            Some(Type::Option(common_type(left_value, right_value)?.into()))
+        }
+        (Type::Iterator(left_item), Type::Iterator(right_item)) => {
+            Some(Type::Iterator(common_type(left_item, right_item)?.into()))
+        }
+        (
+            Type::Task {
+                result: left_result,
+                message: left_message,
+            },
+            Type::Task {
+                result: right_result,
+                message: right_message,
+            },
+        ) if left_message == right_message => Some(Type::Task {
+            result: common_type(left_result, right_result)?.into(),
+            message: left_message.clone(),
+        }),
+        (Type::Tuple(left_types), Type::Tuple(right_types))
+            if left_types.len() == right_types.len() =>
+        {
+            left_types
+                .iter()
+                .zip(right_types)
+                .map(|(left_type, right_type)| common_type(left_type, right_type))
+                .collect::<Option<_>>()
+                .map(Type::Tuple)
+        }
+        (Type::Record(left_fields), Type::Record(right_fields))
+            if left_fields.len() == right_fields.len() =>
+        {
+            left_fields
+                .iter()
+                .zip(right_fields)
+                .map(|((left_name, left_type), (right_name, right_type))| {
+                    (left_name == right_name)
+                        .then(|| Some((*left_name, common_type(left_type, right_type)?)))?
+                })
+                .collect::<Option<_>>()
+                .map(Type::Record)
+        }
+        _ => (left_type == right_type).then(|| left_type.clone()),
+    }
+}
+
 fn can_check_equality(left_type: &Type, right_type: &Type) -> bool {
     // TODO: Function type check?
     match (left_type, right_type) {
         (Type::Any, _)
         | (_, Type::Any)
+        | (Type::Never, _)
+        | (_, Type::Never)
         | (Type::Boolean, Type::Boolean)
         | (Type::Number, Type::Number)
         | (Type::String, Type::String)
@@ -425,6 +1336,17 @@ fn can_check_equality(left_type: &Type, right_type: &Type) -> bool {
 
             true
         }
+        // Records need the same field names in the same order.
+        (Type::Record(left_fields), Type::Record(right_fields)) => {
+            left_fields.len() == right_fields.len()
+                && left_fields.iter().zip(right_fields.iter()).all(
+                    |((left_name, left_type), (right_name, right_type))| {
+                        left_name == right_name && can_check_equality(left_type, right_type)
+                    },
+                )
+        }
+        // References are equal if they refer to the same variable.
+        (Type::Reference(left_type), Type::Reference(right_type)) => left_type == right_type,
         (
             Type::Function {
                 parameters: left_parameters,
@@ -453,6 +1375,22 @@ fn can_check_equality(left_type: &Type, right_type: &Type) -> bool {
 
             true
         }
+        (
+            Type::Result {
+                value: left_value,
+                error: left_error,
+            },
+            Type::Result {
+                value: right_value,
+                error: right_error,
+            },
+        ) => {
+            can_check_equality(left_value, right_value)
+                && can_check_equality(left_error, right_error)
+        }
+        (Type::Option(left_value), Type::Option(right_value)) => {
+            can_check_equality(left_value, right_value)
+        }
         _ => false,
     }
 }
@@ -464,7 +1402,7 @@ impl UnaryOperation {
         expression_type: &Type,
     ) -> Result<Type, CompilerError> {
         match self {
-            UnaryOperation::Minus => match expression_type {
+            UnaryOperation::Minus | UnaryOperation::BitwiseNot => match expression_type {
                 Type::Number => Ok(Type::Number),
                 _ => Err(unary_parse_error(self, expression, expression_type)),
             },
@@ -472,6 +1410,19 @@ impl UnaryOperation {
                 Type::Boolean => Ok(Type::Boolean),
                 _ => Err(unary_parse_error(self, expression, expression_type)),
             },
+            UnaryOperation::Reference => match expression.expression_type {
+                ExpressionType::Variable { .. } => {
+                    Ok(Type::Reference(expression_type.clone().into()))
+                }
+                _ => Err(CompilerError {
+                    lines: expression.lines,
+                    error: diagnostic!(REFERENCE_NOT_VARIABLE, expression),
+                }),
+            },
+            UnaryOperation::Dereference => match expression_type {
+                Type::Reference(value_type) => Ok((**value_type).clone()),
+                _ => Err(unary_parse_error(self, expression, expression_type)),
+            },
         }
     }
 }
@@ -483,9 +1434,11 @@ fn unary_parse_error(
 ) -> CompilerError {
     CompilerError {
         lines: expression.lines,
-        error: format!(
-            "Can't apply {operation:?} on {expression}.\nExpression type: {:?}",
-            expression_type
+        error: diagnostic!(
+            INVALID_UNARY_OPERAND,
+            format!("{operation:?}"),
+            expression,
+            format!("{expression_type:?}")
         ),
     }
 }
@@ -502,6 +1455,9 @@ impl BinaryOperation {
             BinaryOperation::Add => match (left_type, right_type) {
                 (Type::Number, Type::Number) => Ok(Type::Number),
                 (Type::String, Type::String) => Ok(Type::String),
+                (Type::Tuple(left_types), Type::Tuple(right_types)) => Ok(Type::Tuple(
+                    left_types.iter().chain(right_types).cloned().collect(),
+                )),
                 _ => Err(binary_non_matching_types_error(
                     self,
                     left_expression,
@@ -530,7 +1486,12 @@ impl BinaryOperation {
                     right_type,
                 )),
             },
-            BinaryOperation::Divide => match (left_type, right_type) {
+            BinaryOperation::Divide
+            | BinaryOperation::BitwiseAnd
+            | BinaryOperation::BitwiseOr
+            | BinaryOperation::BitwiseXor
+            | BinaryOperation::ShiftLeft
+            | BinaryOperation::ShiftRight => match (left_type, right_type) {
                 (Type::Number, Type::Number) => Ok(Type::Number),
                 _ => Err(binary_non_matching_types_error(
                     self,
@@ -629,11 +1590,16 @@ impl BinaryOperation {
             BinaryOperation::Assignment => {
                 // TODO: Handle any and void?
                 match left_expression.expression_type {
-                    ExpressionType::Variable { .. } | ExpressionType::TupleAccess { .. } => {}
+                    ExpressionType::Variable { .. }
+                    | ExpressionType::TupleAccess { .. }
+                    | ExpressionType::Unary {
+                        operation: UnaryOperation::Dereference,
+                        ..
+                    } => {}
                     _ => {
                         return Err(CompilerError {
                             lines: (left_expression.lines.0, right_expression.lines.1),
-                            error: "Can only assign to variable or tuple elements.".into(),
+                            error: diagnostic!(INVALID_ASSIGNMENT_TARGET),
                         });
                     }
                 }
@@ -663,40 +1629,60 @@ fn binary_non_matching_types_error(
 ) -> CompilerError {
     CompilerError {
         lines: (left_expression.lines.0, right_expression.lines.1),
-        error: format!(
-            "Can't apply {operation:?} on {left_expression} and {right_expression}.\nLeft type: {:?}\nRight type: {:?}",
-            left_type,
-            right_type
-        )
+        error: diagnostic!(
+            INVALID_BINARY_OPERANDS,
+            format!("{operation:?}"),
+            left_expression,
+            right_expression,
+            format!("{left_type:?}"),
+            format!("{right_type:?}")
+        ),
     }
 }
 
-pub fn get_type(
-    variable: &String,
-    stack: &[HashMap<String, (usize, Type)>],
-) -> Option<(usize, usize, Type)> {
-    for (height, map) in stack.iter().rev().enumerate() {
-        if let Some(value_type) = map.get(variable) {
-            let res = value_type.clone();
-            return Some((res.0, height, res.1));
+pub fn get_type(variable: Symbol, stack: &[Scope]) -> Option<(usize, usize, usize, Type)> {
+    for (height, scope) in stack.iter().rev().enumerate() {
+        if let Some((shadow_id, slot, value_type)) = scope.variables.get(&variable) {
+            let height = if scope.static_variables.contains(&(variable, *shadow_id)) {
+                stack.len() - 1
+            } else {
+                height
+            };
+            return Some((*shadow_id, height, *slot, value_type.clone()));
         }
     }
 
     None
 }
 
-pub fn set_type(
-    variable: &str,
-    value_type: Type,
-    stack: &mut [HashMap<String, (usize, Type)>],
-) -> usize {
-    let map = stack.last_mut().unwrap(); // We can unwrap as there will always be a global environment.
-    if map.contains_key(variable) {
-        let previous_shadow_id = map[variable].0;
-        map.insert(variable.to_owned(), (previous_shadow_id + 1, value_type));
-        previous_shadow_id + 1
-    } else {
-        map.insert(variable.to_owned(), (0, value_type));
-        0
-    }
+/// Returns the shadow_id and slot of the new variable.
+pub fn set_type(variable: Symbol, value_type: Type, stack: &mut [Scope]) -> (usize, usize) {
+    let scope = stack.last_mut().unwrap(); // We can unwrap as there will always be a global environment.
+    let shadow_id = match scope.variables.get(&variable) {
+        Some((previous_shadow_id, _, _)) => previous_shadow_id + 1,
+        None => 0,
+    };
+    let slot = scope.slot_count;
+    scope.slot_count += 1;
+    scope
+        .variables
+        .insert(variable, (shadow_id, slot, value_type));
+    (shadow_id, slot)
+}
+
+/// Declares a static variable in the current scope, stored in a new global slot.
+/// Returns the shadow_id and the global slot of the new variable.
+fn set_static_type(variable: Symbol, value_type: Type, stack: &mut [Scope]) -> (usize, usize) {
+    let slot = stack[0].slot_count;
+    stack[0].slot_count += 1;
+    let scope = stack.last_mut().unwrap();
+    let shadow_id = match scope.variables.get(&variable) {
+        Some((previous_shadow_id, _, _)) => previous_shadow_id + 1,
+        None => 0,
+    };
+    scope
+        .variables
+        .insert(variable, (shadow_id, slot, value_type));
+    scope.static_variables.insert((variable, shadow_id));
+    (shadow_id, slot)
 }
@@ -0,0 +1,702 @@
+use std::rc::Rc;
+
+use crate::{
+    compiler::{Function, Instruction},
+    format::interpolate,
+    garbage_collection::GarbageCollectionSchedule,
+    interpreter::{
+        bitwise_operation, match_pattern, next_item, uncaught_error, RuntimeError, Variables,
+        MAX_CALL_DEPTH,
+    },
+    messages::diagnostic,
+    parser::BinaryOperation,
+    small_string::INLINE_CAPACITY,
+    tokenizer::{FunctionBody, FunctionValue, Reference, Value},
+};
+
+/// How many instructions run between checks for whether the program was cancelled from another thread.
+const CANCEL_CHECK_INTERVAL: usize = 1024;
+
+struct Environment {
+    parent: usize,
+    slots: Vec<Value>,
+}
+
+struct CallFrame {
+    function: Rc<Function>,
+    instruction_pointer: usize,
+    environment: usize,
+    stack_height: usize, // The height of the stack below the call, which a return goes back to.
+}
+
+/// A try statement which is running, and where to continue if an error is raised in it.
+struct Handler {
+    catch_start: usize, // The instruction which starts the catch statement.
+    frame_count: usize, // The number of call frames when the try statement started.
+    environment: usize,
+    stack_height: usize,
+}
+
+/// Runs functions produced by the compiler.
+/// Global variables are stored in the global environment of Variables,
+/// the other variables are stored in environments with a fixed number of slots.
+/// Variable and type checking resolves each variable to a slot and each field to an index, so instructions index them
+/// directly and there's no lookup left for inline caches to skip. Declaring a global again gives it a new slot instead
+/// of replacing the old one, which functions declared before still use, so there's nothing to invalidate either.
+pub struct Vm {
+    environments: Vec<Environment>, // Environment 0 is the global environment, whose variables are in Variables.
+    free_environments: Vec<usize>,
+    pub garbage_collection: GarbageCollectionSchedule,
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Vm {
+    pub fn new() -> Vm {
+        Vm {
+            environments: vec![Environment {
+                parent: 0,
+                slots: vec![],
+            }],
+            free_environments: vec![],
+            garbage_collection: GarbageCollectionSchedule::new(),
+        }
+    }
+
+    fn allocate_environment(&mut self, parent: usize, slots: Vec<Value>) -> usize {
+        match self.free_environments.pop() {
+            Some(environment) => {
+                self.environments[environment] = Environment { parent, slots };
+                environment
+            }
+            None => {
+                self.environments.push(Environment { parent, slots });
+                self.environments.len() - 1
+            }
+        }
+    }
+
+    /// The variable a reference refers to.
+    fn referenced<'a>(
+        &'a mut self,
+        globals: &'a mut [Value],
+        reference: &Reference,
+    ) -> &'a mut Value {
+        match reference.environment {
+            0 => &mut globals[reference.slot],
+            environment => &mut self.environments[environment].slots[reference.slot],
+        }
+    }
+
+    fn ancestor(&self, mut environment: usize, depth: usize) -> usize {
+        for _ in 0..depth {
+            environment = self.environments[environment].parent;
+        }
+        environment
+    }
+
+    fn mark_value(value: &Value, queue: &mut Vec<usize>) {
+        match value {
+            Value::Function(function) => queue.push(function.parent_environment),
+            Value::Reference(reference) => queue.push(reference.environment),
+            Value::Tuple(values) => {
+                for value in values.iter() {
+                    Vm::mark_value(value, queue);
+                }
+            }
+            Value::Record(fields) => {
+                for (_, value) in fields.iter() {
+                    Vm::mark_value(value, queue);
+                }
+            }
+            Value::Result(result) => {
+                let (Ok(value) | Err(value)) = &**result;
+                Vm::mark_value(value, queue);
+            }
+            Value::Option(Some(value)) => Vm::mark_value(value, queue),
+            _ => {}
+        }
+    }
+
+    /// Frees the environments which can't be reached from the globals or the roots, between runs.
+    pub fn collect_garbage_between_runs(&mut self, roots: &[Value], variables: &mut Variables) {
+        let globals = variables.take_globals();
+        self.collect_garbage(0, &[], roots, &globals, variables);
+        variables.restore_globals(globals);
+    }
+
+    /// Frees the environments which can't be reached from the roots.
+    /// Needs to be called before an environment is allocated, while its parent is still reachable.
+    fn collect_garbage(
+        &mut self,
+        environment: usize,
+        frames: &[CallFrame],
+        stack: &[Value],
+        globals: &[Value],
+        variables: &mut Variables,
+    ) {
+        let mut reachable = vec![false; self.environments.len()];
+        let mut queue = vec![environment];
+        queue.extend(frames.iter().map(|frame| frame.environment));
+        for value in stack {
+            Vm::mark_value(value, &mut queue);
+        }
+        for value in globals {
+            Vm::mark_value(value, &mut queue);
+        }
+        // Free environments are marked as reachable so that they aren't freed again.
+        let free_environment_count = self.free_environments.len();
+        for free_environment in &self.free_environments {
+            reachable[*free_environment] = true;
+        }
+
+        while let Some(environment) = queue.pop() {
+            if reachable[environment] {
+                continue;
+            }
+            reachable[environment] = true;
+            queue.push(self.environments[environment].parent);
+            for value in &self.environments[environment].slots {
+                Vm::mark_value(value, &mut queue);
+            }
+        }
+
+        let mut live_environments = 0;
+        for (environment, reachable) in reachable.into_iter().enumerate() {
+            if reachable {
+                live_environments += 1;
+            } else {
+                self.environments[environment].slots = vec![];
+                self.free_environments.push(environment);
+            }
+        }
+        live_environments -= free_environment_count;
+
+        self.garbage_collection.collected(live_environments);
+        variables.stats.garbage_collections += 1;
+    }
+
+    pub fn run(
+        &mut self,
+        script: Rc<Function>,
+        variables: &mut Variables,
+    ) -> Result<(), RuntimeError> {
+        // Globals are accessed by slot, so they're taken out of the environment map while the VM runs
+        // to index them directly instead of looking up the global environment on every access.
+        let mut globals = variables.take_globals();
+        let result = self.execute(script, &mut globals, variables);
+        variables.restore_globals(globals);
+        result
+    }
+
+    fn execute(
+        &mut self,
+        script: Rc<Function>,
+        globals: &mut Vec<Value>,
+        variables: &mut Variables,
+    ) -> Result<(), RuntimeError> {
+        let mut stack: Vec<Value> = vec![];
+        let mut frames: Vec<CallFrame> = vec![];
+        let mut handlers: Vec<Handler> = vec![];
+        let mut function = script;
+        let mut instruction_pointer = 0;
+        let mut environment = 0;
+
+        loop {
+            let instruction = function.chunk.instructions[instruction_pointer];
+            instruction_pointer += 1;
+            variables.stats.instructions_executed += 1;
+            if variables
+                .stats
+                .instructions_executed
+                .is_multiple_of(CANCEL_CHECK_INTERVAL)
+                && variables.is_cancelled()
+            {
+                let line = function.chunk.lines[instruction_pointer - 1];
+                return Err(RuntimeError {
+                    lines: (line, line),
+                    error: diagnostic!(CANCELLED),
+                });
+            }
+
+            match instruction {
+                Instruction::Constant(constant) => {
+                    stack.push(function.chunk.constants[constant].clone());
+                }
+                Instruction::Pop => {
+                    stack.pop();
+                }
+                Instruction::GetGlobal(slot) => {
+                    stack.push(globals[slot].clone());
+                }
+                Instruction::SetGlobal(slot) => {
+                    globals[slot] = stack.last().unwrap().clone();
+                }
+                Instruction::StoreGlobal(slot) => {
+                    globals[slot] = stack.pop().unwrap();
+                }
+                Instruction::DefineGlobal(global) => {
+                    let (name, shadow_id, slot) = function.chunk.globals[global];
+                    if globals.len() <= slot {
+                        globals.resize(slot + 1, Value::Void);
+                    }
+                    globals[slot] = stack.pop().unwrap();
+                    variables.global_slots.insert((name, shadow_id), slot);
+                }
+                Instruction::Next(target) => {
+                    let [.., sequence, Value::Number(position)] = &mut stack[..] else {
+                        unreachable!()
+                    };
+                    let mut byte_position = *position as usize;
+                    match next_item(sequence, &mut byte_position) {
+                        Some(value) => {
+                            *position = byte_position as f64;
+                            stack.push(value);
+                        }
+                        None => instruction_pointer = target,
+                    }
+                }
+                Instruction::JumpIfInitialized { slot, target } => {
+                    if variables.initialized_statics.contains(&slot) {
+                        instruction_pointer = target;
+                    }
+                }
+                Instruction::InitializeStatic(slot) => {
+                    globals[slot] = stack.pop().unwrap();
+                    variables.initialized_statics.insert(slot);
+                }
+                Instruction::SetGlobalField { slot, path } => {
+                    let value = stack.last().unwrap().clone();
+                    let variable = &mut globals[slot];
+                    *tuple_field(variable, &function.chunk.paths[path]) = value;
+                }
+                Instruction::GetLocal { depth, slot } => {
+                    let environment = self.ancestor(environment, depth);
+                    stack.push(self.environments[environment].slots[slot].clone());
+                }
+                Instruction::SetLocal { depth, slot } => {
+                    let environment = self.ancestor(environment, depth);
+                    self.environments[environment].slots[slot] = stack.last().unwrap().clone();
+                }
+                Instruction::StoreLocal { depth, slot } => {
+                    let environment = self.ancestor(environment, depth);
+                    self.environments[environment].slots[slot] = stack.pop().unwrap();
+                }
+                Instruction::DefineLocal(slot) => {
+                    self.environments[environment].slots[slot] = stack.pop().unwrap();
+                }
+                Instruction::SetLocalField { depth, slot, path } => {
+                    let environment = self.ancestor(environment, depth);
+                    let value = stack.last().unwrap().clone();
+                    let variable = &mut self.environments[environment].slots[slot];
+                    *tuple_field(variable, &function.chunk.paths[path]) = value;
+                }
+                Instruction::ReferenceGlobal(slot) => {
+                    stack.push(Value::Reference(Rc::new(Reference {
+                        environment: 0,
+                        slot,
+                        value_type: globals[slot].value_type(),
+                    })));
+                    variables.stats.allocations += 1;
+                }
+                Instruction::ReferenceLocal { depth, slot } => {
+                    let environment = self.ancestor(environment, depth);
+                    stack.push(Value::Reference(Rc::new(Reference {
+                        environment,
+                        slot,
+                        value_type: self.environments[environment].slots[slot].value_type(),
+                    })));
+                    variables.stats.allocations += 1;
+                }
+                Instruction::Dereference => {
+                    let Some(Value::Reference(reference)) = stack.pop() else {
+                        unreachable!()
+                    };
+                    stack.push(self.referenced(globals, &reference).clone());
+                }
+                Instruction::SetReference => {
+                    let Some(Value::Reference(reference)) = stack.pop() else {
+                        unreachable!()
+                    };
+                    *self.referenced(globals, &reference) = stack.last().unwrap().clone();
+                }
+                Instruction::SetReferenceField(path) => {
+                    let Some(Value::Reference(reference)) = stack.pop() else {
+                        unreachable!()
+                    };
+                    let value = stack.last().unwrap().clone();
+                    let variable = self.referenced(globals, &reference);
+                    *tuple_field(variable, &function.chunk.paths[path]) = value;
+                }
+                Instruction::PushScope(size) => {
+                    if self.garbage_collection.allocate() {
+                        self.collect_garbage(environment, &frames, &stack, globals, variables);
+                    }
+                    environment = self.allocate_environment(environment, vec![Value::Void; size]);
+                    variables.stats.environment_pushes += 1;
+                }
+                Instruction::PopScope => {
+                    environment = self.environments[environment].parent;
+                }
+                Instruction::Negate => {
+                    let Some(Value::Number(number)) = stack.pop() else {
+                        unreachable!()
+                    };
+                    stack.push(Value::Number(-number));
+                }
+                Instruction::Not => {
+                    let Some(Value::Boolean(boolean)) = stack.pop() else {
+                        unreachable!()
+                    };
+                    stack.push(Value::Boolean(!boolean));
+                }
+                Instruction::BitwiseNot => {
+                    let Some(Value::Number(number)) = stack.pop() else {
+                        unreachable!()
+                    };
+                    stack.push(Value::Number(!(number as i64) as f64));
+                }
+                Instruction::Add
+                | Instruction::Subtract
+                | Instruction::Multiply
+                | Instruction::Divide
+                | Instruction::Equal
+                | Instruction::NotEqual
+                | Instruction::Less
+                | Instruction::LessEqual
+                | Instruction::Greater
+                | Instruction::GreaterEqual
+                | Instruction::BitwiseAnd
+                | Instruction::BitwiseOr
+                | Instruction::BitwiseXor
+                | Instruction::ShiftLeft
+                | Instruction::ShiftRight => {
+                    let right_value = stack.pop().unwrap();
+                    let left_value = stack.pop().unwrap();
+                    let value = binary_operation(instruction, left_value, right_value);
+                    if matches!(&value, Value::String(string) if !string.is_inline())
+                        || matches!(value, Value::Tuple(_))
+                    {
+                        variables.stats.allocations += 1;
+                    }
+                    stack.push(value);
+                }
+                Instruction::Jump(target) => {
+                    instruction_pointer = target;
+                }
+                Instruction::JumpIfFalse(target) => {
+                    if stack.pop() == Some(Value::Boolean(false)) {
+                        instruction_pointer = target;
+                    }
+                }
+                Instruction::JumpIfFalseOrPop(target) => {
+                    if stack.last() == Some(&Value::Boolean(false)) {
+                        instruction_pointer = target;
+                    } else {
+                        stack.pop();
+                    }
+                }
+                Instruction::JumpIfTrueOrPop(target) => {
+                    if stack.last() == Some(&Value::Boolean(true)) {
+                        instruction_pointer = target;
+                    } else {
+                        stack.pop();
+                    }
+                }
+                Instruction::JumpIfOk(target) => {
+                    let Some(Value::Result(result)) = stack.last() else {
+                        unreachable!()
+                    };
+                    if let Ok(value) = &**result {
+                        *stack.last_mut().unwrap() = value.clone();
+                        instruction_pointer = target;
+                    }
+                }
+                Instruction::Match { pattern, target } => {
+                    let pattern = &function.chunk.patterns[pattern];
+                    let mut variables = vec![];
+                    if match_pattern(pattern, stack.last().unwrap(), &mut variables) {
+                        stack.extend(variables);
+                    } else {
+                        instruction_pointer = target;
+                    }
+                }
+                Instruction::JumpUnless { comparison, target } => {
+                    let right_value = stack.pop().unwrap();
+                    let left_value = stack.pop().unwrap();
+                    if binary_operation(comparison.instruction(), left_value, right_value)
+                        == Value::Boolean(false)
+                    {
+                        instruction_pointer = target;
+                    }
+                }
+                Instruction::Tuple(size) => {
+                    let values = stack.split_off(stack.len() - size);
+                    stack.push(Value::Tuple(values.into()));
+                    variables.stats.allocations += 1;
+                }
+                Instruction::Record(record) => {
+                    let names = &function.chunk.records[record];
+                    let values = stack.split_off(stack.len() - names.len());
+                    let fields = names.iter().copied().zip(values).collect::<Vec<_>>();
+                    stack.push(Value::Record(fields.into()));
+                    variables.stats.allocations += 1;
+                }
+                Instruction::Ok => {
+                    let value = stack.pop().unwrap();
+                    stack.push(Value::Result(Ok(value).into()));
+                }
+                Instruction::Err => {
+                    let error = stack.pop().unwrap();
+                    stack.push(Value::Result(Err(error).into()));
+                }
+                Instruction::Some => {
+                    let value = stack.pop().unwrap();
+                    stack.push(Value::Option(Some(value.into())));
+                }
+                Instruction::TupleGet(index) => {
+                    let value = stack.pop().unwrap();
+                    stack.push(value.field(index).clone());
+                }
+                Instruction::Interpolate(interpolation) => {
+                    let (strings, specs) = &function.chunk.interpolations[interpolation];
+                    let values = stack.split_off(stack.len() - specs.len());
+                    let string = interpolate(strings, specs, &values);
+                    if string.len() > INLINE_CAPACITY {
+                        variables.stats.allocations += 1;
+                    }
+                    stack.push(Value::String(string.into()));
+                }
+                Instruction::Closure(closure) => {
+                    let closure = function.chunk.functions[closure].clone();
+                    stack.push(Value::Function(Rc::new(FunctionValue {
+                        parameters: closure.parameters.clone(),
+                        return_type: closure.return_type.clone(),
+                        body: FunctionBody::Bytecode(closure),
+                        parent_environment: environment,
+                    })));
+                    variables.stats.allocations += 1;
+                }
+                Instruction::Call(argument_count) => {
+                    let function_index = stack.len() - argument_count - 1;
+                    let Value::Function(called_function) = &stack[function_index] else {
+                        unreachable!()
+                    };
+                    let parent_environment = called_function.parent_environment;
+
+                    match called_function.body.clone() {
+                        FunctionBody::Bytecode(called_function) => {
+                            if frames.len() == MAX_CALL_DEPTH {
+                                let line = function.chunk.lines[instruction_pointer - 1];
+                                return Err(RuntimeError {
+                                    lines: (line, line),
+                                    error: diagnostic!(CALL_DEPTH_EXCEEDED, MAX_CALL_DEPTH),
+                                });
+                            }
+                            if argument_count > 0 && self.garbage_collection.allocate() {
+                                self.collect_garbage(
+                                    environment,
+                                    &frames,
+                                    &stack,
+                                    globals,
+                                    variables,
+                                );
+                            }
+                            let arguments = stack.split_off(function_index + 1);
+                            stack.pop();
+
+                            frames.push(CallFrame {
+                                function,
+                                instruction_pointer,
+                                environment,
+                                stack_height: function_index,
+                            });
+                            function = called_function;
+                            instruction_pointer = 0;
+                            let stats = &mut variables.stats;
+                            stats.max_call_depth = stats.max_call_depth.max(frames.len());
+                            // Functions without parameters don't need an environment for them.
+                            environment = if arguments.is_empty() {
+                                parent_environment
+                            } else {
+                                variables.stats.environment_pushes += 1;
+                                self.allocate_environment(parent_environment, arguments)
+                            };
+                        }
+                        FunctionBody::RustClosure { closure, .. } => {
+                            let arguments = stack.split_off(function_index + 1);
+                            stack.pop();
+                            let line = function.chunk.lines[instruction_pointer - 1];
+                            variables.builtin_line.set(line);
+                            stack.push(closure(arguments));
+                            if let Some(error) = variables.take_builtin_error() {
+                                return Err(RuntimeError {
+                                    lines: (line, line),
+                                    error,
+                                });
+                            }
+                            if self.garbage_collection.requested() {
+                                self.collect_garbage(
+                                    environment,
+                                    &frames,
+                                    &stack,
+                                    globals,
+                                    variables,
+                                );
+                            }
+                        }
+                        FunctionBody::Statement(_) => {
+                            unreachable!("the VM only creates bytecode functions")
+                        }
+                    }
+                }
+                Instruction::Return => {
+                    let value = stack.pop().unwrap();
+                    let Some(frame) = frames.pop() else {
+                        variables.return_value = value;
+                        // Scripts end with a return of void, so a return before it is one the program made.
+                        variables.returned =
+                            instruction_pointer < function.chunk.instructions.len();
+                        return Ok(());
+                    };
+                    function = frame.function;
+                    instruction_pointer = frame.instruction_pointer;
+                    environment = frame.environment;
+                    // Returning from inside a for loop leaves its string and position on the stack.
+                    stack.truncate(frame.stack_height);
+                    stack.push(value);
+                    // Returning from inside try statements leaves them too.
+                    while handlers
+                        .last()
+                        .is_some_and(|handler| handler.frame_count > frames.len())
+                    {
+                        handlers.pop();
+                    }
+                }
+                Instruction::Try(catch_start) => handlers.push(Handler {
+                    catch_start,
+                    frame_count: frames.len(),
+                    environment,
+                    stack_height: stack.len(),
+                }),
+                Instruction::EndTry => {
+                    handlers.pop();
+                }
+                Instruction::Raise => {
+                    let error = stack.pop().unwrap();
+                    variables.stats.errors_raised += 1;
+                    let Some(handler) = handlers.pop() else {
+                        let line = function.chunk.lines[instruction_pointer - 1];
+                        return Err(uncaught_error(&error, (line, line)));
+                    };
+                    // The frame above the try statement's function holds that function, which it was called from.
+                    if let Some(frame) = frames.drain(handler.frame_count..).next() {
+                        function = frame.function;
+                    }
+                    instruction_pointer = handler.catch_start;
+                    environment = handler.environment;
+                    stack.truncate(handler.stack_height);
+                    stack.push(error);
+                }
+            }
+        }
+    }
+}
+
+fn tuple_field<'a>(mut value: &'a mut Value, path: &[usize]) -> &'a mut Value {
+    for &index in path {
+        value = value.field_mut(index);
+    }
+    value
+}
+
+fn binary_operation(instruction: Instruction, left_value: Value, right_value: Value) -> Value {
+    match (instruction, left_value, right_value) {
+        (Instruction::Equal, left, right) => Value::Boolean(left == right),
+        (Instruction::NotEqual, left, right) => Value::Boolean(left != right),
+        (Instruction::Add, Value::String(left), Value::String(right)) => {
+            Value::String(left.concat(&right))
+        }
+        (Instruction::Add, Value::Tuple(left), Value::Tuple(right)) => Value::Tuple(
+            left.iter()
+                .chain(right.iter())
+                .cloned()
+                .collect::<Vec<_>>()
+                .into(),
+        ),
+        (Instruction::Less, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left < right)
+        }
+        (Instruction::LessEqual, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left <= right)
+        }
+        (Instruction::Greater, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left > right)
+        }
+        (Instruction::GreaterEqual, Value::String(left), Value::String(right)) => {
+            Value::Boolean(left >= right)
+        }
+        (instruction, Value::Number(left), Value::Number(right)) => match instruction {
+            Instruction::Add => Value::Number(left + right),
+            Instruction::Subtract => Value::Number(left - right),
+            Instruction::Multiply => Value::Number(left * right),
+            Instruction::Divide => Value::Number(left / right),
+            Instruction::Less => Value::Boolean(left < right),
+            Instruction::LessEqual => Value::Boolean(left <= right),
+            Instruction::Greater => Value::Boolean(left > right),
+            Instruction::GreaterEqual => Value::Boolean(left >= right),
+            Instruction::BitwiseAnd => {
+                Value::Number(bitwise_operation(BinaryOperation::BitwiseAnd, left, right))
+            }
+            Instruction::BitwiseOr => {
+                Value::Number(bitwise_operation(BinaryOperation::BitwiseOr, left, right))
+            }
+            Instruction::BitwiseXor => {
+                Value::Number(bitwise_operation(BinaryOperation::BitwiseXor, left, right))
+            }
+            Instruction::ShiftLeft => {
+                Value::Number(bitwise_operation(BinaryOperation::ShiftLeft, left, right))
+            }
+            Instruction::ShiftRight => {
+                Value::Number(bitwise_operation(BinaryOperation::ShiftRight, left, right))
+            }
+            _ => unreachable!(),
+        },
+        _ => unreachable!(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{tokenizer::Value, Backend, Interpreter};
+
+    #[test]
+    fn keeps_the_globals_after_a_runtime_error() {
+        let mut interpreter = Interpreter::with_backend(Backend::Vm);
+        interpreter.eval("let a = 1;").unwrap();
+        let program = "a = 2;
+let b = 3;
+fn fail() {
+    assert(false);
+}
+fail();";
+        assert!(interpreter.eval(program).is_err());
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(2.0)));
+        assert_eq!(interpreter.get_global("b"), Some(Value::Number(3.0)));
+        interpreter.eval("a = a + b;").unwrap();
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(5.0)));
+    }
+
+    #[test]
+    fn functions_keep_the_global_they_were_declared_with() {
+        let mut interpreter = Interpreter::with_backend(Backend::Vm);
+        interpreter
+            .eval("let a = 1;\nfn f() -> number { return a; }\nlet a = \"shadowed\";\nlet b = f();")
+            .unwrap();
+        assert_eq!(interpreter.get_global("b"), Some(Value::Number(1.0)));
+    }
+}
@@ -0,0 +1,38 @@
+use std::{env, path::Path};
+
+use decibelle::{test_runner::run_specs, Backend};
+
+/// Runs the programs in tests/spec with each backend and compares their output and exit status to their .expected files.
+/// Run with BLESS=1 to write what each program did with the tree walker to its .expected file instead.
+#[test]
+fn specs() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/spec");
+    let results = run_specs(&directory).unwrap();
+    assert!(!results.is_empty());
+    if env::var_os("BLESS").is_some() {
+        for result in results
+            .iter()
+            .filter(|result| result.backend == Backend::TreeWalker)
+        {
+            result.bless().unwrap();
+        }
+        return;
+    }
+    let failures = results
+        .iter()
+        .filter(|result| !result.passed())
+        .map(|result| {
+            format!(
+                "{} with {:?}\nexpected:\n{}\nactual:\n{}",
+                result.path.display(),
+                result.backend,
+                result
+                    .expected
+                    .as_deref()
+                    .unwrap_or("(no .expected file)\n"),
+                result.actual
+            )
+        })
+        .collect::<Vec<_>>();
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}